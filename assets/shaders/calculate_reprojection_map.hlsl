@@ -96,7 +96,7 @@ void main(uint2 px: SV_DispatchThreadID) {
     float2 prev_gather_uv = (bilinear_at_prev.origin + 1.0) / output_tex_size.xy;
     float4 prev_depth = prev_depth_tex.GatherRed(sampler_nnc, prev_gather_uv).wzxy;
 
-    float4 prev_view_z = rcp(prev_depth * -frame_constants.view_constants.prev_clip_to_prev_view._43);
+    float4 prev_view_z = depth_to_view_z_with_clip_to_view(frame_constants.view_constants.prev_clip_to_prev_view, prev_depth);
 
     // Note: departure from the quoted technique: linear offset from zero distance at previous position instead of scaling.
     float4 quad_dists = abs(plane_dist_prev_dz * (prev_view_z - prev_pvs.z));
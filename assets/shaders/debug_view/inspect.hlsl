@@ -0,0 +1,63 @@
+// Compiled with `IS_UINT` defined as 0 or 1, depending on the input format.
+
+#if IS_UINT
+    [[vk::binding(0)]] Texture2DArray<uint4> input_tex;
+#else
+    [[vk::binding(0)]] Texture2DArray<float4> input_tex;
+#endif
+[[vk::binding(1)]] RWTexture2D<float4> output_tex;
+[[vk::binding(2)]] RWByteAddressBuffer pick_buf;
+[[vk::binding(3)]] cbuffer _ {
+    float2 value_range;
+    float2 pick_uv;
+    uint channel_mask;
+    uint array_layer;
+    uint pick_slot;
+    uint pick_frame;
+};
+
+static const uint PICK_SLOT_BYTES = 32;
+
+float4 load_texel(uint2 px, out uint4 raw) {
+#if IS_UINT
+    raw = input_tex.Load(int4(px, array_layer, 0));
+    return float4(raw);
+#else
+    const float4 value = input_tex.Load(int4(px, array_layer, 0));
+    raw = asuint(value);
+    return value;
+#endif
+}
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    uint2 extent;
+    output_tex.GetDimensions(extent.x, extent.y);
+
+    if (any(px >= extent)) {
+        return;
+    }
+
+    uint4 raw;
+    const float4 value = load_texel(px, raw);
+
+    // Negative coordinates disable picking
+    if (pick_uv.x >= 0.0 && all(px == min(uint2(pick_uv * extent), extent - 1))) {
+        const uint offset = pick_slot * PICK_SLOT_BYTES;
+        pick_buf.Store4(offset, raw);
+        pick_buf.Store4(offset + 16, uint4(px, IS_UINT, pick_frame));
+    }
+
+    const float4 channels = float4((channel_mask.xxxx >> uint4(0, 1, 2, 3)) & 1);
+    const float4 remapped = (value - value_range.x) / max(1e-20, value_range.y - value_range.x);
+
+    float3 color;
+    if (countbits(channel_mask) == 1) {
+        // Show a single channel as grayscale
+        color = dot(remapped, channels).xxx;
+    } else {
+        color = remapped.rgb * channels.rgb;
+    }
+
+    output_tex[px] = float4(max(0.0, color), 1.0);
+}
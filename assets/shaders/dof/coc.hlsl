@@ -4,13 +4,30 @@
 [[vk::binding(0)]] Texture2D<float> depth_tex;
 [[vk::binding(1)]] RWTexture2D<float> output_tex;
 [[vk::binding(2)]] RWTexture2D<float> tile_output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    float4 output_tex_size;
+};
 
 groupshared uint max_abs_coc_asuint;
 
-float coc_size(float depth, float focal_point, float focus_scale) {
-    //return 0;
-	float coc = clamp((1.0 / focal_point - 1.0 / depth) * focus_scale, -0.3, 0.3);
-	return coc * 20.0;
+// Must match `MAX_BLUR_SIZE` in `gather.hlsl`
+static const float MAX_COC = 20.0;
+
+// Signed radius of the circle of confusion in pixels; negative in front of the focus plane.
+float coc_size(float depth) {
+    const float aperture = frame_constants.lens_aperture_radius;
+    const float focus = frame_constants.lens_focus_distance;
+
+    // Radius of the blur disk on the focus plane, projected onto the image
+    const float radius_at_focus = aperture * (depth - focus) / depth;
+
+    // Orthographic projections don't divide by w, so their scale doesn't depend on distance
+    const float4x4 view_to_clip = frame_constants.view_constants.view_to_clip;
+    const bool is_orthographic = view_to_clip._43 == 0.0;
+    const float px_per_unit_at_focus = view_to_clip._22 * 0.5 * output_tex_size.y
+        / (is_orthographic ? 1.0 : focus);
+
+    return clamp(radius_at_focus * px_per_unit_at_focus, -MAX_COC, MAX_COC);
 }
 
 [numthreads(8, 8, 1)]
@@ -19,12 +36,7 @@ void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
     GroupMemoryBarrierWithGroupSync();
 
     float linear_depth = -depth_to_view_z(depth_tex[px]);
-    float max_coc = 20.0;
-
-    float focus = -depth_to_view_z(depth_tex.SampleLevel(sampler_nnc, 0.5, 0));
-    
-    //float coc = clamp((linear_depth - 1) * 20.0, -max_coc, max_coc);
-    float coc = coc_size(linear_depth, focus, 0.7);
+    float coc = coc_size(linear_depth);
 
     InterlockedMax(max_abs_coc_asuint, asuint(abs(coc)));
     GroupMemoryBarrierWithGroupSync();
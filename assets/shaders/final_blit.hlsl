@@ -4,10 +4,13 @@
 [[vk::binding(3)]] cbuffer _ {
     float4 main_tex_size;
     float4 output_tex_size;
+    uint display_target;
+    float paper_white_nits;
 };
 
 #include "inc/image.hlsl"
 #include "inc/color/srgb.hlsl"
+#include "inc/color/display_target.hlsl"
 
 struct LinearToSrgbRemap {
     static LinearToSrgbRemap create() {
@@ -20,9 +23,34 @@ struct LinearToSrgbRemap {
     }
 };
 
+// The UI is rendered in sRGB. Converts it to the primaries of the display.
+float4 gui_to_display_target(float4 gui) {
+    float3 linear_rgb = rec709_to_display_target_primaries(sRGB_EOTF(gui.rgb), display_target);
+    return float4(max(0.0, linear_rgb), gui.a);
+}
+
 [numthreads(8, 8, 1)]
 void main(in uint2 px : SV_DispatchThreadID) {
     #if 1
+    if (display_target_is_hdr(display_target)) {
+        // Composite in linear, with the UI at paper white
+        float3 main;
+        if (any(main_tex_size.xy != output_tex_size.xy)) {
+            main = image_sample_catmull_rom(
+                TextureImage::from_parts(main_tex, main_tex_size.xy),
+                (px + 0.5) / output_tex_size.xy,
+                IdentityImageRemap::create()
+            ).rgb;
+        } else {
+            main = main_tex[px].rgb;
+        }
+        float4 gui = gui_to_display_target(gui_tex[px]);
+
+        float3 result = max(0.0, main) * (1.0 - gui.a) + gui.rgb;
+        output_tex[px] = float4(display_target_encode(result, display_target, paper_white_nits), 1);
+        return;
+    }
+
     float3 main;
     if (any(main_tex_size.xy != output_tex_size.xy)) {
         main = image_sample_catmull_rom(
@@ -34,6 +62,9 @@ void main(in uint2 px : SV_DispatchThreadID) {
         main = sRGB_OETF(saturate(main_tex[px].rgb));
     }
     float4 gui = gui_tex[px];
+    if (display_target != DISPLAY_TARGET_REC709) {
+        gui.rgb = sRGB_OETF(gui_to_display_target(gui).rgb);
+    }
 
     float3 result = main.rgb * (1.0 - gui.a) + gui.rgb;
     //float3 result = lerp(main, gui.rgb, gui.a);
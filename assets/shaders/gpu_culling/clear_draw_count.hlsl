@@ -0,0 +1,6 @@
+[[vk::binding(0)]] RWByteAddressBuffer draw_count_buf;
+
+[numthreads(1, 1, 1)]
+void main() {
+    draw_count_buf.Store(0, 0);
+}
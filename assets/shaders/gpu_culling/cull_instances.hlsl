@@ -0,0 +1,140 @@
+#include "../inc/frame_constants.hlsl"
+#include "../inc/uv.hlsl"
+#include "../inc/raster_instance.hlsl"
+
+struct InstanceTransform {
+    row_major float3x4 current;
+    row_major float3x4 previous;
+};
+
+// `VkDrawIndexedIndirectCommand`
+struct DrawIndexedIndirectCommand {
+    uint index_count;
+    uint instance_count;
+    uint first_index;
+    int vertex_offset;
+    uint first_instance;
+};
+
+[[vk::binding(0)]] StructuredBuffer<InstanceTransform> instance_transforms_dyn;
+[[vk::binding(1)]] StructuredBuffer<RasterInstance> raster_instances_dyn;
+[[vk::binding(2)]] Texture2D<float> hiz_tex;
+[[vk::binding(3)]] RWStructuredBuffer<DrawIndexedIndirectCommand> draw_commands;
+[[vk::binding(4)]] RWByteAddressBuffer draw_count_buf;
+[[vk::binding(5)]] cbuffer _ {
+    uint instance_count;
+    uint use_occlusion_culling;
+    uint2 hiz_extent;
+    uint hiz_mip_count;
+};
+
+float3 aabb_corner(RasterInstance inst, uint i) {
+    return float3(
+        (i & 1) ? inst.aabb_max.x : inst.aabb_min.x,
+        (i & 2) ? inst.aabb_max.y : inst.aabb_min.y,
+        (i & 4) ? inst.aabb_max.z : inst.aabb_min.z
+    );
+}
+
+bool is_in_frustum(RasterInstance inst, float3x4 transform) {
+    // Culled if all corners are outside the same clip plane
+    uint outside_all = 0x1f;
+
+    for (uint i = 0; i < 8; ++i) {
+        const float3 ws_pos = mul(transform, float4(aabb_corner(inst, i), 1.0));
+        const float4 vs_pos = mul(frame_constants.view_constants.world_to_view, float4(ws_pos, 1.0));
+        const float4 cs_pos = mul(frame_constants.view_constants.view_to_clip, vs_pos);
+
+        uint outside = 0;
+        outside |= cs_pos.x < -cs_pos.w ? 1 : 0;
+        outside |= cs_pos.x > cs_pos.w ? 2 : 0;
+        outside |= cs_pos.y < -cs_pos.w ? 4 : 0;
+        outside |= cs_pos.y > cs_pos.w ? 8 : 0;
+        // Reverse-Z; beyond the near plane
+        outside |= cs_pos.z > cs_pos.w ? 16 : 0;
+
+        outside_all &= outside;
+    }
+
+    return outside_all == 0;
+}
+
+// Tests the bounds against the depth pyramid of the previous frame, reprojected with the previous camera.
+bool is_occluded(RasterInstance inst, float3x4 transform) {
+    float2 uv_min = 1.0.xx;
+    float2 uv_max = 0.0.xx;
+    float nearest_depth = 0.0;
+
+    for (uint i = 0; i < 8; ++i) {
+        const float3 ws_pos = mul(transform, float4(aabb_corner(inst, i), 1.0));
+        const float4 vs_pos = mul(frame_constants.view_constants.prev_world_to_prev_view, float4(ws_pos, 1.0));
+        const float4 cs_pos = mul(frame_constants.view_constants.prev_view_to_prev_clip, vs_pos);
+
+        // Crossing the camera plane; can't be bounded in screen space
+        if (cs_pos.w <= 0.0) {
+            return false;
+        }
+
+        const float2 uv = cs_to_uv(cs_pos.xy / cs_pos.w);
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest_depth = max(nearest_depth, cs_pos.z / cs_pos.w);
+    }
+
+    uv_min = saturate(uv_min);
+    uv_max = saturate(uv_max);
+
+    // Pick the mip where the bounds cover at most 2x2 texels
+    const float2 size_px = (uv_max - uv_min) * hiz_extent;
+    const uint mip = min(
+        uint(max(0.0, ceil(log2(max(max(size_px.x, size_px.y), 1.0))))),
+        hiz_mip_count - 1
+    );
+
+    const uint2 mip_extent = max(1u.xx, hiz_extent >> mip);
+    const uint2 px_min = min(uint2(uv_min * hiz_extent) >> mip, mip_extent - 1);
+    const uint2 px_max = min(uint2(uv_max * hiz_extent) >> mip, mip_extent - 1);
+
+    float farthest_occluder = 1.0;
+    for (uint y = px_min.y; y <= px_max.y; ++y) {
+        for (uint x = px_min.x; x <= px_max.x; ++x) {
+            farthest_occluder = min(farthest_occluder, hiz_tex.Load(int3(x, y, mip)));
+        }
+    }
+
+    return nearest_depth < farthest_occluder;
+}
+
+[numthreads(64, 1, 1)]
+void main(uint instance_index: SV_DispatchThreadID) {
+    if (instance_index >= instance_count) {
+        return;
+    }
+
+    const RasterInstance inst = raster_instances_dyn[instance_index];
+    const float3x4 transform = instance_transforms_dyn[instance_index].current;
+
+    if (inst.camera_visible == 0) {
+        return;
+    }
+
+    if (!is_in_frustum(inst, transform)) {
+        return;
+    }
+
+    if (use_occlusion_culling != 0 && is_occluded(inst, transform)) {
+        return;
+    }
+
+    uint draw_slot;
+    draw_count_buf.InterlockedAdd(0, 1, draw_slot);
+
+    DrawIndexedIndirectCommand cmd;
+    cmd.index_count = inst.index_count;
+    cmd.instance_count = 1;
+    cmd.first_index = inst.first_index;
+    cmd.vertex_offset = 0;
+    // The vertex shader reads the instance index back from `SV_InstanceID`
+    cmd.first_instance = instance_index;
+    draw_commands[draw_slot] = cmd;
+}
@@ -0,0 +1,28 @@
+[[vk::binding(0)]] Texture2D<float> input_tex;
+[[vk::binding(1)]] RWTexture2D<float> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 input_extent;
+    uint2 output_extent;
+};
+
+// Keeps the farthest depth (smallest, with reverse-Z) of each footprint.
+// With odd input extents, the last row and column of the output also cover
+// the leftover input texels, so that the pyramid stays conservative.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    if (any(px >= output_extent)) {
+        return;
+    }
+
+    const uint2 src_begin = px * 2;
+    const uint2 src_end = select(px == output_extent - 1, input_extent, src_begin + 2);
+
+    float farthest = 1.0;
+    for (uint y = src_begin.y; y < src_end.y; ++y) {
+        for (uint x = src_begin.x; x < src_end.x; ++x) {
+            farthest = min(farthest, input_tex[uint2(x, y)]);
+        }
+    }
+
+    output_tex[px] = farthest;
+}
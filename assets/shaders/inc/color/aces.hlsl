@@ -0,0 +1,39 @@
+#ifndef ACES_HLSL
+#define ACES_HLSL
+
+// The ACES reference rendering transform (RRT) and the sRGB output device
+// transform (ODT), as fitted by Stephen Hill:
+// https://github.com/TheRealMJP/BakingLab/blob/master/BakingLab/ACES.hlsl
+//
+// The tone scale is applied in ACEScg (AP1). The result is brought back to display-linear
+// Rec.709 primaries, and can be converted to wider display gamuts from there.
+
+// sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+static const float3x3 ACES_INPUT_MAT = float3x3(
+    0.59719, 0.35458, 0.04823,
+    0.07600, 0.90834, 0.01566,
+    0.02840, 0.13383, 0.83777
+);
+
+// ODT_SAT => XYZ => D60_2_D65 => sRGB
+static const float3x3 ACES_OUTPUT_MAT = float3x3(
+     1.60475, -0.53108, -0.07367,
+    -0.10208,  1.10813, -0.00605,
+    -0.00327, -0.07276,  1.07602
+);
+
+float3 aces_rrt_and_odt_fit(float3 v) {
+    float3 a = v * (v + 0.0245786) - 0.000090537;
+    float3 b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return a / b;
+}
+
+// Scene-linear Rec.709 in, display-linear Rec.709 out.
+float3 aces_fitted_rec709(float3 col) {
+    col = mul(ACES_INPUT_MAT, col);
+    col = aces_rrt_and_odt_fit(col);
+    col = mul(ACES_OUTPUT_MAT, col);
+    return saturate(col);
+}
+
+#endif  // ACES_HLSL
@@ -0,0 +1,55 @@
+#ifndef AGX_HLSL
+#define AGX_HLSL
+
+// Troy Sobotka's AgX with the default ("base") look, using the polynomial
+// contrast curve fit by Benjamin Wrensch:
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+//
+// The inset matrices are tuned for Rec.709 primaries, but the transform is applied
+// in the primaries of the display it's rendering for, so that its gamut is used.
+
+static const float3x3 AGX_INSET_MAT = float3x3(
+    0.842479062253094,  0.0784335999999992, 0.0792237451477643,
+    0.0423282422610123, 0.878468636469772,  0.0791661274605434,
+    0.0423756549057051, 0.0784336,          0.879142973793104
+);
+
+static const float3x3 AGX_OUTSET_MAT = float3x3(
+     1.19687900512017,   -0.0980208811401368, -0.0990297440797205,
+    -0.0528968517574562,  1.15190312990417,   -0.0989611768448433,
+    -0.0529716355144438, -0.0980434501171241,  1.15107367264116
+);
+
+static const float AGX_MIN_EV = -12.47393;
+static const float AGX_MAX_EV = 4.026069;
+
+float3 agx_default_contrast_approx(float3 x) {
+    float3 x2 = x * x;
+    float3 x4 = x2 * x2;
+
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+// Scene-linear in, display-linear out, both in the same primaries.
+float3 agx(float3 col) {
+    col = mul(AGX_INSET_MAT, max(0.0, col));
+
+    // Log2 encoding
+    col = clamp(log2(max(1e-10, col)), AGX_MIN_EV, AGX_MAX_EV);
+    col = (col - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+
+    col = agx_default_contrast_approx(col);
+
+    col = mul(AGX_OUTSET_MAT, col);
+
+    // The curve produces a signal for a 2.2 power display
+    return pow(saturate(col), 2.2);
+}
+
+#endif  // AGX_HLSL
@@ -0,0 +1,104 @@
+#ifndef DISPLAY_TARGET_HLSL
+#define DISPLAY_TARGET_HLSL
+
+#include "srgb.hlsl"
+
+// Must match `DisplayTarget` in `post.rs`
+#define DISPLAY_TARGET_REC709 0
+#define DISPLAY_TARGET_DISPLAY_P3 1
+#define DISPLAY_TARGET_REC2020_PQ 2
+#define DISPLAY_TARGET_SCRGB 3
+
+// Luminance of 1.0 in scRGB
+static const float SCRGB_WHITE_NITS = 80.0;
+
+bool display_target_is_hdr(uint target) {
+    return target == DISPLAY_TARGET_REC2020_PQ || target == DISPLAY_TARGET_SCRGB;
+}
+
+float3 rec709_to_display_p3(float3 col) {
+    return mul(float3x3(
+        0.8224621, 0.1775380, 0.0000000,
+        0.0331941, 0.9668058, 0.0000000,
+        0.0170827, 0.0723974, 0.9105199
+    ), col);
+}
+
+float3 rec709_to_rec2020(float3 col) {
+    return mul(float3x3(
+        0.6274039, 0.3292830, 0.0433131,
+        0.0690973, 0.9195404, 0.0113623,
+        0.0163914, 0.0880133, 0.8955953
+    ), col);
+}
+
+float3 rec2020_to_rec709(float3 col) {
+    return mul(float3x3(
+         1.6604910, -0.5876411, -0.0728499,
+        -0.1245505,  1.1328999, -0.0083494,
+        -0.0181508, -0.1005789,  1.1187297
+    ), col);
+}
+
+// SMPTE ST 2084, with `nits` of 1.0 being 10000 nits.
+float3 linear_to_pq(float3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    const float3 y = pow(max(0.0, nits), m1);
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), m2);
+}
+
+float3 pq_to_linear(float3 signal) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    const float3 e = pow(max(0.0, signal), 1.0 / m2);
+    return pow(max(0.0, e - c1) / (c2 - c3 * e), 1.0 / m1);
+}
+
+// From display-linear Rec.709 to display-linear in the target's primaries.
+//
+// scRGB uses Rec.709 primaries, but reaches beyond them with negative values. Those can't
+// be stored in the post-processing output, so it's processed in Rec.2020 up until encoding.
+float3 rec709_to_display_target_primaries(float3 col, uint target) {
+    if (target == DISPLAY_TARGET_DISPLAY_P3) {
+        return rec709_to_display_p3(col);
+    } else if (target == DISPLAY_TARGET_REC2020_PQ || target == DISPLAY_TARGET_SCRGB) {
+        return rec709_to_rec2020(col);
+    } else {
+        return col;
+    }
+}
+
+// From display-linear to the signal sent to the display.
+// Rec.709 and Display P3 both use the sRGB transfer function.
+//
+// On HDR displays, display-linear 1.0 is shown at `paper_white_nits`.
+float3 display_target_encode(float3 col, uint target, float paper_white_nits) {
+    if (target == DISPLAY_TARGET_REC2020_PQ) {
+        return linear_to_pq(col * (paper_white_nits / 10000.0));
+    } else if (target == DISPLAY_TARGET_SCRGB) {
+        return rec2020_to_rec709(col) * (paper_white_nits / SCRGB_WHITE_NITS);
+    } else {
+        return sRGB_OETF(saturate(col));
+    }
+}
+
+float3 display_target_decode(float3 signal, uint target, float paper_white_nits) {
+    if (target == DISPLAY_TARGET_REC2020_PQ) {
+        return pq_to_linear(signal) * (10000.0 / paper_white_nits);
+    } else if (target == DISPLAY_TARGET_SCRGB) {
+        return rec709_to_rec2020(signal) * (SCRGB_WHITE_NITS / paper_white_nits);
+    } else {
+        return sRGB_EOTF(signal);
+    }
+}
+
+#endif  // DISPLAY_TARGET_HLSL
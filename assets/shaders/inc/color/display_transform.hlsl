@@ -80,7 +80,9 @@ float compress_luminance(float v) {
     #endif
 }
 
-float3 display_transform_sRGB(float3 input_stimulus) {
+// `max_output_scale` is the brightest displayable value, relative to the white of SDR output;
+// that is the peak brightness of HDR displays over their paper white.
+float3 display_transform_sRGB_with_max_output(float3 input_stimulus, float max_output_scale) {
     if (USE_BEZOLD_BRUCKE_SHIFT) {
         const float t = sRGB_to_luminance(input_stimulus) / BEZOLD_BRUCKE_SHIFT_RAMP;
         const float shift_amount = t / (t + 1.0);
@@ -106,8 +108,6 @@ float3 display_transform_sRGB(float3 input_stimulus) {
     //return max_intensity_equiv_lum.xxx - 1.0;
     //return saturate(max_intensity_rgb);
 
-    const float max_output_scale = 1.0;
-
     // Compress the brightness. We will then adjust the chromatic shader_input stimulus to match this.
     // Note that this is not the non-linear "L*", but a 0..`max_output_scale` value as a multilpier
     // over the maximum achromatic luminance.
@@ -121,7 +121,7 @@ float3 display_transform_sRGB(float3 input_stimulus) {
     float3 compressed_rgb = (max_intensity_rgb / max_intensity_equiv_lum) * compressed_achromatic_luminance;
 
     // The achromatic stimulus we'll interpolate towards to fix out-of-gamut stimulus.
-    const float clamped_compressed_achromatic_luminance = min(1.0, compressed_achromatic_luminance);
+    const float clamped_compressed_achromatic_luminance = min(max_output_scale, compressed_achromatic_luminance);
 
     // We now want to map the out-of-gamut stimulus back to what our device can display.
     // Since both the `compressed_rgb` and `clamped_compressed_achromatic_luminance` are of the same-ish
@@ -153,8 +153,8 @@ float3 display_transform_sRGB(float3 input_stimulus) {
     const float chroma_attenuation_start = CHROMA_ATTENUATION_START;
     const float chroma_attenuation_exponent = lerp(CHROMA_ATTENUATION_EXPONENT_MAX, CHROMA_ATTENUATION_EXPONENT_MIN, chroma_strength);
     const float chroma_attenuation_t = saturate(
-        (compressed_achromatic_luminance - min(1, max_intensity_equiv_lum) * chroma_attenuation_start)
-        / ((CHROMA_ATTENUATION_BIAS * max_output_scale - min(1, max_intensity_equiv_lum) * chroma_attenuation_start))
+        (compressed_achromatic_luminance - min(1, max_intensity_equiv_lum) * max_output_scale * chroma_attenuation_start)
+        / ((CHROMA_ATTENUATION_BIAS * max_output_scale - min(1, max_intensity_equiv_lum) * max_output_scale * chroma_attenuation_start))
     );
 
 #if USE_LONG_TAILED_CHROMA_ATTENUATION
@@ -164,8 +164,8 @@ float3 display_transform_sRGB(float3 input_stimulus) {
     {
         const float compressed_achromatic_luminance2 = compress_luminance(0.125 * input_equiv_lum / max_output_scale) * max_output_scale;
         const float chroma_attenuation_t2 = saturate(
-            (compressed_achromatic_luminance2 - min(1, max_intensity_equiv_lum) * 0.5)
-            / ((max_output_scale - min(1, max_intensity_equiv_lum) * 0.5))
+            (compressed_achromatic_luminance2 - min(1, max_intensity_equiv_lum) * max_output_scale * 0.5)
+            / ((max_output_scale - min(1, max_intensity_equiv_lum) * max_output_scale * 0.5))
         );
 
         chroma_attenuation = lerp(chroma_attenuation, 1.0,
@@ -196,7 +196,7 @@ float3 display_transform_sRGB(float3 input_stimulus) {
     // This sacrificies hue accuracy and brightness to retain saturation.
 
     if (true) {
-        compressed_rgb = max(compressed_rgb, 0.0.xxx);
+        compressed_rgb = max(compressed_rgb, 0.0.xxx) / max_output_scale;
 
         const float p = 12.0;
         compressed_rgb = compressed_rgb * pow(pow(compressed_rgb, p.xxx) + 1.0, -1.0 / p.xxx);
@@ -207,6 +207,7 @@ float3 display_transform_sRGB(float3 input_stimulus) {
         // Rescale so we can reach 100% white. Avoid rescaling very highly saturated colors,
         // as that would reintroduce discontinuities.
         compressed_rgb /= pow(lerp(0.5, 1.0, max_comp_dist), 1.0 / p);
+        compressed_rgb *= max_output_scale;
     }
 
     //return hk_equivalent_luminance(compressed_rgb).xxx;
@@ -215,4 +216,8 @@ float3 display_transform_sRGB(float3 input_stimulus) {
     return compressed_rgb;
 }
 
+float3 display_transform_sRGB(float3 input_stimulus) {
+    return display_transform_sRGB_with_max_output(input_stimulus, 1.0);
+}
+
 #endif  // NOTORIOUS6_DISPLAY_TRANSFORM_HLSL
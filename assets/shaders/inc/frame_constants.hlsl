@@ -57,6 +57,9 @@ struct RenderOverrides {
     }
 };
 
+// Working color space: all radiance, including colors in these constants, is scene-linear
+// with Rec.709 (sRGB) primaries and a D65 white point. Lighting buffers also carry `pre_exposure`.
+// `post_combine.hlsl` converts it to the primaries of the display; see `display_target.hlsl`.
 struct FrameConstants {
     ViewConstants view_constants;
 
@@ -73,7 +76,13 @@ struct FrameConstants {
     float pre_exposure;
     float pre_exposure_prev;
     float pre_exposure_delta;
-    float pad0;
+    uint punctual_light_count;
+
+    uint area_light_count;
+    // Thin lens parameters in world units; zero radius for a pinhole camera.
+    float lens_aperture_radius;
+    float lens_focus_distance;
+    uint pad0;
 
     RenderOverrides render_overrides;
 
@@ -83,12 +92,40 @@ struct FrameConstants {
 
 [[vk::binding(0, 2)]] ConstantBuffer<FrameConstants> frame_constants;
 
+// Must match `INSTANCE_DYNAMIC_FLAG_*` in `world_renderer.rs`
+static const uint INSTANCE_DYNAMIC_FLAG_EMISSIVE_COLOR_OVERRIDE = 1;
+
 struct InstanceDynamicConstants {
     float emissive_multiplier;
+    float emissive_color_override[3];
+    uint flags;
 };
 
+// Emission of a surface with the given material emissive color, before emissive maps.
+float3 instance_emissive_color(InstanceDynamicConstants params, float3 material_emissive) {
+    float3 emissive = material_emissive;
+
+    // Only replace the color of materials which actually emit
+    if ((params.flags & INSTANCE_DYNAMIC_FLAG_EMISSIVE_COLOR_OVERRIDE) != 0 && any(material_emissive > 0.0)) {
+        emissive = float3(
+            params.emissive_color_override[0],
+            params.emissive_color_override[1],
+            params.emissive_color_override[2]);
+    }
+
+    return emissive * params.emissive_multiplier;
+}
+
 [[vk::binding(1, 2)]] StructuredBuffer<InstanceDynamicConstants> instance_dynamic_parameters_dyn;
-[[vk::binding(2, 2)]] StructuredBuffer<TriangleLightPacked> triangle_lights_dyn;
+[[vk::binding(2, 2)]] StructuredBuffer<PunctualLightPacked> punctual_lights_dyn;
+[[vk::binding(3, 2)]] StructuredBuffer<AreaLightPacked> area_lights_dyn;
+
+// Depth 0 is at infinity for perspective projections, but on the far plane for orthographic ones.
+// Returns the direction from the origin towards the far point, in both cases with z = -1.
+float4 ray_dir_vs_from_far_point(float4 far_vs_h, float4 origin_vs_h) {
+    const float3 dir = far_vs_h.xyz * origin_vs_h.w - origin_vs_h.xyz * far_vs_h.w;
+    return float4(dir / -dir.z, 0.0);
+}
 
 struct ViewRayContext {
     float4 ray_dir_cs;
@@ -144,14 +181,14 @@ struct ViewRayContext {
         ViewConstants view_constants = frame_constants.view_constants;
 
         ViewRayContext res;
-        res.ray_dir_cs = float4(uv_to_cs(uv), 0.0, 1.0);
-        res.ray_dir_vs_h = mul(view_constants.sample_to_view, res.ray_dir_cs);
-        res.ray_dir_ws_h = mul(view_constants.view_to_world, res.ray_dir_vs_h);
-
         res.ray_origin_cs = float4(uv_to_cs(uv), 1.0, 1.0);
         res.ray_origin_vs_h = mul(view_constants.sample_to_view, res.ray_origin_cs);
         res.ray_origin_ws_h = mul(view_constants.view_to_world, res.ray_origin_vs_h);
 
+        res.ray_dir_cs = float4(uv_to_cs(uv), 0.0, 1.0);
+        res.ray_dir_vs_h = ray_dir_vs_from_far_point(mul(view_constants.sample_to_view, res.ray_dir_cs), res.ray_origin_vs_h);
+        res.ray_dir_ws_h = mul(view_constants.view_to_world, res.ray_dir_vs_h);
+
         return res;
     }
 
@@ -159,14 +196,14 @@ struct ViewRayContext {
         ViewConstants view_constants = frame_constants.view_constants;
 
         ViewRayContext res;
-        res.ray_dir_cs = float4(uv_to_cs(uv), 0.0, 1.0);
-        res.ray_dir_vs_h = mul(view_constants.sample_to_view, res.ray_dir_cs);
-        res.ray_dir_ws_h = mul(view_constants.view_to_world, res.ray_dir_vs_h);
-
         res.ray_origin_cs = float4(uv_to_cs(uv), 1.0, 1.0);
         res.ray_origin_vs_h = mul(view_constants.sample_to_view, res.ray_origin_cs);
         res.ray_origin_ws_h = mul(view_constants.view_to_world, res.ray_origin_vs_h);
 
+        res.ray_dir_cs = float4(uv_to_cs(uv), 0.0, 1.0);
+        res.ray_dir_vs_h = ray_dir_vs_from_far_point(mul(view_constants.sample_to_view, res.ray_dir_cs), res.ray_origin_vs_h);
+        res.ray_dir_ws_h = mul(view_constants.view_to_world, res.ray_dir_vs_h);
+
         res.ray_hit_cs = float4(uv_to_cs(uv), depth, 1.0);
         res.ray_hit_vs_h = mul(view_constants.sample_to_view, res.ray_hit_cs);
         res.ray_hit_ws_h = mul(view_constants.view_to_world, res.ray_hit_vs_h);
@@ -189,14 +226,33 @@ float3 get_prev_eye_position() {
     return eye_pos_h.xyz / eye_pos_h.w;
 }
 
+// Works with both perspective and orthographic projections.
+float4 depth_to_view_z_with_clip_to_view(float4x4 clip_to_view, float4 depth) {
+    return (clip_to_view._33 * depth + clip_to_view._34) / (clip_to_view._43 * depth + clip_to_view._44);
+}
+
 float depth_to_view_z(float depth) {
-    return rcp(depth * -frame_constants.view_constants.clip_to_view._43);
+    return depth_to_view_z_with_clip_to_view(frame_constants.view_constants.clip_to_view, depth).x;
 }
 
 float3 direction_view_to_world(float3 v) {
     return mul(frame_constants.view_constants.view_to_world, float4(v, 0)).xyz;
 }
 
+bool is_orthographic_projection() {
+    return frame_constants.view_constants.view_to_clip._43 == 0.0;
+}
+
+// Orthographic projections view everything along the camera's forward axis,
+// so the direction doesn't depend on the distance to the eye.
+float3 get_direction_to_eye(float3 pt_ws) {
+    if (is_orthographic_projection()) {
+        return normalize(direction_view_to_world(float3(0, 0, 1)));
+    } else {
+        return normalize(get_eye_position() - pt_ws);
+    }
+}
+
 float3 direction_world_to_view(float3 v) {
     return mul(frame_constants.view_constants.world_to_view, float4(v, 0)).xyz;
 }
@@ -0,0 +1,268 @@
+#ifndef LIGHTS_AREA_HLSL
+#define LIGHTS_AREA_HLSL
+
+#include "../frame_constants.hlsl"
+#include "../math.hlsl"
+
+// Must match `AREA_LIGHT_*` in `lights.rs`
+#define AREA_LIGHT_RECT 0
+#define AREA_LIGHT_DISK 1
+#define AREA_LIGHT_SPHERE 2
+#define AREA_LIGHT_TUBE 3
+
+#define AREA_LIGHT_FLAG_CAST_SHADOWS 1
+#define AREA_LIGHT_FLAG_TWO_SIDED 2
+
+struct AreaLightSample {
+    float3 to_light_norm;
+    float distance;
+    // Pre-exposed radiance divided by the solid angle pdf of the sample.
+    // Zero if the sampled point doesn't face the receiver.
+    float3 radiance_over_pdf;
+    bool cast_shadows;
+};
+
+// Uniform solid angle sampling of rectangles
+// "An Area-Preserving Parametrization for Spherical Rectangles", Ureña et al. 2013
+struct SphericalRect {
+    float3 o;
+    float3 x;
+    float3 y;
+    float3 z;
+    float z0;
+    float x0;
+    float y0;
+    float x1;
+    float y1;
+    float b0;
+    float b1;
+    float k;
+    float solid_angle;
+
+    // `ex` and `ey` must be orthogonal, and `o` off the plane of the rectangle.
+    static SphericalRect create(float3 o, float3 corner, float3 ex, float3 ey) {
+        const float exl = length(ex);
+        const float eyl = length(ey);
+
+        SphericalRect res;
+        res.o = o;
+        res.x = ex / exl;
+        res.y = ey / eyl;
+        res.z = cross(res.x, res.y);
+
+        const float3 d = corner - o;
+        res.z0 = dot(d, res.z);
+
+        // Flip the frame so that the rectangle lies at negative z
+        if (res.z0 > 0.0) {
+            res.z = -res.z;
+            res.z0 = -res.z0;
+        }
+
+        res.x0 = dot(d, res.x);
+        res.y0 = dot(d, res.y);
+        res.x1 = res.x0 + exl;
+        res.y1 = res.y0 + eyl;
+
+        const float3 v00 = float3(res.x0, res.y0, res.z0);
+        const float3 v01 = float3(res.x0, res.y1, res.z0);
+        const float3 v10 = float3(res.x1, res.y0, res.z0);
+        const float3 v11 = float3(res.x1, res.y1, res.z0);
+
+        const float3 n0 = normalize(cross(v00, v10));
+        const float3 n1 = normalize(cross(v10, v11));
+        const float3 n2 = normalize(cross(v11, v01));
+        const float3 n3 = normalize(cross(v01, v00));
+
+        const float g0 = acos(clamp(-dot(n0, n1), -1.0, 1.0));
+        const float g1 = acos(clamp(-dot(n1, n2), -1.0, 1.0));
+        const float g2 = acos(clamp(-dot(n2, n3), -1.0, 1.0));
+        const float g3 = acos(clamp(-dot(n3, n0), -1.0, 1.0));
+
+        res.b0 = n0.z;
+        res.b1 = n2.z;
+        res.k = M_TAU - g2 - g3;
+        res.solid_angle = g0 + g1 - res.k;
+
+        return res;
+    }
+
+    float3 sample_point(float2 urand) {
+        const float au = urand.x * solid_angle + k;
+        const float fu = (cos(au) * b0 - b1) / sin(au);
+        const float cu = clamp(rsqrt(fu * fu + b0 * b0) * (fu > 0.0 ? 1.0 : -1.0), -1.0, 1.0);
+        const float xu = clamp(-(cu * z0) / max(1e-8, sqrt(1.0 - cu * cu)), x0, x1);
+
+        const float d = sqrt(xu * xu + z0 * z0);
+        const float h0 = y0 / sqrt(d * d + y0 * y0);
+        const float h1 = y1 / sqrt(d * d + y1 * y1);
+        const float hv = h0 + urand.y * (h1 - h0);
+        const float hv2 = hv * hv;
+        const float yv = hv2 < 1.0 - 1e-6 ? (hv * d) / sqrt(1.0 - hv2) : y1;
+
+        return o + xu * x + yv * y + z0 * z;
+    }
+};
+
+AreaLightSample sample_area_light(AreaLightPacked light, float3 pos, float2 urand) {
+    AreaLightSample res;
+    res.cast_shadows = (light.flags & AREA_LIGHT_FLAG_CAST_SHADOWS) != 0;
+    res.radiance_over_pdf = 0.0.xxx;
+
+    const float3 light_radiance = light.radiance * frame_constants.pre_exposure;
+    const float3 light_normal = cross(light.tangent, light.bitangent);
+    const bool two_sided = light.shape != AREA_LIGHT_TUBE && (light.flags & AREA_LIGHT_FLAG_TWO_SIDED) != 0;
+
+    const float3 to_center = light.position - pos;
+    const float center_dist2 = dot(to_center, to_center);
+
+    res.to_light_norm = to_center * rsqrt(max(1e-8, center_dist2));
+    res.distance = 0.0;
+
+    // Height of the receiver above the plane of flat lights
+    const float plane_dist = dot(-to_center, light_normal);
+
+    if (light.shape == AREA_LIGHT_SPHERE) {
+        // Sample the cone of directions subtended by the sphere
+        const float radius2 = square(light.half_size_x);
+
+        // No light from inside the sphere
+        if (center_dist2 <= radius2) {
+            return res;
+        }
+
+        const float cos_theta_max = sqrt(1.0 - radius2 / center_dist2);
+        const float3 dir = mul(build_orthonormal_basis(res.to_light_norm), uniform_sample_cone(urand, cos_theta_max));
+
+        const float b = dot(dir, to_center);
+        res.to_light_norm = dir;
+        res.distance = b - sqrt(max(0.0, b * b - (center_dist2 - radius2)));
+        res.radiance_over_pdf = light_radiance * (M_TAU * (1.0 - cos_theta_max));
+        return res;
+    }
+
+    if (light.shape == AREA_LIGHT_RECT) {
+        if (two_sided ? abs(plane_dist) < 1e-5 : plane_dist < 1e-5) {
+            return res;
+        }
+
+        const SphericalRect rect = SphericalRect::create(
+            pos,
+            light.position - light.tangent * light.half_size_x - light.bitangent * light.half_size_y,
+            light.tangent * (2.0 * light.half_size_x),
+            light.bitangent * (2.0 * light.half_size_y));
+
+        const float3 to_light = rect.sample_point(urand) - pos;
+        res.distance = length(to_light);
+        res.to_light_norm = to_light / max(1e-8, res.distance);
+
+        if (rect.solid_angle > 1e-7) {
+            res.radiance_over_pdf = light_radiance * rect.solid_angle;
+        }
+
+        return res;
+    }
+
+    if (light.shape == AREA_LIGHT_DISK) {
+        if (two_sided ? abs(plane_dist) < 1e-5 : plane_dist < 1e-5) {
+            return res;
+        }
+
+        const float radius2 = square(light.half_size_x);
+
+        // Uniformly sample the cone bounding the disk, rejecting directions which miss it.
+        // Within the bounding sphere, the disk is sampled by area below instead.
+        if (center_dist2 > radius2) {
+            const float cos_theta_max = sqrt(1.0 - radius2 / center_dist2);
+            const float3 dir = mul(build_orthonormal_basis(res.to_light_norm), uniform_sample_cone(urand, cos_theta_max));
+
+            // Where the direction intersects the plane of the disk
+            const float denom = dot(dir, light_normal);
+            const float t = abs(denom) > 1e-8 ? -plane_dist / denom : -1.0;
+
+            res.to_light_norm = dir;
+
+            if (t > 0.0 && length_squared(pos + dir * t - light.position) <= radius2) {
+                res.distance = t;
+                res.radiance_over_pdf = light_radiance * (M_TAU * (1.0 - cos_theta_max));
+            }
+
+            return res;
+        }
+    }
+
+    float3 point_ws;
+    float3 normal_ws;
+    float area_pdf;
+
+    if (light.shape == AREA_LIGHT_DISK) {
+        const float r = light.half_size_x * sqrt(urand.x);
+        const float phi = urand.y * M_TAU;
+        point_ws = light.position + light.tangent * (r * cos(phi)) + light.bitangent * (r * sin(phi));
+        normal_ws = light_normal;
+        area_pdf = 1.0 / (M_PI * square(light.half_size_x));
+    } else {
+        // Tube: a cylinder along the tangent, capped by two hemispheres.
+        // Points on the far side are culled by their normals facing away,
+        // which is exactly the part occluded by the tube itself.
+        const float radius = light.half_size_x;
+        const float half_length = light.half_size_y;
+        const float cylinder_area = M_TAU * radius * 2.0 * half_length;
+        const float caps_area = 2.0 * M_TAU * radius * radius;
+        const float cylinder_fraction = cylinder_area / (cylinder_area + caps_area);
+
+        if (urand.x < cylinder_fraction) {
+            const float u = urand.x / cylinder_fraction;
+
+            const float axis_t = dot(-to_center, light.tangent);
+            const float3 radial = -to_center - light.tangent * axis_t;
+
+            // Distance to the axis, kept away from zero so that the pdf stays finite
+            const float axis_dist = max(length(radial), radius);
+
+            // Position along the axis, uniformly distributed in the angle it subtends,
+            // which follows the inverse square falloff along the tube.
+            const float theta0 = atan((-half_length - axis_t) / axis_dist);
+            const float theta1 = atan((half_length - axis_t) / axis_dist);
+            const float t = axis_t + axis_dist * tan(lerp(theta0, theta1, u));
+            const float t_pdf = axis_dist / ((square(axis_dist) + square(t - axis_t)) * max(1e-8, theta1 - theta0));
+
+            // Angle around the axis, uniform within the half facing the receiver
+            const float phi_center = atan2(dot(radial, light_normal), dot(radial, light.bitangent));
+            const float phi = phi_center + (urand.y - 0.5) * M_PI;
+
+            normal_ws = light.bitangent * cos(phi) + light_normal * sin(phi);
+            point_ws = light.position + light.tangent * t + normal_ws * radius;
+
+            // The area element is `radius * dphi * dt`
+            area_pdf = cylinder_fraction * t_pdf / (M_PI * radius);
+        } else {
+            const float u = (urand.x - cylinder_fraction) / (1.0 - cylinder_fraction);
+            normal_ws = uniform_sample_sphere(float2(u, urand.y));
+            const float cap_side = dot(normal_ws, light.tangent) >= 0.0 ? 1.0 : -1.0;
+            point_ws = light.position + light.tangent * (cap_side * half_length) + normal_ws * radius;
+            area_pdf = (1.0 - cylinder_fraction) / caps_area;
+        }
+    }
+
+    const float3 to_light = point_ws - pos;
+    const float dist2 = max(1e-8, dot(to_light, to_light));
+    const float dist = sqrt(dist2);
+
+    res.to_light_norm = to_light / dist;
+    res.distance = dist;
+
+    float cos_light = dot(normal_ws, -res.to_light_norm);
+    if (two_sided) {
+        cos_light = abs(cos_light);
+    }
+
+    if (cos_light > 0.0) {
+        // The solid angle pdf is `area_pdf * dist2 / cos_light`
+        res.radiance_over_pdf = light_radiance * (cos_light / (area_pdf * dist2));
+    }
+
+    return res;
+}
+
+#endif
@@ -5,4 +5,35 @@ struct TriangleLightPacked {
     float packed[12];
 };
 
+// Must match `GpuPunctualLight` in `lights.rs`
+struct PunctualLightPacked {
+    float3 position;
+    uint kind;
+    // The direction light travels in
+    float3 direction;
+    // Zero for infinite range
+    float inv_range;
+    float3 intensity;
+    float spot_angle_scale;
+    float spot_angle_offset;
+    uint flags;
+    uint2 pad;
+};
+
+// Must match `GpuAreaLight` in `lights.rs`
+struct AreaLightPacked {
+    float3 position;
+    uint shape;
+    // Local X axis of the light
+    float3 tangent;
+    // Half the width of rects; radius of the other shapes
+    float half_size_x;
+    // Local Y axis of the light
+    float3 bitangent;
+    // Half the height of rects; half the length of tubes
+    float half_size_y;
+    float3 radiance;
+    uint flags;
+};
+
 #endif
\ No newline at end of file
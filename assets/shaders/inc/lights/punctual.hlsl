@@ -0,0 +1,51 @@
+#ifndef LIGHTS_PUNCTUAL_HLSL
+#define LIGHTS_PUNCTUAL_HLSL
+
+#include "../frame_constants.hlsl"
+#include "../math.hlsl"
+
+// Must match `PUNCTUAL_LIGHT_*` in `lights.rs`
+#define PUNCTUAL_LIGHT_POINT 0
+#define PUNCTUAL_LIGHT_SPOT 1
+#define PUNCTUAL_LIGHT_DIRECTIONAL 2
+
+#define PUNCTUAL_LIGHT_FLAG_CAST_SHADOWS 1
+
+struct PunctualLightSample {
+    float3 to_light_norm;
+    // FLT_MAX for directional lights
+    float distance;
+    // Pre-exposed irradiance on a surface facing the light
+    float3 irradiance;
+    bool cast_shadows;
+};
+
+PunctualLightSample sample_punctual_light(PunctualLightPacked light, float3 pos) {
+    PunctualLightSample res;
+    res.cast_shadows = (light.flags & PUNCTUAL_LIGHT_FLAG_CAST_SHADOWS) != 0;
+
+    if (light.kind == PUNCTUAL_LIGHT_DIRECTIONAL) {
+        res.to_light_norm = -light.direction;
+        res.distance = FLT_MAX;
+        res.irradiance = light.intensity * frame_constants.pre_exposure;
+        return res;
+    }
+
+    const float3 to_light = light.position - pos;
+    const float dist2 = max(1e-8, dot(to_light, to_light));
+    const float dist = sqrt(dist2);
+
+    res.to_light_norm = to_light / dist;
+    res.distance = dist;
+
+    // Inverse-square falloff, windowed to reach zero at the light's range.
+    const float range_window = square(saturate(1.0 - square(square(dist * light.inv_range))));
+    const float cone = square(saturate(
+        dot(light.direction, -res.to_light_norm) * light.spot_angle_scale + light.spot_angle_offset
+    ));
+
+    res.irradiance = light.intensity * (range_window * cone / dist2) * frame_constants.pre_exposure;
+    return res;
+}
+
+#endif
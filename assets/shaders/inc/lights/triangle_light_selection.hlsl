@@ -0,0 +1,56 @@
+#ifndef LIGHTS_TRIANGLE_LIGHT_SELECTION_HLSL
+#define LIGHTS_TRIANGLE_LIGHT_SELECTION_HLSL
+
+#include "../frame_constants.hlsl"
+#include "packed.hlsl"
+
+// World-space emissive triangles of all instances, and the inclusive prefix sum of their power. Written every frame by `expand_triangle_lights.hlsl`.
+// Must match `bindless_descriptor_set.rs`
+[[vk::binding(3, 1)]] StructuredBuffer<TriangleLightPacked> triangle_lights;
+[[vk::binding(4, 1)]] StructuredBuffer<float> triangle_light_cdf;
+
+struct TriangleLightSelection {
+    uint light_idx;
+    // Probability of having selected this light
+    float pmf;
+};
+
+// Picks a light proportionally to its power with a binary search over the CDF.
+// Only valid if `frame_constants.triangle_light_count > 0`.
+TriangleLightSelection select_triangle_light(float urand) {
+    const uint light_count = frame_constants.triangle_light_count;
+    const float total_weight = triangle_light_cdf[light_count - 1];
+
+    TriangleLightSelection res;
+
+    // None of the lights emit anything
+    if (!(total_weight > 0.0)) {
+        res.light_idx = 0;
+        res.pmf = 1.0;
+        return res;
+    }
+
+    // Kept below the total, which would otherwise select the last light even if it doesn't emit
+    const float target = min(urand * total_weight, asfloat(asuint(total_weight) - 1));
+
+    // Find the first light whose prefix sum exceeds the target
+    uint lo = 0;
+    uint hi = light_count - 1;
+    while (lo < hi) {
+        const uint mid = (lo + hi) / 2;
+        if (triangle_light_cdf[mid] > target) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+
+    // Computed from the same prefix sums as the search, so rounding can't make it inconsistent
+    const float weight = triangle_light_cdf[lo] - (lo > 0 ? triangle_light_cdf[lo - 1] : 0.0);
+
+    res.light_idx = lo;
+    res.pmf = weight / total_weight;
+    return res;
+}
+
+#endif
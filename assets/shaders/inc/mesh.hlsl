@@ -15,6 +15,8 @@ struct Mesh {
     uint vertex_tangent_offset;
     uint mat_data_offset;
     uint index_offset;
+    uint meshlet_offset;
+    uint meshlet_count;
 };
 
 struct Vertex {
@@ -0,0 +1,45 @@
+#ifndef MESHLET_HLSL
+#define MESHLET_HLSL
+
+#include "bindless.hlsl"
+
+static const uint MESHLET_MAX_VERTICES = 64;
+static const uint MESHLET_MAX_TRIANGLES = 124;
+
+// Meshlets surviving culling in a task shader workgroup
+static const uint MESHLET_TASK_GROUP_SIZE = 32;
+
+struct Meshlet {
+    float3 center;
+    float radius;
+    float3 cone_axis;
+    float cone_cutoff;
+    uint vertex_offset;
+    uint triangle_offset;
+    uint vertex_count;
+    uint triangle_count;
+};
+
+Meshlet load_meshlet(Mesh mesh, uint meshlet_index) {
+    const uint offset = mesh.meshlet_offset + meshlet_index * 48;
+    const uint4 data0 = vertices.Load4(offset);
+    const uint4 data1 = vertices.Load4(offset + 16);
+    const uint4 data2 = vertices.Load4(offset + 32);
+
+    Meshlet res;
+    res.center = asfloat(data0.xyz);
+    res.radius = asfloat(data0.w);
+    res.cone_axis = asfloat(data1.xyz);
+    res.cone_cutoff = asfloat(data1.w);
+    res.vertex_offset = data2.x;
+    res.triangle_offset = data2.y;
+    res.vertex_count = data2.z;
+    res.triangle_count = data2.w;
+    return res;
+}
+
+struct MeshletTaskPayload {
+    uint meshlet_indices[MESHLET_TASK_GROUP_SIZE];
+};
+
+#endif
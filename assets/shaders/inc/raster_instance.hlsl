@@ -0,0 +1,18 @@
+#ifndef RASTER_INSTANCE_HLSL
+#define RASTER_INSTANCE_HLSL
+
+// Per-instance data for G-buffer rasterization and GPU culling.
+// Must match `GpuRasterInstance` in `raster_meshes.rs`.
+struct RasterInstance {
+    // Object-space bounds of the mesh
+    float3 aabb_min;
+    uint mesh_index;
+    float3 aabb_max;
+    uint index_count;
+    uint first_index;
+    // Zero for instances which are hidden from the camera
+    uint camera_visible;
+    uint2 pad;
+};
+
+#endif
@@ -5,6 +5,13 @@
 #include "gbuffer.hlsl"
 #include "ray_cone.hlsl"
 
+// TLAS instance mask bits; must match `RT_INSTANCE_MASK_*` in `world_renderer.rs`.
+// Primary rays of the path tracer; the raster G-buffer uses the same flag.
+#define RT_INSTANCE_MASK_CAMERA 1
+// Diffuse GI, reflection and irradiance cache rays
+#define RT_INSTANCE_MASK_INDIRECT 2
+#define RT_INSTANCE_MASK_SHADOW 4
+
 struct GbufferRayPayload {
     GbufferDataPacked gbuffer_packed;
     float t;
@@ -63,7 +70,7 @@ bool rt_is_shadowed(
     TraceRay(
         acceleration_structure,
         RAY_FLAG_ACCEPT_FIRST_HIT_AND_END_SEARCH | RAY_FLAG_SKIP_CLOSEST_HIT_SHADER,
-        0xff, 0, 0, 1, ray, shadow_payload
+        RT_INSTANCE_MASK_SHADOW, 0, 0, 1, ray, shadow_payload
     );
 
     return shadow_payload.is_shadowed;
@@ -119,7 +126,11 @@ struct GbufferRaytrace {
             trace_flags |= RAY_FLAG_CULL_BACK_FACING_TRIANGLES;
         }
 
-        TraceRay(acceleration_structure, trace_flags, 0xff, 0, 0, 0, this.ray, payload);
+        const uint instance_mask = this.path_length == 0
+            ? RT_INSTANCE_MASK_CAMERA
+            : RT_INSTANCE_MASK_INDIRECT;
+
+        TraceRay(acceleration_structure, trace_flags, instance_mask, 0, 0, 0, this.ray, payload);
 
         if (payload.is_hit()) {
             GbufferPathVertex res;
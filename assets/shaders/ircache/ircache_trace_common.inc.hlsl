@@ -135,8 +135,9 @@ IrcacheTraceResult ircache_trace(Vertex entry, DiffuseBrdf brdf, SampleParams sa
             }
 
             if (USE_LIGHTS && frame_constants.triangle_light_count > 0/* && path_length > 0*/) {   // rtr comp
-                const float light_selection_pmf = 1.0 / frame_constants.triangle_light_count;
-                const uint light_idx = hash1_mut(rng) % frame_constants.triangle_light_count;
+                const TriangleLightSelection light_selection = select_triangle_light(uint_to_u01_float(hash1_mut(rng)));
+                const float light_selection_pmf = light_selection.pmf;
+                const uint light_idx = light_selection.light_idx;
                 //const float light_selection_pmf = 1;
                 //for (uint light_idx = 0; light_idx < frame_constants.triangle_light_count; light_idx += 1)
                 {
@@ -145,7 +146,7 @@ IrcacheTraceResult ircache_trace(Vertex entry, DiffuseBrdf brdf, SampleParams sa
                         uint_to_u01_float(hash1_mut(rng))
                     );
 
-                    TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights_dyn[light_idx]);
+                    TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights[light_idx]);
                     LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
                     const float3 shadow_ray_origin = primary_hit.position;
                     const float3 to_light_ws = light_sample.pos - primary_hit.position;
@@ -13,6 +13,7 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/mesh.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/triangle_light_selection.hlsl"
 #include "../wrc/bindings.hlsl"
 #include "../inc/color.hlsl"
 
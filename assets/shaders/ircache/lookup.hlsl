@@ -157,7 +157,7 @@ IrcacheLookupMaybeAllocate IrcacheLookupParams::lookup_maybe_allocate(inout uint
     const uint cascade = ws_pos_to_ircache_coord(pt_ws, normal_ws, jitter).cascade;
     const float cell_diameter = ircache_grid_cell_diameter_in_cascade(cascade);
 
-    float3 to_eye = normalize(get_eye_position() - pt_ws.xyz);
+    float3 to_eye = get_direction_to_eye(pt_ws.xyz);
     float3 offset_towards_query = query_from_ws - pt_ws.xyz;
     const float MAX_OFFSET = cell_diameter;   // world units
     const float MAX_OFFSET_AS_FRAC = 0.5;   // fraction of the distance from query point
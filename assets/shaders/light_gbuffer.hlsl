@@ -36,7 +36,8 @@ DEFINE_WRC_BINDINGS(14)
 [[vk::binding(16)]] RWTexture2D<float4> output_tex;
 [[vk::binding(17)]] TextureCube<float4> unconvolved_sky_cube_tex;
 [[vk::binding(18)]] TextureCube<float4> sky_cube_tex;
-[[vk::binding(19)]] cbuffer _ {
+[[vk::binding(19)]] Texture2D<float4> analytic_lighting_tex;
+[[vk::binding(20)]] cbuffer _ {
     float4 output_tex_size;
     uint debug_shading_mode;
     uint debug_show_wrc;
@@ -153,6 +154,11 @@ void main(in uint2 px : SV_DispatchThreadID) {
     const float3 light_radiance = shadow_mask * SUN_COLOR;
     float3 total_radiance = brdf_value * light_radiance;
 
+    [branch]
+    if (debug_shading_mode != SHADING_MODE_RTX_OFF) {
+        total_radiance += analytic_lighting_tex[px].rgb;
+    }
+
     total_radiance += gbuffer.emissive;
 
     float3 gi_irradiance = 0.0.xxx;
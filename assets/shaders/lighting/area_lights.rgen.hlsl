@@ -0,0 +1,118 @@
+#include "../inc/uv.hlsl"
+#include "../inc/pack_unpack.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/brdf.hlsl"
+#include "../inc/brdf_lut.hlsl"
+#include "../inc/layered_brdf.hlsl"
+#include "../inc/blue_noise.hlsl"
+#include "../inc/quasi_random.hlsl"
+#include "../inc/color.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/lights/area.hlsl"
+
+// Samples per light used for the unshadowed lighting. Those don't need rays, so can be plenty.
+static const uint UNSHADOWED_SAMPLE_COUNT = 8;
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+
+[[vk::binding(0)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(1)]] Texture2D<float> depth_tex;
+[[vk::binding(2)]] Texture2D<float3> geometric_normal_tex;
+[[vk::binding(3)]] RWTexture2D<float4> unshadowed_tex;
+[[vk::binding(4)]] RWTexture2D<float> shadow_mask_tex;
+[[vk::binding(5)]] cbuffer _ {
+    float4 output_tex_size;
+};
+
+// Splits area lighting into unshadowed radiance, and the fraction of it which is visible.
+// The latter is traced with one shadow ray per light, and denoised separately.
+//
+// This is an approximation: each light is treated as either fully visible or fully occluded
+// based on its single ray, with the denoiser turning that into soft penumbrae. The mask is also
+// a single luminance ratio over all lights, so where colored lights overlap, their shadows take on
+// the tint of the combined lighting rather than the color of the light being blocked.
+[shader("raygeneration")]
+void main() {
+    const uint2 px = DispatchRaysIndex().xy;
+    const float depth = depth_tex[px];
+
+    if (0.0 == depth) {
+        unshadowed_tex[px] = 0.0.xxxx;
+        shadow_mask_tex[px] = 1.0;
+        return;
+    }
+
+    const float2 uv = get_uv(px, output_tex_size);
+    const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_depth(uv, depth);
+
+    const float3 normal_vs = geometric_normal_tex[px] * 2.0 - 1.0;
+    const float3 geometric_normal_ws = direction_view_to_world(normal_vs);
+    const float3 ray_origin = view_ray_context.biased_secondary_ray_origin_ws_with_normal(geometric_normal_ws);
+    const float3 pt_ws = view_ray_context.ray_hit_ws();
+
+    GbufferData gbuffer = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[px])).unpack();
+
+    const float3x3 tangent_to_world = build_orthonormal_basis(gbuffer.normal);
+    float3 wo = mul(-view_ray_context.ray_dir_ws(), tangent_to_world);
+
+    // Same hack as in `light_gbuffer.hlsl`
+    if (wo.z < 0.0) {
+        wo.z *= -0.25;
+        wo = normalize(wo);
+    }
+
+    LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+
+    const float2 blue_noise = blue_noise_for_pixel(px, frame_constants.frame_index).xy;
+
+    float3 unshadowed = 0.0.xxx;
+    float3 shadowed = 0.0.xxx;
+
+    for (uint light_idx = 0; light_idx < frame_constants.area_light_count; ++light_idx) {
+        const AreaLightPacked light = area_lights_dyn[light_idx];
+
+        float3 light_unshadowed = 0.0.xxx;
+        bool is_shadowed = false;
+        bool visibility_traced = false;
+
+        for (uint sample_idx = 0; sample_idx < UNSHADOWED_SAMPLE_COUNT; ++sample_idx) {
+            const float2 urand = frac(blue_noise + r2_sequence(sample_idx + light_idx * UNSHADOWED_SAMPLE_COUNT));
+            const AreaLightSample light_sample = sample_area_light(light, pt_ws, urand);
+            const float3 wi = mul(light_sample.to_light_norm, tangent_to_world);
+
+            if (wi.z <= 0.0 || all(light_sample.radiance_over_pdf == 0.0)) {
+                continue;
+            }
+
+            light_unshadowed += brdf.evaluate(wo, wi) * wi.z * light_sample.radiance_over_pdf;
+
+            // The first valid sample determines visibility of the whole light
+            if (!visibility_traced && light_sample.cast_shadows) {
+                visibility_traced = true;
+                is_shadowed = rt_is_shadowed(
+                    acceleration_structure,
+                    new_ray(
+                        ray_origin,
+                        light_sample.to_light_norm,
+                        0,
+                        light_sample.distance * (1.0 - 1e-4)
+                    ));
+            }
+        }
+
+        light_unshadowed /= UNSHADOWED_SAMPLE_COUNT;
+        unshadowed += light_unshadowed;
+
+        if (!is_shadowed) {
+            shadowed += light_unshadowed;
+        }
+    }
+
+    const float unshadowed_luminance = sRGB_to_luminance(unshadowed);
+
+    unshadowed_tex[px] = float4(unshadowed, 1.0);
+    shadow_mask_tex[px] = unshadowed_luminance > 0.0
+        ? saturate(sRGB_to_luminance(shadowed) / unshadowed_luminance)
+        : 1.0;
+}
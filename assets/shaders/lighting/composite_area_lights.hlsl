@@ -0,0 +1,9 @@
+[[vk::binding(0)]] Texture2D<float4> unshadowed_tex;
+[[vk::binding(1)]] Texture2D<float> shadow_mask_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float3 area_lighting = unshadowed_tex[px].rgb * shadow_mask_tex[px].x;
+    output_tex[px] = float4(output_tex[px].rgb + area_lighting, 1.0);
+}
@@ -0,0 +1,98 @@
+#include "../inc/frame_constants.hlsl"
+#include "../inc/color/srgb.hlsl"
+#include "../inc/samplers.hlsl"
+#include "../inc/bindless.hlsl"
+
+// Must match `GpuEmissiveInstance` in `triangle_lights.rs`
+struct EmissiveInstance {
+    row_major float3x4 transform;
+    // Byte offset of the mesh-space `MeshTriangleLight`s in `vertices`
+    uint light_data_offset;
+    uint first_light;
+    uint instance_index;
+    uint mesh_index;
+    uint material_id;
+    uint pad[3];
+};
+
+[[vk::binding(0)]] StructuredBuffer<EmissiveInstance> emissive_instances_dyn;
+[[vk::binding(1)]] RWByteAddressBuffer triangle_lights_buf;
+[[vk::binding(2)]] RWByteAddressBuffer triangle_light_weights_buf;
+[[vk::binding(3)]] cbuffer _ {
+    uint emissive_instance_count;
+    uint light_count;
+};
+
+// Must match `MeshTriangleLight` in `world_renderer.rs`
+static const uint MESH_TRIANGLE_LIGHT_SIZE_BYTES = 16 * 4;
+
+// Must match `TriangleLightPacked` in `inc/lights/packed.hlsl`
+static const uint TRIANGLE_LIGHT_SIZE_BYTES = 12 * 4;
+
+// Transforms one emissive triangle to world space, resolves its radiance from the material
+// and instance parameters, and writes its light selection weight.
+// The weights are turned into a CDF by an inclusive floating point prefix scan afterwards.
+[numthreads(64, 1, 1)]
+void main(uint light_idx: SV_DispatchThreadID) {
+    if (light_idx >= light_count) {
+        // Never select lights past the end
+        triangle_light_weights_buf.Store(light_idx * 4, asuint(0.0));
+        return;
+    }
+
+    // Find the last instance starting at or before this light
+    uint lo = 0;
+    uint hi = emissive_instance_count - 1;
+    while (lo < hi) {
+        const uint mid = (lo + hi + 1) / 2;
+        if (emissive_instances_dyn[mid].first_light <= light_idx) {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+
+    const EmissiveInstance inst = emissive_instances_dyn[lo];
+    const uint src_offset = inst.light_data_offset + (light_idx - inst.first_light) * MESH_TRIANGLE_LIGHT_SIZE_BYTES;
+
+    const float4 data0 = asfloat(vertices.Load4(src_offset + 0));
+    const float4 data1 = asfloat(vertices.Load4(src_offset + 16));
+    const float4 data2 = asfloat(vertices.Load4(src_offset + 32));
+    const float4 data3 = asfloat(vertices.Load4(src_offset + 48));
+
+    const float3 v0 = mul(inst.transform, float4(data0.xyz, 1.0));
+    const float3 v1 = mul(inst.transform, float4(data0.w, data1.xy, 1.0));
+    const float3 v2 = mul(inst.transform, float4(data1.zw, data2.x, 1.0));
+
+    const Mesh mesh = meshes[inst.mesh_index];
+    const MeshMaterial material = vertices.Load<MeshMaterial>(mesh.mat_data_offset + inst.material_id * sizeof(MeshMaterial));
+
+    const float2 uv0 = transform_material_uv(material, data2.yz, 3);
+    const float2 uv1 = transform_material_uv(material, float2(data2.w, data3.x), 3);
+    const float2 uv2 = transform_material_uv(material, data3.yz, 3);
+
+    // Approximate the average emission over the triangle with a single tap
+    // at its centroid, from a mip level covering about as many texels as the triangle.
+    const float2 tex_size = bindless_texture_sizes[NonUniformResourceIndex(material.emissive_map)].xy;
+    const float2 uv_d1 = (uv1 - uv0) * tex_size;
+    const float2 uv_d2 = (uv2 - uv0) * tex_size;
+    const float texel_area = 0.5 * abs(uv_d1.x * uv_d2.y - uv_d1.y * uv_d2.x);
+    const float lod = 0.5 * log2(max(texel_area, 1.0));
+
+    Texture2D emissive_tex = bindless_textures[NonUniformResourceIndex(material.emissive_map)];
+    const float3 emissive_tex_val = emissive_tex.SampleLevel(sampler_llr, (uv0 + uv1 + uv2) / 3.0, lod).rgb;
+
+    const float3 radiance = emissive_tex_val
+        * instance_emissive_color(instance_dynamic_parameters_dyn[inst.instance_index], float3(material.emissive));
+
+    const uint dst_offset = light_idx * TRIANGLE_LIGHT_SIZE_BYTES;
+    triangle_lights_buf.Store4(dst_offset + 0, asuint(float4(v0, v1.x)));
+    triangle_lights_buf.Store4(dst_offset + 16, asuint(float4(v1.yz, v2.xy)));
+    triangle_lights_buf.Store4(dst_offset + 32, asuint(float4(v2.z, radiance)));
+
+    const float area = 0.5 * length(cross(v1 - v0, v2 - v0));
+    const float power = area * sRGB_to_luminance(radiance);
+
+    // Lights which don't emit are never selected
+    triangle_light_weights_buf.Store(light_idx * 4, asuint(max(0.0, power)));
+}
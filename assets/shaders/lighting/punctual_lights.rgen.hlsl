@@ -0,0 +1,82 @@
+#include "../inc/uv.hlsl"
+#include "../inc/pack_unpack.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/brdf.hlsl"
+#include "../inc/brdf_lut.hlsl"
+#include "../inc/layered_brdf.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/lights/punctual.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+
+[[vk::binding(0)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(1)]] Texture2D<float> depth_tex;
+[[vk::binding(2)]] Texture2D<float3> geometric_normal_tex;
+[[vk::binding(3)]] RWTexture2D<float4> output_tex;
+[[vk::binding(4)]] cbuffer _ {
+    float4 output_tex_size;
+};
+
+// Direct lighting from all punctual lights, with hard ray-traced shadows.
+[shader("raygeneration")]
+void main() {
+    const uint2 px = DispatchRaysIndex().xy;
+    const float depth = depth_tex[px];
+
+    if (0.0 == depth) {
+        output_tex[px] = 0.0.xxxx;
+        return;
+    }
+
+    const float2 uv = get_uv(px, output_tex_size);
+    const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_depth(uv, depth);
+
+    const float3 normal_vs = geometric_normal_tex[px] * 2.0 - 1.0;
+    const float3 geometric_normal_ws = direction_view_to_world(normal_vs);
+    const float3 ray_origin = view_ray_context.biased_secondary_ray_origin_ws_with_normal(geometric_normal_ws);
+    const float3 pt_ws = view_ray_context.ray_hit_ws();
+
+    GbufferData gbuffer = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[px])).unpack();
+
+    const float3x3 tangent_to_world = build_orthonormal_basis(gbuffer.normal);
+    float3 wo = mul(-view_ray_context.ray_dir_ws(), tangent_to_world);
+
+    // Same hack as in `light_gbuffer.hlsl`
+    if (wo.z < 0.0) {
+        wo.z *= -0.25;
+        wo = normalize(wo);
+    }
+
+    LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+
+    float3 total_radiance = 0.0.xxx;
+
+    for (uint light_idx = 0; light_idx < frame_constants.punctual_light_count; ++light_idx) {
+        const PunctualLightSample light = sample_punctual_light(punctual_lights_dyn[light_idx], pt_ws);
+        const float3 wi = mul(light.to_light_norm, tangent_to_world);
+
+        if (wi.z <= 0.0 || all(light.irradiance == 0.0)) {
+            continue;
+        }
+
+        if (light.cast_shadows) {
+            const bool is_shadowed = rt_is_shadowed(
+                acceleration_structure,
+                new_ray(
+                    ray_origin,
+                    light.to_light_norm,
+                    0,
+                    light.distance
+                ));
+
+            if (is_shadowed) {
+                continue;
+            }
+        }
+
+        total_radiance += brdf.evaluate_directional_light(wo, wi) * wi.z * light.irradiance;
+    }
+
+    output_tex[px] = float4(total_radiance, 1.0);
+}
@@ -1,20 +1,79 @@
 #include "../inc/uv.hlsl"
 #include "../inc/pack_unpack.hlsl"
 #include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/color.hlsl"
 #include "../inc/blue_noise.hlsl"
+#include "../inc/hash.hlsl"
+#include "../inc/reservoir.hlsl"
 #include "../inc/rt.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/triangle_light_selection.hlsl"
+
+// Lights picked from the power CDF every frame, and resampled by their unshadowed contribution
+static const uint CANDIDATE_COUNT = 8;
+
+// Limits how long samples linger in the temporal reservoirs
+static const float TEMPORAL_M_CLAMP = 20.0 * CANDIDATE_COUNT;
 
 [[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
 
-[[vk::binding(0)]] Texture2D<float> depth_tex;
-[[vk::binding(1)]] RWTexture2D<float4> out0_tex;
-[[vk::binding(2)]] RWTexture2D<float4> out1_tex;
-[[vk::binding(3)]] RWTexture2D<float4> out2_tex;
-[[vk::binding(4)]] cbuffer _ {
+[[vk::binding(0)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(1)]] Texture2D<float> depth_tex;
+[[vk::binding(2)]] Texture2D<float4> reprojection_tex;
+[[vk::binding(3)]] Texture2D<uint4> reservoir_history_tex;
+[[vk::binding(4)]] RWTexture2D<float4> out0_tex;
+[[vk::binding(5)]] RWTexture2D<float4> out1_tex;
+[[vk::binding(6)]] RWTexture2D<float4> out2_tex;
+[[vk::binding(7)]] RWTexture2D<uint4> reservoir_output_tex;
+[[vk::binding(8)]] cbuffer _ {
     float4 gbuffer_tex_size;
 };
 
+struct TriangleLightPoint {
+    float3 pos;
+    float3 normal;
+    float3 radiance;
+    // Wrt. the surface area of the light
+    float pdf;
+
+    // Reservoirs store the light index and `urand`, from which the point can be re-created.
+    static TriangleLightPoint create(uint light_idx, float2 urand) {
+        const TriangleLight light = TriangleLight::from_packed(triangle_lights[light_idx]);
+        const LightSampleResultArea light_sample = sample_triangle_light(light.as_triangle(), urand);
+
+        TriangleLightPoint res;
+        res.pos = light_sample.pos;
+        res.normal = light_sample.normal;
+        res.radiance = light.radiance();
+        res.pdf = light_sample.pdf.value;
+        return res;
+    }
+
+    // Unshadowed irradiance at `origin`, used as the resampling target
+    float target_pdf(float3 origin, float3 origin_normal) {
+        const float3 to_light = pos - origin;
+        const float dist2 = max(1e-8, dot(to_light, to_light));
+        const float3 dir = to_light * rsqrt(dist2);
+
+        return sRGB_to_luminance(radiance)
+            * max(0.0, dot(origin_normal, dir))
+            * max(0.0, dot(normal, -dir))
+            / dist2;
+    }
+};
+
+uint pack_urand(float2 urand) {
+    return pack_unorm(urand.x, 16) | (pack_unorm(urand.y, 16) << 16);
+}
+
+float2 unpack_urand(uint pckd) {
+    return float2(unpack_unorm(pckd, 16), unpack_unorm(pckd >> 16, 16));
+}
+
+// Resampled importance sampling of the triangle lights, with temporal reuse (ReSTIR).
+// Outputs a single light sample along with the reciprocal of its contribution weight,
+// which `spatial_reuse_lights.hlsl` treats as the sampling pdf.
 [shader("raygeneration")]
 void main() {
     const uint2 px = DispatchRaysIndex().xy;
@@ -23,29 +82,90 @@ void main() {
 
     if (0.0 == depth) {
         out0_tex[px] = 0.0.xxxx;
+        reservoir_output_tex[px] = 0;
         return;
     }
 
     const float2 uv = get_uv(hi_px, gbuffer_tex_size);
     const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_depth(uv, depth);
     const float3 shadow_ray_origin = view_ray_context.biased_secondary_ray_origin_ws();
+    const float3 pt_ws = view_ray_context.ray_hit_ws();
+    const float3 normal_ws = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[hi_px])).unpack().normal;
+
+    uint rng = hash3(uint3(px, frame_constants.frame_index));
+    const float3 blue_noise = blue_noise_for_pixel(px, frame_constants.frame_index).xyz;
 
-    const float3 urand3 = blue_noise_for_pixel(px, frame_constants.frame_index).xyz;
-    const float2 urand = urand3.xy;
+    Reservoir1spp reservoir = Reservoir1spp::create();
+    Reservoir1sppStreamState stream_state = Reservoir1sppStreamState::create();
+    float2 selected_urand = 0.0.xx;
 
-    const uint light_count = frame_constants.triangle_light_count;
-    const uint light_idx = uint(urand3.z * light_count) % light_count;
-    //uint rng = hash3(uint3(px, frame_constants.frame_index));
-    //const uint light_idx = rng % light_count;
-    const float light_choice_pmf = 1.0 / light_count;
+    for (uint candidate_idx = 0; candidate_idx < CANDIDATE_COUNT; ++candidate_idx) {
+        const float3 urand3 = candidate_idx == 0
+            ? blue_noise
+            : float3(
+                uint_to_u01_float(hash1_mut(rng)),
+                uint_to_u01_float(hash1_mut(rng)),
+                uint_to_u01_float(hash1_mut(rng)));
 
-    TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights_dyn[light_idx]);
-    LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
-    const float3 to_light_ws = light_sample.pos - shadow_ray_origin;
+        const TriangleLightSelection light_selection = select_triangle_light(urand3.z);
+
+        // Quantized up front so that reusing the sample re-creates the exact same point
+        const float2 urand = unpack_urand(pack_urand(urand3.xy));
+        const TriangleLightPoint light_point = TriangleLightPoint::create(light_selection.light_idx, urand);
+        const float source_pdf = light_point.pdf * light_selection.pmf;
+
+        Reservoir1spp candidate = Reservoir1spp::create();
+        candidate.M = 1;
+        candidate.W = source_pdf > 0.0 ? 1.0 / source_pdf : 0.0;
+
+        if (reservoir.update_with_stream(
+            candidate,
+            light_point.target_pdf(pt_ws, normal_ws),
+            1.0,
+            stream_state,
+            light_selection.light_idx,
+            rng
+        )) {
+            selected_urand = urand;
+        }
+    }
+
+    const float4 reproj = reprojection_tex[hi_px];
+    const int2 rpx = floor(float2(px) + gbuffer_tex_size.xy * reproj.xy * 0.5 + 0.5);
+
+    if (reproj.z != 0 && all(rpx >= 0) && all(rpx < int2(gbuffer_tex_size.xy * 0.5))) {
+        const uint4 history = reservoir_history_tex[rpx];
+        Reservoir1spp prev = Reservoir1spp::from_raw(history.xy);
+        prev.M = min(prev.M, TEMPORAL_M_CLAMP);
+
+        // The light list is rebuilt every frame. Indices stay stable unless instances change,
+        // in which case the target pdf re-evaluated here keeps the result plausible.
+        if (prev.M > 0 && prev.W > 0 && prev.payload < frame_constants.triangle_light_count) {
+            const float2 prev_urand = unpack_urand(history.z);
+            const TriangleLightPoint light_point = TriangleLightPoint::create(prev.payload, prev_urand);
+
+            if (reservoir.update_with_stream(
+                prev,
+                light_point.target_pdf(pt_ws, normal_ws),
+                1.0,
+                stream_state,
+                prev.payload,
+                rng
+            )) {
+                selected_urand = prev_urand;
+            }
+        }
+    }
+
+    reservoir.finish_stream(stream_state);
+
+    const TriangleLightPoint light_point = TriangleLightPoint::create(reservoir.payload, selected_urand);
+    const float3 to_light_ws = light_point.pos - shadow_ray_origin;
     const float dist_to_light = length(to_light_ws);
 
     const bool is_shadowed =
-        rt_is_shadowed(
+        reservoir.W > 0.0
+        && rt_is_shadowed(
             acceleration_structure,
             new_ray(
                 shadow_ray_origin,
@@ -54,10 +174,17 @@ void main() {
                 dist_to_light - 1e-4
         ));
 
-    out0_tex[px] = float4(select(is_shadowed, 0, triangle_light.radiance(), 1));
+    out0_tex[px] = float4(select(is_shadowed, 0.0.xxx, light_point.radiance), 1);
     out1_tex[px] = float4(
         view_ray_context.ray_hit_vs() + direction_world_to_view(to_light_ws),
-        light_sample.pdf.value * light_choice_pmf
+        reservoir.W > 0.0 ? 1.0 / reservoir.W : 0.0
     );
-    out2_tex[px] = float4(direction_world_to_view(light_sample.normal), 0);
+    out2_tex[px] = float4(direction_world_to_view(light_point.normal), 0);
+
+    // Occluded samples aren't worth reusing
+    if (is_shadowed) {
+        reservoir.W = 0;
+    }
+
+    reservoir_output_tex[px] = uint4(reservoir.as_raw(), pack_urand(selected_urand), 0);
 }
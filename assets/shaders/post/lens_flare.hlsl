@@ -0,0 +1,56 @@
+#include "../inc/samplers.hlsl"
+#include "../inc/uv.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWTexture2D<float4> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    float4 output_tex_size;
+};
+
+// Screen-space ghosts and halo, after John Chapman's "Pseudo Lens Flare":
+// http://john-chapman-graphics.blogspot.com/2013/02/pseudo-lens-flare.html
+//
+// Reflections between lens elements show bright parts of the image mirrored
+// through the optical axis, at various scales.
+
+static const uint GHOST_COUNT = 4;
+// Distance between the ghosts, as a fraction of the way to the center
+static const float GHOST_SPACING = 0.37;
+// Radius of the halo ring, in units of the frame height
+static const float HALO_RADIUS = 0.45;
+
+// Ghosts and the halo fade out away from the center, where they'd be
+// vignetted by the lens barrel. Also hides the clamped edges of the input.
+float ghost_weight(float2 uv) {
+    return pow(saturate(1.0 - length(uv - 0.5) * 2.0), 5.0);
+}
+
+float halo_weight(float2 uv) {
+    return pow(saturate(1.0 - length(uv - 0.5) * 1.5), 5.0);
+}
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float2 uv = get_uv(px, output_tex_size);
+
+    const float2 flipped_uv = 1.0 - uv;
+    const float2 to_center = 0.5 - flipped_uv;
+
+    float3 flare = 0.0.xxx;
+
+    for (uint ghost_idx = 0; ghost_idx < GHOST_COUNT; ++ghost_idx) {
+        const float2 sample_uv = flipped_uv + to_center * GHOST_SPACING * ghost_idx;
+        flare += input_tex.SampleLevel(sampler_lnc, sample_uv, 0).rgb * ghost_weight(sample_uv);
+    }
+
+    // Round regardless of the aspect ratio
+    const float2 aspect = float2(output_tex_size.x * output_tex_size.w, 1.0);
+    const float to_center_len = length(to_center * aspect);
+
+    if (to_center_len > 1e-5) {
+        const float2 halo_uv = flipped_uv + to_center / to_center_len * HALO_RADIUS;
+        flare += input_tex.SampleLevel(sampler_lnc, halo_uv, 0).rgb * halo_weight(halo_uv);
+    }
+
+    output_tex[px] = float4(flare, 1);
+}
@@ -2,6 +2,9 @@
 #include "inc/uv.hlsl"
 #include "inc/frame_constants.hlsl"
 #include "inc/bindless_textures.hlsl"
+#include "inc/color/srgb.hlsl"
+#include "inc/hash.hlsl"
+#include "inc/math.hlsl"
 #include "post/luminance_histogram_common.hlsl"
 
 #define DECLARE_BEZOLD_BRUCKE_LUT
@@ -9,30 +12,55 @@ static float2 SAMPLE_BEZOLD_BRUCKE_LUT(float coord) {
     return bindless_textures[BINDLESS_LUT_BEZOLD_BRUCKE].SampleLevel(sampler_llr, float2(coord, 0.5), 0).xy;
 }
 #include "inc/color/display_transform.hlsl"
+#include "inc/color/aces.hlsl"
+#include "inc/color/agx.hlsl"
+#include "inc/color/display_target.hlsl"
 
 [[vk::binding(0)]] Texture2D<float4> input_tex;
 //[[vk::binding(1)]] Texture2D<float4> debug_input_tex;
 [[vk::binding(1)]] Texture2D<float4> blur_pyramid_tex;
 [[vk::binding(2)]] Texture2D<float4> rev_blur_pyramid_tex;
 [[vk::binding(3)]] StructuredBuffer<uint> histogram_buffer;
-[[vk::binding(4)]] RWTexture2D<float4> output_tex;
-[[vk::binding(5)]] cbuffer _ {
+[[vk::binding(4)]] Texture3D<float4> output_lut_tex;
+[[vk::binding(5)]] Texture2D<float4> lens_dirt_tex;
+[[vk::binding(6)]] Texture2D<float4> lens_flare_tex;
+[[vk::binding(7)]] RWTexture2D<float4> output_tex;
+[[vk::binding(8)]] cbuffer _ {
     float4 output_tex_size;
     float input_multiplier;
     float contrast;
+    uint display_transform;
+    uint display_target;
+    // xyz: domain min; w: 1 if enabled
+    float4 output_lut_domain_min;
+    // xyz: domain max; w: LUT size
+    float4 output_lut_domain_max;
+    float paper_white_nits;
+    float peak_brightness_nits;
+    // See `PostProcessSettings` in `post.rs`
+    float bloom_intensity;
+    float bloom_threshold;
+    float vignette;
+    float chromatic_aberration;
+    float film_grain;
+    float lens_dirt_intensity;
+    float lens_flare_intensity;
 };
 
+// Must match `DisplayTransform` in `post.rs`
+#define DISPLAY_TRANSFORM_NEUTRAL 0
+#define DISPLAY_TRANSFORM_ACES 1
+#define DISPLAY_TRANSFORM_AGX 2
+
 #define USE_GRADE 0
 #define USE_DISPLAY_TRANSFORM 1
+#define USE_OUTPUT_LUT 1
 #define USE_DITHER 1
 #define USE_SHARPEN 0
-#define USE_VIGNETTE 1
 
 #define DEBUG_HISTOGRAM 0
 
 static const float sharpen_amount = 0.1;
-static const float glare_amount = 0.05;
-//static const float glare_amount = 0.0;
 
 float sharpen_remap(float l) {
     return sqrt(l);
@@ -66,6 +94,91 @@ float3 push_down_black_point(float3 x, float m, float c) {
     return T * w0 + L * w1;
 }
 
+// ACES and AgX are fitted to SDR, and peak at 1.0. On HDR displays, their shoulder is
+// stretched to reach `max_output_scale`, while the midtones stay close to SDR:
+// 0.2 is lifted by under 4% at 1000 nits peak and 203 nits paper white.
+//
+// Scales the color by its max component so that the hue is kept.
+float3 expand_sdr_highlights(float3 col, float max_output_scale) {
+    const float y = min(max3(col.r, col.g, col.b), 1.0);
+    const float expanded = y / (1.0 - y * y * (1.0 - 1.0 / max_output_scale));
+    return y > 0.0 ? col * (expanded / y) : col;
+}
+
+// Scene-linear Rec.709 to display-linear in the primaries of `display_target`.
+// On HDR displays, 1.0 is paper white.
+float3 apply_display_transform(float3 col) {
+    const float max_output_scale = display_target_is_hdr(display_target)
+        ? max(1.0, peak_brightness_nits / paper_white_nits)
+        : 1.0;
+
+    if (display_transform == DISPLAY_TRANSFORM_ACES) {
+        col = rec709_to_display_target_primaries(aces_fitted_rec709(col), display_target);
+        return expand_sdr_highlights(col, max_output_scale);
+    } else if (display_transform == DISPLAY_TRANSFORM_AGX) {
+        col = agx(rec709_to_display_target_primaries(col, display_target));
+        return expand_sdr_highlights(col, max_output_scale);
+    } else {
+        return rec709_to_display_target_primaries(
+            display_transform_sRGB_with_max_output(col, max_output_scale),
+            display_target
+        );
+    }
+}
+
+// Red and blue spread radially away from green.
+float3 sample_input_with_chromatic_aberration(uint2 px, float2 uv) {
+    const float2 offset = (uv - 0.5) * 2.0 * chromatic_aberration * output_tex_size.zw;
+
+    return float3(
+        input_tex.SampleLevel(sampler_lnc, uv + offset, 0).r,
+        input_tex[px].g,
+        input_tex.SampleLevel(sampler_lnc, uv - offset, 0).b
+    );
+}
+
+// Boosts the light scattered by the lens where it's dirty.
+float lens_dirt_scale(float2 uv) {
+    if (lens_dirt_intensity > 0.0) {
+        const float dirt = sRGB_to_luminance(lens_dirt_tex.SampleLevel(sampler_lnc, uv, 0).rgb);
+        return 1.0 + dirt * lens_dirt_intensity;
+    } else {
+        return 1.0;
+    }
+}
+
+// Fraction of the image to replace by the blurred `glare`.
+float calculate_bloom_amount(float3 glare, float2 uv) {
+    float amount = bloom_intensity;
+
+    if (bloom_threshold > 0.0) {
+        // Only the part of the glare above the threshold blooms
+        const float exposed_luminance = sRGB_to_luminance(glare) * input_multiplier;
+        amount *= max(0.0, exposed_luminance - bloom_threshold) / max(1e-5, exposed_luminance);
+    }
+
+    return saturate(amount * lens_dirt_scale(uv));
+}
+
+float3 apply_output_lut(float3 col) {
+    const float lut_size = output_lut_domain_max.w;
+
+    // scRGB is unbounded, and would be clipped at 80 nits by the LUT domain.
+    // Its LUTs are applied to a Rec.2020 PQ signal instead, like HDR10.
+    const uint lut_target = display_target == DISPLAY_TARGET_SCRGB
+        ? DISPLAY_TARGET_REC2020_PQ
+        : display_target;
+
+    float3 signal = display_target_encode(col, lut_target, paper_white_nits);
+    float3 coord = saturate((signal - output_lut_domain_min.xyz) / (output_lut_domain_max.xyz - output_lut_domain_min.xyz));
+
+    // Sample between the centers of the first and last texels
+    coord = (coord * (lut_size - 1.0) + 0.5) / lut_size;
+    signal = output_lut_tex.SampleLevel(sampler_lnc, coord, 0).rgb;
+
+    return display_target_decode(signal, lut_target, paper_white_nits);
+}
+
 groupshared uint max_histogram_bin;
 void debug_histogram(int2 px, uint idx_within_group, inout float3 color) {
     const uint bins = LUMINANCE_HISTOGRAM_BIN_COUNT;
@@ -115,7 +228,9 @@ void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
 #endif
 
     float3 glare = rev_blur_pyramid_tex.SampleLevel(sampler_lnc, uv, 0).rgb;
-    float3 col = input_tex[px].rgb;
+    float3 col = chromatic_aberration > 0.0
+        ? sample_input_with_chromatic_aberration(px, uv)
+        : input_tex[px].rgb;
 
 #if USE_SHARPEN
 	float neighbors = 0;
@@ -146,15 +261,18 @@ void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
 	col.rgb *= max(0.0, sharpened_luma / max(1e-5, sRGB_to_luminance(col.rgb)));
 #endif
 
-    col = lerp(col, glare, glare_amount);
+    col = lerp(col, glare, calculate_bloom_amount(glare, uv));
+
+    if (lens_flare_intensity > 0.0) {
+        col += lens_flare_tex.SampleLevel(sampler_lnc, uv, 0).rgb * lens_flare_intensity * lens_dirt_scale(uv);
+    }
+
     col = max(0.0, col);
     //col = col * (1.0 - debug_input_tex[px].a) + debug_input_tex[px].rgb;
 
     col *= input_multiplier;
 
-#if USE_VIGNETTE
-    col *= exp(-2 * pow(length(uv - 0.5), 3));
-#endif
+    col *= exp(-2 * vignette * pow(length(uv - 0.5), 3));
 
 #if USE_GRADE
     // Lift mids
@@ -165,12 +283,24 @@ void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
 #endif
 
 #if USE_DISPLAY_TRANSFORM
-    // Apply a perceptually neutral display transform
-    col = display_transform_sRGB(col);
+    col = apply_display_transform(col);
+#else
+    col = rec709_to_display_target_primaries(col, display_target);
 #endif
 
     // Crank up the contrast
-    col = pow(col, contrast);
+    col = pow(max(0.0, col), contrast);
+
+    if (film_grain > 0.0) {
+        const float grain = triangle_remap(uint_to_u01_float(hash3(uint3(px, frame_constants.frame_index))));
+        col *= max(0.0, 1.0 + grain * film_grain);
+    }
+
+#if USE_OUTPUT_LUT
+    if (output_lut_domain_min.w != 0.0) {
+        col = apply_output_lut(col);
+    }
+#endif
 
     // Dither
 #if USE_DITHER
@@ -1,9 +1,11 @@
+#include "prefix_scan_common.hlsl"
+
 #define THREAD_GROUP_SIZE 512
 #define SEGMENT_SIZE (THREAD_GROUP_SIZE * 2)
 
 [[vk::binding(0)]] RWByteAddressBuffer inout_buf;
 
-groupshared uint shared_data[SEGMENT_SIZE];
+groupshared scan_value_t shared_data[SEGMENT_SIZE];
 
 uint2 load_input2(uint idx, uint segment) {
     return inout_buf.Load2(sizeof(uint) * (idx + segment * SEGMENT_SIZE));
@@ -18,8 +20,8 @@ void main(uint idx: SV_GroupThreadID, uint segment: SV_GroupID) {
     const uint STEP_COUNT = uint(log2(THREAD_GROUP_SIZE)) + 1;
 
     const uint2 input2 = load_input2(idx * 2, segment);
-    shared_data[idx * 2] = input2.x;
-    shared_data[idx * 2 + 1]	= input2.y;
+    shared_data[idx * 2] = scan_value_from_bits(input2.x);
+    shared_data[idx * 2 + 1] = scan_value_from_bits(input2.y);
 
     GroupMemoryBarrierWithGroupSync();
 
@@ -34,5 +36,5 @@ void main(uint idx: SV_GroupThreadID, uint segment: SV_GroupID) {
         GroupMemoryBarrierWithGroupSync();
     }
 
-    store_output2(idx * 2, segment, uint2(shared_data[idx * 2], shared_data[idx * 2 + 1]));
+    store_output2(idx * 2, segment, uint2(scan_value_to_bits(shared_data[idx * 2]), scan_value_to_bits(shared_data[idx * 2 + 1])));
 }
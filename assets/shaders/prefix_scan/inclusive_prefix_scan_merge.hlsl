@@ -1,3 +1,5 @@
+#include "prefix_scan_common.hlsl"
+
 #define THREAD_GROUP_SIZE 512
 #define SEGMENT_SIZE (THREAD_GROUP_SIZE * 2)
 
@@ -6,9 +8,11 @@
 
 uint2 load_input2(uint idx, uint segment) {
     const uint2 internal_sum = inout_buf.Load2(sizeof(uint) * (idx + segment * SEGMENT_SIZE));
-    const uint prev_segment_sum = select(segment == 0, 0, segment_sum_buf.Load(sizeof(uint) * (segment - 1)));
+    const scan_value_t prev_segment_sum = scan_value_from_bits(select(segment == 0, 0, segment_sum_buf.Load(sizeof(uint) * (segment - 1))));
 
-    return internal_sum + prev_segment_sum;
+    return uint2(
+        scan_value_to_bits(scan_value_from_bits(internal_sum.x) + prev_segment_sum),
+        scan_value_to_bits(scan_value_from_bits(internal_sum.y) + prev_segment_sum));
 }
 
 void store_output2(uint idx, uint segment, uint2 val) {
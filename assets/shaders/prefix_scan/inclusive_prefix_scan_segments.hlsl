@@ -1,14 +1,16 @@
+#include "prefix_scan_common.hlsl"
+
 #define THREAD_GROUP_SIZE 512
 #define SEGMENT_SIZE (THREAD_GROUP_SIZE * 2)
 
 [[vk::binding(0)]] ByteAddressBuffer input_buf;
 [[vk::binding(1)]] RWByteAddressBuffer output_buf;
 
-groupshared uint shared_data[SEGMENT_SIZE];
+groupshared scan_value_t shared_data[SEGMENT_SIZE];
 
-uint load_input(uint idx) {
+scan_value_t load_input(uint idx) {
     const uint segment_sum_idx = idx * SEGMENT_SIZE + SEGMENT_SIZE - 1;
-    return input_buf.Load(sizeof(uint) * segment_sum_idx);
+    return scan_value_from_bits(input_buf.Load(sizeof(uint) * segment_sum_idx));
 }
 
 void store_output2(uint idx, uint2 val) {
@@ -35,5 +37,5 @@ void main(uint idx: SV_GroupThreadID, uint segment: SV_GroupID) {
         GroupMemoryBarrierWithGroupSync();
     }
 
-    store_output2(idx * 2, uint2(shared_data[idx * 2], shared_data[idx * 2 + 1]));
+    store_output2(idx * 2, uint2(scan_value_to_bits(shared_data[idx * 2]), scan_value_to_bits(shared_data[idx * 2 + 1])));
 }
@@ -0,0 +1,15 @@
+#ifndef PREFIX_SCAN_COMMON_HLSL
+#define PREFIX_SCAN_COMMON_HLSL
+
+// Values are stored as raw bits, and summed either as `uint` or, with `SCAN_FLOAT`, as `float`.
+#if SCAN_FLOAT
+    #define scan_value_t float
+    #define scan_value_from_bits(v) asfloat(v)
+    #define scan_value_to_bits(v) asuint(v)
+#else
+    #define scan_value_t uint
+    #define scan_value_from_bits(v) (v)
+    #define scan_value_to_bits(v) (v)
+#endif
+
+#endif  // PREFIX_SCAN_COMMON_HLSL
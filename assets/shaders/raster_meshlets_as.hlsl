@@ -0,0 +1,109 @@
+#include "inc/frame_constants.hlsl"
+#include "inc/mesh.hlsl"
+#include "inc/bindless.hlsl"
+#include "inc/meshlet.hlsl"
+
+static const uint MESHLET_CULL_FLAG_CONE = 1;
+
+[[vk::push_constant]]
+struct {
+    uint draw_index;
+    uint mesh_index;
+    uint cull_flags;
+} push_constants;
+
+struct InstanceTransform {
+    row_major float3x4 current;
+    row_major float3x4 previous;
+};
+
+[[vk::binding(0)]] StructuredBuffer<InstanceTransform> instance_transforms_dyn;
+
+groupshared MeshletTaskPayload task_payload;
+groupshared uint visible_count;
+
+// Sphere against the side planes of the view frustum, extracted from the projection matrix.
+bool is_sphere_in_frustum(float3 vs_center, float radius) {
+    const float4x4 m = frame_constants.view_constants.view_to_clip;
+    const float4 planes[4] = {
+        m[3] + m[0],
+        m[3] - m[0],
+        m[3] + m[1],
+        m[3] - m[1],
+    };
+
+    for (uint i = 0; i < 4; ++i) {
+        const float4 plane = planes[i] / length(planes[i].xyz);
+        if (dot(plane, float4(vs_center, 1.0)) < -radius) {
+            return false;
+        }
+    }
+
+    // Behind the camera
+    return vs_center.z - radius < 0.0;
+}
+
+// Normals transform by the inverse transpose. The cofactor matrix is that times the determinant,
+// so it also transforms the cross products which meshlet normal cones are built from.
+float3x3 cofactor(float3x3 m) {
+    return float3x3(
+        cross(m[1], m[2]),
+        cross(m[2], m[0]),
+        cross(m[0], m[1])
+    );
+}
+
+bool is_meshlet_visible(Meshlet meshlet, float3x4 transform) {
+    const float3 ws_center = mul(transform, float4(meshlet.center, 1.0));
+    const float scale = max(
+        length(transform._11_21_31),
+        max(length(transform._12_22_32), length(transform._13_23_33))
+    );
+    const float radius = meshlet.radius * scale;
+
+    const float3 vs_center = mul(frame_constants.view_constants.world_to_view, float4(ws_center, 1.0)).xyz;
+    if (!is_sphere_in_frustum(vs_center, radius)) {
+        return false;
+    }
+
+    if ((push_constants.cull_flags & MESHLET_CULL_FLAG_CONE) != 0 && meshlet.cone_cutoff < 1.0) {
+        const float3 cone_axis = normalize(mul(cofactor((float3x3)transform), meshlet.cone_axis));
+        if (is_orthographic_projection()) {
+            // View rays are parallel, so the whole bounding sphere is seen from the same direction
+            if (dot(-get_direction_to_eye(ws_center), cone_axis) >= meshlet.cone_cutoff) {
+                return false;
+            }
+        } else {
+            const float3 eye_to_center = ws_center - get_eye_position();
+
+            if (dot(eye_to_center, cone_axis) >= meshlet.cone_cutoff * length(eye_to_center) + radius) {
+                return false;
+            }
+        }
+    }
+
+    return true;
+}
+
+[numthreads(MESHLET_TASK_GROUP_SIZE, 1, 1)]
+void main(uint group_thread_id: SV_GroupThreadID, uint meshlet_index: SV_DispatchThreadID) {
+    if (group_thread_id == 0) {
+        visible_count = 0;
+    }
+    GroupMemoryBarrierWithGroupSync();
+
+    const Mesh mesh = meshes[push_constants.mesh_index];
+
+    if (meshlet_index < mesh.meshlet_count) {
+        const Meshlet meshlet = load_meshlet(mesh, meshlet_index);
+
+        if (is_meshlet_visible(meshlet, instance_transforms_dyn[push_constants.draw_index].current)) {
+            uint slot;
+            InterlockedAdd(visible_count, 1, slot);
+            task_payload.meshlet_indices[slot] = meshlet_index;
+        }
+    }
+
+    GroupMemoryBarrierWithGroupSync();
+    DispatchMesh(visible_count, 1, 1, task_payload);
+}
@@ -0,0 +1,100 @@
+#include "inc/frame_constants.hlsl"
+#include "inc/mesh.hlsl"
+#include "inc/bindless.hlsl"
+#include "inc/meshlet.hlsl"
+
+[[vk::push_constant]]
+struct {
+    uint draw_index;
+    uint mesh_index;
+    uint cull_flags;
+} push_constants;
+
+struct InstanceTransform {
+    row_major float3x4 current;
+    row_major float3x4 previous;
+};
+
+[[vk::binding(0)]] StructuredBuffer<InstanceTransform> instance_transforms_dyn;
+
+// Must match `VsOut` in `raster_simple_vs.hlsl`, as both feed `raster_simple_ps.hlsl`.
+struct VsOut {
+	float4 position: SV_Position;
+    [[vk::location(0)]] float4 color: TEXCOORD0;
+    [[vk::location(1)]] float2 uv: TEXCOORD1;
+    [[vk::location(2)]] float3 normal: TEXCOORD2;
+    [[vk::location(3)]] nointerpolation uint material_id: TEXCOORD3;
+    [[vk::location(4)]] float3 tangent: TEXCOORD4;
+    [[vk::location(5)]] float3 bitangent: TEXCOORD5;
+    [[vk::location(6)]] float3 vs_pos: TEXCOORD6;
+    [[vk::location(7)]] float3 prev_vs_pos: TEXCOORD7;
+    [[vk::location(8)]] nointerpolation uint draw_index: TEXCOORD8;
+    [[vk::location(9)]] nointerpolation uint mesh_index: TEXCOORD9;
+};
+
+VsOut transform_vertex(Mesh mesh, uint vid) {
+    VsOut vsout;
+
+    VertexPacked vp = VertexPacked(asfloat(vertices.Load4(vid * sizeof(float4) + mesh.vertex_core_offset)));
+    Vertex v = unpack_vertex(vp);
+
+    float4 v_color =
+        select(mesh.vertex_aux_offset != 0,
+            asfloat(vertices.Load4(vid * sizeof(float4) + mesh.vertex_aux_offset)),
+            1.0.xxxx);
+
+    float4 v_tangent_packed =
+        select(mesh.vertex_tangent_offset != 0,
+            asfloat(vertices.Load4(vid * sizeof(float4) + mesh.vertex_tangent_offset)),
+            float4(1, 0, 0, 1));
+
+    float2 uv = asfloat(vertices.Load2(vid * sizeof(float2) + mesh.vertex_uv_offset));
+    uint material_id = vertices.Load(vid * sizeof(uint) + mesh.vertex_mat_offset);
+
+    float3 ws_pos = mul(instance_transforms_dyn[push_constants.draw_index].current, float4(v.position, 1.0));
+    float4 vs_pos = mul(frame_constants.view_constants.world_to_view, float4(ws_pos, 1.0));
+    float4 cs_pos = mul(frame_constants.view_constants.view_to_sample, vs_pos);
+
+    float3 prev_ws_pos = mul(instance_transforms_dyn[push_constants.draw_index].previous, float4(v.position, 1.0));
+    float4 prev_vs_pos = mul(frame_constants.view_constants.world_to_view, float4(prev_ws_pos, 1.0));
+
+    vsout.position = cs_pos;
+    vsout.color = v_color;
+    vsout.uv = uv;
+    vsout.normal = v.normal;
+    vsout.material_id = material_id;
+    vsout.tangent = v_tangent_packed.xyz;
+    vsout.bitangent = normalize(cross(v.normal, vsout.tangent) * v_tangent_packed.w);
+
+    vsout.vs_pos = vs_pos.xyz / vs_pos.w;
+    vsout.prev_vs_pos = prev_vs_pos.xyz / prev_vs_pos.w;
+    vsout.draw_index = push_constants.draw_index;
+    vsout.mesh_index = push_constants.mesh_index;
+
+    return vsout;
+}
+
+[outputtopology("triangle")]
+[numthreads(MESHLET_MAX_VERTICES, 1, 1)]
+void main(
+    uint group_thread_id: SV_GroupThreadID,
+    uint group_id: SV_GroupID,
+    in payload MeshletTaskPayload task_payload,
+    out vertices VsOut verts[MESHLET_MAX_VERTICES],
+    out indices uint3 tris[MESHLET_MAX_TRIANGLES]
+) {
+    const Mesh mesh = meshes[push_constants.mesh_index];
+    const Meshlet meshlet = load_meshlet(mesh, task_payload.meshlet_indices[group_id]);
+
+    SetMeshOutputCounts(meshlet.vertex_count, meshlet.triangle_count);
+
+    if (group_thread_id < meshlet.vertex_count) {
+        const uint vid = vertices.Load(meshlet.vertex_offset + group_thread_id * sizeof(uint));
+        verts[group_thread_id] = transform_vertex(mesh, vid);
+    }
+
+    for (uint tri = group_thread_id; tri < meshlet.triangle_count; tri += MESHLET_MAX_VERTICES) {
+        const uint packed = vertices.Load(meshlet.triangle_offset + tri * sizeof(uint));
+        tris[tri] = uint3(packed & 0xff, (packed >> 8) & 0xff, (packed >> 16) & 0xff);
+    }
+}
@@ -15,14 +15,10 @@ struct PsIn {
     [[vk::location(5)]] float3 bitangent: TEXCOORD5;
     [[vk::location(6)]] float3 vs_pos: TEXCOORD6;
     [[vk::location(7)]] float3 prev_vs_pos: TEXCOORD7;
+    [[vk::location(8)]] nointerpolation uint draw_index: TEXCOORD8;
+    [[vk::location(9)]] nointerpolation uint mesh_index: TEXCOORD9;
 };
 
-[[vk::push_constant]]
-struct {
-    uint draw_index;
-    uint mesh_index;
-} push_constants;
-
 struct InstanceTransform {
     row_major float3x4 current;
     row_major float3x4 previous;
@@ -37,7 +33,7 @@ struct PsOut {
 };
 
 PsOut main(PsIn ps) {
-    Mesh mesh = meshes[push_constants.mesh_index];
+    Mesh mesh = meshes[ps.mesh_index];
     MeshMaterial material = vertices.Load<MeshMaterial>(mesh.mat_data_offset + ps.material_id * sizeof(MeshMaterial));
 
     const float lod_bias = -0.5;
@@ -93,7 +89,7 @@ PsOut main(PsIn ps) {
         }
 
         // Transform to world space
-        normal_ws = normalize(mul(instance_transforms_dyn[push_constants.draw_index].current, float4(normal_os, 0.0)));
+        normal_ws = normalize(mul(instance_transforms_dyn[ps.draw_index].current, float4(normal_os, 0.0)));
     }
 
     // Derive normal from depth
@@ -117,8 +113,7 @@ PsOut main(PsIn ps) {
     Texture2D emissive_tex = bindless_textures[NonUniformResourceIndex(material.emissive_map)];
     float3 emissive = 1.0.xxx
         * emissive_tex.SampleBias(sampler_llr, emissive_uv, lod_bias).rgb
-        * float3(material.emissive)
-        * instance_dynamic_parameters_dyn[push_constants.draw_index].emissive_multiplier
+        * instance_emissive_color(instance_dynamic_parameters_dyn[ps.draw_index], float3(material.emissive))
         * frame_constants.pre_exposure;
 
     //albedo = float3(0.966653, 0.802156, 0.323968); // Au from Mitsuba
@@ -1,12 +1,7 @@
 #include "inc/frame_constants.hlsl"
 #include "inc/mesh.hlsl"
 #include "inc/bindless.hlsl"
-
-[[vk::push_constant]]
-struct {
-    uint draw_index;
-    uint mesh_index;
-} push_constants;
+#include "inc/raster_instance.hlsl"
 
 struct InstanceTransform {
     row_major float3x4 current;
@@ -14,6 +9,7 @@ struct InstanceTransform {
 };
 
 [[vk::binding(0)]] StructuredBuffer<InstanceTransform> instance_transforms_dyn;
+[[vk::binding(1)]] StructuredBuffer<RasterInstance> raster_instances_dyn;
 
 struct VsOut {
 	float4 position: SV_Position;
@@ -25,12 +21,17 @@ struct VsOut {
     [[vk::location(5)]] float3 bitangent: TEXCOORD5;
     [[vk::location(6)]] float3 vs_pos: TEXCOORD6;
     [[vk::location(7)]] float3 prev_vs_pos: TEXCOORD7;
+    [[vk::location(8)]] nointerpolation uint draw_index: TEXCOORD8;
+    [[vk::location(9)]] nointerpolation uint mesh_index: TEXCOORD9;
 };
 
-VsOut main(uint vid: SV_VertexID, uint instance_index: SV_InstanceID) {
+// Draws are issued with `firstInstance` set to the index of the instance being drawn,
+// and `SV_InstanceID` includes it.
+VsOut main(uint vid: SV_VertexID, uint draw_index: SV_InstanceID) {
     VsOut vsout;
 
-    const Mesh mesh = meshes[push_constants.mesh_index];
+    const uint mesh_index = raster_instances_dyn[draw_index].mesh_index;
+    const Mesh mesh = meshes[mesh_index];
 
     // TODO: replace with Load<float4> once there's a fast path for NV
     // https://github.com/microsoft/DirectXShaderCompiler/issues/2193
@@ -51,12 +52,12 @@ VsOut main(uint vid: SV_VertexID, uint instance_index: SV_InstanceID) {
     uint material_id = vertices.Load(vid * sizeof(uint) + mesh.vertex_mat_offset);
 
     //float3 ws_pos = v.position + float3(push_constants.instance_position);
-    float3 ws_pos = mul(instance_transforms_dyn[push_constants.draw_index].current, float4(v.position, 1.0));
+    float3 ws_pos = mul(instance_transforms_dyn[draw_index].current, float4(v.position, 1.0));
     
     float4 vs_pos = mul(frame_constants.view_constants.world_to_view, float4(ws_pos, 1.0));
     float4 cs_pos = mul(frame_constants.view_constants.view_to_sample, vs_pos);
 
-    float3 prev_ws_pos = mul(instance_transforms_dyn[push_constants.draw_index].previous, float4(v.position, 1.0));
+    float3 prev_ws_pos = mul(instance_transforms_dyn[draw_index].previous, float4(v.position, 1.0));
     float4 prev_vs_pos = mul(frame_constants.view_constants.world_to_view, float4(prev_ws_pos, 1.0));
     //float4 prev_cs_pos = mul(frame_constants.view_constants.view_to_sample, prev_vs_pos);
 
@@ -70,6 +71,8 @@ VsOut main(uint vid: SV_VertexID, uint instance_index: SV_InstanceID) {
 
     vsout.vs_pos = vs_pos.xyz / vs_pos.w;
     vsout.prev_vs_pos = prev_vs_pos.xyz / prev_vs_pos.w;
+    vsout.draw_index = draw_index;
+    vsout.mesh_index = mesh_index;
 
     return vsout;
 }
\ No newline at end of file
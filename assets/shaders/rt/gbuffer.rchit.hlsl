@@ -178,8 +178,7 @@ void main(inout GbufferRayPayload payload: SV_RayPayload, in RayHitAttrib attrib
     if (0 == payload.path_length || 0 == (material.flags & MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT)) {
         emissive = 1.0.xxx
             * emissive_tex.tex.SampleLevel(sampler_llr, emissive_uv, emissive_tex.lod).rgb
-            * float3(material.emissive)
-            * instance_dynamic_parameters_dyn[InstanceIndex()].emissive_multiplier
+            * instance_emissive_color(instance_dynamic_parameters_dyn[InstanceIndex()], float3(material.emissive))
             * frame_constants.pre_exposure;
     }
 
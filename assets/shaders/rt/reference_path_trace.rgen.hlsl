@@ -11,6 +11,9 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/sun.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/triangle_light_selection.hlsl"
+#include "../inc/lights/punctual.hlsl"
+#include "../inc/lights/area.hlsl"
 
 [[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
 
@@ -105,11 +108,26 @@ void main() {
             RayDesc outgoing_ray;
             {
                 const ViewRayContext view_ray_context = ViewRayContext::from_uv(uv);
-                const float3 ray_dir_ws = view_ray_context.ray_dir_ws();
+                float3 ray_origin_ws = view_ray_context.ray_origin_ws();
+                float3 ray_dir_ws = normalize(view_ray_context.ray_dir_ws());
+
+                // Thin lens: rays through a pixel start anywhere on the aperture,
+                // and converge on the focus plane. Must match `dof/coc.hlsl`.
+                if (frame_constants.lens_aperture_radius > 0.0) {
+                    const float3 ray_dir_vs = view_ray_context.ray_dir_vs();
+                    const float3 focus_point_vs = ray_dir_vs * (frame_constants.lens_focus_distance / -ray_dir_vs.z);
+
+                    const float r = frame_constants.lens_aperture_radius * sqrt(uint_to_u01_float(hash1_mut(rng)));
+                    const float phi = uint_to_u01_float(hash1_mut(rng)) * M_TAU;
+                    const float3 lens_point_vs = float3(r * cos(phi), r * sin(phi), 0.0);
+
+                    ray_origin_ws = mul(frame_constants.view_constants.view_to_world, float4(lens_point_vs, 1.0)).xyz;
+                    ray_dir_ws = normalize(mul(frame_constants.view_constants.view_to_world, float4(focus_point_vs - lens_point_vs, 0.0)).xyz);
+                }
 
                 outgoing_ray = new_ray(
-                    view_ray_context.ray_origin_ws(), 
-                    normalize(ray_dir_ws.xyz),
+                    ray_origin_ws,
+                    ray_dir_ws,
                     0.0,
                     FLT_MAX
                 );
@@ -254,10 +272,60 @@ void main() {
                         if (USE_EMISSIVE) {
                             total_radiance += gbuffer.emissive * throughput;
                         }
+
+                        if (!(INDIRECT_ONLY && path_length == 0)) {
+                            for (uint light_idx = 0; light_idx < frame_constants.punctual_light_count; ++light_idx) {
+                                const PunctualLightSample light = sample_punctual_light(punctual_lights_dyn[light_idx], primary_hit.position);
+                                const float3 wi = mul(light.to_light_norm, tangent_to_world);
+
+                                if (wi.z > 0.0 && any(light.irradiance > 0.0)) {
+                                    const bool is_shadowed =
+                                        light.cast_shadows && rt_is_shadowed(
+                                            acceleration_structure,
+                                            new_ray(
+                                                primary_hit.position,
+                                                light.to_light_norm,
+                                                1e-4,
+                                                light.distance
+                                        ));
+
+                                    if (!is_shadowed) {
+                                        total_radiance += throughput * brdf.evaluate_directional_light(wo, wi) * light.irradiance * wi.z;
+                                    }
+                                }
+                            }
+
+                            for (uint light_idx = 0; light_idx < frame_constants.area_light_count; ++light_idx) {
+                                const float2 urand = float2(
+                                    uint_to_u01_float(hash1_mut(rng)),
+                                    uint_to_u01_float(hash1_mut(rng))
+                                );
+
+                                const AreaLightSample light_sample = sample_area_light(area_lights_dyn[light_idx], primary_hit.position, urand);
+                                const float3 wi = mul(light_sample.to_light_norm, tangent_to_world);
+
+                                if (wi.z > 0.0 && any(light_sample.radiance_over_pdf > 0.0)) {
+                                    const bool is_shadowed =
+                                        light_sample.cast_shadows && rt_is_shadowed(
+                                            acceleration_structure,
+                                            new_ray(
+                                                primary_hit.position,
+                                                light_sample.to_light_norm,
+                                                1e-4,
+                                                light_sample.distance * (1.0 - 1e-4)
+                                        ));
+
+                                    if (!is_shadowed) {
+                                        total_radiance += throughput * brdf.evaluate(wo, wi) * light_sample.radiance_over_pdf * wi.z;
+                                    }
+                                }
+                            }
+                        }
                         
                         if (USE_LIGHTS && frame_constants.triangle_light_count > 0/* && path_length > 0*/) {   // rtr comp
-                            const float light_selection_pmf = 1.0 / frame_constants.triangle_light_count;
-                            const uint light_idx = hash1_mut(rng) % frame_constants.triangle_light_count;
+                            const TriangleLightSelection light_selection = select_triangle_light(uint_to_u01_float(hash1_mut(rng)));
+                            const float light_selection_pmf = light_selection.pmf;
+                            const uint light_idx = light_selection.light_idx;
                             //const float light_selection_pmf = 1;
                             //for (uint light_idx = 0; light_idx < frame_constants.triangle_light_count; light_idx += 1)
                             {
@@ -266,7 +334,7 @@ void main() {
                                     uint_to_u01_float(hash1_mut(rng))
                                 );
 
-                                TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights_dyn[light_idx]);
+                                TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights[light_idx]);
                                 LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
                                 const float3 shadow_ray_origin = primary_hit.position;
                                 const float3 to_light_ws = light_sample.pos - primary_hit.position;
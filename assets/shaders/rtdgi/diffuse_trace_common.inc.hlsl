@@ -135,6 +135,53 @@ TraceResult do_the_thing(uint2 px, float3 normal_ws, inout uint rng, RayDesc out
             total_radiance += brdf_value * light_radiance;
         }
 
+        for (uint light_idx = 0; light_idx < frame_constants.punctual_light_count; ++light_idx) {
+            const PunctualLightSample light = sample_punctual_light(punctual_lights_dyn[light_idx], primary_hit.position);
+            const float3 wi = mul(light.to_light_norm, tangent_to_world);
+
+            if (wi.z > 0.0 && any(light.irradiance > 0.0)) {
+                const bool is_shadowed =
+                    light.cast_shadows && rt_is_shadowed(
+                        acceleration_structure,
+                        new_ray(
+                            primary_hit.position,
+                            light.to_light_norm,
+                            1e-4,
+                            min(light.distance, SKY_DIST)
+                    ));
+
+                if (!is_shadowed) {
+                    total_radiance += brdf.evaluate(wo, wi) * wi.z * light.irradiance;
+                }
+            }
+        }
+
+        for (uint light_idx = 0; light_idx < frame_constants.area_light_count; ++light_idx) {
+            const float2 urand = float2(
+                uint_to_u01_float(hash1_mut(rng)),
+                uint_to_u01_float(hash1_mut(rng))
+            );
+
+            const AreaLightSample light_sample = sample_area_light(area_lights_dyn[light_idx], primary_hit.position, urand);
+            const float3 wi = mul(light_sample.to_light_norm, tangent_to_world);
+
+            if (wi.z > 0.0 && any(light_sample.radiance_over_pdf > 0.0)) {
+                const bool is_shadowed =
+                    light_sample.cast_shadows && rt_is_shadowed(
+                        acceleration_structure,
+                        new_ray(
+                            primary_hit.position,
+                            light_sample.to_light_norm,
+                            1e-4,
+                            light_sample.distance * (1.0 - 1e-4)
+                    ));
+
+                if (!is_shadowed) {
+                    total_radiance += brdf.evaluate(wo, wi) * wi.z * light_sample.radiance_over_pdf;
+                }
+            }
+        }
+
         if (USE_EMISSIVE) {
             total_radiance += gbuffer.emissive;
         }
@@ -142,47 +189,46 @@ TraceResult do_the_thing(uint2 px, float3 normal_ws, inout uint rng, RayDesc out
         if (USE_SCREEN_GI_REPROJECTION && is_on_screen) {
             total_radiance += reprojected_radiance.rgb * gbuffer.albedo;
         } else {
-            if (USE_LIGHTS) {
+            if (USE_LIGHTS && frame_constants.triangle_light_count > 0) {
                 float2 urand = float2(
                     uint_to_u01_float(hash1_mut(rng)),
                     uint_to_u01_float(hash1_mut(rng))
                 );
 
-                for (uint light_idx = 0; light_idx < frame_constants.triangle_light_count; light_idx += 1) {
-                    TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights_dyn[light_idx]);
-                    LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
-                    const float3 shadow_ray_origin = primary_hit.position;
-                    const float3 to_light_ws = light_sample.pos - shadow_ray_origin;
-                    const float dist_to_light2 = dot(to_light_ws, to_light_ws);
-                    const float3 to_light_norm_ws = to_light_ws * rsqrt(dist_to_light2);
-
-                    const float to_psa_metric =
-                        max(0.0, dot(to_light_norm_ws, gbuffer.normal))
-                        * max(0.0, dot(to_light_norm_ws, -light_sample.normal))
-                        / dist_to_light2;
-
-                    if (to_psa_metric > 0.0) {
-                        const bool is_shadowed =
-                            rt_is_shadowed(
-                                acceleration_structure,
-                                new_ray(
-                                    shadow_ray_origin,
-                                    to_light_norm_ws,
-                                    1e-3,
-                                    sqrt(dist_to_light2) - 2e-3
-                            ));
-
-                        #if 1
-                            const float3 bounce_albedo = lerp(gbuffer.albedo, 1.0.xxx, 0.04);
-                            const float3 brdf_value = bounce_albedo * to_psa_metric / M_PI;
-                        #else
-                            const float3 wi = mul(to_light_norm_ws, tangent_to_world);
-                            const float3 brdf_value = brdf.evaluate(wo, wi) * to_psa_metric;
-                        #endif
-
-                        total_radiance +=
-                            select(!is_shadowed, (triangle_light.radiance() * brdf_value / light_sample.pdf.value), 0);
-                    }
+                const TriangleLightSelection light_selection = select_triangle_light(uint_to_u01_float(hash1_mut(rng)));
+                TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights[light_selection.light_idx]);
+                LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
+                const float3 shadow_ray_origin = primary_hit.position;
+                const float3 to_light_ws = light_sample.pos - shadow_ray_origin;
+                const float dist_to_light2 = dot(to_light_ws, to_light_ws);
+                const float3 to_light_norm_ws = to_light_ws * rsqrt(dist_to_light2);
+
+                const float to_psa_metric =
+                    max(0.0, dot(to_light_norm_ws, gbuffer.normal))
+                    * max(0.0, dot(to_light_norm_ws, -light_sample.normal))
+                    / dist_to_light2;
+
+                if (to_psa_metric > 0.0) {
+                    const bool is_shadowed =
+                        rt_is_shadowed(
+                            acceleration_structure,
+                            new_ray(
+                                shadow_ray_origin,
+                                to_light_norm_ws,
+                                1e-3,
+                                sqrt(dist_to_light2) - 2e-3
+                        ));
+
+                    #if 1
+                        const float3 bounce_albedo = lerp(gbuffer.albedo, 1.0.xxx, 0.04);
+                        const float3 brdf_value = bounce_albedo * to_psa_metric / M_PI;
+                    #else
+                        const float3 wi = mul(to_light_norm_ws, tangent_to_world);
+                        const float3 brdf_value = brdf.evaluate(wo, wi) * to_psa_metric;
+                    #endif
+
+                    total_radiance +=
+                        select(!is_shadowed, (triangle_light.radiance() * brdf_value / light_sample.pdf.value / light_selection.pmf), 0);
                 }
             }
 
@@ -10,6 +10,9 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/sun.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/triangle_light_selection.hlsl"
+#include "../inc/lights/punctual.hlsl"
+#include "../inc/lights/area.hlsl"
 #include "../inc/reservoir.hlsl"
 #include "../ircache/bindings.hlsl"
 #include "../wrc/bindings.hlsl"
@@ -157,47 +157,46 @@ RtrTraceResult do_the_thing(uint2 px, float3 normal_ws, float roughness, inout u
 
                     total_radiance += reprojected_radiance.rgb * gbuffer.albedo;
                 } else {
-                    if (USE_LIGHTS) {
+                    if (USE_LIGHTS && frame_constants.triangle_light_count > 0) {
                         float2 urand = float2(
                             uint_to_u01_float(hash1_mut(rng)),
                             uint_to_u01_float(hash1_mut(rng))
                         );
 
-                        for (uint light_idx = 0; light_idx < frame_constants.triangle_light_count; light_idx += 1) {
-                            TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights_dyn[light_idx]);
-                            LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
-                            const float3 shadow_ray_origin = primary_hit.position;
-                            const float3 to_light_ws = light_sample.pos - shadow_ray_origin;
-                            const float dist_to_light2 = dot(to_light_ws, to_light_ws);
-                            const float3 to_light_norm_ws = to_light_ws * rsqrt(dist_to_light2);
-
-                            const float to_psa_metric =
-                                max(0.0, dot(to_light_norm_ws, gbuffer.normal))
-                                * max(0.0, dot(to_light_norm_ws, -light_sample.normal))
-                                / dist_to_light2;
-
-                            if (to_psa_metric > 0.0) {
-                                const bool is_shadowed =
-                                    rt_is_shadowed(
-                                        acceleration_structure,
-                                        new_ray(
-                                            shadow_ray_origin,
-                                            to_light_norm_ws,
-                                            1e-4,
-                                            sqrt(dist_to_light2) - 2e-4
-                                    ));
-
-                                #if 1
-                                    const float3 bounce_albedo = lerp(gbuffer.albedo, 1.0.xxx, 0.04);
-                                    const float3 brdf_value = bounce_albedo * to_psa_metric / M_PI;
-                                #else
-                                    const float3 wi = mul(to_light_norm_ws, tangent_to_world);
-                                    const float3 brdf_value = brdf.evaluate(wo, wi) * to_psa_metric;
-                                #endif
-
-                                total_radiance +=
-                                    select(!is_shadowed, (triangle_light.radiance() * brdf_value / light_sample.pdf.value), 0);
-                            }
+                        const TriangleLightSelection light_selection = select_triangle_light(uint_to_u01_float(hash1_mut(rng)));
+                        TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights[light_selection.light_idx]);
+                        LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
+                        const float3 shadow_ray_origin = primary_hit.position;
+                        const float3 to_light_ws = light_sample.pos - shadow_ray_origin;
+                        const float dist_to_light2 = dot(to_light_ws, to_light_ws);
+                        const float3 to_light_norm_ws = to_light_ws * rsqrt(dist_to_light2);
+
+                        const float to_psa_metric =
+                            max(0.0, dot(to_light_norm_ws, gbuffer.normal))
+                            * max(0.0, dot(to_light_norm_ws, -light_sample.normal))
+                            / dist_to_light2;
+
+                        if (to_psa_metric > 0.0) {
+                            const bool is_shadowed =
+                                rt_is_shadowed(
+                                    acceleration_structure,
+                                    new_ray(
+                                        shadow_ray_origin,
+                                        to_light_norm_ws,
+                                        1e-4,
+                                        sqrt(dist_to_light2) - 2e-4
+                                ));
+
+                            #if 1
+                                const float3 bounce_albedo = lerp(gbuffer.albedo, 1.0.xxx, 0.04);
+                                const float3 brdf_value = bounce_albedo * to_psa_metric / M_PI;
+                            #else
+                                const float3 wi = mul(to_light_norm_ws, tangent_to_world);
+                                const float3 brdf_value = brdf.evaluate(wo, wi) * to_psa_metric;
+                            #endif
+
+                            total_radiance +=
+                                select(!is_shadowed, (triangle_light.radiance() * brdf_value / light_sample.pdf.value / light_selection.pmf), 0);
                         }
                     }
 
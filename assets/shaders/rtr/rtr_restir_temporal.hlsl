@@ -403,7 +403,7 @@ void main(uint2 px : SV_DispatchThreadID) {
                 //const float3 prev_wo = normalize(ViewRayContext::from_uv(uv + center_reproj.xy).ray_dir_vs());
 
                 // TODO: take object motion into account too
-                const float3 current_wo = normalize(view_ray_context.ray_hit_ws() - get_eye_position());
+                const float3 current_wo = view_ray_context.ray_dir_ws();
                 const float3 prev_wo = normalize(view_ray_context.ray_hit_ws() - get_prev_eye_position());
 
                 const float wo_dot = saturate(dot(current_wo, prev_wo));
@@ -164,7 +164,7 @@ void main(uint2 px: SV_DispatchThreadID) {
     float wo_similarity;
     {
         // TODO: take object motion into account too
-        const float3 current_wo = normalize(view_ray_context.ray_hit_ws() - get_eye_position());
+        const float3 current_wo = view_ray_context.ray_dir_ws();
         const float3 prev_wo = normalize(view_ray_context.ray_hit_ws() - get_prev_eye_position());
 
         const float clamped_roughness = max(0.1, gbuffer.roughness);
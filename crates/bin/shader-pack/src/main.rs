@@ -0,0 +1,313 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use kajiya::{
+    backend::{shader_pack::ShaderPack, vulkan::RenderBackendConfig, RenderBackend},
+    lights::{AreaLightDesc, AreaLightShape, LightDesc},
+    renderers::ibl::ImageRgba16f,
+    rg::{renderer::Renderer, GraphDebugHook, GraphDebugView, RenderDebugHook, RgDebugPass},
+    ui_renderer::UiRenderer,
+    world_renderer::{AddMeshOptions, WorldRenderer},
+};
+use kajiya_simple::*;
+use structopt::StructOpt;
+use turbosloth::*;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "shader-pack",
+    about = "Precompiles the shaders used by kajiya into a pack, so they can be loaded without dxc."
+)]
+struct Opt {
+    #[structopt(short = "o", long, default_value = "assets/shaders.pack")]
+    output: PathBuf,
+
+    #[structopt(long, default_value = "1920")]
+    width: u32,
+
+    #[structopt(long, default_value = "1080")]
+    height: u32,
+
+    #[structopt(long)]
+    physical_device_index: Option<usize>,
+}
+
+/// World renderer settings which change the render graph, and thus the pipelines it registers.
+#[derive(Clone, Copy)]
+struct Configuration {
+    render_mode: RenderMode,
+    debug_mode: RenderDebugMode,
+    use_gpu_culling: bool,
+    use_meshlets: bool,
+    depth_of_field: bool,
+    ibl: bool,
+}
+
+const STANDARD: Configuration = Configuration {
+    render_mode: RenderMode::Standard,
+    debug_mode: RenderDebugMode::None,
+    use_gpu_culling: false,
+    use_meshlets: false,
+    depth_of_field: false,
+    ibl: false,
+};
+
+// Each one enables an optional part of the frame. They're rendered with the scene from
+// `populate_scene`, which has one of every kind of light.
+const CONFIGURATIONS: &[Configuration] = &[
+    STANDARD,
+    Configuration {
+        debug_mode: RenderDebugMode::WorldRadianceCache,
+        ..STANDARD
+    },
+    Configuration {
+        use_gpu_culling: true,
+        ..STANDARD
+    },
+    Configuration {
+        use_meshlets: true,
+        ..STANDARD
+    },
+    Configuration {
+        depth_of_field: true,
+        ..STANDARD
+    },
+    Configuration {
+        ibl: true,
+        ..STANDARD
+    },
+    Configuration {
+        render_mode: RenderMode::Reference,
+        ..STANDARD
+    },
+];
+
+// Baked from `EMISSIVE_MESH_SOURCE` for the triangle lights
+const EMISSIVE_MESH_SOURCE: &str = "/meshes/emissive/triangle.glb";
+const EMISSIVE_MESH_NAME: &str = "shader_pack_emissive";
+
+// More triangle lights than one segment of the prefix scan building their CDF holds,
+// so that the passes merging segments are used too.
+const EMISSIVE_INSTANCE_COUNT: u32 = 1025;
+
+fn populate_scene(world_renderer: &mut WorldRenderer) -> anyhow::Result<()> {
+    kajiya_asset_pipe::process_mesh_asset(kajiya_asset_pipe::MeshAssetProcessParams {
+        path: EMISSIVE_MESH_SOURCE.into(),
+        output_name: EMISSIVE_MESH_NAME.to_owned(),
+        scale: 1.0,
+    })
+    .with_context(|| format!("Baking {}", EMISSIVE_MESH_SOURCE))?;
+
+    let mesh = world_renderer.add_baked_mesh(
+        format!("/cache/{}.mesh", EMISSIVE_MESH_NAME),
+        AddMeshOptions::new().use_lights(true),
+    )?;
+    for instance_idx in 0..EMISSIVE_INSTANCE_COUNT {
+        let position = Vec3::new(
+            (instance_idx % 32) as f32,
+            0.0,
+            -((instance_idx / 32) as f32),
+        );
+        world_renderer.add_instance(mesh, Affine3A::from_translation(position));
+    }
+
+    world_renderer.add_light(LightDesc::point(Vec3::new(0.0, 2.0, 0.0), Vec3::ONE, 1.0))?;
+    world_renderer.add_area_light(AreaLightDesc::new(
+        AreaLightShape::Rect {
+            width: 1.0,
+            height: 1.0,
+        },
+        Vec3::new(0.0, 2.0, 1.0),
+        Vec3::ONE,
+        1.0,
+    ))?;
+
+    Ok(())
+}
+
+fn apply_configuration(world_renderer: &mut WorldRenderer, config: &Configuration) {
+    world_renderer.render_mode = config.render_mode;
+    world_renderer.debug_mode = config.debug_mode;
+    world_renderer.use_gpu_culling = config.use_gpu_culling;
+    world_renderer.use_meshlets = config.use_meshlets;
+    world_renderer.physical_camera = config.depth_of_field.then(|| PhysicalCamera {
+        depth_of_field: true,
+        ..Default::default()
+    });
+
+    if config.ibl {
+        world_renderer.ibl.set_image(ImageRgba16f::new(2, 1));
+    } else {
+        world_renderer.ibl.unload_image();
+    }
+}
+
+/// Prepares one frame with a fresh renderer, returning the shaders it compiled
+/// and its passes, as available to `GraphDebugHook`.
+fn prepare_frame(
+    render_backend: &RenderBackend,
+    world_renderer: &mut WorldRenderer,
+    ui_renderer: &mut UiRenderer,
+    frame_desc: &WorldFrameDesc,
+) -> anyhow::Result<(ShaderPack, Vec<RgDebugPass>)> {
+    // Frames are only prepared and never drawn, so each one needs a fresh renderer.
+    let mut rg_renderer = Renderer::new(render_backend)?;
+    rg_renderer.record_shader_pack();
+
+    rg_renderer.prepare_frame(|rg| {
+        rg.debug_hook = world_renderer.rg_debug_hook.take();
+
+        let main_img = world_renderer.prepare_render_graph(rg, frame_desc);
+        let ui_img = ui_renderer.prepare_render_graph(rg);
+        add_final_blit_pass(
+            rg,
+            &main_img,
+            &ui_img,
+            frame_desc.render_extent,
+            world_renderer.display_target,
+            world_renderer.hdr_display.paper_white_nits,
+        );
+    })?;
+
+    Ok((
+        rg_renderer.take_recorded_shader_pack().unwrap_or_default(),
+        rg_renderer.debug_pass_list().to_vec(),
+    ))
+}
+
+/// Hooks the first inspectable image matching `is_uint`, so that its shader variant is used.
+fn inspector_hook(passes: &[RgDebugPass], is_uint: bool) -> Option<GraphDebugHook> {
+    passes.iter().find_map(|pass| {
+        let image_idx = pass
+            .images
+            .iter()
+            .position(|image| image.inspectable && image.is_uint() == is_uint)?;
+
+        Some(GraphDebugHook {
+            render_debug_hook: RenderDebugHook {
+                name: pass.name.clone(),
+                id: pass.idx as u64,
+            },
+            view: GraphDebugView {
+                image: Some(image_idx),
+                ..Default::default()
+            },
+        })
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    set_vfs_mount_point("/meshes", "assets/meshes");
+
+    kajiya::logging::set_up_logging(log::LevelFilter::Info)?;
+    let opt = Opt::from_args();
+
+    // A device can't be created without a surface
+    let event_loop = winit::event_loop::EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("shader-pack")
+        .with_visible(false)
+        .with_inner_size(winit::dpi::PhysicalSize::new(opt.width, opt.height))
+        .build(&event_loop)?;
+
+    let render_extent = [opt.width, opt.height];
+    let render_backend = RenderBackend::new(
+        &window,
+        RenderBackendConfig {
+            swapchain_extent: render_extent,
+            vsync: false,
+            graphics_debugging: false,
+            device_index: opt.physical_device_index,
+            hdr_format: None,
+        },
+    )?;
+
+    let lazy_cache = LazyCache::create();
+    let mut world_renderer =
+        WorldRenderer::new(render_extent, render_extent, &render_backend, &lazy_cache)?;
+    let mut ui_renderer = UiRenderer::default();
+
+    let frame_desc = WorldFrameDesc {
+        camera_matrices: (Vec3::new(0.0, 1.0, 2.5), Quat::IDENTITY).through(&CameraLens {
+            aspect_ratio: opt.width as f32 / opt.height as f32,
+            ..Default::default()
+        }),
+        render_extent,
+        sun_direction: Vec3::new(4.0, 1.0, 1.0).normalize(),
+    };
+
+    if !render_backend.device.ray_tracing_enabled() {
+        log::warn!("Ray tracing is not supported by the device; its shaders will be missing");
+    }
+
+    populate_scene(&mut world_renderer)?;
+
+    let mut pack = ShaderPack::default();
+
+    // Passes of the `STANDARD` configuration, which comes first
+    let mut standard_passes = Vec::new();
+
+    for (config_idx, config) in CONFIGURATIONS.iter().enumerate() {
+        if config.use_meshlets && !render_backend.device.mesh_shaders_enabled() {
+            log::warn!("Mesh shaders are not supported by the device; skipping meshlet shaders");
+            continue;
+        }
+
+        if config.use_gpu_culling && !render_backend.device.draw_indirect_count_enabled() {
+            log::warn!(
+                "Indirect count draws are not supported by the device; skipping GPU culling shaders"
+            );
+            continue;
+        }
+
+        apply_configuration(&mut world_renderer, config);
+
+        let (config_pack, passes) = prepare_frame(
+            &render_backend,
+            &mut world_renderer,
+            &mut ui_renderer,
+            &frame_desc,
+        )
+        .with_context(|| format!("Preparing a frame for configuration {}", config_idx))?;
+
+        pack.merge(config_pack);
+
+        if config_idx == 0 {
+            standard_passes = passes;
+        }
+    }
+
+    // The graph inspector has a variant for float and one for uint images
+    apply_configuration(&mut world_renderer, &STANDARD);
+
+    for is_uint in [false, true] {
+        let hook = if let Some(hook) = inspector_hook(&standard_passes, is_uint) {
+            hook
+        } else {
+            log::warn!("No image to inspect with IS_UINT={}", is_uint as u32);
+            continue;
+        };
+
+        world_renderer.rg_debug_hook = Some(hook);
+
+        let (config_pack, _) = prepare_frame(
+            &render_backend,
+            &mut world_renderer,
+            &mut ui_renderer,
+            &frame_desc,
+        )
+        .with_context(|| {
+            format!(
+                "Preparing a frame for the inspector, IS_UINT={}",
+                is_uint as u32
+            )
+        })?;
+
+        pack.merge(config_pack);
+    }
+
+    pack.save(&opt.output)?;
+    log::info!("Wrote {} shaders to {:?}", pack.len(), opt.output);
+
+    Ok(())
+}
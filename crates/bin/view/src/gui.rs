@@ -1,9 +1,14 @@
 use imgui::im_str;
-use kajiya::RenderOverrideFlags;
+use kajiya::{
+    backend::rust_shader_compiler::{rust_shader_build_status, RustShaderBuildStatus},
+    lights::MAX_AREA_LIGHTS,
+    RenderOverrideFlags,
+};
 use kajiya_simple::*;
 
 use crate::{
-    runtime::{RuntimeState, MAX_FPS_LIMIT},
+    persisted::{AreaLightShapeKind, DisplayTargetKind, DisplayTransformKind, PostProcessState},
+    runtime::{LeftClickEditMode, PassTimingsSortColumn, RuntimeState, MAX_FPS_LIMIT},
     PersistedState,
 };
 
@@ -13,10 +18,46 @@ impl RuntimeState {
             self.show_gui = !self.show_gui;
         }
 
+        if let Some(hook) = self.locked_rg_debug_hook.as_mut() {
+            hook.view = self.rg_debug_view.clone();
+
+            if self.rg_debug_pick_under_cursor {
+                let window_size = ctx.window.inner_size();
+                hook.view.pick_uv = Some([
+                    (self.mouse.physical_position.x / window_size.width.max(1) as f64) as f32,
+                    (self.mouse.physical_position.y / window_size.height.max(1) as f64) as f32,
+                ]);
+            }
+        }
+
         ctx.world_renderer.rg_debug_hook = self.locked_rg_debug_hook.clone();
 
-        if self.show_gui {
+        // Shader errors are shown even with the rest of the UI hidden
+        let shader_compile_errors = ctx.shader_compile_errors;
+        let rust_shader_build_status = rust_shader_build_status();
+        let rust_shader_build_failed = matches!(
+            rust_shader_build_status,
+            RustShaderBuildStatus::Failed { .. }
+        );
+
+        if self.show_gui || !shader_compile_errors.is_empty() || rust_shader_build_failed {
             ctx.imgui.take().unwrap().frame(|ui| {
+                if !shader_compile_errors.is_empty() || rust_shader_build_failed {
+                    Self::do_shader_compile_errors(
+                        ui,
+                        shader_compile_errors,
+                        &rust_shader_build_status,
+                    );
+                }
+
+                if !self.show_gui {
+                    return;
+                }
+
+                if rust_shader_build_status == RustShaderBuildStatus::Building {
+                    ui.text_disabled("Building Rust-GPU shaders...");
+                }
+
                 if imgui::CollapsingHeader::new(im_str!("Tweaks"))
                     .default_open(true)
                     .build(ui)
@@ -59,6 +100,65 @@ impl RuntimeState {
                         .speed(0.001)
                         .build(ui, &mut persisted.exposure.contrast);
 
+                    {
+                        let mut transform = DisplayTransformKind::ALL
+                            .iter()
+                            .position(|transform| *transform == persisted.display.transform)
+                            .unwrap_or_default();
+
+                        imgui::ComboBox::new(im_str!("Display transform")).build_simple_string(
+                            ui,
+                            &mut transform,
+                            &[im_str!("Neutral"), im_str!("ACES"), im_str!("AgX")],
+                        );
+                        persisted.display.transform = DisplayTransformKind::ALL[transform];
+                    }
+
+                    if ctx.hdr_swapchain {
+                        ui.text(match ctx.world_renderer.display_target {
+                            DisplayTarget::ScRgb => im_str!("Display target: scRGB"),
+                            _ => im_str!("Display target: HDR10"),
+                        });
+
+                        imgui::Drag::<f32>::new(im_str!("Paper white (nits)"))
+                            .range(80.0..=500.0)
+                            .speed(1.0)
+                            .build(ui, &mut persisted.display.paper_white_nits);
+
+                        imgui::Drag::<f32>::new(im_str!("Peak brightness (nits)"))
+                            .range(persisted.display.paper_white_nits..=10000.0)
+                            .speed(10.0)
+                            .build(ui, &mut persisted.display.peak_brightness_nits);
+                    } else {
+                        let mut target = DisplayTargetKind::ALL
+                            .iter()
+                            .position(|target| *target == persisted.display.target)
+                            .unwrap_or_default();
+
+                        imgui::ComboBox::new(im_str!("Display target")).build_simple_string(
+                            ui,
+                            &mut target,
+                            &[im_str!("Rec.709"), im_str!("Display P3")],
+                        );
+                        persisted.display.target = DisplayTargetKind::ALL[target];
+
+                        if persisted.display.target == DisplayTargetKind::DisplayP3
+                            && ctx.world_renderer.display_target != DisplayTarget::DisplayP3
+                        {
+                            ui.text(im_str!("The swapchain is not Display P3; showing Rec.709"));
+                        }
+                    }
+
+                    if let Some(lut) = persisted.display.output_lut.as_ref() {
+                        ui.text(im_str!("Output LUT: {:?}", lut));
+                        if ui.button(im_str!("Unload LUT"), [0.0, 0.0]) {
+                            ctx.world_renderer.unload_output_lut();
+                            persisted.display.output_lut = None;
+                        }
+                    } else {
+                        ui.text(im_str!("Drag a .cube file to load as output LUT"));
+                    }
+
                     imgui::Drag::<f32>::new(im_str!("Emissive multiplier"))
                         .range(0.0..=10.0)
                         .speed(0.1)
@@ -104,23 +204,28 @@ impl RuntimeState {
                         &mut ctx.world_renderer.debug_show_wrc,
                     );*/
 
-                    /*if ui.radio_button_bool(
+                    if ui.radio_button_bool(
                         im_str!("Move sun"),
-                        left_click_edit_mode == LeftClickEditMode::MoveSun,
+                        self.left_click_edit_mode == LeftClickEditMode::MoveSun,
                     ) {
-                        left_click_edit_mode = LeftClickEditMode::MoveSun;
+                        self.left_click_edit_mode = LeftClickEditMode::MoveSun;
                     }
 
                     if ui.radio_button_bool(
                         im_str!("Move local lights"),
-                        left_click_edit_mode == LeftClickEditMode::MoveLocalLights,
+                        self.left_click_edit_mode == LeftClickEditMode::MoveLocalLights,
                     ) {
-                        left_click_edit_mode = LeftClickEditMode::MoveLocalLights;
+                        self.left_click_edit_mode = LeftClickEditMode::MoveLocalLights;
                     }
 
                     imgui::Drag::<u32>::new(im_str!("Light count"))
                         .range(0..=10)
-                        .build(ui, &mut state.lights.count);*/
+                        .build(ui, &mut persisted.light.local_lights.count);
+
+                    imgui::Drag::<f32>::new(im_str!("Light distance"))
+                        .range(0.0..=100.0)
+                        .speed(0.01)
+                        .build(ui, &mut persisted.light.local_lights.distance);
 
                     ui.checkbox(
                         im_str!("Scroll irradiance cache"),
@@ -147,12 +252,250 @@ impl RuntimeState {
                         &mut ctx.world_renderer.rtr.reuse_rtdgi_rays,
                     );
 
+                    ui.checkbox(
+                        im_str!("GPU instance culling"),
+                        &mut ctx.world_renderer.use_gpu_culling,
+                    );
+
+                    if ctx.world_renderer.use_gpu_culling {
+                        ui.checkbox(
+                            im_str!("Occlusion culling"),
+                            &mut ctx.world_renderer.gpu_culling.use_occlusion_culling,
+                        );
+                    }
+
+                    ui.checkbox(
+                        im_str!("Meshlet rasterization (mesh shaders)"),
+                        &mut ctx.world_renderer.use_meshlets,
+                    );
+
+                    if ctx.world_renderer.use_meshlets {
+                        ui.checkbox(
+                            im_str!("Meshlet cone culling"),
+                            &mut ctx.world_renderer.meshlet_cone_culling,
+                        );
+                    }
+
+                    imgui::Drag::<u32>::new(im_str!("TLAS rebuild interval"))
+                        .range(1..=600)
+                        .build(ui, &mut ctx.world_renderer.tlas_rebuild_interval);
+
+                    let blas_memory = ctx.world_renderer.blas_memory_stats();
+                    ui.text(format!(
+                        "BLAS memory: {:.1} MiB ({:.1} MiB saved by compaction)",
+                        blas_memory.total_bytes as f64 / (1024.0 * 1024.0),
+                        blas_memory.compaction_savings_bytes as f64 / (1024.0 * 1024.0),
+                    ));
+
                     #[cfg(feature = "dlss")]
                     {
                         ui.checkbox(im_str!("Use DLSS"), &mut ctx.world_renderer.use_dlss);
                     }
                 }
 
+                if imgui::CollapsingHeader::new(im_str!("Post-processing"))
+                    .default_open(false)
+                    .build(ui)
+                {
+                    imgui::Drag::<f32>::new(im_str!("Bloom intensity"))
+                        .range(0.0..=1.0)
+                        .speed(0.001)
+                        .build(ui, &mut persisted.post.bloom_intensity);
+
+                    imgui::Drag::<f32>::new(im_str!("Bloom threshold"))
+                        .range(0.0..=10.0)
+                        .speed(0.01)
+                        .build(ui, &mut persisted.post.bloom_threshold);
+
+                    imgui::Drag::<f32>::new(im_str!("Bloom radius"))
+                        .range(0.0..=1.0)
+                        .speed(0.005)
+                        .build(ui, &mut persisted.post.bloom_radius);
+
+                    imgui::Drag::<f32>::new(im_str!("Vignette"))
+                        .range(0.0..=10.0)
+                        .speed(0.01)
+                        .build(ui, &mut persisted.post.vignette);
+
+                    imgui::Drag::<f32>::new(im_str!("Chromatic aberration (px)"))
+                        .range(0.0..=20.0)
+                        .speed(0.05)
+                        .build(ui, &mut persisted.post.chromatic_aberration);
+
+                    imgui::Drag::<f32>::new(im_str!("Film grain"))
+                        .range(0.0..=1.0)
+                        .speed(0.001)
+                        .build(ui, &mut persisted.post.film_grain);
+
+                    imgui::Drag::<f32>::new(im_str!("Lens flare intensity"))
+                        .range(0.0..=1.0)
+                        .speed(0.001)
+                        .build(ui, &mut persisted.post.lens_flare_intensity);
+
+                    if let Some(lens_dirt) = persisted.post.lens_dirt.as_ref() {
+                        ui.text(im_str!("Lens dirt: {:?}", lens_dirt));
+
+                        imgui::Drag::<f32>::new(im_str!("Lens dirt intensity"))
+                            .range(0.0..=10.0)
+                            .speed(0.01)
+                            .build(ui, &mut persisted.post.lens_dirt_intensity);
+
+                        if ui.button(im_str!("Unload lens dirt"), [0.0, 0.0]) {
+                            ctx.world_renderer.unload_lens_dirt();
+                            persisted.post.lens_dirt = None;
+                        }
+                    } else {
+                        ui.text(im_str!("Drag a .png or .jpg file to load as lens dirt"));
+                    }
+
+                    if ui.button(im_str!("Reset post-processing"), [0.0, 0.0]) {
+                        let lens_dirt = persisted.post.lens_dirt.take();
+                        persisted.post = PostProcessState {
+                            lens_dirt,
+                            ..Default::default()
+                        };
+                    }
+                }
+
+                if imgui::CollapsingHeader::new(im_str!("Area lights"))
+                    .default_open(false)
+                    .build(ui)
+                {
+                    let mut light_to_remove = None;
+                    for (idx, light) in persisted.light.area_lights.iter_mut().enumerate() {
+                        ui.dummy([0.0, 10.0]);
+
+                        let id_token = ui.push_id(idx as i32);
+
+                        {
+                            let mut shape = AreaLightShapeKind::ALL
+                                .iter()
+                                .position(|shape| *shape == light.shape)
+                                .unwrap_or_default();
+
+                            ui.set_next_item_width(200.0);
+                            imgui::ComboBox::new(im_str!("shape")).build_simple_string(
+                                ui,
+                                &mut shape,
+                                &[
+                                    im_str!("Rectangle"),
+                                    im_str!("Disk"),
+                                    im_str!("Sphere"),
+                                    im_str!("Tube"),
+                                ],
+                            );
+                            light.shape = AreaLightShapeKind::ALL[shape];
+                        }
+
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("Delete"), [0.0, 0.0]) {
+                            light_to_remove = Some(idx);
+                        }
+
+                        // Position
+                        {
+                            ui.set_next_item_width(100.0);
+                            imgui::Drag::<f32>::new(im_str!("x"))
+                                .speed(0.01)
+                                .build(ui, &mut light.position.x);
+
+                            ui.same_line(0.0);
+
+                            ui.set_next_item_width(100.0);
+                            imgui::Drag::<f32>::new(im_str!("y"))
+                                .speed(0.01)
+                                .build(ui, &mut light.position.y);
+
+                            ui.same_line(0.0);
+
+                            ui.set_next_item_width(100.0);
+                            imgui::Drag::<f32>::new(im_str!("z"))
+                                .speed(0.01)
+                                .build(ui, &mut light.position.z);
+                        }
+
+                        // Rotation
+                        {
+                            ui.set_next_item_width(100.0);
+                            imgui::Drag::<f32>::new(im_str!("rx"))
+                                .speed(0.1)
+                                .build(ui, &mut light.rotation_euler_degrees.x);
+
+                            ui.same_line(0.0);
+
+                            ui.set_next_item_width(100.0);
+                            imgui::Drag::<f32>::new(im_str!("ry"))
+                                .speed(0.1)
+                                .build(ui, &mut light.rotation_euler_degrees.y);
+
+                            ui.same_line(0.0);
+
+                            ui.set_next_item_width(100.0);
+                            imgui::Drag::<f32>::new(im_str!("rz"))
+                                .speed(0.1)
+                                .build(ui, &mut light.rotation_euler_degrees.z);
+                        }
+
+                        // Size
+                        {
+                            let (x_label, y_label) = match light.shape {
+                                AreaLightShapeKind::Rect => {
+                                    (im_str!("width"), Some(im_str!("height")))
+                                }
+                                AreaLightShapeKind::Tube => {
+                                    (im_str!("radius"), Some(im_str!("length")))
+                                }
+                                _ => (im_str!("radius"), None),
+                            };
+
+                            ui.set_next_item_width(100.0);
+                            imgui::Drag::<f32>::new(x_label)
+                                .range(0.001..=100.0)
+                                .speed(0.01)
+                                .build(ui, &mut light.size.x);
+
+                            if let Some(y_label) = y_label {
+                                ui.same_line(0.0);
+
+                                ui.set_next_item_width(100.0);
+                                imgui::Drag::<f32>::new(y_label)
+                                    .range(0.0..=100.0)
+                                    .speed(0.01)
+                                    .build(ui, &mut light.size.y);
+                            }
+                        }
+
+                        {
+                            let mut color: [f32; 3] = light.color.into();
+                            if imgui::ColorEdit::new(im_str!("color"), &mut color).build(ui) {
+                                light.color = color.into();
+                            }
+
+                            imgui::Drag::<f32>::new(im_str!("intensity"))
+                                .range(0.0..=10000.0)
+                                .speed(0.1)
+                                .flags(imgui::SliderFlags::LOGARITHMIC)
+                                .build(ui, &mut light.intensity);
+                        }
+
+                        ui.checkbox(im_str!("two-sided"), &mut light.two_sided);
+                        ui.same_line(0.0);
+                        ui.checkbox(im_str!("shadows"), &mut light.cast_shadows);
+
+                        id_token.pop(ui);
+                    }
+
+                    if let Some(idx) = light_to_remove {
+                        persisted.light.area_lights.remove(idx);
+                    }
+
+                    if persisted.light.area_lights.len() < MAX_AREA_LIGHTS
+                        && ui.button(im_str!("Add area light"), [0.0, 0.0])
+                    {
+                        persisted.light.area_lights.push(Default::default());
+                    }
+                }
+
                 if imgui::CollapsingHeader::new(im_str!("Scene"))
                     .default_open(true)
                     .build(ui)
@@ -237,6 +580,17 @@ impl RuntimeState {
                                 .build(ui, &mut elem.transform.rotation_euler_degrees.z);
                         }
 
+                        // Visibility
+                        {
+                            ui.checkbox(im_str!("camera"), &mut elem.flags.visible_to_camera);
+                            ui.same_line(0.0);
+                            ui.checkbox(im_str!("indirect"), &mut elem.flags.visible_in_indirect);
+                            ui.same_line(0.0);
+                            ui.checkbox(im_str!("shadows"), &mut elem.flags.cast_shadows);
+                            ui.same_line(0.0);
+                            ui.checkbox(im_str!("double-sided"), &mut elem.flags.double_sided);
+                        }
+
                         id_token.pop(ui);
                     }
 
@@ -370,6 +724,31 @@ impl RuntimeState {
                     }
                 }
 
+                if imgui::CollapsingHeader::new(im_str!("Poster"))
+                    .default_open(false)
+                    .build(ui)
+                {
+                    imgui::Drag::<u32>::new(im_str!("Width"))
+                        .range(1..=32768)
+                        .build(ui, &mut self.poster.extent[0]);
+
+                    imgui::Drag::<u32>::new(im_str!("Height"))
+                        .range(1..=32768)
+                        .build(ui, &mut self.poster.extent[1]);
+
+                    imgui::Drag::<u32>::new(im_str!("Samples per pixel"))
+                        .range(1..=1000)
+                        .build(ui, &mut self.poster.samples_per_pixel);
+
+                    ui.text(format!("Output: {}", self.poster.output_path.display()));
+
+                    if let Some(progress) = ctx.poster_progress {
+                        imgui::ProgressBar::new(progress).build(ui);
+                    } else if ui.button(im_str!("Render poster"), [0.0, 0.0]) {
+                        ctx.render_poster(self.poster.clone());
+                    }
+                }
+
                 if imgui::CollapsingHeader::new(im_str!("Debug"))
                     .default_open(false)
                     .build(ui)
@@ -451,14 +830,25 @@ impl RuntimeState {
                             }
 
                             if ui.is_item_hovered() {
+                                let is_locked =
+                                    self.locked_rg_debug_hook.as_ref().map_or(false, |hook| {
+                                        hook.render_debug_hook == render_debug_hook
+                                    });
+
                                 ctx.world_renderer.rg_debug_hook =
-                                    Some(kajiya::rg::GraphDebugHook { render_debug_hook });
+                                    Some(kajiya::rg::GraphDebugHook {
+                                        render_debug_hook,
+                                        view: kajiya::rg::GraphDebugView {
+                                            image: None,
+                                            ..self.rg_debug_view.clone()
+                                        },
+                                    });
 
                                 if ui.is_item_clicked(imgui::MouseButton::Left) {
-                                    if self.locked_rg_debug_hook == ctx.world_renderer.rg_debug_hook
-                                    {
+                                    if is_locked {
                                         self.locked_rg_debug_hook = None;
                                     } else {
+                                        self.rg_debug_view.image = None;
                                         self.locked_rg_debug_hook =
                                             ctx.world_renderer.rg_debug_hook.clone();
                                     }
@@ -467,7 +857,304 @@ impl RuntimeState {
                         }
                     }
                 }
+
+                if imgui::CollapsingHeader::new(im_str!("Inspector")).build(ui) {
+                    self.do_inspector(ui, ctx.rg_debug_passes, ctx.rg_debug_picked_pixel);
+                }
+
+                if let Some(timings) = ctx.pass_timings {
+                    if imgui::CollapsingHeader::new(im_str!("Pass timings")).build(ui) {
+                        ui.text(format!(
+                            "GPU: {:.3}ms, CPU recording: {:.3}ms",
+                            timings.total_gpu_ms(),
+                            timings.total_cpu_record_ms()
+                        ));
+
+                        if ui.button(im_str!("Export Chrome trace"), [0.0, 0.0]) {
+                            let path = "pass_timings.json";
+                            match std::fs::write(path, timings.to_chrome_trace_json()) {
+                                Ok(()) => log::info!("Wrote {}", path),
+                                Err(err) => log::error!("Failed to write {}: {}", path, err),
+                            }
+                        }
+
+                        self.do_pass_timings_table(ui, timings);
+                    }
+                }
+            });
+        }
+    }
+
+    fn do_shader_compile_errors(
+        ui: &imgui::Ui,
+        errors: &[kajiya::backend::pipeline_cache::PipelineCompileError],
+        rust_shader_build_status: &RustShaderBuildStatus,
+    ) {
+        use kajiya::backend::shader_diagnostics::ShaderDiagnosticSeverity;
+
+        // cargo output can be long; the errors are at the end.
+        const MAX_RUST_SHADER_OUTPUT_LINES: usize = 40;
+
+        imgui::Window::new(im_str!("Shader errors"))
+            .position([10.0, 10.0], imgui::Condition::FirstUseEver)
+            .always_auto_resize(true)
+            .build(ui, || {
+                ui.text("Pipelines keep running their last good version until fixed.");
+
+                if let RustShaderBuildStatus::Failed { output } = rust_shader_build_status {
+                    ui.separator();
+                    ui.text("Rust-GPU shader build");
+
+                    let lines: Vec<&str> = output.lines().collect();
+                    let first_line = lines.len().saturating_sub(MAX_RUST_SHADER_OUTPUT_LINES);
+                    for line in &lines[first_line..] {
+                        ui.text_colored([1.0, 0.3, 0.3, 1.0], line);
+                    }
+                }
+
+                for error in errors {
+                    ui.separator();
+                    ui.text(&error.pipeline);
+
+                    if error.diagnostics.is_empty() {
+                        ui.text_colored([1.0, 0.3, 0.3, 1.0], &error.message);
+                    }
+
+                    for diagnostic in &error.diagnostics {
+                        let color = match diagnostic.severity {
+                            ShaderDiagnosticSeverity::Error => [1.0, 0.3, 0.3, 1.0],
+                            ShaderDiagnosticSeverity::Warning => [1.0, 0.8, 0.2, 1.0],
+                            ShaderDiagnosticSeverity::Note => [0.7, 0.7, 0.7, 1.0],
+                        };
+
+                        ui.text_colored(
+                            color,
+                            format!(
+                                "{}:{}:{}: {}",
+                                diagnostic.file,
+                                diagnostic.line,
+                                diagnostic.column,
+                                diagnostic.message
+                            ),
+                        );
+                    }
+                }
+            });
+    }
+
+    fn do_inspector(
+        &mut self,
+        ui: &imgui::Ui,
+        passes: &[kajiya::rg::RgDebugPass],
+        picked_pixel: Option<kajiya::rg::RgDebugPickedPixel>,
+    ) {
+        let locked_pass = self
+            .locked_rg_debug_hook
+            .as_ref()
+            .map(|hook| hook.render_debug_hook.clone());
+
+        if locked_pass.is_some() {
+            if ui.button(im_str!("Stop inspecting"), [0.0, 0.0]) {
+                self.locked_rg_debug_hook = None;
+            }
+
+            let inspected_desc = locked_pass
+                .as_ref()
+                .and_then(|hook| {
+                    passes
+                        .iter()
+                        .find(|pass| hook.name == pass.name && hook.id == pass.idx as u64)
+                })
+                .and_then(|pass| pass.selected_image(&self.rg_debug_view))
+                .map(|image| image.desc);
+
+            let (max_mip_level, max_array_layer) = inspected_desc.map_or((0, 0), |desc| {
+                (desc.mip_levels as u32 - 1, desc.array_layer_count() - 1)
+            });
+
+            self.rg_debug_view.mip_level = self.rg_debug_view.mip_level.min(max_mip_level);
+            self.rg_debug_view.array_layer = self.rg_debug_view.array_layer.min(max_array_layer);
+
+            imgui::Drag::<u32>::new(im_str!("Mip level"))
+                .range(0..=max_mip_level)
+                .build(ui, &mut self.rg_debug_view.mip_level);
+
+            imgui::Drag::<u32>::new(im_str!("Array layer"))
+                .range(0..=max_array_layer)
+                .build(ui, &mut self.rg_debug_view.array_layer);
+
+            for (bit, label) in [im_str!("R"), im_str!("G"), im_str!("B"), im_str!("A")]
+                .iter()
+                .enumerate()
+            {
+                if bit > 0 {
+                    ui.same_line(0.0);
+                }
+
+                let mut enabled = self.rg_debug_view.channel_mask & (1 << bit) != 0;
+                if ui.checkbox(label, &mut enabled) {
+                    self.rg_debug_view.channel_mask ^= 1 << bit;
+                }
+            }
+
+            imgui::Drag::<f32>::new(im_str!("Range min"))
+                .speed(0.01)
+                .build(ui, &mut self.rg_debug_view.range[0]);
+
+            imgui::Drag::<f32>::new(im_str!("Range max"))
+                .speed(0.01)
+                .build(ui, &mut self.rg_debug_view.range[1]);
+
+            ui.checkbox(
+                im_str!("Pick pixel under cursor"),
+                &mut self.rg_debug_pick_under_cursor,
+            );
+
+            if self.rg_debug_pick_under_cursor {
+                match picked_pixel {
+                    Some(kajiya::rg::RgDebugPickedPixel { pixel, value }) => {
+                        let value = match value {
+                            kajiya::rg::RgDebugPixelValue::Float(v) => format!("{:?}", v),
+                            kajiya::rg::RgDebugPixelValue::Uint(v) => format!("{:?}", v),
+                        };
+                        ui.text(format!("[{}, {}]: {}", pixel[0], pixel[1], value));
+                    }
+                    None => ui.text("-"),
+                }
+            }
+
+            ui.separator();
+        }
+
+        for pass in passes {
+            if pass.images.is_empty() {
+                continue;
+            }
+
+            let is_locked_pass = locked_pass.as_ref().map_or(false, |hook| {
+                hook.name == pass.name && hook.id == pass.idx as u64
             });
+
+            let pass_label = imgui::ImString::new(format!("{}##{}", pass.name, pass.idx));
+            let node = match imgui::TreeNode::new(&pass_label)
+                .default_open(is_locked_pass)
+                .push(ui)
+            {
+                Some(node) => node,
+                None => continue,
+            };
+
+            for (image_idx, image) in pass.images.iter().enumerate() {
+                let desc = &image.desc;
+                let label = imgui::ImString::new(format!(
+                    "{} {:?} {}x{}x{} mips:{} {}##{}_{}",
+                    if image.written { "W" } else { "R" },
+                    desc.format,
+                    desc.extent[0],
+                    desc.extent[1],
+                    desc.extent[2],
+                    desc.mip_levels,
+                    image
+                        .created_at
+                        .map(|loc| format!("{}:{}", loc.file(), loc.line()))
+                        .unwrap_or_default(),
+                    pass.idx,
+                    image_idx
+                ));
+
+                let selected = is_locked_pass && self.rg_debug_view.image == Some(image_idx);
+
+                if imgui::Selectable::new(&label)
+                    .selected(selected)
+                    .disabled(!image.inspectable)
+                    .build(ui)
+                {
+                    self.rg_debug_view.image = Some(image_idx);
+                    self.locked_rg_debug_hook = Some(kajiya::rg::GraphDebugHook {
+                        render_debug_hook: kajiya::rg::RenderDebugHook {
+                            name: pass.name.clone(),
+                            id: pass.idx as u64,
+                        },
+                        view: self.rg_debug_view.clone(),
+                    });
+                }
+            }
+
+            node.pop(ui);
         }
     }
+
+    fn do_pass_timings_table(&mut self, ui: &imgui::Ui, timings: &kajiya::rg::RgFrameTimings) {
+        let mut rows: Vec<(usize, &kajiya::rg::RgPassTiming)> =
+            timings.passes.iter().enumerate().collect();
+
+        let sort = self.pass_timings_sort;
+        rows.sort_by(|(a_idx, a), (b_idx, b)| {
+            let ordering = match sort.column {
+                PassTimingsSortColumn::Order => a_idx.cmp(b_idx),
+                PassTimingsSortColumn::Name => a.name.cmp(&b.name),
+                PassTimingsSortColumn::Gpu => a
+                    .gpu_ms
+                    .partial_cmp(&b.gpu_ms)
+                    .unwrap_or(std::cmp::Ordering::Equal),
+                PassTimingsSortColumn::Cpu => a
+                    .cpu_record_ms
+                    .unwrap_or_default()
+                    .partial_cmp(&b.cpu_record_ms.unwrap_or_default())
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            };
+
+            if sort.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        ui.columns(4, im_str!("pass timings"), true);
+
+        // Clicking a header sorts by that column; clicking it again flips the order.
+        for (label, column) in [
+            (im_str!("#"), PassTimingsSortColumn::Order),
+            (im_str!("Pass"), PassTimingsSortColumn::Name),
+            (im_str!("GPU ms"), PassTimingsSortColumn::Gpu),
+            (im_str!("CPU ms"), PassTimingsSortColumn::Cpu),
+        ] {
+            let selected = self.pass_timings_sort.column == column;
+
+            if imgui::Selectable::new(label).selected(selected).build(ui) {
+                if selected {
+                    self.pass_timings_sort.descending = !self.pass_timings_sort.descending;
+                } else {
+                    self.pass_timings_sort.column = column;
+                    // Most expensive passes first by default
+                    self.pass_timings_sort.descending = matches!(
+                        column,
+                        PassTimingsSortColumn::Gpu | PassTimingsSortColumn::Cpu
+                    );
+                }
+            }
+
+            ui.next_column();
+        }
+
+        ui.separator();
+
+        for (idx, pass) in rows {
+            ui.text(format!("{}", idx));
+            ui.next_column();
+            ui.text(&pass.name);
+            ui.next_column();
+            ui.text(format!("{:.3}", pass.gpu_ms));
+            ui.next_column();
+            if let Some(cpu_ms) = pass.cpu_record_ms {
+                ui.text(format!("{:.3}", cpu_ms));
+            } else {
+                ui.text("-");
+            }
+            ui.next_column();
+        }
+
+        ui.columns(1, im_str!(""), false);
+    }
 }
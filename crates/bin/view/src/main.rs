@@ -35,6 +35,8 @@ impl AppState {
             .temporal_upsampling(opt.temporal_upsampling)
             .default_log_level(log::LevelFilter::Info)
             .fullscreen(opt.fullscreen.then_some(FullscreenMode::Exclusive))
+            .shader_pack(opt.shader_pack.clone())
+            .hdr(opt.hdr)
             .build(
                 WindowBuilder::new()
                     .with_title("kajiya")
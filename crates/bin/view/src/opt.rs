@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use kajiya_simple::HdrSwapchainFormat;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -40,4 +41,20 @@ pub struct Opt {
 
     #[structopt(long)]
     pub keymap: Option<PathBuf>,
+
+    /// Load shaders from a pack created by `shader-pack` instead of compiling them
+    #[structopt(long)]
+    pub shader_pack: Option<PathBuf>,
+
+    /// Output HDR if the display supports it: `hdr10` or `scrgb`
+    #[structopt(long, parse(try_from_str = parse_hdr_format))]
+    pub hdr: Option<HdrSwapchainFormat>,
+}
+
+fn parse_hdr_format(s: &str) -> anyhow::Result<HdrSwapchainFormat> {
+    match s {
+        "hdr10" => Ok(HdrSwapchainFormat::Hdr10),
+        "scrgb" => Ok(HdrSwapchainFormat::ScRgb),
+        _ => anyhow::bail!("Unknown HDR format {:?}; expected hdr10 or scrgb", s),
+    }
 }
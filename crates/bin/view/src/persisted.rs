@@ -1,7 +1,13 @@
 use std::path::PathBuf;
 
-use kajiya::world_renderer::InstanceHandle;
-use kajiya_simple::{Affine3A, EulerRot, Mat2, Quat, Vec2, Vec3, Vec3Swizzles};
+use kajiya::{
+    lights::{AreaLightDesc, AreaLightShape},
+    world_renderer::{InstanceFlags, InstanceHandle},
+};
+use kajiya_simple::{
+    Affine3A, DisplayTarget, DisplayTransform, EulerRot, HdrDisplaySettings, Mat2,
+    PostProcessSettings, Quat, Vec2, Vec3, Vec3Swizzles,
+};
 
 use crate::{misc::smoothstep, sequence::Sequence};
 
@@ -136,6 +142,77 @@ pub struct LocalLightsState {
     pub multiplier: f32,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AreaLightShapeKind {
+    Rect,
+    Disk,
+    Sphere,
+    Tube,
+}
+
+impl AreaLightShapeKind {
+    pub const ALL: [Self; 4] = [Self::Rect, Self::Disk, Self::Sphere, Self::Tube];
+}
+
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AreaLightState {
+    pub shape: AreaLightShapeKind,
+    pub position: Vec3,
+    pub rotation_euler_degrees: Vec3,
+    /// Width and height of rects; radius and length of tubes; just the radius otherwise.
+    pub size: Vec2,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub two_sided: bool,
+    pub cast_shadows: bool,
+}
+
+impl Default for AreaLightState {
+    fn default() -> Self {
+        Self {
+            shape: AreaLightShapeKind::Rect,
+            position: Vec3::new(0.0, 2.0, 0.0),
+            rotation_euler_degrees: Vec3::new(90.0, 0.0, 0.0),
+            size: Vec2::ONE,
+            color: Vec3::ONE,
+            intensity: 10.0,
+            two_sided: false,
+            cast_shadows: true,
+        }
+    }
+}
+
+impl AreaLightState {
+    pub fn desc(&self) -> AreaLightDesc {
+        let shape = match self.shape {
+            AreaLightShapeKind::Rect => AreaLightShape::Rect {
+                width: self.size.x,
+                height: self.size.y,
+            },
+            AreaLightShapeKind::Disk => AreaLightShape::Disk {
+                radius: self.size.x,
+            },
+            AreaLightShapeKind::Sphere => AreaLightShape::Sphere {
+                radius: self.size.x,
+            },
+            AreaLightShapeKind::Tube => AreaLightShape::Tube {
+                radius: self.size.x,
+                length: self.size.y,
+            },
+        };
+
+        AreaLightDesc::new(shape, self.position, self.color, self.intensity)
+            .with_rotation(Quat::from_euler(
+                EulerRot::YXZ,
+                self.rotation_euler_degrees.y.to_radians(),
+                self.rotation_euler_degrees.x.to_radians(),
+                self.rotation_euler_degrees.z.to_radians(),
+            ))
+            .with_two_sided(self.two_sided)
+            .with_cast_shadows(self.cast_shadows)
+    }
+}
+
 pub trait ShouldResetPathTracer {
     fn should_reset_path_tracer(&self, _: &Self) -> bool {
         false
@@ -173,6 +250,8 @@ pub struct LightState {
     pub enable_emissive: bool,
     pub sun: SunState,
     pub local_lights: LocalLightsState,
+    #[serde(default)]
+    pub area_lights: Vec<AreaLightState>,
 }
 
 impl Default for LightState {
@@ -188,6 +267,7 @@ impl Default for LightState {
                 distance: 1.5,
                 multiplier: 10.0,
             },
+            area_lights: Vec::new(),
         }
     }
 }
@@ -198,6 +278,7 @@ impl ShouldResetPathTracer for LightState {
             || self.enable_emissive != other.enable_emissive
             || self.sun != other.sun
             || self.local_lights != other.local_lights
+            || self.area_lights != other.area_lights
     }
 }
 
@@ -254,6 +335,133 @@ impl Default for ExposureState {
 
 impl ShouldResetPathTracer for ExposureState {}
 
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DisplayTransformKind {
+    Neutral,
+    Aces,
+    Agx,
+}
+
+impl DisplayTransformKind {
+    pub const ALL: [Self; 3] = [Self::Neutral, Self::Aces, Self::Agx];
+}
+
+impl From<DisplayTransformKind> for DisplayTransform {
+    fn from(kind: DisplayTransformKind) -> Self {
+        match kind {
+            DisplayTransformKind::Neutral => Self::Neutral,
+            DisplayTransformKind::Aces => Self::Aces,
+            DisplayTransformKind::Agx => Self::Agx,
+        }
+    }
+}
+
+/// Targets of SDR swapchains. HDR targets follow the swapchain instead.
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DisplayTargetKind {
+    // PQ used to be selectable without an HDR swapchain
+    #[serde(alias = "Rec2020Pq")]
+    Rec709,
+    DisplayP3,
+}
+
+impl DisplayTargetKind {
+    pub const ALL: [Self; 2] = [Self::Rec709, Self::DisplayP3];
+}
+
+impl From<DisplayTargetKind> for DisplayTarget {
+    fn from(kind: DisplayTargetKind) -> Self {
+        match kind {
+            DisplayTargetKind::Rec709 => Self::Rec709,
+            DisplayTargetKind::DisplayP3 => Self::DisplayP3,
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct DisplayState {
+    pub transform: DisplayTransformKind,
+    pub target: DisplayTargetKind,
+    /// A `.cube` file applied to the display signal
+    pub output_lut: Option<PathBuf>,
+    #[serde(default = "default_paper_white_nits")]
+    pub paper_white_nits: f32,
+    #[serde(default = "default_peak_brightness_nits")]
+    pub peak_brightness_nits: f32,
+}
+
+fn default_paper_white_nits() -> f32 {
+    HdrDisplaySettings::default().paper_white_nits
+}
+
+fn default_peak_brightness_nits() -> f32 {
+    HdrDisplaySettings::default().peak_brightness_nits
+}
+
+impl Default for DisplayState {
+    fn default() -> Self {
+        Self {
+            transform: DisplayTransformKind::Neutral,
+            target: DisplayTargetKind::Rec709,
+            output_lut: None,
+            paper_white_nits: default_paper_white_nits(),
+            peak_brightness_nits: default_peak_brightness_nits(),
+        }
+    }
+}
+
+impl ShouldResetPathTracer for DisplayState {}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PostProcessState {
+    pub bloom_intensity: f32,
+    pub bloom_threshold: f32,
+    pub bloom_radius: f32,
+    pub vignette: f32,
+    pub chromatic_aberration: f32,
+    pub film_grain: f32,
+    pub lens_dirt_intensity: f32,
+    /// An image modulating the bloom and lens flare
+    pub lens_dirt: Option<PathBuf>,
+    pub lens_flare_intensity: f32,
+}
+
+impl Default for PostProcessState {
+    fn default() -> Self {
+        let settings = PostProcessSettings::default();
+
+        Self {
+            bloom_intensity: settings.bloom_intensity,
+            bloom_threshold: settings.bloom_threshold,
+            bloom_radius: settings.bloom_radius,
+            vignette: settings.vignette,
+            chromatic_aberration: settings.chromatic_aberration,
+            film_grain: settings.film_grain,
+            lens_dirt_intensity: settings.lens_dirt_intensity,
+            lens_dirt: None,
+            lens_flare_intensity: settings.lens_flare_intensity,
+        }
+    }
+}
+
+impl From<&PostProcessState> for PostProcessSettings {
+    fn from(state: &PostProcessState) -> Self {
+        Self {
+            bloom_intensity: state.bloom_intensity,
+            bloom_threshold: state.bloom_threshold,
+            bloom_radius: state.bloom_radius,
+            vignette: state.vignette,
+            chromatic_aberration: state.chromatic_aberration,
+            film_grain: state.film_grain,
+            lens_dirt_intensity: state.lens_dirt_intensity,
+            lens_flare_intensity: state.lens_flare_intensity,
+        }
+    }
+}
+
+impl ShouldResetPathTracer for PostProcessState {}
+
 #[derive(Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct SceneElementTransform {
     pub position: Vec3,
@@ -295,6 +503,45 @@ pub struct SceneElement {
 
     pub source: MeshSource,
     pub transform: SceneElementTransform,
+    #[serde(default)]
+    pub flags: SceneElementFlags,
+}
+
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SceneElementFlags {
+    pub visible_to_camera: bool,
+    pub visible_in_indirect: bool,
+    pub cast_shadows: bool,
+    pub double_sided: bool,
+}
+
+impl Default for SceneElementFlags {
+    fn default() -> Self {
+        Self::from(InstanceFlags::default())
+    }
+}
+
+impl From<InstanceFlags> for SceneElementFlags {
+    fn from(flags: InstanceFlags) -> Self {
+        Self {
+            visible_to_camera: flags.visible_to_camera,
+            visible_in_indirect: flags.visible_in_indirect,
+            cast_shadows: flags.cast_shadows,
+            double_sided: flags.double_sided,
+        }
+    }
+}
+
+impl From<SceneElementFlags> for InstanceFlags {
+    fn from(flags: SceneElementFlags) -> Self {
+        Self {
+            visible_to_camera: flags.visible_to_camera,
+            visible_in_indirect: flags.visible_in_indirect,
+            cast_shadows: flags.cast_shadows,
+            double_sided: flags.double_sided,
+        }
+    }
 }
 
 #[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
@@ -316,6 +563,10 @@ pub struct PersistedState {
     pub camera: CameraState,
     pub light: LightState,
     pub exposure: ExposureState,
+    #[serde(default)]
+    pub display: DisplayState,
+    #[serde(default)]
+    pub post: PostProcessState,
     pub movement: MovementState,
     pub sequence: Sequence,
     #[serde(default)]
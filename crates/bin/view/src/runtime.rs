@@ -4,7 +4,8 @@ use anyhow::Context;
 
 use dolly::prelude::*;
 use kajiya::{
-    rg::GraphDebugHook,
+    lights::{AreaLightHandle, LightDesc, LightHandle},
+    rg::{GraphDebugHook, GraphDebugView},
     world_renderer::{AddMeshOptions, MeshHandle, WorldRenderer},
 };
 use kajiya_simple::*;
@@ -41,15 +42,24 @@ pub struct RuntimeState {
 
     pub max_fps: u32,
     pub locked_rg_debug_hook: Option<GraphDebugHook>,
+    pub rg_debug_view: GraphDebugView,
+    pub rg_debug_pick_under_cursor: bool,
     pub grab_cursor_pos: winit::dpi::PhysicalPosition<f64>,
+    pub pass_timings_sort: PassTimingsSort,
 
     pub reset_path_tracer: bool,
 
+    /// Settings for the next poster render; not persisted
+    pub poster: PosterDesc,
+
     pub active_camera_key: Option<usize>,
     sequence_playback_state: SequencePlaybackState,
     pub sequence_playback_speed: f32,
 
     known_meshes: HashMap<PathBuf, MeshHandle>,
+    local_lights: Vec<LightHandle>,
+    // Parallel to `LightState::area_lights`
+    area_lights: Vec<AreaLightHandle>,
 }
 
 enum SequencePlaybackState {
@@ -101,15 +111,26 @@ impl RuntimeState {
 
             max_fps: MAX_FPS_LIMIT,
             locked_rg_debug_hook: None,
+            rg_debug_view: Default::default(),
+            rg_debug_pick_under_cursor: false,
             grab_cursor_pos: Default::default(),
+            pass_timings_sort: Default::default(),
 
             reset_path_tracer: false,
 
+            poster: PosterDesc {
+                extent: [3840, 2160],
+                samples_per_pixel: 256,
+                output_path: "poster.exr".into(),
+            },
+
             active_camera_key: None,
             sequence_playback_state: SequencePlaybackState::NotPlaying,
             sequence_playback_speed: 1.0,
 
             known_meshes: Default::default(),
+            local_lights: Default::default(),
+            area_lights: Default::default(),
         };
 
         // Load meshes that the persisted scene was referring to
@@ -134,6 +155,20 @@ impl RuntimeState {
             }
         }
 
+        if let Some(lut) = persisted.display.output_lut.as_ref() {
+            if let Err(err) = world_renderer.load_output_lut(lut) {
+                log::error!("Failed to load output LUT: {:#}", err);
+                persisted.display.output_lut = None;
+            }
+        }
+
+        if let Some(lens_dirt) = persisted.post.lens_dirt.as_ref() {
+            if let Err(err) = world_renderer.load_lens_dirt(lens_dirt) {
+                log::error!("Failed to load lens dirt: {:#}", err);
+                persisted.post.lens_dirt = None;
+            }
+        }
+
         res
     }
 
@@ -183,6 +218,7 @@ impl RuntimeState {
                 source: MeshSource::File(mesh_path),
                 instance: render_instance,
                 transform,
+                flags: Default::default(),
             });
         }
 
@@ -306,10 +342,11 @@ impl RuntimeState {
                         .sun
                         .controller
                         .view_space_rotate(&ref_frame, delta_x, delta_y);
-                } /*LeftClickEditMode::MoveLocalLights => {
-                      persisted.light.lights.theta += theta_delta;
-                      persisted.light.lights.phi += phi_delta;
-                  }*/
+                }
+                LeftClickEditMode::MoveLocalLights => {
+                    persisted.light.local_lights.theta += delta_x;
+                    persisted.light.local_lights.phi += delta_y;
+                }
             }
         }
 
@@ -365,38 +402,66 @@ impl RuntimeState {
             persisted.light.local_lights.distance *= 0.99;
         }*/
 
-        /*#[allow(clippy::comparison_chain)]
-        if light_instances.len() > state.lights.count as usize {
-            for extra_light in light_instances.drain(state.lights.count as usize..) {
-                ctx.world_renderer.remove_instance(extra_light);
+        let light_count = persisted.light.local_lights.count as usize;
+
+        #[allow(clippy::comparison_chain)]
+        if self.local_lights.len() > light_count {
+            for extra_light in self.local_lights.drain(light_count..) {
+                ctx.world_renderer.remove_light(extra_light);
+            }
+        } else if self.local_lights.len() < light_count {
+            for _ in self.local_lights.len()..light_count {
+                match ctx
+                    .world_renderer
+                    .add_light(LightDesc::point(Vec3::ZERO, Vec3::ONE, 0.0))
+                {
+                    Ok(light) => self.local_lights.push(light),
+                    Err(err) => {
+                        log::error!("{:#}", err);
+                        break;
+                    }
+                }
             }
-        } else if light_instances.len() < state.lights.count as usize {
-            light_instances.extend(
-                (0..(state.lights.count as usize - light_instances.len())).map(|_| {
-                    ctx.world_renderer
-                        .add_instance(light_mesh, Vec3::ZERO, Quat::IDENTITY)
-                }),
-            );
         }
 
-        for (i, inst) in light_instances.iter().enumerate() {
-            let ring_rot = Quat::from_rotation_y(
-                (i as f32) / light_instances.len() as f32 * std::f32::consts::TAU,
-            );
+        let lights = &persisted.light.local_lights;
+        for (i, light) in self.local_lights.iter().enumerate() {
+            let ring_rot =
+                Quat::from_rotation_y((i as f32) / light_count as f32 * std::f32::consts::TAU);
+
+            let rot = Quat::from_euler(EulerRot::YXZ, -lights.theta, -lights.phi, 0.0) * ring_rot;
 
-            let rot =
-                Quat::from_euler(EulerRot::YXZ, -state.lights.theta, -state.lights.phi, 0.0)
-                    * ring_rot;
-            ctx.world_renderer.set_instance_transform(
-                *inst,
-                rot * (Vec3::Z * state.lights.distance) + Vec3::new(0.1, 1.2, 0.0),
-                rot,
+            ctx.world_renderer.set_light_params(
+                *light,
+                LightDesc::point(
+                    rot * (Vec3::Z * lights.distance) + Vec3::new(0.1, 1.2, 0.0),
+                    Vec3::ONE,
+                    lights.multiplier,
+                ),
             );
+        }
 
-            ctx.world_renderer
-                .get_instance_dynamic_parameters_mut(*inst)
-                .emissive_multiplier = state.lights.multiplier;
-        }*/
+        let area_light_count = persisted.light.area_lights.len();
+        if self.area_lights.len() > area_light_count {
+            for extra_light in self.area_lights.drain(area_light_count..) {
+                ctx.world_renderer.remove_area_light(extra_light);
+            }
+        }
+
+        for (i, light) in persisted.light.area_lights.iter().enumerate() {
+            if let Some(handle) = self.area_lights.get(i) {
+                ctx.world_renderer
+                    .set_area_light_params(*handle, light.desc());
+            } else {
+                match ctx.world_renderer.add_area_light(light.desc()) {
+                    Ok(handle) => self.area_lights.push(handle),
+                    Err(err) => {
+                        log::error!("{:#}", err);
+                        break;
+                    }
+                }
+            }
+        }
     }
 
     fn update_objects(&mut self, persisted: &mut PersistedState, ctx: &mut FrameContext) {
@@ -412,6 +477,8 @@ impl RuntimeState {
                 .emissive_multiplier = persisted.light.emissive_multiplier * emissive_toggle_mult;
             ctx.world_renderer
                 .set_instance_transform(elem.instance, elem.transform.affine_transform());
+            ctx.world_renderer
+                .set_instance_flags(elem.instance, elem.flags.into());
         }
     }
 
@@ -465,6 +532,13 @@ impl RuntimeState {
 
         ctx.world_renderer.ev_shift = persisted.exposure.ev_shift;
         ctx.world_renderer.contrast = persisted.exposure.contrast;
+        ctx.world_renderer.display_transform = persisted.display.transform.into();
+        ctx.world_renderer.display_target = persisted.display.target.into();
+        ctx.world_renderer.post_settings = (&persisted.post).into();
+        ctx.world_renderer.hdr_display = HdrDisplaySettings {
+            paper_white_nits: persisted.display.paper_white_nits,
+            peak_brightness_nits: persisted.display.peak_brightness_nits,
+        };
         ctx.world_renderer.dynamic_exposure.enabled = persisted.exposure.use_dynamic_adaptation;
         ctx.world_renderer.dynamic_exposure.speed_log2 =
             persisted.exposure.dynamic_adaptation_speed;
@@ -659,6 +733,7 @@ impl RuntimeState {
             source,
             instance: inst,
             transform,
+            flags: Default::default(),
         });
 
         Ok(())
@@ -692,6 +767,28 @@ impl RuntimeState {
                                 }
                             }
                         }
+                        "cube" => {
+                            // Output LUT
+                            match world_renderer.load_output_lut(path) {
+                                Ok(_) => {
+                                    persisted.display.output_lut = Some(path.clone());
+                                }
+                                Err(err) => {
+                                    log::error!("{:#}", err);
+                                }
+                            }
+                        }
+                        "png" | "jpg" | "jpeg" => {
+                            // Lens dirt
+                            match world_renderer.load_lens_dirt(path) {
+                                Ok(_) => {
+                                    persisted.post.lens_dirt = Some(path.clone());
+                                }
+                                Err(err) => {
+                                    log::error!("{:#}", err);
+                                }
+                            }
+                        }
                         "ron" => {
                             // Scene
                             if let Err(err) = self.load_scene(persisted, world_renderer, path) {
@@ -721,5 +818,28 @@ impl RuntimeState {
 #[derive(PartialEq, Eq)]
 pub enum LeftClickEditMode {
     MoveSun,
-    //MoveLocalLights,
+    MoveLocalLights,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum PassTimingsSortColumn {
+    Order,
+    Name,
+    Gpu,
+    Cpu,
+}
+
+#[derive(Clone, Copy)]
+pub struct PassTimingsSort {
+    pub column: PassTimingsSortColumn,
+    pub descending: bool,
+}
+
+impl Default for PassTimingsSort {
+    fn default() -> Self {
+        Self {
+            column: PassTimingsSortColumn::Order,
+            descending: false,
+        }
+    }
 }
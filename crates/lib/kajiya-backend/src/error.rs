@@ -13,6 +13,9 @@ pub enum BackendError {
 
     #[error("Invalid resource access: {info:?}")]
     ResourceAccess { info: String },
+
+    #[error("Resources bound do not match the shader: {info}")]
+    ShaderBindingMismatch { info: String },
 }
 
 impl From<ash::vk::Result> for BackendError {
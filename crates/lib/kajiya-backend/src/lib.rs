@@ -5,7 +5,11 @@ mod error;
 pub mod file;
 pub mod pipeline_cache;
 pub mod rust_shader_compiler;
+pub mod shader_cache;
 pub mod shader_compiler;
+pub mod shader_diagnostics;
+pub mod shader_pack;
+pub mod shader_reflection;
 pub mod transient_resource_cache;
 pub mod vulkan;
 
@@ -16,4 +20,6 @@ pub use gpu_allocator;
 pub use gpu_profiler;
 pub use rspirv_reflect;
 pub use vk_sync;
-pub use vulkan::{device::Device, image::*, shader::MAX_DESCRIPTOR_SETS, RenderBackend};
+pub use vulkan::{
+    device::Device, image::*, shader::MAX_DESCRIPTOR_SETS, HdrSwapchainFormat, RenderBackend,
+};
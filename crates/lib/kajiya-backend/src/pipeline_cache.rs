@@ -1,11 +1,14 @@
 use crate::{
     rust_shader_compiler::CompileRustShader,
     shader_compiler::{CompileShader, CompiledShader},
+    shader_diagnostics::{ShaderCompileError, ShaderDiagnostic},
+    shader_pack::ShaderPack,
     vulkan::{
         ray_tracing::{create_ray_tracing_pipeline, RayTracingPipeline, RayTracingPipelineDesc},
         shader::*,
     },
 };
+use bytes::Bytes;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use std::{collections::HashMap, sync::Arc};
@@ -18,6 +21,7 @@ struct ComputePipelineCacheEntry {
     lazy_handle: Lazy<CompiledShader>,
     desc: ComputePipelineDesc,
     pipeline: Option<Arc<ComputePipeline>>,
+    compile_error: Option<PipelineCompileError>,
 }
 
 #[derive(Clone, Copy, Hash, Eq, PartialEq)]
@@ -49,13 +53,8 @@ impl LazyWorker for CompilePipelineShaders {
                 .eval(&ctx),
                 ShaderSource::Hlsl { path } => CompileShader {
                     path: path.clone(),
-                    profile: match desc.stage {
-                        ShaderPipelineStage::Vertex => "vs".to_owned(),
-                        ShaderPipelineStage::Pixel => "ps".to_owned(),
-                        ShaderPipelineStage::RayGen
-                        | ShaderPipelineStage::RayMiss
-                        | ShaderPipelineStage::RayClosestHit => "lib".to_owned(),
-                    },
+                    profile: hlsl_profile(desc.stage).to_owned(),
+                    defines: desc.defines.clone(),
                 }
                 .into_lazy()
                 .eval(&ctx),
@@ -76,16 +75,87 @@ impl LazyWorker for CompilePipelineShaders {
     }
 }
 
+fn hlsl_profile(stage: ShaderPipelineStage) -> &'static str {
+    match stage {
+        ShaderPipelineStage::Vertex => "vs",
+        ShaderPipelineStage::Pixel => "ps",
+        ShaderPipelineStage::RayGen
+        | ShaderPipelineStage::RayMiss
+        | ShaderPipelineStage::RayClosestHit => "lib",
+        ShaderPipelineStage::Task => "as",
+        ShaderPipelineStage::Mesh => "ms",
+    }
+}
+
+// Takes the pack rather than `&mut self` so that it can be called while a cache entry is borrowed
+fn record_shader(
+    recorded_shader_pack: &mut Option<ShaderPack>,
+    source: &ShaderSource,
+    profile: &str,
+    defines: &[ShaderDefine],
+    spirv: &Bytes,
+) {
+    if let (Some(pack), ShaderSource::Hlsl { path }) = (recorded_shader_pack, source) {
+        pack.insert(ShaderPack::key(path, profile, defines), spirv.clone());
+    }
+}
+
 struct RasterPipelineCacheEntry {
     lazy_handle: Lazy<CompiledPipelineShaders>,
     desc: RasterPipelineDesc,
     pipeline: Option<Arc<RasterPipeline>>,
+    shader_descs: Vec<PipelineShaderDesc>,
+    compile_error: Option<PipelineCompileError>,
 }
 
 struct RtPipelineCacheEntry {
     lazy_handle: Lazy<CompiledPipelineShaders>,
     desc: RayTracingPipelineDesc,
     pipeline: Option<Arc<RayTracingPipeline>>,
+    shader_descs: Vec<PipelineShaderDesc>,
+    compile_error: Option<PipelineCompileError>,
+}
+
+/// The most recent failure to compile a pipeline. If the pipeline compiled successfully before,
+/// the last good version keeps being used until the error is fixed.
+#[derive(Clone, Debug)]
+pub struct PipelineCompileError {
+    /// Shader sources of the pipeline
+    pub pipeline: String,
+    pub message: String,
+    /// Compiler messages mapped to the original source files, if available
+    pub diagnostics: Vec<ShaderDiagnostic>,
+}
+
+impl PipelineCompileError {
+    fn new(pipeline: String, err: &anyhow::Error) -> Self {
+        let diagnostics = err
+            .chain()
+            .find_map(|err| err.downcast_ref::<ShaderCompileError>())
+            .map(|err| err.diagnostics.clone())
+            .unwrap_or_default();
+
+        Self {
+            pipeline,
+            message: format!("{:?}", err),
+            diagnostics,
+        }
+    }
+}
+
+fn describe_shaders(shaders: &[PipelineShaderDesc]) -> String {
+    shaders
+        .iter()
+        .map(|shader| describe_shader_source(&shader.source))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_shader_source(source: &ShaderSource) -> String {
+    match source {
+        ShaderSource::Rust { entry } => entry.clone(),
+        ShaderSource::Hlsl { path } => path.to_string_lossy().into_owned(),
+    }
 }
 
 pub struct PipelineCache {
@@ -95,9 +165,11 @@ pub struct PipelineCache {
     raster_entries: HashMap<RasterPipelineHandle, RasterPipelineCacheEntry>,
     rt_entries: HashMap<RtPipelineHandle, RtPipelineCacheEntry>,
 
-    compute_shader_to_handle: HashMap<ShaderSource, ComputePipelineHandle>,
+    compute_shader_to_handle: HashMap<(ShaderSource, Vec<ShaderDefine>), ComputePipelineHandle>,
     raster_shaders_to_handle: HashMap<Vec<PipelineShaderDesc>, RasterPipelineHandle>,
     rt_shaders_to_handle: HashMap<Vec<PipelineShaderDesc>, RtPipelineHandle>,
+
+    recorded_shader_pack: Option<ShaderPack>,
 }
 
 impl PipelineCache {
@@ -113,12 +185,28 @@ impl PipelineCache {
 
             raster_shaders_to_handle: Default::default(),
             rt_shaders_to_handle: Default::default(),
+
+            recorded_shader_pack: None,
         }
     }
 
+    /// Starts collecting the SPIR-V of all HLSL shaders compiled from now on,
+    /// for offline precompilation. See `take_recorded_shader_pack`.
+    pub fn record_shader_pack(&mut self) {
+        self.recorded_shader_pack
+            .get_or_insert_with(Default::default);
+    }
+
+    pub fn take_recorded_shader_pack(&mut self) -> Option<ShaderPack> {
+        self.recorded_shader_pack.take()
+    }
+
     // TODO: should probably use the `desc` as key as well
     pub fn register_compute(&mut self, desc: &ComputePipelineDesc) -> ComputePipelineHandle {
-        match self.compute_shader_to_handle.entry(desc.source.clone()) {
+        match self
+            .compute_shader_to_handle
+            .entry((desc.source.clone(), desc.defines.clone()))
+        {
             std::collections::hash_map::Entry::Occupied(occupied) => *occupied.get(),
             std::collections::hash_map::Entry::Vacant(vacant) => {
                 let handle = ComputePipelineHandle(self.compute_entries.len());
@@ -130,6 +218,7 @@ impl PipelineCache {
                     ShaderSource::Hlsl { path } => CompileShader {
                         path: path.clone(),
                         profile: "cs".to_owned(),
+                        defines: desc.defines.clone(),
                     }
                     .into_lazy(),
                 };
@@ -140,6 +229,7 @@ impl PipelineCache {
                         lazy_handle: compile_task,
                         desc: desc.clone(),
                         pipeline: None,
+                        compile_error: None,
                     },
                 );
                 vacant.insert(handle);
@@ -178,6 +268,8 @@ impl PipelineCache {
                 .into_lazy(),
                 desc: desc.clone(),
                 pipeline: None,
+                shader_descs: shaders.to_vec(),
+                compile_error: None,
             },
         );
         handle
@@ -212,6 +304,8 @@ impl PipelineCache {
                 .into_lazy(),
                 desc: desc.clone(),
                 pipeline: None,
+                shader_descs: shaders.to_vec(),
+                compile_error: None,
             },
         );
         handle
@@ -226,27 +320,26 @@ impl PipelineCache {
             .unwrap()
     }
 
-    fn invalidate_stale_pipelines(&mut self) {
-        for entry in self.compute_entries.values_mut() {
-            if entry.pipeline.is_some() && entry.lazy_handle.is_stale() {
-                // TODO: release
-                entry.pipeline = None;
-            }
-        }
-
-        for entry in self.raster_entries.values_mut() {
-            if entry.pipeline.is_some() && entry.lazy_handle.is_stale() {
-                // TODO: release
-                entry.pipeline = None;
-            }
-        }
+    /// Pipelines which failed to compile the last time they were built.
+    pub fn compile_errors(&self) -> Vec<PipelineCompileError> {
+        let mut errors: Vec<PipelineCompileError> = self
+            .compute_entries
+            .values()
+            .filter_map(|entry| entry.compile_error.clone())
+            .chain(
+                self.raster_entries
+                    .values()
+                    .filter_map(|entry| entry.compile_error.clone()),
+            )
+            .chain(
+                self.rt_entries
+                    .values()
+                    .filter_map(|entry| entry.compile_error.clone()),
+            )
+            .collect();
 
-        for entry in self.rt_entries.values_mut() {
-            if entry.pipeline.is_some() && entry.lazy_handle.is_stale() {
-                // TODO: release
-                entry.pipeline = None;
-            }
-        }
+        errors.sort_by(|a, b| a.pipeline.cmp(&b.pipeline));
+        errors
     }
 
     pub fn parallel_compile_shaders(
@@ -255,33 +348,48 @@ impl PipelineCache {
     ) -> anyhow::Result<()> {
         // Prepare build tasks for compute
         let compute = self.compute_entries.iter().filter_map(|(&handle, entry)| {
-            entry.pipeline.is_none().then(|| {
+            // Stale pipelines keep being used until they are successfully rebuilt
+            (entry.pipeline.is_none()
+                || entry.compile_error.is_some()
+                || entry.lazy_handle.is_stale())
+            .then(|| {
                 let task = entry.lazy_handle.eval(&self.lazy_cache);
                 smol::spawn(async move {
                     task.await
                         .map(|compiled| CompileTaskOutput::Compute { handle, compiled })
+                        .map_err(|err| (AnyPipelineHandle::Compute(handle), err))
                 })
             })
         });
 
         // Prepare build tasks for raster
         let raster = self.raster_entries.iter().filter_map(|(&handle, entry)| {
-            entry.pipeline.is_none().then(|| {
+            // Stale pipelines keep being used until they are successfully rebuilt
+            (entry.pipeline.is_none()
+                || entry.compile_error.is_some()
+                || entry.lazy_handle.is_stale())
+            .then(|| {
                 let task = entry.lazy_handle.eval(&self.lazy_cache);
                 smol::spawn(async move {
                     task.await
                         .map(|compiled| CompileTaskOutput::Raster { handle, compiled })
+                        .map_err(|err| (AnyPipelineHandle::Raster(handle), err))
                 })
             })
         });
 
         // Prepare build tasks for rt
         let rt = self.rt_entries.iter().filter_map(|(&handle, entry)| {
-            entry.pipeline.is_none().then(|| {
+            // Stale pipelines keep being used until they are successfully rebuilt
+            (entry.pipeline.is_none()
+                || entry.compile_error.is_some()
+                || entry.lazy_handle.is_stale())
+            .then(|| {
                 let task = entry.lazy_handle.eval(&self.lazy_cache);
                 smol::spawn(async move {
                     task.await
                         .map(|compiled| CompileTaskOutput::Rt { handle, compiled })
+                        .map_err(|err| (AnyPipelineHandle::Rt(handle), err))
                 })
             })
         });
@@ -291,14 +399,30 @@ impl PipelineCache {
 
         if !shader_tasks.is_empty() {
             // Compile all the things
-            let compiled: Vec<CompileTaskOutput> =
-                smol::block_on(futures::future::try_join_all(shader_tasks))?;
+            let results = smol::block_on(futures::future::join_all(shader_tasks));
 
             // Build pipelines from all compiled shaders
-            for compiled in compiled {
+            for result in results {
+                let compiled = match result {
+                    Ok(compiled) => compiled,
+                    Err((handle, err)) => {
+                        self.set_compile_error(handle, &err);
+                        continue;
+                    }
+                };
+
                 match compiled {
                     CompileTaskOutput::Compute { handle, compiled } => {
                         let entry = self.compute_entries.get_mut(&handle).unwrap();
+
+                        record_shader(
+                            &mut self.recorded_shader_pack,
+                            &entry.desc.source,
+                            "cs",
+                            &entry.desc.defines,
+                            &compiled.spirv,
+                        );
+
                         log::trace!(
                             "Creating compute pipeline {:?}:{:?}",
                             compiled.name,
@@ -309,8 +433,19 @@ impl PipelineCache {
                             &compiled.spirv,
                             &entry.desc,
                         )));
+                        entry.compile_error = None;
                     }
                     CompileTaskOutput::Raster { handle, compiled } => {
+                        for shader in &compiled.shaders {
+                            record_shader(
+                                &mut self.recorded_shader_pack,
+                                &shader.desc.source,
+                                hlsl_profile(shader.desc.stage),
+                                &shader.desc.defines,
+                                &shader.code.spirv,
+                            );
+                        }
+
                         let entry = self.raster_entries.get_mut(&handle).unwrap();
                         log::trace!(
                             "Creating raster pipeline {}",
@@ -339,8 +474,19 @@ impl PipelineCache {
                             create_raster_pipeline(device.as_ref(), &compiled_shaders, &entry.desc)
                                 .expect("create_raster_pipeline"),
                         ));
+                        entry.compile_error = None;
                     }
                     CompileTaskOutput::Rt { handle, compiled } => {
+                        for shader in &compiled.shaders {
+                            record_shader(
+                                &mut self.recorded_shader_pack,
+                                &shader.desc.source,
+                                hlsl_profile(shader.desc.stage),
+                                &shader.desc.defines,
+                                &shader.code.spirv,
+                            );
+                        }
+
                         let entry = self.rt_entries.get_mut(&handle).unwrap();
                         log::trace!(
                             "Creating rt pipeline {}",
@@ -373,25 +519,91 @@ impl PipelineCache {
                             )
                             .expect("create_ray_tracing_pipeline"),
                         ));
+                        entry.compile_error = None;
                     }
                 }
             }
         }
 
+        // Pipelines which have never compiled can't be used in the frame
+        let missing_pipeline_error = self
+            .compute_entries
+            .values()
+            .filter(|entry| entry.pipeline.is_none())
+            .filter_map(|entry| entry.compile_error.as_ref())
+            .chain(
+                self.raster_entries
+                    .values()
+                    .filter(|entry| entry.pipeline.is_none())
+                    .filter_map(|entry| entry.compile_error.as_ref()),
+            )
+            .chain(
+                self.rt_entries
+                    .values()
+                    .filter(|entry| entry.pipeline.is_none())
+                    .filter_map(|entry| entry.compile_error.as_ref()),
+            )
+            .next();
+
+        if let Some(error) = missing_pipeline_error {
+            anyhow::bail!("{}", error.message);
+        }
+
         Ok(())
     }
 
+    fn set_compile_error(&mut self, handle: AnyPipelineHandle, err: &anyhow::Error) {
+        let (pipeline, compile_error) = match handle {
+            AnyPipelineHandle::Compute(handle) => {
+                let entry = self.compute_entries.get_mut(&handle).unwrap();
+                (
+                    describe_shader_source(&entry.desc.source),
+                    &mut entry.compile_error,
+                )
+            }
+            AnyPipelineHandle::Raster(handle) => {
+                let entry = self.raster_entries.get_mut(&handle).unwrap();
+                (
+                    describe_shaders(&entry.shader_descs),
+                    &mut entry.compile_error,
+                )
+            }
+            AnyPipelineHandle::Rt(handle) => {
+                let entry = self.rt_entries.get_mut(&handle).unwrap();
+                (
+                    describe_shaders(&entry.shader_descs),
+                    &mut entry.compile_error,
+                )
+            }
+        };
+
+        let error = PipelineCompileError::new(pipeline, err);
+
+        // Failed pipelines are retried every frame; only report new errors.
+        if compile_error.as_ref().map(|prev| &prev.message) != Some(&error.message) {
+            log::error!("{}", error.message);
+        }
+
+        *compile_error = Some(error);
+    }
+
     pub fn prepare_frame(
         &mut self,
         device: &Arc<crate::vulkan::device::Device>,
     ) -> anyhow::Result<()> {
-        self.invalidate_stale_pipelines();
         self.parallel_compile_shaders(device)?;
 
         Ok(())
     }
 }
 
+#[derive(Clone, Copy)]
+enum AnyPipelineHandle {
+    Compute(ComputePipelineHandle),
+    Raster(RasterPipelineHandle),
+    Rt(RtPipelineHandle),
+}
+
 enum CompileTaskOutput {
     Compute {
         handle: ComputePipelineHandle,
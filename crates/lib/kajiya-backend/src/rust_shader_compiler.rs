@@ -1,5 +1,6 @@
-use crate::{file::LoadFile, normalized_path_from_vfs, shader_compiler::CompiledShader};
+use crate::{canonical_path_from_vfs, normalized_path_from_vfs, shader_compiler::CompiledShader};
 use anyhow::{Context, Result};
+use bytes::Bytes;
 use nanoserde::DeJson;
 use parking_lot::Mutex;
 use std::process::Command;
@@ -17,40 +18,116 @@ impl LazyWorker for CompileRustShader {
     async fn run(self, ctx: RunContext) -> Self::Output {
         CompileRustShaderCrate.into_lazy().eval(&ctx).await?;
 
-        let compile_result = LoadFile::new("/rust-shaders-compiled/shaders.json")?
-            .into_lazy()
-            .eval(&ctx)
-            .await?;
+        let modules = LoadRustShaderModules.into_lazy().eval(&ctx).await?;
 
-        let compile_result =
-            RustShaderCompileResult::deserialize_json(std::str::from_utf8(&compile_result)?)?;
-
-        let shader_file = compile_result
-            .entry_to_shader_module
-            .into_iter()
-            .find_map(|(entry, module)| {
-                if entry == self.entry {
-                    Some(module)
-                } else {
-                    None
-                }
-            })
+        let spirv = modules
+            .iter()
+            .find_map(|(entry, spirv)| (*entry == self.entry).then(|| spirv.clone()))
             .ok_or_else(|| {
                 anyhow::anyhow!("No Rust-GPU module found for entry point {}", self.entry)
             })?;
 
-        let spirv_blob = LoadFile::new(format!("/rust-shaders-compiled/{}", shader_file))?
-            .into_lazy()
-            .eval(&ctx)
-            .await?;
-
         Ok(CompiledShader {
             name: "rust-gpu".to_owned(),
-            spirv: (*spirv_blob).clone(),
+            spirv,
         })
     }
 }
 
+/// Loads all the modules listed in `shaders.json`, and invalidates when any of them are replaced.
+///
+/// The shader builder moves new modules over the old ones, which `LoadFile` can't follow,
+/// as file watches are lost when the file is replaced. The whole directory is watched instead.
+#[derive(Clone, Hash)]
+struct LoadRustShaderModules;
+
+#[async_trait]
+impl LazyWorker for LoadRustShaderModules {
+    // entry name -> SPIR-V
+    type Output = Result<Vec<(String, Bytes)>>;
+
+    async fn run(self, ctx: RunContext) -> Self::Output {
+        let compiled_dir = canonical_path_from_vfs("/rust-shaders-compiled")?;
+
+        let invalidation_trigger = ctx.get_invalidation_trigger();
+        crate::file::FILE_WATCHER
+            .lock()
+            .watch(compiled_dir.clone(), move |event| {
+                if is_modification_event(&event) {
+                    invalidation_trigger();
+                }
+            })
+            .with_context(|| {
+                format!("LoadRustShaderModules: trying to watch {:?}", compiled_dir)
+            })?;
+
+        let compile_result = std::fs::read_to_string(compiled_dir.join("shaders.json"))
+            .context("Reading shaders.json")?;
+        let compile_result = RustShaderCompileResult::deserialize_json(&compile_result)?;
+
+        compile_result
+            .entry_to_shader_module
+            .into_iter()
+            .map(|(entry, module)| {
+                let spirv = std::fs::read(compiled_dir.join(&module))
+                    .with_context(|| format!("Reading Rust-GPU module {}", module))?;
+                Ok((entry, Bytes::from(spirv)))
+            })
+            .collect()
+    }
+}
+
+// Editors and the shader builder often replace files instead of writing to them.
+fn is_modification_event(event: &hotwatch::Event) -> bool {
+    matches!(
+        event,
+        hotwatch::Event::Write(_)
+            | hotwatch::Event::Create(_)
+            | hotwatch::Event::Rename(_, _)
+            | hotwatch::Event::Remove(_)
+    )
+}
+
+/// State of the background Rust-GPU shader build, for display in the UI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RustShaderBuildStatus {
+    /// No build has been started, or the shader sources were not found
+    Idle,
+    Building,
+    Succeeded,
+    /// The previously compiled shaders remain in use
+    Failed {
+        output: String,
+    },
+}
+
+lazy_static::lazy_static! {
+    // Along with the generation of the build it belongs to
+    static ref RUST_SHADER_BUILD_STATUS: Mutex<(u64, RustShaderBuildStatus)> =
+        Mutex::new((0, RustShaderBuildStatus::Idle));
+}
+
+pub fn rust_shader_build_status() -> RustShaderBuildStatus {
+    RUST_SHADER_BUILD_STATUS.lock().1.clone()
+}
+
+// Returns the generation of the new build
+fn begin_rust_shader_build() -> u64 {
+    let mut status = RUST_SHADER_BUILD_STATUS.lock();
+    status.0 += 1;
+    status.1 = RustShaderBuildStatus::Building;
+    status.0
+}
+
+// Ignored if a newer build has started since, as that one owns the status. Cancellation
+// races with completion, so a superseded build may still finish after its replacement begins.
+fn set_rust_shader_build_status(generation: u64, new_status: RustShaderBuildStatus) {
+    let mut status = RUST_SHADER_BUILD_STATUS.lock();
+    if status.0 == generation {
+        status.1 = new_status;
+    }
+}
+
 #[derive(DeJson)]
 struct RustShaderCompileResult {
     // entry name -> shader path
@@ -104,12 +181,27 @@ impl LazyWorker for CompileRustShaderCrate {
             let _ = cancel.send(());
         }
 
+        let generation = begin_rust_shader_build();
+
         // Spawn the worker thread.
         std::thread::spawn(move || -> anyhow::Result<()> {
             log::info!("Building Rust-GPU shaders in the background...");
 
-            if let Err(err) = compile_rust_shader_crate_thread(cancel_rx) {
-                log::error!("Failed to build Rust-GPU shaders. Falling back to the previously compiled ones. Error: {:?}", err);
+            match compile_rust_shader_crate_thread(cancel_rx) {
+                Ok(BuildOutcome::Finished) => {
+                    set_rust_shader_build_status(generation, RustShaderBuildStatus::Succeeded);
+                }
+                // A newer build has replaced this one, and owns the status
+                Ok(BuildOutcome::Cancelled) => {}
+                Err(err) => {
+                    log::error!("Failed to build Rust-GPU shaders. Falling back to the previously compiled ones. Error: {:?}", err);
+                    set_rust_shader_build_status(
+                        generation,
+                        RustShaderBuildStatus::Failed {
+                            output: format!("{:?}", err),
+                        },
+                    );
+                }
             }
 
             Ok(())
@@ -121,7 +213,7 @@ impl LazyWorker for CompileRustShaderCrate {
             crate::file::FILE_WATCHER
                 .lock()
                 .watch(src_dir.clone(), move |event| {
-                    if matches!(event, hotwatch::Event::Write(_)) {
+                    if is_modification_event(&event) {
                         invalidation_trigger();
                     }
                 })
@@ -134,10 +226,15 @@ impl LazyWorker for CompileRustShaderCrate {
     }
 }
 
+enum BuildOutcome {
+    Finished,
+    Cancelled,
+}
+
 // Runs cargo in a sub-process to execute the rust shader builder.
 fn compile_rust_shader_crate_thread(
     cancel_rx: std::sync::mpsc::Receiver<()>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<BuildOutcome> {
     let builder_dir = normalized_path_from_vfs("/kajiya/crates/bin/rust-shader-builder")?;
 
     let mut child = Command::new("cargo")
@@ -171,7 +268,10 @@ fn compile_rust_shader_crate_thread(
 
         if should_bail {
             log::info!("Rust-GPU shader builder thread received a stop command.");
-            return child.kill().context("killing the Rust-GPU shader builder");
+            child
+                .kill()
+                .context("killing the Rust-GPU shader builder")?;
+            return Ok(BuildOutcome::Cancelled);
         }
 
         match child.try_wait() {
@@ -195,5 +295,5 @@ fn compile_rust_shader_crate_thread(
         log::info!("Rust-GPU cargo process finished.");
     }
 
-    Ok(())
+    Ok(BuildOutcome::Finished)
 }
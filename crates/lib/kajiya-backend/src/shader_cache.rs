@@ -0,0 +1,170 @@
+use crate::file::normalized_path_from_vfs;
+use bytes::Bytes;
+use com_rs::ComPtr;
+use lazy_static::lazy_static;
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::CStr,
+    hash::{Hash, Hasher},
+    os::raw::c_char,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Bump when the layout of cached files or the key changes
+const SHADER_CACHE_VERSION: u32 = 2;
+
+static SHADER_DISK_CACHE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+lazy_static! {
+    // Compiler upgrades need to invalidate the cache. `None` if the version of the compiler
+    // can't be determined, in which case the disk cache is not used.
+    static ref DXC_VERSION: Option<String> = match dxc_version() {
+        Ok(version) => {
+            log::info!("Using dxc {}", version);
+            Some(version)
+        }
+        Err(err) => {
+            log::warn!(
+                "Could not determine the dxc version; not using the shader disk cache: {:#}",
+                err
+            );
+            None
+        }
+    };
+}
+
+// The path `hassle_rs::compile_hlsl` loads dxcompiler from
+#[cfg(target_os = "windows")]
+const DXCOMPILER_LIB_PATH: &str = "dxcompiler.dll";
+
+#[cfg(target_os = "macos")]
+const DXCOMPILER_LIB_PATH: &str = "./libdxcompiler.dylib";
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const DXCOMPILER_LIB_PATH: &str = "./libdxcompiler.so";
+
+// Asks the compiler which `hassle_rs::compile_hlsl` uses for its version. The library is
+// reference-counted by the OS, so this resolves to the same one.
+fn dxc_version() -> anyhow::Result<String> {
+    use hassle_rs::{
+        CLSID_DxcCompiler, DxcCreateInstanceProc, IDxcVersionInfo, IDxcVersionInfo2,
+        IID_IDxcVersionInfo, IID_IDxcVersionInfo2,
+    };
+
+    unsafe {
+        let dxc = libloading::Library::new(DXCOMPILER_LIB_PATH)?;
+        let create_instance: libloading::Symbol<DxcCreateInstanceProc> =
+            dxc.get(b"DxcCreateInstance\0")?;
+
+        let mut version_info: ComPtr<IDxcVersionInfo> = ComPtr::new();
+        create_instance(
+            &CLSID_DxcCompiler,
+            &IID_IDxcVersionInfo,
+            version_info.as_mut_ptr(),
+        )
+        .result()?;
+
+        let (mut major, mut minor) = (0, 0);
+        version_info.get_version(&mut major, &mut minor).result()?;
+
+        // Releases of the same major and minor version are told apart by the commit
+        let mut commit_info: ComPtr<IDxcVersionInfo2> = ComPtr::new();
+        let commit = if create_instance(
+            &CLSID_DxcCompiler,
+            &IID_IDxcVersionInfo2,
+            commit_info.as_mut_ptr(),
+        )
+        .is_err()
+        {
+            None
+        } else {
+            let mut commit_count = 0;
+            let mut commit_hash: *mut u8 = std::ptr::null_mut();
+            commit_info
+                .get_commit_info(&mut commit_count, &mut commit_hash)
+                .result()?;
+
+            // The string is allocated with `CoTaskMemAlloc`, and leaked as this only runs once
+            Some(format!(
+                "{} ({})",
+                commit_count,
+                CStr::from_ptr(commit_hash as *const c_char).to_string_lossy()
+            ))
+        };
+
+        Ok(match commit {
+            Some(commit) => format!("{}.{}, commit {}", major, minor, commit),
+            None => format!("{}.{}", major, minor),
+        })
+    }
+}
+
+/// Enables or disables reading and writing compiled SPIR-V in `/cache/shaders`.
+/// Enabled by default, but only used if the version of dxc can be determined.
+pub fn set_shader_disk_cache_enabled(enabled: bool) {
+    SHADER_DISK_CACHE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Identifies the output of a single dxc invocation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct ShaderCacheKey(u64);
+
+impl ShaderCacheKey {
+    pub(crate) fn new(
+        preprocessed_source: &str,
+        target_profile: &str,
+        args: &[&str],
+        defines: &[(&str, Option<&str>)],
+    ) -> Self {
+        // Not stable across Rust releases, which only results in cache misses.
+        let mut hasher = DefaultHasher::new();
+        SHADER_CACHE_VERSION.hash(&mut hasher);
+        DXC_VERSION.hash(&mut hasher);
+        preprocessed_source.hash(&mut hasher);
+        target_profile.hash(&mut hasher);
+        args.hash(&mut hasher);
+        defines.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+
+    fn path(self) -> anyhow::Result<PathBuf> {
+        normalized_path_from_vfs(format!("/cache/shaders/{:016x}.spv", self.0))
+    }
+}
+
+fn shader_disk_cache_enabled() -> bool {
+    SHADER_DISK_CACHE_ENABLED.load(Ordering::Relaxed) && DXC_VERSION.is_some()
+}
+
+pub(crate) fn load_cached_spirv(key: ShaderCacheKey) -> Option<Bytes> {
+    if !shader_disk_cache_enabled() {
+        return None;
+    }
+
+    let spirv = std::fs::read(key.path().ok()?).ok()?;
+
+    // Discard truncated files
+    (!spirv.is_empty() && spirv.len() % 4 == 0).then(|| spirv.into())
+}
+
+pub(crate) fn store_cached_spirv(key: ShaderCacheKey, spirv: &[u8]) {
+    if !shader_disk_cache_enabled() {
+        return;
+    }
+
+    let res = (|| -> anyhow::Result<()> {
+        let path = key.path()?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+
+        // Write to a temporary file first, so that concurrent readers never see partial data
+        let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+        std::fs::write(&tmp_path, spirv)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    })();
+
+    if let Err(err) = res {
+        log::warn!("Failed to write the shader cache: {:#}", err);
+    }
+}
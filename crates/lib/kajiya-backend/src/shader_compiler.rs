@@ -1,4 +1,10 @@
-use crate::file::LoadFile;
+use crate::{
+    file::LoadFile,
+    shader_cache::{load_cached_spirv, store_cached_spirv, ShaderCacheKey},
+    shader_diagnostics::ShaderCompileError,
+    shader_pack::{active_shader_pack, ShaderPack},
+    vulkan::shader::ShaderDefine,
+};
 use anyhow::{anyhow, bail, Context, Result};
 use bytes::Bytes;
 use relative_path::RelativePathBuf;
@@ -14,6 +20,7 @@ pub struct CompiledShader {
 pub struct CompileShader {
     pub path: PathBuf,
     pub profile: String,
+    pub defines: Vec<ShaderDefine>,
 }
 
 #[async_trait]
@@ -40,6 +47,15 @@ impl LazyWorker for CompileShader {
                 Ok(CompiledShader { name, spirv })
             }
             "hlsl" => {
+                if let Some(pack) = active_shader_pack() {
+                    let key = ShaderPack::key(&self.path, &self.profile, &self.defines);
+                    let spirv = pack
+                        .get(&key)
+                        .with_context(|| format!("{} is missing from the shader pack", key))?;
+
+                    return Ok(CompiledShader { name, spirv });
+                }
+
                 let file_path = self.path.to_str().unwrap().to_owned();
                 let source = shader_prepper::process_file(
                     &file_path,
@@ -49,8 +65,17 @@ impl LazyWorker for CompileShader {
                 let source = source
                     .map_err(|err| anyhow!("{}", err))
                     .with_context(|| format!("shader path: {:?}", self.path))?;
-                let target_profile = format!("{}_6_4", self.profile);
-                let spirv = compile_generic_shader_hlsl_impl(&name, &source, &target_profile)?;
+                let target_profile = match self.profile.as_str() {
+                    // Mesh and amplification shaders need shader model 6.5
+                    "as" | "ms" => format!("{}_6_5", self.profile),
+                    _ => format!("{}_6_4", self.profile),
+                };
+                let spirv = compile_generic_shader_hlsl_impl(
+                    &name,
+                    &source,
+                    &target_profile,
+                    &self.defines,
+                )?;
 
                 Ok(CompiledShader { name, spirv })
             }
@@ -98,7 +123,7 @@ impl LazyWorker for CompileRayTracingShader {
             "glsl" => unimplemented!(),
             "hlsl" => {
                 let target_profile = "lib_6_4";
-                let spirv = compile_generic_shader_hlsl_impl(&name, &source, target_profile)?;
+                let spirv = compile_generic_shader_hlsl_impl(&name, &source, target_profile, &[])?;
 
                 Ok(RayTracingShader { name, spirv })
             }
@@ -163,35 +188,67 @@ pub fn get_cs_local_size_from_spirv(spirv: &[u32]) -> Result<[u32; 3]> {
     Err(anyhow!("Could not find a ExecutionMode SPIR-V op"))
 }
 
+const DXC_ARGS: &[&str] = &[
+    "-spirv",
+    //"-enable-16bit-types",
+    "-fspv-target-env=vulkan1.2",
+    "-WX",      // warnings as errors
+    "-Ges",     // strict mode
+    "-HV 2021", // HLSL version 2021
+];
+
+// dxc targets `SPV_NV_mesh_shader` by default. Listing extensions makes it use only those.
+const DXC_MESH_SHADER_ARGS: &[&str] = &[
+    "-fspv-extension=SPV_EXT_mesh_shader",
+    "-fspv-extension=SPV_EXT_descriptor_indexing",
+    "-fspv-extension=SPV_KHR_shader_draw_parameters",
+];
+
+fn dxc_args(target_profile: &str) -> Vec<&'static str> {
+    let mut args = DXC_ARGS.to_vec();
+    if target_profile.starts_with("as_") || target_profile.starts_with("ms_") {
+        args.extend_from_slice(DXC_MESH_SHADER_ARGS);
+    }
+    args
+}
+
 fn compile_generic_shader_hlsl_impl(
     name: &str,
     source: &[shader_prepper::SourceChunk],
     target_profile: &str,
+    defines: &[ShaderDefine],
 ) -> Result<Bytes> {
     let mut source_text = String::new();
     for s in source {
         source_text += &s.source;
     }
 
+    let defines: Vec<(&str, Option<&str>)> = defines
+        .iter()
+        .map(|define| (define.name.as_str(), define.value.as_deref()))
+        .collect();
+
+    let args = dxc_args(target_profile);
+
+    let cache_key = ShaderCacheKey::new(&source_text, target_profile, &args, &defines);
+    if let Some(spirv) = load_cached_spirv(cache_key) {
+        log::trace!("Loaded {} from the shader cache", name);
+        return Ok(spirv);
+    }
+
     let t0 = std::time::Instant::now();
-    let spirv = hassle_rs::compile_hlsl(
-        name,
-        &source_text,
-        "main",
-        target_profile,
-        &[
-            "-spirv",
-            //"-enable-16bit-types",
-            "-fspv-target-env=vulkan1.2",
-            "-WX",      // warnings as errors
-            "-Ges",     // strict mode
-            "-HV 2021", // HLSL version 2021
-        ],
-        &[],
-    )
-    .map_err(|err| anyhow!("{}", err))?;
+    let spirv =
+        hassle_rs::compile_hlsl(name, &source_text, "main", target_profile, &args, &defines)
+            .map_err(|err| match err {
+                hassle_rs::HassleError::CompileError(output) => {
+                    ShaderCompileError::from_dxc_output(name, &output, source).into()
+                }
+                err => anyhow!("{}", err),
+            })?;
 
     log::trace!("dxc took {:?} for {}", t0.elapsed(), name,);
 
+    store_cached_spirv(cache_key, &spirv);
+
     Ok(spirv.into())
 }
@@ -0,0 +1,243 @@
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShaderDiagnosticSeverity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A single message from the shader compiler, pointing at the original source file
+/// rather than the preprocessed source passed to the compiler.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ShaderDiagnostic {
+    pub file: String,
+    /// 1-based
+    pub line: usize,
+    /// 1-based
+    pub column: usize,
+    pub severity: ShaderDiagnosticSeverity,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            ShaderDiagnosticSeverity::Error => "error",
+            ShaderDiagnosticSeverity::Warning => "warning",
+            ShaderDiagnosticSeverity::Note => "note",
+        };
+
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.file, self.line, self.column, severity, self.message
+        )
+    }
+}
+
+/// Returned (wrapped in `anyhow::Error`) when dxc fails to compile a shader.
+#[derive(Clone, Debug)]
+pub struct ShaderCompileError {
+    pub shader: String,
+    pub diagnostics: Vec<ShaderDiagnostic>,
+    /// Compiler output which could not be parsed into diagnostics
+    pub unparsed_output: String,
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Failed to compile {}", self.shader)?;
+
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{}", diagnostic)?;
+        }
+
+        if !self.unparsed_output.is_empty() {
+            write!(f, "{}", self.unparsed_output)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderCompileError {}
+
+impl ShaderCompileError {
+    /// Parses dxc output referring to the concatenation of `chunks`.
+    pub(crate) fn from_dxc_output(
+        shader: &str,
+        output: &str,
+        chunks: &[shader_prepper::SourceChunk],
+    ) -> Self {
+        let source_map = SourceMap::new(chunks);
+        let mut diagnostics = Vec::new();
+        let mut unparsed_output = String::new();
+
+        for line in output.lines() {
+            match parse_dxc_diagnostic(line) {
+                Some((line, column, severity, message)) => {
+                    let (file, line, column) = source_map.resolve(line, column);
+                    diagnostics.push(ShaderDiagnostic {
+                        file,
+                        line,
+                        column,
+                        severity,
+                        message,
+                    });
+                }
+                // Source excerpts and caret lines refer to the preprocessed source; skip them.
+                None if !diagnostics.is_empty() => {}
+                None => {
+                    unparsed_output += line;
+                    unparsed_output += "\n";
+                }
+            }
+        }
+
+        Self {
+            shader: shader.to_owned(),
+            diagnostics,
+            unparsed_output,
+        }
+    }
+}
+
+// `name:line:column: severity: message`
+fn parse_dxc_diagnostic(line: &str) -> Option<(usize, usize, ShaderDiagnosticSeverity, String)> {
+    let mut parts = line.splitn(5, ':');
+    let _name = parts.next()?;
+    let line_number = parts.next()?.trim().parse().ok()?;
+    let column = parts.next()?.trim().parse().ok()?;
+    let severity = match parts.next()?.trim() {
+        "error" | "fatal error" => ShaderDiagnosticSeverity::Error,
+        "warning" => ShaderDiagnosticSeverity::Warning,
+        "note" => ShaderDiagnosticSeverity::Note,
+        _ => return None,
+    };
+    let message = parts.next()?.trim().to_owned();
+
+    Some((line_number, column, severity, message))
+}
+
+struct SourceMapChunk<'a> {
+    file: &'a str,
+    line_offset: usize,
+    // Position of the chunk in the concatenated source, 0-based
+    start_line: usize,
+    start_column: usize,
+}
+
+struct SourceMap<'a> {
+    chunks: Vec<SourceMapChunk<'a>>,
+}
+
+impl<'a> SourceMap<'a> {
+    fn new(chunks: &'a [shader_prepper::SourceChunk]) -> Self {
+        let mut line = 0;
+        let mut column = 0;
+
+        let chunks = chunks
+            .iter()
+            .map(|chunk| {
+                let res = SourceMapChunk {
+                    file: &chunk.file,
+                    line_offset: chunk.line_offset,
+                    start_line: line,
+                    start_column: column,
+                };
+
+                match chunk.source.rfind('\n') {
+                    Some(last_newline) => {
+                        line += chunk.source.matches('\n').count();
+                        column = chunk.source.len() - last_newline - 1;
+                    }
+                    None => column += chunk.source.len(),
+                }
+
+                res
+            })
+            .collect();
+
+        Self { chunks }
+    }
+
+    /// Maps a 1-based position in the concatenated source to a file and 1-based position in it.
+    fn resolve(&self, line: usize, column: usize) -> (String, usize, usize) {
+        let line0 = line.saturating_sub(1);
+        let column0 = column.saturating_sub(1);
+
+        let chunk = self
+            .chunks
+            .iter()
+            .rev()
+            .find(|chunk| {
+                chunk.start_line < line0
+                    || (chunk.start_line == line0 && chunk.start_column <= column0)
+            })
+            .or_else(|| self.chunks.first());
+
+        match chunk {
+            Some(chunk) => {
+                let column0 = if line0 == chunk.start_line {
+                    column0 - chunk.start_column.min(column0)
+                } else {
+                    column0
+                };
+
+                (
+                    chunk.file.to_owned(),
+                    chunk.line_offset + line0 - chunk.start_line + 1,
+                    column0 + 1,
+                )
+            }
+            None => (String::new(), line, column),
+        }
+    }
+}
+
+#[test]
+fn test_dxc_error_source_mapping() {
+    let chunk = |source: &str, file: &str, line_offset: usize| shader_prepper::SourceChunk {
+        source: source.to_owned(),
+        file: file.to_owned(),
+        line_offset,
+    };
+
+    // main.hlsl includes common.hlsl on its second line
+    let chunks = [
+        chunk("// main\n", "/shaders/main.hlsl", 0),
+        chunk("float a;\nfloat b;\n", "/shaders/common.hlsl", 0),
+        chunk("void main() {\n    c = 1;\n}\n", "/shaders/main.hlsl", 2),
+    ];
+
+    let output = "\
+main:5:5: error: use of undeclared identifier 'c'
+    c = 1;
+    ^
+main:3:7: warning: unused variable 'b'
+";
+
+    let err = ShaderCompileError::from_dxc_output("/shaders/main.hlsl", output, &chunks);
+
+    assert_eq!(
+        err.diagnostics,
+        vec![
+            ShaderDiagnostic {
+                file: "/shaders/main.hlsl".to_owned(),
+                line: 4,
+                column: 5,
+                severity: ShaderDiagnosticSeverity::Error,
+                message: "use of undeclared identifier 'c'".to_owned(),
+            },
+            ShaderDiagnostic {
+                file: "/shaders/common.hlsl".to_owned(),
+                line: 2,
+                column: 7,
+                severity: ShaderDiagnosticSeverity::Warning,
+                message: "unused variable 'b'".to_owned(),
+            },
+        ]
+    );
+    assert!(err.unparsed_output.is_empty());
+}
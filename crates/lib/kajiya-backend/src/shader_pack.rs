@@ -0,0 +1,149 @@
+use crate::vulkan::shader::ShaderDefine;
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use lazy_static::lazy_static;
+use nanoserde::{DeBin, SerBin};
+use parking_lot::RwLock;
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+const SHADER_PACK_MAGIC: u32 = 0x4b53_504b; // "KSPK"
+const SHADER_PACK_VERSION: u32 = 1;
+
+lazy_static! {
+    static ref ACTIVE_SHADER_PACK: RwLock<Option<Arc<ShaderPack>>> = RwLock::new(None);
+}
+
+#[derive(SerBin, DeBin)]
+struct ShaderPackFile {
+    magic: u32,
+    version: u32,
+    entries: Vec<ShaderPackFileEntry>,
+}
+
+#[derive(SerBin, DeBin)]
+struct ShaderPackFileEntry {
+    key: String,
+    spirv: Vec<u8>,
+}
+
+/// Precompiled SPIR-V of HLSL shaders, keyed by path, profile and defines.
+///
+/// Created by recording the shaders compiled by a `PipelineCache`
+/// (see `PipelineCache::record_shader_pack`), and consumed via `use_shader_pack`.
+#[derive(Default)]
+pub struct ShaderPack {
+    entries: HashMap<String, Bytes>,
+}
+
+impl ShaderPack {
+    pub(crate) fn key(path: &Path, profile: &str, defines: &[ShaderDefine]) -> String {
+        let mut key = format!("{}:{}", path.to_string_lossy(), profile);
+
+        for define in defines {
+            key += " -D";
+            key += &define.name;
+            if let Some(value) = &define.value {
+                key += "=";
+                key += value;
+            }
+        }
+
+        key
+    }
+
+    pub(crate) fn insert(&mut self, key: String, spirv: Bytes) {
+        self.entries.insert(key, spirv);
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<Bytes> {
+        self.entries.get(key).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn merge(&mut self, other: ShaderPack) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+
+        let file = ShaderPackFile::deserialize_bin(&data)
+            .map_err(|err| anyhow::anyhow!("{:?}", err))
+            .with_context(|| format!("Parsing {:?}", path))?;
+
+        if file.magic != SHADER_PACK_MAGIC || file.version != SHADER_PACK_VERSION {
+            bail!("{:?} is not a compatible shader pack", path);
+        }
+
+        Ok(Self {
+            entries: file
+                .entries
+                .into_iter()
+                .map(|entry| (entry.key, entry.spirv.into()))
+                .collect(),
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        // Sorted for reproducible output
+        let mut entries: Vec<ShaderPackFileEntry> = self
+            .entries
+            .iter()
+            .map(|(key, spirv)| ShaderPackFileEntry {
+                key: key.clone(),
+                spirv: spirv.to_vec(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let file = ShaderPackFile {
+            magic: SHADER_PACK_MAGIC,
+            version: SHADER_PACK_VERSION,
+            entries,
+        };
+
+        std::fs::write(path, file.serialize_bin()).with_context(|| format!("Writing {:?}", path))
+    }
+}
+
+/// Makes all HLSL shaders load from `pack` instead of being compiled. dxc is never invoked
+/// afterwards, and shaders missing from the pack fail to load.
+pub fn use_shader_pack(pack: ShaderPack) {
+    log::info!("Using a shader pack with {} shaders", pack.len());
+    *ACTIVE_SHADER_PACK.write() = Some(Arc::new(pack));
+}
+
+pub(crate) fn active_shader_pack() -> Option<Arc<ShaderPack>> {
+    ACTIVE_SHADER_PACK.read().clone()
+}
+
+#[test]
+fn test_shader_pack_roundtrip() {
+    let mut pack = ShaderPack::default();
+    let key = ShaderPack::key(
+        Path::new("/shaders/foo.hlsl"),
+        "cs",
+        &[ShaderDefine::new("A", 1), ShaderDefine::flag("B")],
+    );
+    assert_eq!(key, "/shaders/foo.hlsl:cs -DA=1 -DB");
+
+    pack.insert(key.clone(), Bytes::from_static(&[1, 2, 3, 4]));
+
+    let path = std::env::temp_dir().join(format!("shader_pack_test_{}.bin", std::process::id()));
+    pack.save(&path).unwrap();
+    let loaded = ShaderPack::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded.get(&key).as_deref(), Some(&[1u8, 2, 3, 4][..]));
+}
@@ -0,0 +1,581 @@
+use anyhow::{anyhow, Context as _, Result};
+use ash::vk;
+use byte_slice_cast::AsSliceOf as _;
+use rspirv::{
+    dr::{Instruction, Module, Operand},
+    spirv::{Decoration, Dim, Op, StorageClass},
+};
+use std::collections::HashMap;
+
+/// What the shader declares at a descriptor binding, beyond the descriptor type
+/// already provided by `rspirv_reflect`. Used to validate resources bound by the render graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderBindingReflection {
+    pub name: String,
+    /// The view type the shader expects, for image bindings
+    pub image_view_type: Option<vk::ImageViewType>,
+    /// Bytes read by the shader, for constant buffers
+    pub uniform_buffer_size: Option<u32>,
+}
+
+/// Keyed by `(set, binding)`
+pub type ShaderBindingReflections = HashMap<(u32, u32), ShaderBindingReflection>;
+
+/// Reflects the bindings of all the stages of a pipeline. Failures are logged rather
+/// than returned, as the reflection is only used for validation.
+pub fn reflect_shader_bindings<'a>(
+    stages: impl IntoIterator<Item = &'a [u8]>,
+) -> ShaderBindingReflections {
+    let mut res = ShaderBindingReflections::new();
+
+    for spirv in stages {
+        match reflect_stage_bindings(spirv) {
+            Ok(stage) => {
+                for (key, binding) in stage {
+                    let merged = res.entry(key).or_insert_with(|| binding.clone());
+                    merged.uniform_buffer_size =
+                        merged.uniform_buffer_size.max(binding.uniform_buffer_size);
+                }
+            }
+            Err(err) => log::warn!("Failed to reflect shader bindings: {:#}", err),
+        }
+    }
+
+    res
+}
+
+fn load_module(spirv: &[u8]) -> Result<Module> {
+    let words = spirv
+        .as_slice_of::<u32>()
+        .map_err(|err| anyhow!("{:?}", err))?;
+
+    let mut loader = rspirv::dr::Loader::new();
+    rspirv::binary::parse_words(words, &mut loader)
+        .map_err(|err| anyhow!("{:?}", err))
+        .context("Parsing SPIR-V")?;
+    Ok(loader.module())
+}
+
+fn reflect_stage_bindings(spirv: &[u8]) -> Result<ShaderBindingReflections> {
+    let module = load_module(spirv)?;
+    let types = Types::new(&module);
+
+    let mut names: HashMap<u32, &str> = HashMap::new();
+    for inst in &module.debug_names {
+        if let (Op::Name, [Operand::IdRef(id), Operand::LiteralString(name)]) =
+            (inst.class.opcode, inst.operands.as_slice())
+        {
+            names.insert(*id, name);
+        }
+    }
+
+    let mut res = ShaderBindingReflections::new();
+
+    for inst in &module.types_global_values {
+        if inst.class.opcode != Op::Variable {
+            continue;
+        }
+
+        let (var_id, ptr_type) = match (inst.result_id, inst.result_type) {
+            (Some(var_id), Some(ptr_type)) => (var_id, ptr_type),
+            _ => continue,
+        };
+
+        let (set, binding) = match (
+            types.decorations.get(var_id, Decoration::DescriptorSet),
+            types.decorations.get(var_id, Decoration::Binding),
+        ) {
+            (Some(set), Some(binding)) => (set, binding),
+            _ => continue,
+        };
+
+        let storage_class = match inst.operands.first() {
+            Some(Operand::StorageClass(storage_class)) => *storage_class,
+            _ => continue,
+        };
+
+        // Pointer -> (array of) resource
+        let mut ty = types.get(ptr_type).and_then(|ptr| ptr.operands.get(1));
+        let mut resource_type = None;
+        while let Some(Operand::IdRef(id)) = ty {
+            let inst = types.get(*id);
+            match inst.map(|inst| inst.class.opcode) {
+                Some(Op::TypeArray | Op::TypeRuntimeArray | Op::TypeSampledImage) => {
+                    ty = inst.and_then(|inst| inst.operands.first());
+                }
+                _ => {
+                    resource_type = Some(*id);
+                    break;
+                }
+            }
+        }
+
+        let resource_type = match resource_type {
+            Some(resource_type) => resource_type,
+            None => continue,
+        };
+
+        let image_view_type = types.get(resource_type).and_then(image_view_type);
+
+        let uniform_buffer_size = if storage_class == StorageClass::Uniform
+            && types.decorations.has(resource_type, Decoration::Block)
+        {
+            types.size_of(resource_type, None)
+        } else {
+            None
+        };
+
+        let name = names
+            .get(&var_id)
+            .filter(|name| !name.is_empty())
+            .or_else(|| names.get(&resource_type))
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+
+        res.insert(
+            (set, binding),
+            ShaderBindingReflection {
+                name,
+                image_view_type,
+                uniform_buffer_size,
+            },
+        );
+    }
+
+    Ok(res)
+}
+
+fn image_view_type(inst: &Instruction) -> Option<vk::ImageViewType> {
+    if inst.class.opcode != Op::TypeImage {
+        return None;
+    }
+
+    // Sampled type, Dim, Depth, Arrayed, ...
+    let dim = match inst.operands.get(1) {
+        Some(Operand::Dim(dim)) => *dim,
+        _ => return None,
+    };
+    let arrayed = matches!(inst.operands.get(3), Some(Operand::LiteralInt32(1)));
+
+    Some(match (dim, arrayed) {
+        (Dim::Dim1D, false) => vk::ImageViewType::TYPE_1D,
+        (Dim::Dim1D, true) => vk::ImageViewType::TYPE_1D_ARRAY,
+        (Dim::Dim2D, false) => vk::ImageViewType::TYPE_2D,
+        (Dim::Dim2D, true) => vk::ImageViewType::TYPE_2D_ARRAY,
+        (Dim::Dim3D, _) => vk::ImageViewType::TYPE_3D,
+        (Dim::DimCube, false) => vk::ImageViewType::CUBE,
+        (Dim::DimCube, true) => vk::ImageViewType::CUBE_ARRAY,
+        // Texel buffers and subpass inputs have no image view type
+        _ => return None,
+    })
+}
+
+struct Decorations {
+    // (target, decoration) -> literal
+    decorations: HashMap<(u32, Decoration), u32>,
+    // (struct, member, decoration) -> literal
+    member_decorations: HashMap<(u32, u32, Decoration), u32>,
+}
+
+impl Decorations {
+    fn new(annotations: &[Instruction]) -> Self {
+        let mut decorations = HashMap::new();
+        let mut member_decorations = HashMap::new();
+
+        for inst in annotations {
+            match (inst.class.opcode, inst.operands.as_slice()) {
+                (
+                    Op::Decorate,
+                    [Operand::IdRef(target), Operand::Decoration(decoration), rest @ ..],
+                ) => {
+                    let literal = match rest.first() {
+                        Some(Operand::LiteralInt32(literal)) => *literal,
+                        _ => 0,
+                    };
+                    decorations.insert((*target, *decoration), literal);
+                }
+                (
+                    Op::MemberDecorate,
+                    [Operand::IdRef(target), Operand::LiteralInt32(member), Operand::Decoration(decoration), rest @ ..],
+                ) => {
+                    let literal = match rest.first() {
+                        Some(Operand::LiteralInt32(literal)) => *literal,
+                        _ => 0,
+                    };
+                    member_decorations.insert((*target, *member, *decoration), literal);
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            decorations,
+            member_decorations,
+        }
+    }
+
+    fn get(&self, target: u32, decoration: Decoration) -> Option<u32> {
+        self.decorations.get(&(target, decoration)).copied()
+    }
+
+    fn has(&self, target: u32, decoration: Decoration) -> bool {
+        self.decorations.contains_key(&(target, decoration))
+    }
+
+    fn get_member(&self, target: u32, member: u32, decoration: Decoration) -> Option<u32> {
+        self.member_decorations
+            .get(&(target, member, decoration))
+            .copied()
+    }
+}
+
+struct Types<'a> {
+    types: HashMap<u32, &'a Instruction>,
+    decorations: Decorations,
+}
+
+impl<'a> Types<'a> {
+    fn new(module: &'a Module) -> Self {
+        Self {
+            types: module
+                .types_global_values
+                .iter()
+                .filter_map(|inst| Some((inst.result_id?, inst)))
+                .collect(),
+            decorations: Decorations::new(&module.annotations),
+        }
+    }
+
+    fn get(&self, id: u32) -> Option<&'a Instruction> {
+        self.types.get(&id).copied()
+    }
+
+    fn id_operand(&self, inst: &Instruction, idx: usize) -> Option<u32> {
+        match inst.operands.get(idx) {
+            Some(Operand::IdRef(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    fn literal_operand(&self, inst: &Instruction, idx: usize) -> Option<u32> {
+        match inst.operands.get(idx) {
+            Some(Operand::LiteralInt32(literal)) => Some(*literal),
+            _ => None,
+        }
+    }
+
+    /// Number of bytes covered by a value of the type, excluding trailing padding.
+    /// `matrix_stride` comes from the struct member containing the type, if any.
+    fn size_of(&self, id: u32, matrix_stride: Option<u32>) -> Option<u32> {
+        let inst = self.get(id)?;
+
+        match inst.class.opcode {
+            Op::TypeBool => Some(4),
+            Op::TypeInt | Op::TypeFloat => Some(self.literal_operand(inst, 0)? / 8),
+            Op::TypeVector => {
+                let component = self.size_of(self.id_operand(inst, 0)?, None)?;
+                Some(component * self.literal_operand(inst, 1)?)
+            }
+            Op::TypeMatrix => {
+                let column = self.size_of(self.id_operand(inst, 0)?, None)?;
+                let columns = self.literal_operand(inst, 1)?;
+                Some(matrix_stride.unwrap_or(16) * (columns - 1) + column)
+            }
+            Op::TypeArray => {
+                let element_type = self.id_operand(inst, 0)?;
+                let element = self.size_of(element_type, matrix_stride)?;
+                let length = self.literal_operand(self.get(self.id_operand(inst, 1)?)?, 0)?;
+                let stride = self
+                    .decorations
+                    .get(id, Decoration::ArrayStride)
+                    .unwrap_or(element);
+                Some(stride * length.saturating_sub(1) + element)
+            }
+            Op::TypeStruct => (0..inst.operands.len() as u32)
+                .map(|member| {
+                    let member_type = self.id_operand(inst, member as usize)?;
+                    let offset = self
+                        .decorations
+                        .get_member(id, member, Decoration::Offset)
+                        .unwrap_or(0);
+                    let matrix_stride =
+                        self.decorations
+                            .get_member(id, member, Decoration::MatrixStride);
+                    Some(offset + self.size_of(member_type, matrix_stride)?)
+                })
+                .try_fold(0, |size, member_end| Some(size.max(member_end?))),
+            _ => None,
+        }
+    }
+}
+
+// Assembles a module from `(opcode, operands)` pairs, with ids below `bound`
+#[cfg(test)]
+fn assemble_spirv(bound: u32, instructions: &[(Op, Vec<u32>)]) -> Vec<u8> {
+    let mut words = vec![rspirv::spirv::MAGIC_NUMBER, 0x0001_0000, 0, bound, 0];
+    for (op, operands) in instructions {
+        words.push(((operands.len() as u32 + 1) << 16) | *op as u32);
+        words.extend_from_slice(operands);
+    }
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+// A nul-terminated literal string, padded to whole words
+#[cfg(test)]
+fn spirv_string(s: &str) -> Vec<u32> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+    bytes
+        .chunks(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+fn spirv_preamble() -> Vec<(Op, Vec<u32>)> {
+    use rspirv::spirv::{AddressingModel, Capability, MemoryModel};
+
+    vec![
+        (Op::Capability, vec![Capability::Shader as u32]),
+        (
+            Op::MemoryModel,
+            vec![AddressingModel::Logical as u32, MemoryModel::GLSL450 as u32],
+        ),
+    ]
+}
+
+#[test]
+fn test_struct_layout() {
+    const FLOAT: u32 = 1;
+    const FLOAT4: u32 = 2;
+    const FLOAT4X4: u32 = 3;
+    const UINT: u32 = 4;
+    const THREE: u32 = 5;
+    const PACKED_ARRAY: u32 = 6;
+    const STRIDED_ARRAY: u32 = 7;
+    const STRUCT: u32 = 8;
+
+    let spirv = assemble_spirv(
+        9,
+        &[
+            spirv_preamble(),
+            vec![
+                (
+                    Op::Decorate,
+                    vec![STRIDED_ARRAY, Decoration::ArrayStride as u32, 16],
+                ),
+                (
+                    Op::MemberDecorate,
+                    vec![STRUCT, 0, Decoration::Offset as u32, 0],
+                ),
+                (
+                    Op::MemberDecorate,
+                    vec![STRUCT, 0, Decoration::MatrixStride as u32, 32],
+                ),
+                (
+                    Op::MemberDecorate,
+                    vec![STRUCT, 1, Decoration::Offset as u32, 128],
+                ),
+                (Op::TypeFloat, vec![FLOAT, 32]),
+                (Op::TypeVector, vec![FLOAT4, FLOAT, 4]),
+                (Op::TypeMatrix, vec![FLOAT4X4, FLOAT4, 4]),
+                (Op::TypeInt, vec![UINT, 32, 0]),
+                (Op::Constant, vec![UINT, THREE, 3]),
+                (Op::TypeArray, vec![PACKED_ARRAY, FLOAT, THREE]),
+                (Op::TypeArray, vec![STRIDED_ARRAY, FLOAT, THREE]),
+                (Op::TypeStruct, vec![STRUCT, FLOAT4X4, STRIDED_ARRAY]),
+            ],
+        ]
+        .concat(),
+    );
+
+    let module = load_module(&spirv).unwrap();
+    let types = Types::new(&module);
+
+    assert_eq!(types.size_of(FLOAT, None), Some(4));
+    assert_eq!(types.size_of(FLOAT4, None), Some(16));
+    assert_eq!(types.size_of(UINT, None), Some(4));
+
+    // The last column is not padded to the stride
+    assert_eq!(types.size_of(FLOAT4X4, None), Some(16 * 3 + 16));
+    assert_eq!(types.size_of(FLOAT4X4, Some(32)), Some(32 * 3 + 16));
+
+    // Neither is the last element
+    assert_eq!(types.size_of(PACKED_ARRAY, None), Some(4 * 3));
+    assert_eq!(types.size_of(STRIDED_ARRAY, None), Some(16 * 2 + 4));
+
+    // Ends with the member furthest along, using the member's matrix stride
+    assert_eq!(types.size_of(STRUCT, None), Some(128 + 16 * 2 + 4));
+
+    // Not a type
+    assert_eq!(types.size_of(THREE, None), None);
+}
+
+#[test]
+fn test_reflect_stage_bindings() {
+    const FLOAT: u32 = 1;
+    const FLOAT4: u32 = 2;
+    const STRUCT: u32 = 3;
+    const STRUCT_PTR: u32 = 4;
+    const CONSTANTS: u32 = 5;
+    const UINT: u32 = 6;
+    const FOUR: u32 = 7;
+    const IMAGE: u32 = 8;
+    const IMAGE_ARRAY: u32 = 9;
+    const IMAGE_ARRAY_PTR: u32 = 10;
+    const TEXTURES: u32 = 11;
+    const PRIVATE_PTR: u32 = 12;
+    const PRIVATE: u32 = 13;
+
+    let spirv = assemble_spirv(
+        14,
+        &[
+            spirv_preamble(),
+            vec![
+                (Op::Name, [vec![STRUCT], spirv_string("Constants")].concat()),
+                (Op::Name, [vec![CONSTANTS], spirv_string("")].concat()),
+                (
+                    Op::Name,
+                    [vec![TEXTURES], spirv_string("textures")].concat(),
+                ),
+                (Op::Decorate, vec![STRUCT, Decoration::Block as u32]),
+                (
+                    Op::MemberDecorate,
+                    vec![STRUCT, 0, Decoration::Offset as u32, 0],
+                ),
+                (
+                    Op::MemberDecorate,
+                    vec![STRUCT, 1, Decoration::Offset as u32, 16],
+                ),
+                (
+                    Op::Decorate,
+                    vec![CONSTANTS, Decoration::DescriptorSet as u32, 0],
+                ),
+                (Op::Decorate, vec![CONSTANTS, Decoration::Binding as u32, 1]),
+                (
+                    Op::Decorate,
+                    vec![TEXTURES, Decoration::DescriptorSet as u32, 1],
+                ),
+                (Op::Decorate, vec![TEXTURES, Decoration::Binding as u32, 0]),
+                (Op::TypeFloat, vec![FLOAT, 32]),
+                (Op::TypeVector, vec![FLOAT4, FLOAT, 4]),
+                (Op::TypeStruct, vec![STRUCT, FLOAT4, FLOAT]),
+                (
+                    Op::TypePointer,
+                    vec![STRUCT_PTR, StorageClass::Uniform as u32, STRUCT],
+                ),
+                (
+                    Op::Variable,
+                    vec![STRUCT_PTR, CONSTANTS, StorageClass::Uniform as u32],
+                ),
+                (Op::TypeInt, vec![UINT, 32, 0]),
+                (Op::Constant, vec![UINT, FOUR, 4]),
+                // Texture2DArray<float4>: sampled type, dim, depth, arrayed, ms, sampled, format
+                (
+                    Op::TypeImage,
+                    vec![IMAGE, FLOAT, Dim::Dim2D as u32, 0, 1, 0, 1, 0],
+                ),
+                (Op::TypeArray, vec![IMAGE_ARRAY, IMAGE, FOUR]),
+                (
+                    Op::TypePointer,
+                    vec![
+                        IMAGE_ARRAY_PTR,
+                        StorageClass::UniformConstant as u32,
+                        IMAGE_ARRAY,
+                    ],
+                ),
+                (
+                    Op::Variable,
+                    vec![
+                        IMAGE_ARRAY_PTR,
+                        TEXTURES,
+                        StorageClass::UniformConstant as u32,
+                    ],
+                ),
+                // Not a resource
+                (
+                    Op::TypePointer,
+                    vec![PRIVATE_PTR, StorageClass::Private as u32, FLOAT],
+                ),
+                (
+                    Op::Variable,
+                    vec![PRIVATE_PTR, PRIVATE, StorageClass::Private as u32],
+                ),
+            ],
+        ]
+        .concat(),
+    );
+
+    let bindings = reflect_stage_bindings(&spirv).unwrap();
+    assert_eq!(bindings.len(), 2);
+
+    // Unnamed constant buffers are named after their type
+    assert_eq!(
+        bindings[&(0, 1)],
+        ShaderBindingReflection {
+            name: "Constants".to_owned(),
+            image_view_type: None,
+            uniform_buffer_size: Some(16 + 4),
+        }
+    );
+
+    // Arrays of images reflect the type of their elements
+    assert_eq!(
+        bindings[&(1, 0)],
+        ShaderBindingReflection {
+            name: "textures".to_owned(),
+            image_view_type: Some(vk::ImageViewType::TYPE_2D_ARRAY),
+            uniform_buffer_size: None,
+        }
+    );
+}
+
+#[test]
+fn test_image_view_type() {
+    let image = |dim: Dim, arrayed: u32| {
+        Instruction::new(
+            Op::TypeImage,
+            None,
+            Some(2),
+            vec![
+                Operand::IdRef(1),
+                Operand::Dim(dim),
+                Operand::LiteralInt32(0),
+                Operand::LiteralInt32(arrayed),
+                Operand::LiteralInt32(0),
+                Operand::LiteralInt32(1),
+                Operand::ImageFormat(rspirv::spirv::ImageFormat::Unknown),
+            ],
+        )
+    };
+
+    let cases = [
+        (Dim::Dim1D, 0, Some(vk::ImageViewType::TYPE_1D)),
+        (Dim::Dim1D, 1, Some(vk::ImageViewType::TYPE_1D_ARRAY)),
+        (Dim::Dim2D, 0, Some(vk::ImageViewType::TYPE_2D)),
+        (Dim::Dim2D, 1, Some(vk::ImageViewType::TYPE_2D_ARRAY)),
+        (Dim::Dim3D, 0, Some(vk::ImageViewType::TYPE_3D)),
+        (Dim::DimCube, 0, Some(vk::ImageViewType::CUBE)),
+        (Dim::DimCube, 1, Some(vk::ImageViewType::CUBE_ARRAY)),
+        (Dim::DimBuffer, 0, None),
+        (Dim::DimSubpassData, 0, None),
+    ];
+
+    for (dim, arrayed, expected) in cases {
+        assert_eq!(
+            image_view_type(&image(dim, arrayed)),
+            expected,
+            "{:?}, arrayed: {}",
+            dim,
+            arrayed
+        );
+    }
+
+    let float = Instruction::new(
+        Op::TypeFloat,
+        None,
+        Some(1),
+        vec![Operand::LiteralInt32(32)],
+    );
+    assert_eq!(image_view_type(&float), None);
+}
@@ -4,7 +4,10 @@ pub use super::profiler::VkProfilerData;
 use super::{
     buffer::Buffer,
     error::CrashMarkerNames,
+    image::Image,
+    mesh_shader,
     physical_device::{PhysicalDevice, QueueFamily},
+    pipeline_cache_file::{load_pipeline_cache_data, save_pipeline_cache_data},
     profiler::ProfilerBackend,
 };
 use anyhow::Result;
@@ -46,15 +49,28 @@ impl DeferredRelease for vk::DescriptorPool {
 #[derive(Default)]
 pub struct PendingResourceReleases {
     pub descriptor_pools: Vec<vk::DescriptorPool>,
+    pub images: Vec<Arc<Image>>,
 }
 
 impl PendingResourceReleases {
-    fn release_all(&mut self, device: &ash::Device) {
+    fn release_all(&mut self, device: &Device) {
         unsafe {
             for res in self.descriptor_pools.drain(..) {
-                device.destroy_descriptor_pool(res, None);
+                device.raw.destroy_descriptor_pool(res, None);
             }
         }
+
+        // Images still referenced elsewhere are retried the next time this frame comes around
+        self.images = std::mem::take(&mut self.images)
+            .into_iter()
+            .filter_map(|image| match Arc::try_unwrap(image) {
+                Ok(image) => {
+                    device.immediate_destroy_image(image);
+                    None
+                }
+                Err(image) => Some(image),
+            })
+            .collect();
     }
 }
 
@@ -154,10 +170,20 @@ pub struct Device {
     pub(crate) crash_tracking_buffer: Buffer,
     pub(crate) crash_marker_names: Mutex<CrashMarkerNames>,
 
+    /// `None` if `VK_KHR_draw_indirect_count`, `multiDrawIndirect`
+    /// or `drawIndirectFirstInstance` are not supported
+    pub draw_indirect_count_ext: Option<khr::DrawIndirectCount>,
     pub acceleration_structure_ext: khr::AccelerationStructure,
     pub ray_tracing_pipeline_ext: khr::RayTracingPipeline,
     // pub ray_query_ext: khr::RayQuery,
     pub ray_tracing_pipeline_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR,
+    /// `None` if `VK_EXT_mesh_shader` is not supported
+    pub mesh_shader_ext: Option<mesh_shader::MeshShader>,
+    /// `None` if `VK_EXT_hdr_metadata` is not supported, or there's no presentation
+    pub hdr_metadata_ext: Option<vk::ExtHdrMetadataFn>,
+
+    // Persisted across runs; see `save_pipeline_cache`
+    pub(crate) pipeline_cache: vk::PipelineCache,
 
     frames: [Mutex<Arc<DeviceFrame>>; 2],
 
@@ -247,10 +273,26 @@ impl Device {
             device_extension_names.extend(ray_tracing_extensions.iter());
         }
 
+        // Optional; used for GPU-driven rendering
+        let draw_indirect_count_supported =
+            supported_extensions.contains(vk::KhrDrawIndirectCountFn::name().to_str().unwrap());
+
+        // Optional; used for meshlet rendering
+        let mesh_shader_supported =
+            supported_extensions.contains(mesh_shader::extension_name().to_str().unwrap());
+
+        // Optional; describes the mastering display of HDR swapchains
+        let hdr_metadata_enabled = pdevice.presentation_requested
+            && supported_extensions.contains(vk::ExtHdrMetadataFn::name().to_str().unwrap());
+
         if pdevice.presentation_requested {
             device_extension_names.push(khr::Swapchain::name().as_ptr());
         }
 
+        if hdr_metadata_enabled {
+            device_extension_names.push(vk::ExtHdrMetadataFn::name().as_ptr());
+        }
+
         unsafe {
             for &ext in &device_extension_names {
                 let ext = std::ffi::CStr::from_ptr(ext).to_string_lossy();
@@ -295,9 +337,24 @@ impl Device {
         let mut ray_tracing_pipeline_features =
             ash::vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
 
+        let mut mesh_shader_features = mesh_shader::PhysicalDeviceMeshShaderFeatures::default();
+
         unsafe {
             let instance = &pdevice.instance.raw;
 
+            // Queried on its own, so that it's only chained into device creation if enabled
+            let mesh_shader_enabled = mesh_shader_supported && {
+                let mut mesh_shader_query = vk::PhysicalDeviceFeatures2::builder()
+                    .push_next(&mut mesh_shader_features)
+                    .build();
+
+                instance
+                    .fp_v1_1()
+                    .get_physical_device_features2(pdevice.raw, &mut mesh_shader_query);
+
+                mesh_shader_features.task_shader != 0 && mesh_shader_features.mesh_shader != 0
+            };
+
             let mut features2 = vk::PhysicalDeviceFeatures2::builder()
                 .push_next(&mut scalar_block)
                 .push_next(&mut descriptor_indexing)
@@ -312,12 +369,36 @@ impl Device {
                     .push_next(&mut ray_tracing_pipeline_features);
             }
 
+            if mesh_shader_enabled {
+                features2 = features2.push_next(&mut mesh_shader_features);
+            }
+
             let mut features2 = features2.build();
 
             instance
                 .fp_v1_1()
                 .get_physical_device_features2(pdevice.raw, &mut features2);
 
+            if mesh_shader_enabled {
+                log::info!("Mesh shaders are supported");
+                device_extension_names.push(mesh_shader::extension_name().as_ptr());
+
+                // Only request what's used
+                mesh_shader_features.multiview_mesh_shader = 0;
+                mesh_shader_features.primitive_fragment_shading_rate_mesh_shader = 0;
+                mesh_shader_features.mesh_shader_queries = 0;
+            }
+
+            // The core features come along with `features2` if supported
+            let draw_indirect_count_enabled = draw_indirect_count_supported
+                && features2.features.multi_draw_indirect != 0
+                && features2.features.draw_indirect_first_instance != 0;
+
+            if draw_indirect_count_enabled {
+                log::info!("Indirect count draws are supported");
+                device_extension_names.push(vk::KhrDrawIndirectCountFn::name().as_ptr());
+            }
+
             debug!("{:#?}", &scalar_block);
             debug!("{:#?}", &descriptor_indexing);
             debug!("{:#?}", &imageless_framebuffer);
@@ -410,6 +491,8 @@ impl Device {
             let immutable_samplers = Self::create_samplers(&device);
             let setup_cb = CommandBuffer::new(&device, &universal_queue.family).unwrap();
 
+            let draw_indirect_count_ext = draw_indirect_count_enabled
+                .then(|| khr::DrawIndirectCount::new(&pdevice.instance.raw, &device));
             let acceleration_structure_ext =
                 khr::AccelerationStructure::new(&pdevice.instance.raw, &device);
             let ray_tracing_pipeline_ext =
@@ -418,6 +501,41 @@ impl Device {
             let ray_tracing_pipeline_properties =
                 khr::RayTracingPipeline::get_properties(&pdevice.instance.raw, pdevice.raw);
 
+            let mesh_shader_ext = if mesh_shader_enabled {
+                mesh_shader::MeshShader::new(&pdevice.instance.raw, &device)
+            } else {
+                None
+            };
+
+            let hdr_metadata_ext = hdr_metadata_enabled.then(|| {
+                vk::ExtHdrMetadataFn::load(|name| {
+                    std::mem::transmute(
+                        pdevice
+                            .instance
+                            .raw
+                            .get_device_proc_addr(device.handle(), name.as_ptr()),
+                    )
+                })
+            });
+
+            let pipeline_cache = {
+                let initial_data = load_pipeline_cache_data(&pdevice.properties);
+                let create_info =
+                    vk::PipelineCacheCreateInfo::builder().initial_data(&initial_data);
+
+                match device.create_pipeline_cache(&create_info, None) {
+                    Ok(cache) => cache,
+                    // Should not happen with validated data, but don't fail over a cache
+                    Err(err) => {
+                        warn!(
+                            "Failed to create a pipeline cache with saved data: {:?}",
+                            err
+                        );
+                        device.create_pipeline_cache(&Default::default(), None)?
+                    }
+                }
+            };
+
             let crash_tracking_buffer = Self::create_buffer_impl(
                 &device,
                 &mut global_allocator,
@@ -436,9 +554,13 @@ impl Device {
                 crash_tracking_buffer,
                 crash_marker_names: Default::default(),
                 acceleration_structure_ext,
+                draw_indirect_count_ext,
                 ray_tracing_pipeline_ext,
                 // ray_query_ext,
                 ray_tracing_pipeline_properties,
+                mesh_shader_ext,
+                hdr_metadata_ext,
+                pipeline_cache,
                 frames: [
                     Mutex::new(Arc::new(frame0)),
                     Mutex::new(Arc::new(frame1)),
@@ -538,10 +660,7 @@ impl Device {
             }
 
             puffin::profile_scope!("release pending resources");
-            frame0
-                .pending_resource_releases
-                .get_mut()
-                .release_all(&self.raw);
+            frame0.pending_resource_releases.get_mut().release_all(self);
         }
 
         frame0.clone()
@@ -551,6 +670,17 @@ impl Device {
         resource.enqueue_release(&mut self.frames[0].lock().pending_resource_releases.lock());
     }
 
+    /// Destroys `image` once the GPU is done with the current frame,
+    /// and no other references to it remain.
+    pub fn defer_release_image(&self, image: Arc<Image>) {
+        self.frames[0]
+            .lock()
+            .pending_resource_releases
+            .lock()
+            .images
+            .push(image);
+    }
+
     pub fn with_setup_cb(
         &self,
         callback: impl FnOnce(vk::CommandBuffer),
@@ -630,6 +760,21 @@ impl Device {
     pub fn ray_tracing_enabled(&self) -> bool {
         self.ray_tracing_enabled
     }
+
+    pub fn mesh_shaders_enabled(&self) -> bool {
+        self.mesh_shader_ext.is_some()
+    }
+
+    pub fn draw_indirect_count_enabled(&self) -> bool {
+        self.draw_indirect_count_ext.is_some()
+    }
+
+    /// Writes the contents of the Vulkan pipeline cache to `/cache`, to be loaded
+    /// on the next run. Called automatically when the device is dropped.
+    pub fn save_pipeline_cache(&self) -> Result<()> {
+        let data = unsafe { self.raw.get_pipeline_cache_data(self.pipeline_cache)? };
+        save_pipeline_cache_data(&data)
+    }
 }
 
 impl Drop for Device {
@@ -638,6 +783,14 @@ impl Drop for Device {
             log::trace!("device_wait_idle");
             let _ = self.raw.device_wait_idle();
         }
+
+        if let Err(err) = self.save_pipeline_cache() {
+            warn!("Failed to save the pipeline cache: {:#}", err);
+        }
+
+        unsafe {
+            self.raw.destroy_pipeline_cache(self.pipeline_cache, None);
+        }
     }
 }
 
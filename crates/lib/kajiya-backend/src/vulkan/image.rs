@@ -153,6 +153,47 @@ impl ImageDesc {
     pub fn extent_2d(&self) -> [u32; 2] {
         [self.extent[0], self.extent[1]]
     }
+
+    /// Number of Vulkan array layers, counting each face of a cube separately.
+    pub fn array_layer_count(&self) -> u32 {
+        match self.image_type {
+            ImageType::Tex1d | ImageType::Tex2d | ImageType::Tex3d => 1,
+            ImageType::Tex1dArray | ImageType::Tex2dArray => self.array_elements,
+            ImageType::Cube => 6,
+            ImageType::CubeArray => 6 * self.array_elements,
+        }
+    }
+}
+
+/// Size of a single texel for uncompressed formats, or `None` if the format isn't handled.
+pub fn format_texel_size_bytes(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT => Some(1),
+        vk::Format::R16_SFLOAT | vk::Format::R16_UNORM | vk::Format::R16_UINT => Some(2),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2R10G10B10_UNORM_PACK32
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::E5B9G9R9_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R16G16_UNORM
+        | vk::Format::R16G16_SNORM
+        | vk::Format::R32_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::D32_SFLOAT => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SNORM
+        | vk::Format::R32G32_SFLOAT
+        | vk::Format::R32G32_UINT => Some(8),
+        vk::Format::R32G32B32_SFLOAT => Some(12),
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT => Some(16),
+        _ => None,
+    }
 }
 
 pub struct ImageSubResourceData<'a> {
@@ -165,12 +206,24 @@ pub struct Image {
     pub raw: vk::Image,
     pub desc: ImageDesc,
     pub views: Mutex<HashMap<ImageViewDesc, vk::ImageView>>,
-    //allocation: gpu_allocator::SubAllocation,
+    /// `None` for images owned by the swapchain
+    pub(crate) allocation: Option<gpu_allocator::SubAllocation>,
 }
 unsafe impl Send for Image {}
 unsafe impl Sync for Image {}
 
 impl Image {
+    /// An image without any GPU resources, standing in for a real one where nothing
+    /// is executed on the GPU, such as in mock render graphs.
+    pub fn new_placeholder(desc: ImageDesc) -> Self {
+        Self {
+            raw: vk::Image::null(),
+            desc,
+            views: Default::default(),
+            allocation: None,
+        }
+    }
+
     pub fn view(
         &self,
         device: &Device,
@@ -209,10 +262,10 @@ impl Image {
                 base_mip_level: desc.base_mip_level,
                 level_count: desc.level_count.unwrap_or(image_desc.mip_levels as u32),
                 base_array_layer: 0,
-                layer_count: match image_desc.image_type {
+                layer_count: desc.layer_count.unwrap_or(match image_desc.image_type {
                     ImageType::Cube | ImageType::CubeArray => 6,
                     _ => 1,
-                },
+                }),
             })
             .build()
     }
@@ -231,7 +284,9 @@ pub struct ImageViewDesc {
     pub base_mip_level: u32,
     #[builder(default = "None")]
     pub level_count: Option<u32>,
-    // TODO
+    /// Defaults to a single layer, or six for cube images.
+    #[builder(default = "None")]
+    pub layer_count: Option<u32>,
 }
 
 impl ImageViewDesc {
@@ -404,12 +459,29 @@ impl Device {
         ImageHandle(handle)*/
         Ok(Image {
             raw: image,
-            //allocation,
             desc,
             views: Default::default(),
+            allocation: Some(allocation),
         })
     }
 
+    /// The image must not be in use by the GPU; see `Device::defer_release_image`.
+    pub fn immediate_destroy_image(&self, image: Image) {
+        unsafe {
+            for view in image.views.into_inner().into_values() {
+                self.raw.destroy_image_view(view, None);
+            }
+            self.raw.destroy_image(image.raw, None);
+        }
+
+        if let Some(allocation) = image.allocation {
+            self.global_allocator
+                .lock()
+                .free(allocation)
+                .expect("image memory deallocated");
+        }
+    }
+
     fn create_image_view(
         &self,
         desc: ImageViewDesc,
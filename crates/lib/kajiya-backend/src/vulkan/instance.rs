@@ -46,9 +46,22 @@ impl Instance {
         DeviceBuilder::default()
     }
 
-    fn extension_names(builder: &DeviceBuilder) -> Vec<*const i8> {
+    fn extension_names(entry: &ash::Entry, builder: &DeviceBuilder) -> Vec<*const i8> {
         let mut names = vec![vk::KhrGetPhysicalDeviceProperties2Fn::name().as_ptr()];
 
+        // Optional; exposes the HDR color spaces of swapchains
+        let colorspace_supported = entry
+            .enumerate_instance_extension_properties()
+            .unwrap_or_default()
+            .iter()
+            .any(|ext| unsafe {
+                CStr::from_ptr(ext.extension_name.as_ptr()) == vk::ExtSwapchainColorspaceFn::name()
+            });
+
+        if colorspace_supported {
+            names.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+        }
+
         if builder.graphics_debugging {
             #[allow(deprecated)]
             names.push(ext::DebugReport::name().as_ptr());
@@ -72,7 +85,7 @@ impl Instance {
             .required_extensions
             .iter()
             .map(|ext| ext.as_ptr())
-            .chain(Self::extension_names(&builder).into_iter())
+            .chain(Self::extension_names(&entry, &builder).into_iter())
             .collect::<Vec<_>>();
 
         let layer_names = Self::layer_names(&builder);
@@ -0,0 +1,138 @@
+//! `VK_EXT_mesh_shader`, which predates the version of `ash` in use.
+//!
+//! The shader and pipeline stage bits of the extension share their values
+//! with `VK_NV_mesh_shader`, so the `*_NV` flags from `ash` are aliased here.
+
+use ash::vk;
+use std::{
+    ffi::{c_void, CStr},
+    mem::transmute,
+};
+
+pub const SHADER_STAGE_TASK: vk::ShaderStageFlags = vk::ShaderStageFlags::TASK_NV;
+pub const SHADER_STAGE_MESH: vk::ShaderStageFlags = vk::ShaderStageFlags::MESH_NV;
+
+pub const PIPELINE_STAGE_TASK_SHADER: vk::PipelineStageFlags =
+    vk::PipelineStageFlags::TASK_SHADER_NV;
+pub const PIPELINE_STAGE_MESH_SHADER: vk::PipelineStageFlags =
+    vk::PipelineStageFlags::MESH_SHADER_NV;
+
+pub(crate) fn extension_name() -> &'static CStr {
+    CStr::from_bytes_with_nul(b"VK_EXT_mesh_shader\0").unwrap()
+}
+
+// VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MESH_SHADER_FEATURES_EXT
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_MESH_SHADER_FEATURES: vk::StructureType =
+    vk::StructureType::from_raw(1_000_328_000);
+
+/// `VkPhysicalDeviceMeshShaderFeaturesEXT`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalDeviceMeshShaderFeatures {
+    pub s_type: vk::StructureType,
+    pub p_next: *mut c_void,
+    pub task_shader: vk::Bool32,
+    pub mesh_shader: vk::Bool32,
+    pub multiview_mesh_shader: vk::Bool32,
+    pub primitive_fragment_shading_rate_mesh_shader: vk::Bool32,
+    pub mesh_shader_queries: vk::Bool32,
+}
+
+impl Default for PhysicalDeviceMeshShaderFeatures {
+    fn default() -> Self {
+        Self {
+            s_type: STRUCTURE_TYPE_PHYSICAL_DEVICE_MESH_SHADER_FEATURES,
+            p_next: std::ptr::null_mut(),
+            task_shader: 0,
+            mesh_shader: 0,
+            multiview_mesh_shader: 0,
+            primitive_fragment_shading_rate_mesh_shader: 0,
+            mesh_shader_queries: 0,
+        }
+    }
+}
+
+// Same layout rules as the structs generated by `ash`: `s_type` and `p_next` come first.
+unsafe impl vk::ExtendsPhysicalDeviceFeatures2 for PhysicalDeviceMeshShaderFeatures {}
+
+type PfnCmdDrawMeshTasks = unsafe extern "system" fn(
+    command_buffer: vk::CommandBuffer,
+    group_count_x: u32,
+    group_count_y: u32,
+    group_count_z: u32,
+);
+
+type PfnCmdDrawMeshTasksIndirectCount = unsafe extern "system" fn(
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    count_buffer: vk::Buffer,
+    count_buffer_offset: vk::DeviceSize,
+    max_draw_count: u32,
+    stride: u32,
+);
+
+/// Device-level commands of `VK_EXT_mesh_shader`.
+pub struct MeshShader {
+    cmd_draw_mesh_tasks: PfnCmdDrawMeshTasks,
+    cmd_draw_mesh_tasks_indirect_count: PfnCmdDrawMeshTasksIndirectCount,
+}
+
+impl MeshShader {
+    pub(crate) fn new(instance: &ash::Instance, device: &ash::Device) -> Option<Self> {
+        unsafe {
+            let load = |name: &[u8]| {
+                instance.get_device_proc_addr(
+                    device.handle(),
+                    CStr::from_bytes_with_nul(name).unwrap().as_ptr(),
+                )
+            };
+
+            Some(Self {
+                cmd_draw_mesh_tasks: transmute(load(b"vkCmdDrawMeshTasksEXT\0")?),
+                cmd_draw_mesh_tasks_indirect_count: transmute(load(
+                    b"vkCmdDrawMeshTasksIndirectCountEXT\0",
+                )?),
+            })
+        }
+    }
+
+    /// # Safety
+    /// `command_buffer` must be recording inside a render pass with a mesh pipeline bound.
+    pub unsafe fn cmd_draw_mesh_tasks(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        group_count: [u32; 3],
+    ) {
+        (self.cmd_draw_mesh_tasks)(
+            command_buffer,
+            group_count[0],
+            group_count[1],
+            group_count[2],
+        );
+    }
+
+    /// # Safety
+    /// `command_buffer` must be recording inside a render pass with a mesh pipeline bound.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn cmd_draw_mesh_tasks_indirect_count(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        count_buffer: vk::Buffer,
+        count_buffer_offset: vk::DeviceSize,
+        max_draw_count: u32,
+        stride: u32,
+    ) {
+        (self.cmd_draw_mesh_tasks_indirect_count)(
+            command_buffer,
+            buffer,
+            offset,
+            count_buffer,
+            count_buffer_offset,
+            max_draw_count,
+            stride,
+        );
+    }
+}
@@ -4,7 +4,9 @@ pub mod device;
 pub mod error;
 pub mod image;
 pub mod instance;
+pub mod mesh_shader;
 pub mod physical_device;
+mod pipeline_cache_file;
 mod profiler;
 pub mod ray_tracing;
 pub mod shader;
@@ -17,7 +19,46 @@ use log::{debug, error, info, trace, warn};
 use raw_window_handle::HasRawWindowHandle;
 use std::sync::Arc;
 
-fn select_surface_format(formats: Vec<vk::SurfaceFormatKHR>) -> Option<vk::SurfaceFormatKHR> {
+/// Swapchain formats for HDR displays
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HdrSwapchainFormat {
+    /// 10 bits per channel, Rec.2020 primaries with the PQ curve
+    Hdr10,
+    /// 16-bit float, linear with Rec.709 primaries, and 1.0 at 80 nits
+    ScRgb,
+}
+
+impl HdrSwapchainFormat {
+    fn surface_format(self) -> vk::SurfaceFormatKHR {
+        match self {
+            Self::Hdr10 => vk::SurfaceFormatKHR {
+                format: vk::Format::A2B10G10R10_UNORM_PACK32,
+                color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+            },
+            Self::ScRgb => vk::SurfaceFormatKHR {
+                format: vk::Format::R16G16B16A16_SFLOAT,
+                color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            },
+        }
+    }
+}
+
+fn select_surface_format(
+    formats: Vec<vk::SurfaceFormatKHR>,
+    hdr_format: Option<HdrSwapchainFormat>,
+) -> Option<vk::SurfaceFormatKHR> {
+    if let Some(hdr_format) = hdr_format {
+        let preferred = hdr_format.surface_format();
+        if formats.contains(&preferred) {
+            return Some(preferred);
+        }
+
+        warn!(
+            "{:?} swapchains are not supported by the display; falling back to SDR",
+            hdr_format
+        );
+    }
+
     let preferred = vk::SurfaceFormatKHR {
         format: vk::Format::B8G8R8A8_UNORM,
         color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
@@ -42,6 +83,8 @@ pub struct RenderBackendConfig {
     pub vsync: bool,
     pub graphics_debugging: bool,
     pub device_index: Option<usize>,
+    /// Falls back to SDR if the display doesn't support it
+    pub hdr_format: Option<HdrSwapchainFormat>,
 }
 
 impl RenderBackend {
@@ -99,7 +142,8 @@ impl RenderBackend {
             &device,
             &surface,
             swapchain::SwapchainDesc {
-                format: select_surface_format(surface_formats).expect("suitable surface format"),
+                format: select_surface_format(surface_formats, config.hdr_format)
+                    .expect("suitable surface format"),
                 dims: vk::Extent2D {
                     width: config.swapchain_extent[0],
                     height: config.swapchain_extent[1],
@@ -0,0 +1,100 @@
+use crate::file::normalized_path_from_vfs;
+use anyhow::{Context, Result};
+use ash::vk;
+
+const PIPELINE_CACHE_PATH: &str = "/cache/vk_pipeline_cache.bin";
+
+// `VkPipelineCacheHeaderVersionOne`
+const PIPELINE_CACHE_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// Checks the header of serialized `vk::PipelineCache` data against the device it is
+/// about to be used with. Some drivers don't survive data from a different driver version.
+pub(crate) fn is_pipeline_cache_data_compatible(
+    data: &[u8],
+    properties: &vk::PhysicalDeviceProperties,
+) -> bool {
+    if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset: usize| u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let header_size = read_u32(0) as usize;
+    let header_version = read_u32(4);
+    let vendor_id = read_u32(8);
+    let device_id = read_u32(12);
+    let uuid = &data[16..16 + vk::UUID_SIZE];
+
+    header_size >= PIPELINE_CACHE_HEADER_SIZE
+        && header_size <= data.len()
+        && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && vendor_id == properties.vendor_id
+        && device_id == properties.device_id
+        && uuid == properties.pipeline_cache_uuid
+}
+
+/// Returns the contents of the pipeline cache saved by a previous run,
+/// or an empty vector if there's none, or it was created by a different driver or device.
+pub(crate) fn load_pipeline_cache_data(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+    let data = match normalized_path_from_vfs(PIPELINE_CACHE_PATH)
+        .ok()
+        .and_then(|path| std::fs::read(path).ok())
+    {
+        Some(data) => data,
+        None => return Vec::new(),
+    };
+
+    if is_pipeline_cache_data_compatible(&data, properties) {
+        log::info!("Loaded the pipeline cache ({} bytes)", data.len());
+        data
+    } else {
+        log::info!("Discarding the pipeline cache created by a different device or driver");
+        Vec::new()
+    }
+}
+
+pub(crate) fn save_pipeline_cache_data(data: &[u8]) -> Result<()> {
+    let path = normalized_path_from_vfs(PIPELINE_CACHE_PATH)?;
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, data).with_context(|| format!("Writing {:?}", tmp_path))?;
+    std::fs::rename(&tmp_path, &path).with_context(|| format!("Renaming to {:?}", path))?;
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_cache_header_validation() {
+    let properties = vk::PhysicalDeviceProperties {
+        vendor_id: 0x10de,
+        device_id: 0x2204,
+        pipeline_cache_uuid: [7; vk::UUID_SIZE],
+        ..Default::default()
+    };
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&(PIPELINE_CACHE_HEADER_SIZE as u32).to_ne_bytes());
+    data.extend_from_slice(&1u32.to_ne_bytes());
+    data.extend_from_slice(&0x10deu32.to_ne_bytes());
+    data.extend_from_slice(&0x2204u32.to_ne_bytes());
+    data.extend_from_slice(&[7; vk::UUID_SIZE]);
+    data.extend_from_slice(&[0xab; 64]);
+
+    assert!(is_pipeline_cache_data_compatible(&data, &properties));
+    assert!(!is_pipeline_cache_data_compatible(&data[..20], &properties));
+
+    let mut other_driver = data.clone();
+    other_driver[16] = 8;
+    assert!(!is_pipeline_cache_data_compatible(
+        &other_driver,
+        &properties
+    ));
+
+    let mut other_device = data;
+    other_device[12] = 0;
+    assert!(!is_pipeline_cache_data_compatible(
+        &other_device,
+        &properties
+    ));
+}
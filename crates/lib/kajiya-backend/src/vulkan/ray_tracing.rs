@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use crate::{dynamic_constants::DynamicConstants, BackendError, MAX_DESCRIPTOR_SETS};
+use crate::{
+    dynamic_constants::DynamicConstants, shader_reflection::reflect_shader_bindings, BackendError,
+    MAX_DESCRIPTOR_SETS,
+};
 
 use super::{
     device::Device,
@@ -43,6 +46,21 @@ pub struct RayTracingInstanceDesc {
     pub blas: Arc<RayTracingAcceleration>,
     pub transformation: Affine3A,
     pub mesh_index: u32,
+    /// Rays only see the instance if this intersects the mask they're traced with
+    pub mask: u8,
+    /// Disables back-face culling of the instance's triangles
+    pub double_sided: bool,
+}
+
+impl RayTracingInstanceDesc {
+    fn geometry_instance_flags(&self) -> vk::GeometryInstanceFlagsKHR {
+        if self.double_sided {
+            vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE
+                | vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE
+        } else {
+            vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -66,6 +84,21 @@ pub struct RayTracingShaderTableDesc {
 pub struct RayTracingAcceleration {
     pub raw: vk::AccelerationStructureKHR,
     backing_buffer: super::buffer::Buffer,
+    // Size of the backing buffer before compaction, if compacted
+    uncompacted_size: Option<usize>,
+}
+
+impl RayTracingAcceleration {
+    /// Size of the memory backing the acceleration structure, in bytes
+    pub fn size(&self) -> usize {
+        self.backing_buffer.desc.size
+    }
+
+    /// Bytes saved by compacting the acceleration structure after its build
+    pub fn compaction_savings(&self) -> usize {
+        self.uncompacted_size
+            .map_or(0, |uncompacted_size| uncompacted_size - self.size())
+    }
 }
 
 #[derive(Clone)]
@@ -75,6 +108,13 @@ pub struct RayTracingAccelerationScratchBuffer {
 
 const RT_TLAS_SCRATCH_BUFFER_SIZE: usize = 256 * 1024;
 
+// Updates must use the same flags as the build they refit.
+const TLAS_BUILD_FLAGS: vk::BuildAccelerationStructureFlagsKHR =
+    vk::BuildAccelerationStructureFlagsKHR::from_raw(
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE.as_raw()
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE.as_raw(),
+    );
+
 impl Device {
     pub fn create_ray_tracing_acceleration_scratch_buffer(
         &self,
@@ -144,7 +184,10 @@ impl Device {
 
         let geometry_info = ash::vk::AccelerationStructureBuildGeometryInfoKHR::builder()
             .ty(ash::vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
-            .flags(ash::vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .flags(
+                ash::vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                    | ash::vk::BuildAccelerationStructureFlagsKHR::ALLOW_COMPACTION,
+            )
             .geometries(geometries.as_slice())
             .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
             .build();
@@ -158,14 +201,174 @@ impl Device {
         // Create bottom-level acceleration structure
 
         let preallocate_bytes = 0;
-        self.create_ray_tracing_acceleration(
+        let blas = self.create_ray_tracing_acceleration(
             vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
             geometry_info,
             &build_range_infos,
             &max_primitive_counts,
             preallocate_bytes,
             None,
-        )
+        )?;
+
+        self.compact_ray_tracing_bottom_acceleration(blas)
+    }
+
+    // Copies the acceleration structure into a buffer of its compacted size,
+    // and releases the original one.
+    fn compact_ray_tracing_bottom_acceleration(
+        &self,
+        accel: RayTracingAcceleration,
+    ) -> Result<RayTracingAcceleration, BackendError> {
+        let compacted_size = self.query_ray_tracing_acceleration_compacted_size(&accel);
+
+        let compacted_size = match compacted_size {
+            Ok(compacted_size) if compacted_size > 0 && compacted_size < accel.size() => {
+                compacted_size
+            }
+            Ok(_) => return Ok(accel),
+            Err(err) => {
+                self.destroy_ray_tracing_acceleration(accel);
+                return Err(err);
+            }
+        };
+
+        let compacted = (|| -> Result<RayTracingAcceleration, BackendError> {
+            let accel_buffer = self.create_buffer(
+                super::buffer::BufferDesc::new_gpu_only(
+                    compacted_size,
+                    vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                ),
+                "Acceleration structure buffer",
+                None,
+            )?;
+
+            let accel_info = ash::vk::AccelerationStructureCreateInfoKHR::builder()
+                .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+                .buffer(accel_buffer.raw)
+                .size(compacted_size as u64)
+                .build();
+
+            let accel_raw = match unsafe {
+                self.acceleration_structure_ext
+                    .create_acceleration_structure(&accel_info, None)
+            } {
+                Ok(accel_raw) => accel_raw,
+                Err(err) => {
+                    self.immediate_destroy_buffer(accel_buffer);
+                    return Err(err.into());
+                }
+            };
+
+            let compacted = RayTracingAcceleration {
+                raw: accel_raw,
+                backing_buffer: accel_buffer,
+                uncompacted_size: Some(accel.size()),
+            };
+
+            let copied = self.with_setup_cb(|cb| unsafe {
+                self.acceleration_structure_ext
+                    .cmd_copy_acceleration_structure(
+                        cb,
+                        &ash::vk::CopyAccelerationStructureInfoKHR::builder()
+                            .src(accel.raw)
+                            .dst(compacted.raw)
+                            .mode(vk::CopyAccelerationStructureModeKHR::COMPACT)
+                            .build(),
+                    );
+
+                self.raw.cmd_pipeline_barrier(
+                    cb,
+                    ash::vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
+                    ash::vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
+                    ash::vk::DependencyFlags::empty(),
+                    &[ash::vk::MemoryBarrier::builder()
+                        .src_access_mask(ash::vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR)
+                        .dst_access_mask(
+                            ash::vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR
+                                | ash::vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR,
+                        )
+                        .build()],
+                    &[],
+                    &[],
+                );
+            });
+
+            match copied {
+                Ok(()) => Ok(compacted),
+                Err(err) => {
+                    self.destroy_ray_tracing_acceleration(compacted);
+                    Err(err)
+                }
+            }
+        })();
+
+        // `with_setup_cb` waits for the device, so the original is no longer in use.
+        self.destroy_ray_tracing_acceleration(accel);
+
+        let compacted = compacted?;
+
+        log::info!(
+            "Compacted acceleration structure from {} to {} bytes",
+            compacted.uncompacted_size.unwrap_or_default(),
+            compacted.size(),
+        );
+
+        Ok(compacted)
+    }
+
+    fn query_ray_tracing_acceleration_compacted_size(
+        &self,
+        accel: &RayTracingAcceleration,
+    ) -> Result<usize, BackendError> {
+        let query_pool = unsafe {
+            self.raw.create_query_pool(
+                &vk::QueryPoolCreateInfo::builder()
+                    .query_type(vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR)
+                    .query_count(1),
+                None,
+            )?
+        };
+
+        let res = self
+            .with_setup_cb(|cb| unsafe {
+                self.raw.cmd_reset_query_pool(cb, query_pool, 0, 1);
+                self.acceleration_structure_ext
+                    .cmd_write_acceleration_structures_properties(
+                        cb,
+                        std::slice::from_ref(&accel.raw),
+                        vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR,
+                        query_pool,
+                        0,
+                    );
+            })
+            .and_then(|()| {
+                let mut compacted_size = [0u64];
+                unsafe {
+                    self.raw.get_query_pool_results(
+                        query_pool,
+                        0,
+                        1,
+                        &mut compacted_size,
+                        vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                    )?;
+                }
+                Ok(compacted_size[0] as usize)
+            });
+
+        unsafe {
+            self.raw.destroy_query_pool(query_pool, None);
+        }
+
+        res
+    }
+
+    fn destroy_ray_tracing_acceleration(&self, accel: RayTracingAcceleration) {
+        unsafe {
+            self.acceleration_structure_ext
+                .destroy_acceleration_structure(accel.raw, None);
+        }
+        self.immediate_destroy_buffer(accel.backing_buffer);
     }
 
     pub fn create_ray_tracing_top_acceleration(
@@ -208,11 +411,9 @@ impl Device {
                 GeometryInstance::new(
                     transform,
                     desc.mesh_index, /* instance id */
-                    0xff,
+                    desc.mask,
                     0,
-                    /*ash::vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE
-                    | */
-                    ash::vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE,
+                    desc.geometry_instance_flags(),
                     blas_address,
                 )
             })
@@ -255,7 +456,7 @@ impl Device {
 
         let geometry_info = ash::vk::AccelerationStructureBuildGeometryInfoKHR::builder()
             .ty(ash::vk::AccelerationStructureTypeKHR::TOP_LEVEL)
-            .flags(ash::vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .flags(TLAS_BUILD_FLAGS)
             .geometries(std::slice::from_ref(&geometry))
             .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
             .build();
@@ -392,6 +593,7 @@ impl Device {
                 Ok(RayTracingAcceleration {
                     raw: accel_raw,
                     backing_buffer: accel_buffer,
+                    uncompacted_size: None,
                 })
             }
         };
@@ -440,11 +642,9 @@ impl Device {
             GeometryInstance::new(
                 transform,
                 desc.mesh_index, /* instance id */
-                0xff,
+                desc.mask,
                 0,
-                /*ash::vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE
-                | */
-                ash::vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE,
+                desc.geometry_instance_flags(),
                 blas_address,
             )
         }));
@@ -459,6 +659,47 @@ impl Device {
         instance_count: usize,
         tlas: &RayTracingAcceleration,
         scratch_buffer: &RayTracingAccelerationScratchBuffer,
+    ) {
+        self.build_ray_tracing_top_acceleration(
+            cb,
+            instance_buffer_address,
+            instance_count,
+            tlas,
+            scratch_buffer,
+            vk::BuildAccelerationStructureModeKHR::BUILD,
+        )
+    }
+
+    /// Refits the TLAS in place. Much cheaper than a rebuild, but only valid when
+    /// the instances are the same as in the last build, with only their transforms
+    /// changed. Trace performance degrades as the instances move further from where
+    /// they were built, so a full rebuild should happen every now and then.
+    pub fn update_ray_tracing_top_acceleration(
+        &self,
+        cb: vk::CommandBuffer,
+        instance_buffer_address: vk::DeviceAddress,
+        instance_count: usize,
+        tlas: &RayTracingAcceleration,
+        scratch_buffer: &RayTracingAccelerationScratchBuffer,
+    ) {
+        self.build_ray_tracing_top_acceleration(
+            cb,
+            instance_buffer_address,
+            instance_count,
+            tlas,
+            scratch_buffer,
+            vk::BuildAccelerationStructureModeKHR::UPDATE,
+        )
+    }
+
+    fn build_ray_tracing_top_acceleration(
+        &self,
+        cb: vk::CommandBuffer,
+        instance_buffer_address: vk::DeviceAddress,
+        instance_count: usize,
+        tlas: &RayTracingAcceleration,
+        scratch_buffer: &RayTracingAccelerationScratchBuffer,
+        mode: vk::BuildAccelerationStructureModeKHR,
     ) {
         let geometry = ash::vk::AccelerationStructureGeometryKHR::builder()
             .geometry_type(ash::vk::GeometryTypeKHR::INSTANCES)
@@ -477,15 +718,13 @@ impl Device {
 
         let geometry_info = ash::vk::AccelerationStructureBuildGeometryInfoKHR::builder()
             .ty(ash::vk::AccelerationStructureTypeKHR::TOP_LEVEL)
-            .flags(ash::vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .flags(TLAS_BUILD_FLAGS)
             .geometries(std::slice::from_ref(&geometry))
-            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .mode(mode)
             .build();
 
         let max_primitive_counts = [instance_count as u32];
 
-        // Create top-level acceleration structure
-
         self.rebuild_ray_tracing_acceleration(
             cb,
             geometry_info,
@@ -522,12 +761,22 @@ impl Device {
 
         let scratch_buffer = scratch_buffer.buffer.lock();
 
+        let is_update = geometry_info.mode == vk::BuildAccelerationStructureModeKHR::UPDATE;
+        let scratch_size = if is_update {
+            memory_requirements.update_scratch_size
+        } else {
+            memory_requirements.build_scratch_size
+        };
+
         assert!(
-            memory_requirements.build_scratch_size as usize <= scratch_buffer.desc.size,
+            scratch_size as usize <= scratch_buffer.desc.size,
             "todo: scratch"
         );
 
         unsafe {
+            if is_update {
+                geometry_info.src_acceleration_structure = accel.raw;
+            }
             geometry_info.dst_acceleration_structure = accel.raw;
             geometry_info.scratch_data = ash::vk::DeviceOrHostAddressKHR {
                 device_address: self.raw.get_buffer_device_address(
@@ -871,7 +1120,7 @@ pub fn create_ray_tracing_pipeline(
             .ray_tracing_pipeline_ext
             .create_ray_tracing_pipelines(
                 vk::DeferredOperationKHR::null(),
-                vk::PipelineCache::null(),
+                device.pipeline_cache,
                 &[ash::vk::RayTracingPipelineCreateInfoKHR::builder()
                     .stages(&shader_stages)
                     .groups(&shader_groups)
@@ -913,6 +1162,9 @@ pub fn create_ray_tracing_pipeline(
                 pipeline,
                 //render_pass: desc.render_pass.clone(),
                 set_layout_info,
+                binding_reflection: reflect_shader_bindings(
+                    shaders.iter().map(|shader| &shader.code[..]),
+                ),
                 descriptor_pool_sizes,
                 descriptor_set_layouts,
                 pipeline_bind_point: vk::PipelineBindPoint::RAY_TRACING_KHR,
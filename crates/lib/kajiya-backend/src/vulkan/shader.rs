@@ -3,8 +3,13 @@
 use super::{
     device::{Device, SamplerDesc},
     image::ImageDesc,
+    mesh_shader::{SHADER_STAGE_MESH, SHADER_STAGE_TASK},
+};
+use crate::{
+    chunky_list::TempList,
+    shader_compiler::get_cs_local_size_from_spirv,
+    shader_reflection::{reflect_shader_bindings, ShaderBindingReflections},
 };
-use crate::{chunky_list::TempList, shader_compiler::get_cs_local_size_from_spirv};
 use arrayvec::ArrayVec;
 use ash::vk;
 use byte_slice_cast::AsSliceOf as _;
@@ -27,6 +32,7 @@ pub struct ShaderPipelineCommon {
     pub pipeline_layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
     pub set_layout_info: Vec<HashMap<u32, vk::DescriptorType>>,
+    pub binding_reflection: ShaderBindingReflections,
     pub descriptor_pool_sizes: Vec<vk::DescriptorPoolSize>,
     pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
     pub pipeline_bind_point: vk::PipelineBindPoint,
@@ -46,6 +52,8 @@ impl std::ops::Deref for ComputePipeline {
 
 pub struct RasterPipeline {
     pub common: ShaderPipelineCommon,
+    /// Stages with access to descriptors and push constants
+    pub stage_flags: vk::ShaderStageFlags,
 }
 
 impl std::ops::Deref for RasterPipeline {
@@ -345,6 +353,31 @@ impl ShaderSource {
     }
 }
 
+/// A preprocessor define passed to the shader compiler, used to create permutations
+/// of a single shader source. Ignored by Rust shaders.
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct ShaderDefine {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl ShaderDefine {
+    pub fn new(name: impl Into<String>, value: impl ToString) -> Self {
+        Self {
+            name: name.into(),
+            value: Some(value.to_string()),
+        }
+    }
+
+    /// A define without a value, like `-DNAME`
+    pub fn flag(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: None,
+        }
+    }
+}
+
 #[derive(Builder, Clone)]
 #[builder(pattern = "owned", derive(Clone))]
 pub struct ComputePipelineDesc {
@@ -353,6 +386,8 @@ pub struct ComputePipelineDesc {
     #[builder(default)]
     pub push_constants_bytes: usize,
     pub source: ShaderSource,
+    #[builder(default)]
+    pub defines: Vec<ShaderDefine>,
 }
 
 impl ComputePipelineDescBuilder {
@@ -441,8 +476,7 @@ pub fn create_compute_pipeline(
 
         let pipeline = device
             .raw
-            // TODO: pipeline cache
-            .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .create_compute_pipelines(device.pipeline_cache, &[pipeline_info.build()], None)
             .expect("pipeline")[0];
 
         let mut descriptor_pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
@@ -464,6 +498,7 @@ pub fn create_compute_pipeline(
                 pipeline_layout,
                 pipeline,
                 set_layout_info,
+                binding_reflection: reflect_shader_bindings(std::iter::once(spirv)),
                 descriptor_pool_sizes,
                 descriptor_set_layouts,
                 pipeline_bind_point: vk::PipelineBindPoint::COMPUTE,
@@ -480,6 +515,10 @@ pub enum ShaderPipelineStage {
     RayGen,
     RayMiss,
     RayClosestHit,
+    /// Optional, precedes `Mesh`. Requires `Device::mesh_shaders_enabled`.
+    Task,
+    /// Replaces `Vertex` and the input assembler. Requires `Device::mesh_shaders_enabled`.
+    Mesh,
 }
 
 #[derive(Builder, Hash, PartialEq, Eq, Clone, Debug)]
@@ -493,6 +532,8 @@ pub struct PipelineShaderDesc {
     #[builder(default = "\"main\".to_owned()")]
     pub entry: String,
     pub source: ShaderSource,
+    #[builder(default)]
+    pub defines: Vec<ShaderDefine>,
 }
 
 impl PipelineShaderDesc {
@@ -834,10 +875,36 @@ pub fn create_raster_pipeline(
         })
         .collect::<Vec<_>>();
 
+    let uses_mesh_shaders = shaders.iter().any(|shader| {
+        matches!(
+            shader.desc.stage,
+            ShaderPipelineStage::Task | ShaderPipelineStage::Mesh
+        )
+    });
+
+    if uses_mesh_shaders {
+        anyhow::ensure!(
+            device.mesh_shaders_enabled(),
+            "Mesh shaders are not supported by the device"
+        );
+        anyhow::ensure!(
+            shaders
+                .iter()
+                .all(|shader| shader.desc.stage != ShaderPipelineStage::Vertex),
+            "Mesh shader pipelines can't have a vertex shader"
+        );
+    }
+
+    let stage_flags = if uses_mesh_shaders {
+        vk::ShaderStageFlags::ALL_GRAPHICS | SHADER_STAGE_TASK | SHADER_STAGE_MESH
+    } else {
+        vk::ShaderStageFlags::ALL_GRAPHICS
+    };
+
     let (descriptor_set_layouts, set_layout_info) = super::shader::create_descriptor_set_layouts(
         device,
         &merge_shader_stage_layouts(stage_layouts),
-        vk::ShaderStageFlags::ALL_GRAPHICS,
+        stage_flags,
         //desc.descriptor_set_layout_flags.unwrap_or(&[]),  // TODO: merge flags
         &desc.descriptor_set_opts,
     );
@@ -847,7 +914,7 @@ pub fn create_raster_pipeline(
             vk::PipelineLayoutCreateInfo::builder().set_layouts(&descriptor_set_layouts);
 
         let push_constant_ranges = vk::PushConstantRange {
-            stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+            stage_flags,
             offset: 0,
             size: desc.push_constants_bytes as _,
         };
@@ -877,6 +944,8 @@ pub fn create_raster_pipeline(
                 let stage = match desc.desc.stage {
                     ShaderPipelineStage::Vertex => vk::ShaderStageFlags::VERTEX,
                     ShaderPipelineStage::Pixel => vk::ShaderStageFlags::FRAGMENT,
+                    ShaderPipelineStage::Task => SHADER_STAGE_TASK,
+                    ShaderPipelineStage::Mesh => SHADER_STAGE_MESH,
                     _ => unimplemented!(),
                 };
 
@@ -958,10 +1027,8 @@ pub fn create_raster_pipeline(
         let dynamic_state_info =
             vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_state);
 
-        let graphic_pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        let mut graphic_pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(&shader_stage_create_infos)
-            .vertex_input_state(&vertex_input_state_info)
-            .input_assembly_state(&vertex_input_assembly_state_info)
             .viewport_state(&viewport_state_info)
             .rasterization_state(&rasterization_info)
             .multisample_state(&multisample_state_info)
@@ -971,10 +1038,17 @@ pub fn create_raster_pipeline(
             .layout(pipeline_layout)
             .render_pass(desc.render_pass.raw);
 
+        // Mesh shaders generate primitives themselves
+        if !uses_mesh_shaders {
+            graphic_pipeline_info = graphic_pipeline_info
+                .vertex_input_state(&vertex_input_state_info)
+                .input_assembly_state(&vertex_input_assembly_state_info);
+        }
+
         let pipeline = device
             .raw
             .create_graphics_pipelines(
-                vk::PipelineCache::null(),
+                device.pipeline_cache,
                 &[graphic_pipeline_info.build()],
                 None,
             )
@@ -1000,10 +1074,14 @@ pub fn create_raster_pipeline(
                 pipeline,
                 //render_pass: desc.render_pass.clone(),
                 set_layout_info,
+                binding_reflection: reflect_shader_bindings(
+                    shaders.iter().map(|shader| &shader.code[..]),
+                ),
                 descriptor_pool_sizes,
                 descriptor_set_layouts,
                 pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
             },
+            stage_flags,
         })
     }
 }
@@ -32,6 +32,16 @@ pub struct Swapchain {
     surface: Arc<Surface>,
 }
 
+/// Describes the content of HDR swapchains to the display, which may use it to tone map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HdrMetadata {
+    /// Peak luminance of the content, in nits
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+    /// Maximum average luminance of a frame, in nits; 0 if unknown
+    pub max_frame_average_light_level: f32,
+}
+
 pub struct SwapchainImage {
     pub image: Arc<crate::Image>,
     pub image_index: u32,
@@ -167,13 +177,14 @@ impl Swapchain {
                         image_type: crate::ImageType::Tex2d,
                         usage: vk::ImageUsageFlags::STORAGE,
                         flags: vk::ImageCreateFlags::empty(),
-                        format: vk::Format::B8G8R8A8_UNORM,
+                        format: desc.format.format,
                         extent: [desc.dims.width, desc.dims.height, 0],
                         tiling: vk::ImageTiling::OPTIMAL,
                         mip_levels: 1,
                         array_elements: 1,
                     },
                     views: Default::default(),
+                    allocation: None,
                 })
             })
             .collect();
@@ -215,6 +226,47 @@ impl Swapchain {
         })
     }
 
+    /// Whether the swapchain expects HDR content, as opposed to sRGB
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.desc.format.color_space,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT | vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+        )
+    }
+
+    /// No-op for SDR swapchains, or if `VK_EXT_hdr_metadata` is not supported.
+    pub fn set_hdr_metadata(&self, metadata: HdrMetadata) {
+        let fns = match &self.device.hdr_metadata_ext {
+            Some(fns) if self.is_hdr() => fns,
+            _ => return,
+        };
+
+        let xy = |x: f32, y: f32| vk::XYColorEXT { x, y };
+
+        // The mastering display has the primaries of the swapchain's color space
+        let (red, green, blue) =
+            if self.desc.format.color_space == vk::ColorSpaceKHR::HDR10_ST2084_EXT {
+                (xy(0.708, 0.292), xy(0.170, 0.797), xy(0.131, 0.046))
+            } else {
+                (xy(0.640, 0.330), xy(0.300, 0.600), xy(0.150, 0.060))
+            };
+
+        let hdr_metadata = vk::HdrMetadataEXT::builder()
+            .display_primary_red(red)
+            .display_primary_green(green)
+            .display_primary_blue(blue)
+            .white_point(xy(0.3127, 0.3290))
+            .max_luminance(metadata.max_luminance)
+            .min_luminance(metadata.min_luminance)
+            .max_content_light_level(metadata.max_luminance)
+            .max_frame_average_light_level(metadata.max_frame_average_light_level)
+            .build();
+
+        unsafe {
+            fns.set_hdr_metadata_ext(self.device.raw.handle(), 1, &self.raw, &hdr_metadata);
+        }
+    }
+
     pub fn extent(&self) -> [u32; 2] {
         [self.desc.dims.width, self.desc.dims.height]
     }
@@ -0,0 +1,382 @@
+use std::{marker::PhantomData, panic::Location, sync::Arc};
+
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{
+        barrier::image_aspect_mask_from_format,
+        buffer::{Buffer, BufferDesc},
+    },
+    ImageDesc, ImageType, ImageViewDesc,
+};
+
+use crate::{
+    graph::{
+        GraphResourceCreateInfo, GraphResourceImportInfo, GraphResourceInfo, RecordedPass,
+        RenderGraph,
+    },
+    GraphRawResourceHandle, GraphResourceDesc, Handle, Image, SimpleRenderPass,
+};
+
+// uint4 raw texel bits, uint2 pixel, uint is_uint, uint frame
+pub(crate) const DEBUG_PICK_SLOT_BYTES: usize = 32;
+
+/// Selects what a `GraphDebugHook` displays from the hooked pass.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GraphDebugView {
+    /// Index into `RgDebugPass::images`. `None` picks the first 2D color image written by the pass.
+    pub image: Option<usize>,
+    pub mip_level: u32,
+    pub array_layer: u32,
+    /// One bit per RGBA channel. A single selected channel is displayed as grayscale.
+    pub channel_mask: u32,
+    /// Values in this range are remapped to [0, 1] for display.
+    pub range: [f32; 2],
+    /// Normalized coordinates of a pixel whose raw value should be read back.
+    /// See `Renderer::debug_picked_pixel`.
+    pub pick_uv: Option<[f32; 2]>,
+}
+
+impl Default for GraphDebugView {
+    fn default() -> Self {
+        Self {
+            image: None,
+            mip_level: 0,
+            array_layer: 0,
+            channel_mask: 0b1111,
+            range: [0.0, 1.0],
+            pick_uv: None,
+        }
+    }
+}
+
+/// An image used by a pass.
+#[derive(Clone, Debug)]
+pub struct RgDebugPassImage {
+    pub desc: ImageDesc,
+    pub written: bool,
+    /// Whether the image can be displayed via `GraphDebugView`.
+    pub inspectable: bool,
+    pub created_at: Option<&'static Location<'static>>,
+}
+
+impl RgDebugPassImage {
+    /// Whether the image holds unsigned integers, which are inspected with a separate shader variant.
+    pub fn is_uint(&self) -> bool {
+        is_uint_format(self.desc.format)
+    }
+}
+
+/// A pass along with the images it uses, as listed by `RenderGraph::debug_pass_list`.
+#[derive(Clone, Debug)]
+pub struct RgDebugPass {
+    pub name: String,
+    pub idx: usize,
+    pub images: Vec<RgDebugPassImage>,
+}
+
+impl RgDebugPass {
+    /// The image `view` selects from this pass.
+    pub fn selected_image(&self, view: &GraphDebugView) -> Option<&RgDebugPassImage> {
+        match view.image {
+            Some(idx) => self.images.get(idx),
+            None => self
+                .images
+                .iter()
+                .find(|image| is_default_debug_image(image)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RgDebugPixelValue {
+    Float([f32; 4]),
+    Uint([u32; 4]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RgDebugPickedPixel {
+    pub pixel: [u32; 2],
+    pub value: RgDebugPixelValue,
+}
+
+impl RgDebugPickedPixel {
+    pub(crate) fn from_slot(slot: &[u8], expected_frame: u32) -> Option<Self> {
+        let mut words = [0u32; DEBUG_PICK_SLOT_BYTES / 4];
+        for (word, bytes) in words.iter_mut().zip(slot.chunks_exact(4)) {
+            *word = u32::from_ne_bytes(bytes.try_into().unwrap());
+        }
+
+        let [r, g, b, a, x, y, is_uint, frame] = words;
+
+        if frame != expected_frame {
+            return None;
+        }
+
+        Some(Self {
+            pixel: [x, y],
+            value: if is_uint != 0 {
+                RgDebugPixelValue::Uint([r, g, b, a])
+            } else {
+                RgDebugPixelValue::Float([r, g, b, a].map(f32::from_bits))
+            },
+        })
+    }
+}
+
+/// Where the debug pass should write the picked pixel
+pub(crate) struct DebugPickTarget {
+    pub(crate) buffer: Arc<Buffer>,
+    pub(crate) slot: u32,
+    pub(crate) frame: u32,
+}
+
+fn is_uint_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_UINT
+            | vk::Format::R16_UINT
+            | vk::Format::R32_UINT
+            | vk::Format::R32G32_UINT
+            | vk::Format::R8G8B8A8_UINT
+            | vk::Format::R16G16B16A16_UINT
+            | vk::Format::R32G32B32A32_UINT
+    )
+}
+
+// The debug pass views images as 2D arrays
+fn is_inspectable_image_type(image_type: ImageType) -> bool {
+    matches!(
+        image_type,
+        ImageType::Tex2d | ImageType::Tex2dArray | ImageType::Cube | ImageType::CubeArray
+    )
+}
+
+// Displayed when `GraphDebugView::image` is `None`
+fn is_default_debug_image(image: &RgDebugPassImage) -> bool {
+    image.written
+        && image.desc.image_type == ImageType::Tex2d
+        && image_aspect_mask_from_format(image.desc.format) == vk::ImageAspectFlags::COLOR
+}
+
+impl RenderGraph {
+    /// Images used by the pass, in order of first reference, reads before writes.
+    pub(crate) fn debug_pass_images(
+        &self,
+        pass: &RecordedPass,
+    ) -> Vec<(GraphRawResourceHandle, RgDebugPassImage)> {
+        let mut images: Vec<(GraphRawResourceHandle, RgDebugPassImage)> = Vec::new();
+
+        for (res_ref, written) in pass
+            .read
+            .iter()
+            .map(|r| (r, false))
+            .chain(pass.write.iter().map(|r| (r, true)))
+        {
+            if let Some((_, image)) = images
+                .iter_mut()
+                .find(|(handle, _)| handle.id == res_ref.handle.id)
+            {
+                image.written |= written;
+                continue;
+            }
+
+            let (desc, inspectable) = match &self.resources[res_ref.handle.id as usize] {
+                // Resources created by the render graph get `SAMPLED` usage from the debug pass
+                GraphResourceInfo::Created(GraphResourceCreateInfo {
+                    desc: GraphResourceDesc::Image(desc),
+                }) => (*desc, is_inspectable_image_type(desc.image_type)),
+
+                // Imported resources have their usage flags supplied externally
+                GraphResourceInfo::Imported(GraphResourceImportInfo::Image {
+                    resource, ..
+                }) => (
+                    resource.desc,
+                    is_inspectable_image_type(resource.desc.image_type)
+                        && resource.desc.usage.contains(vk::ImageUsageFlags::SAMPLED),
+                ),
+                _ => continue,
+            };
+
+            images.push((
+                res_ref.handle,
+                RgDebugPassImage {
+                    desc,
+                    written,
+                    inspectable,
+                    created_at: self
+                        .resource_creation_sites
+                        .get(res_ref.handle.id as usize)
+                        .copied(),
+                },
+            ));
+        }
+
+        images
+    }
+
+    /// Lists the passes recorded so far along with the images they use,
+    /// for selecting what to display with a `GraphDebugHook`.
+    pub fn debug_pass_list(&self) -> Vec<RgDebugPass> {
+        self.passes
+            .iter()
+            .map(|pass| RgDebugPass {
+                name: pass.name.clone(),
+                idx: pass.idx,
+                images: self
+                    .debug_pass_images(pass)
+                    .into_iter()
+                    .map(|(_, image)| image)
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn select_debug_image(
+        &self,
+        pass: &RecordedPass,
+        view: &GraphDebugView,
+    ) -> Option<Handle<Image>> {
+        let mut images = self.debug_pass_images(pass).into_iter();
+
+        let (raw, image) = match view.image {
+            Some(idx) => images.nth(idx)?,
+            None => images.find(|(_, image)| is_default_debug_image(image))?,
+        };
+
+        if !image.inspectable {
+            return None;
+        }
+
+        Some(Handle {
+            raw,
+            desc: image.desc,
+            marker: PhantomData,
+        })
+    }
+
+    /// Copies the selected mip and layer of `src` into a displayable image,
+    /// optionally reading back the value of a single pixel.
+    pub(crate) fn record_debug_view_pass(
+        &mut self,
+        src: &Handle<Image>,
+        view: &GraphDebugView,
+    ) -> Handle<Image> {
+        let src_desc = *src.desc();
+        let mip_level = view.mip_level.min(src_desc.mip_levels as u32 - 1);
+        let array_layer = view.array_layer.min(src_desc.array_layer_count() - 1);
+
+        let extent = [
+            (src_desc.extent[0] >> mip_level).max(1),
+            (src_desc.extent[1] >> mip_level).max(1),
+        ];
+
+        let mut dst = self.create(ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, extent));
+
+        let pick_target = self
+            .debug_pick_target
+            .as_ref()
+            .map(|target| (target.buffer.clone(), target.slot, target.frame));
+
+        let (mut pick_buf, pick_slot, pick_frame) = match pick_target {
+            Some((buffer, slot, frame)) => (self.import(buffer, AccessType::Nothing), slot, frame),
+            None => (
+                self.create(BufferDesc::new_gpu_only(
+                    DEBUG_PICK_SLOT_BYTES,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                )),
+                0,
+                0,
+            ),
+        };
+
+        let aspect_mask = if image_aspect_mask_from_format(src_desc.format)
+            .contains(vk::ImageAspectFlags::DEPTH)
+        {
+            vk::ImageAspectFlags::DEPTH
+        } else {
+            vk::ImageAspectFlags::COLOR
+        };
+
+        let is_uint = if is_uint_format(src_desc.format) {
+            "1"
+        } else {
+            "0"
+        };
+
+        // Negative coordinates disable picking
+        let pick_uv = view.pick_uv.unwrap_or([-1.0, -1.0]);
+
+        SimpleRenderPass::new_compute_with_defines(
+            self.add_pass("debug"),
+            "/shaders/debug_view/inspect.hlsl",
+            &[("IS_UINT", is_uint)],
+        )
+        .read_view(
+            src,
+            ImageViewDesc::builder()
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .aspect_mask(aspect_mask)
+                .base_mip_level(mip_level)
+                .level_count(Some(1))
+                .layer_count(Some(src_desc.array_layer_count())),
+        )
+        .write(&mut dst)
+        .write(&mut pick_buf)
+        .constants((
+            [view.range[0], view.range[1], pick_uv[0], pick_uv[1]],
+            [view.channel_mask, array_layer, pick_slot, pick_frame],
+        ))
+        .dispatch([extent[0], extent[1], 1]);
+
+        dst
+    }
+}
+
+#[test]
+fn test_layer_range_out_of_bounds() {
+    use crate::RgValidationErrorKind;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let mut img = rg.create(ImageDesc::new_cube(vk::Format::R16G16B16A16_SFLOAT, 8));
+
+    SimpleRenderPass::new_compute(rg.add_pass("all faces"), "/shaders/blur.hlsl")
+        .write_view(
+            &mut img,
+            ImageViewDesc::builder()
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .layer_count(Some(6)),
+        )
+        .dispatch([8, 8, 1]);
+
+    // The inspector views all layers, and selects one in the shader
+    rg.record_debug_view_pass(
+        &img,
+        &GraphDebugView {
+            array_layer: 5,
+            ..Default::default()
+        },
+    );
+    assert!(rg.validation_errors().is_empty());
+
+    SimpleRenderPass::new_compute(rg.add_pass("too many layers"), "/shaders/blur.hlsl")
+        .write_view(
+            &mut img,
+            ImageViewDesc::builder()
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .layer_count(Some(7)),
+        )
+        .dispatch([8, 8, 1]);
+
+    let errors = rg.validation_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        errors[0].kind,
+        RgValidationErrorKind::LayerRangeOutOfBounds {
+            layer_count: 7,
+            array_layers: 6,
+        }
+    ));
+}
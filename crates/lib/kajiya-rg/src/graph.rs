@@ -3,11 +3,14 @@
 use crate::{renderer::FrameConstantsLayout, resource_registry::PendingRenderResourceInfo};
 
 use super::{
+    debug_view::{DebugPickTarget, GraphDebugView},
     pass_builder::PassBuilder,
+    pass_timings::{PassCpuTiming, PassCpuTimings},
     resource::*,
     resource_registry::{
         AnyRenderResource, AnyRenderResourceRef, RegistryResource, ResourceRegistry,
     },
+    validation::{PassBindingValidator, RenderGraphValidator, RgValidationError},
     RenderPassApi,
 };
 
@@ -41,8 +44,10 @@ use std::{
     ffi::CString,
     hash::Hash,
     marker::PhantomData,
+    panic::Location,
     path::{Path, PathBuf},
     sync::{Arc, Weak},
+    time::Instant,
 };
 
 #[derive(Clone)]
@@ -79,7 +84,7 @@ pub(crate) enum ExportableGraphResource {
 }
 
 impl ExportableGraphResource {
-    fn raw(&self) -> GraphRawResourceHandle {
+    pub(crate) fn raw(&self) -> GraphRawResourceHandle {
         match self {
             ExportableGraphResource::Image(h) => h.raw,
             ExportableGraphResource::Buffer(h) => h.raw,
@@ -126,14 +131,15 @@ pub struct RenderDebugHook {
     pub id: u64,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq)]
 pub struct GraphDebugHook {
     pub render_debug_hook: RenderDebugHook,
+    pub view: GraphDebugView,
 }
 
 pub struct RenderGraph {
-    passes: Vec<RecordedPass>,
-    resources: Vec<GraphResourceInfo>,
+    pub(crate) passes: Vec<RecordedPass>,
+    pub(crate) resources: Vec<GraphResourceInfo>,
     exported_resources: Vec<(ExportableGraphResource, vk_sync::AccessType)>,
     pub(crate) compute_pipelines: Vec<RgComputePipeline>,
     pub(crate) raster_pipelines: Vec<RgRasterPipeline>,
@@ -142,6 +148,11 @@ pub struct RenderGraph {
 
     pub debug_hook: Option<GraphDebugHook>,
     pub debugged_resource: Option<Handle<Image>>,
+    pub(crate) debug_pick_target: Option<DebugPickTarget>,
+
+    // Where each resource was created or imported; used in validation errors.
+    pub(crate) resource_creation_sites: Vec<&'static Location<'static>>,
+    pub(crate) validator: Option<RenderGraphValidator>,
 }
 
 pub trait ImportExportToRenderGraph
@@ -162,19 +173,15 @@ where
 }
 
 impl ImportExportToRenderGraph for Image {
+    #[track_caller]
     fn import(
         self: Arc<Self>,
         rg: &mut RenderGraph,
         access_type_at_import_time: vk_sync::AccessType,
     ) -> Handle<Self> {
-        let res = GraphRawResourceHandle {
-            id: rg.resources.len() as u32,
-            version: 0,
-        };
-
         let desc = self.desc;
 
-        rg.resources.push(GraphResourceInfo::Imported(
+        let res = rg.push_resource(GraphResourceInfo::Imported(
             GraphResourceImportInfo::Image {
                 resource: self,
                 access_type: access_type_at_import_time,
@@ -204,19 +211,15 @@ impl ImportExportToRenderGraph for Image {
 }
 
 impl ImportExportToRenderGraph for Buffer {
+    #[track_caller]
     fn import(
         self: Arc<Self>,
         rg: &mut RenderGraph,
         access_type_at_import_time: vk_sync::AccessType,
     ) -> Handle<Self> {
-        let res = GraphRawResourceHandle {
-            id: rg.resources.len() as u32,
-            version: 0,
-        };
-
         let desc = self.desc;
 
-        rg.resources.push(GraphResourceInfo::Imported(
+        let res = rg.push_resource(GraphResourceInfo::Imported(
             GraphResourceImportInfo::Buffer {
                 resource: self,
                 access_type: access_type_at_import_time,
@@ -246,19 +249,15 @@ impl ImportExportToRenderGraph for Buffer {
 }
 
 impl ImportExportToRenderGraph for RayTracingAcceleration {
+    #[track_caller]
     fn import(
         self: Arc<Self>,
         rg: &mut RenderGraph,
         access_type_at_import_time: vk_sync::AccessType,
     ) -> Handle<Self> {
-        let res = GraphRawResourceHandle {
-            id: rg.resources.len() as u32,
-            version: 0,
-        };
-
         let desc = RayTracingAccelerationDesc;
 
-        rg.resources.push(GraphResourceInfo::Imported(
+        let res = rg.push_resource(GraphResourceInfo::Imported(
             GraphResourceImportInfo::RayTracingAcceleration {
                 resource: self,
                 access_type: access_type_at_import_time,
@@ -311,9 +310,13 @@ impl RenderGraph {
             predefined_descriptor_set_layouts: HashMap::new(),
             debug_hook: None,
             debugged_resource: None,
+            debug_pick_target: None,
+            resource_creation_sites: Vec::new(),
+            validator: None,
         }
     }
 
+    #[track_caller]
     pub fn create<Desc: ResourceDesc>(
         &mut self,
         desc: Desc,
@@ -332,19 +335,27 @@ impl RenderGraph {
         handle
     }
 
+    #[track_caller]
     pub(crate) fn create_raw_resource(
         &mut self,
         info: GraphResourceCreateInfo,
     ) -> GraphRawResourceHandle {
+        self.push_resource(GraphResourceInfo::Created(info))
+    }
+
+    #[track_caller]
+    pub(crate) fn push_resource(&mut self, info: GraphResourceInfo) -> GraphRawResourceHandle {
         let res = GraphRawResourceHandle {
             id: self.resources.len() as u32,
             version: 0,
         };
 
-        self.resources.push(GraphResourceInfo::Created(info));
+        self.resources.push(info);
+        self.resource_creation_sites.push(Location::caller());
         res
     }
 
+    #[track_caller]
     pub fn import<Res: ImportExportToRenderGraph>(
         &mut self,
         resource: Arc<Res>,
@@ -361,13 +372,9 @@ impl RenderGraph {
         ImportExportToRenderGraph::export(resource, self, access_type)
     }
 
+    #[track_caller]
     pub fn get_swap_chain(&mut self) -> Handle<Image> {
-        let res = GraphRawResourceHandle {
-            id: self.resources.len() as u32,
-            version: 0,
-        };
-
-        self.resources.push(GraphResourceInfo::Imported(
+        let res = self.push_resource(GraphResourceInfo::Imported(
             GraphResourceImportInfo::SwapchainImage,
         ));
 
@@ -386,10 +393,10 @@ struct ResourceLifetime {
     last_access: Option<usize>,
 }
 
-struct ResourceInfo {
+pub(crate) struct ResourceInfo {
     _lifetimes: Vec<ResourceLifetime>,
-    image_usage_flags: Vec<vk::ImageUsageFlags>,
-    buffer_usage_flags: Vec<vk::BufferUsageFlags>,
+    pub(crate) image_usage_flags: Vec<vk::ImageUsageFlags>,
+    pub(crate) buffer_usage_flags: Vec<vk::BufferUsageFlags>,
 }
 
 pub struct RenderGraphExecutionParams<'a> {
@@ -414,6 +421,7 @@ pub struct CompiledRenderGraph {
 
 struct PendingDebugPass {
     img: Handle<Image>,
+    view: GraphDebugView,
 }
 
 impl RenderGraph {
@@ -427,7 +435,7 @@ impl RenderGraph {
         }
     }
 
-    fn calculate_resource_info(&self) -> ResourceInfo {
+    pub(crate) fn calculate_resource_info(&self) -> ResourceInfo {
         let mut lifetimes: Vec<ResourceLifetime> = self
             .resources
             .iter()
@@ -547,7 +555,45 @@ impl RenderGraph {
         }
     }
 
+    /// Opt into CPU-side checks of pass resource usage. Errors are collected as passes
+    /// are added, and the graph panics with all of them when it is compiled. Resources
+    /// bound by render functions are checked against each pass's declarations, and against
+    /// the bindings declared by their shaders, as it executes.
+    pub fn enable_validation(&mut self) {
+        if self.validator.is_none() {
+            let mut validator = RenderGraphValidator::default();
+            for pass in &self.passes {
+                validator.mark_pass_writes(pass);
+            }
+            self.validator = Some(validator);
+        }
+    }
+
+    pub fn validation_errors(&self) -> &[RgValidationError] {
+        self.validator
+            .as_ref()
+            .map(|validator| validator.errors.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn assert_valid(&self) {
+        let errors = self.validation_errors();
+
+        if !errors.is_empty() {
+            panic!(
+                "Render graph validation failed:\n{}",
+                errors
+                    .iter()
+                    .map(|err| format!("  {}", err))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+    }
+
     pub fn compile(self, pipeline_cache: &mut PipelineCache) -> CompiledRenderGraph {
+        self.assert_valid();
+
         let resource_info = self.calculate_resource_info();
         // TODO: alias resources
 
@@ -590,67 +636,29 @@ impl RenderGraph {
     }
 
     pub(crate) fn record_pass(&mut self, pass: RecordedPass) {
+        if let Some(mut validator) = self.validator.take() {
+            validator.validate_pass(self, &pass);
+            self.validator = Some(validator);
+        }
+
         let debug_pass = self.hook_debug_pass(&pass);
         self.passes.push(pass);
 
         if let Some(debug_pass) = debug_pass {
-            let src_handle = debug_pass.img;
-            let src_desc = *src_handle.desc();
-
-            let mut dst = self.create(src_desc);
-            let debug_pass = self.add_pass("debug");
-
-            crate::SimpleRenderPass::new_compute(debug_pass, "/shaders/copy_color.hlsl")
-                .read(&src_handle)
-                .write(&mut dst)
-                .dispatch(src_desc.extent);
-
-            self.debugged_resource = Some(dst);
+            self.debugged_resource =
+                Some(self.record_debug_view_pass(&debug_pass.img, &debug_pass.view));
         }
     }
 
     fn hook_debug_pass(&mut self, pass: &RecordedPass) -> Option<PendingDebugPass> {
-        let scope_hook = &self.debug_hook.as_ref()?.render_debug_hook;
+        let hook = self.debug_hook.as_ref()?;
+        let scope_hook = &hook.render_debug_hook;
 
         if pass.name == scope_hook.name && pass.idx as u64 == scope_hook.id {
-            fn is_debug_compatible(desc: &ImageDesc) -> bool {
-                kajiya_backend::vulkan::barrier::image_aspect_mask_from_format(desc.format)
-                    == vk::ImageAspectFlags::COLOR
-                    && desc.image_type == ImageType::Tex2d
-            }
-
-            // Grab the first compatible image written by this pass
-            let (src_handle, src_desc) = pass.write.iter().find_map(|src_ref| {
-                let src = &self.resources[src_ref.handle.id as usize];
-                match src {
-                    // Resources created by the render graph can be used as-is, as long as they have a color aspect
-                    GraphResourceInfo::Created(GraphResourceCreateInfo {
-                        desc: GraphResourceDesc::Image(img_desc),
-                    }) if is_debug_compatible(img_desc) => Some((src_ref.handle, *img_desc)),
-
-                    // Imported resources must also support vk::ImageUsageFlags::SAMPLED because their
-                    // usage flags are supplied externally, and not derived by the graph
-                    GraphResourceInfo::Imported(GraphResourceImportInfo::Image {
-                        resource: img,
-                        ..
-                    }) if img.desc.usage.contains(vk::ImageUsageFlags::SAMPLED)
-                        && is_debug_compatible(&img.desc) =>
-                    {
-                        Some((src_ref.handle, img.desc))
-                    }
-                    _ => None,
-                }
-            })?;
-
-            let src_handle: Handle<Image> = Handle {
-                raw: src_handle,
-                desc: TypeEquals::same(src_desc)
-                    .mip_levels(1)
-                    .format(vk::Format::B10G11R11_UFLOAT_PACK32),
-                marker: PhantomData,
-            };
-
-            Some(PendingDebugPass { img: src_handle })
+            Some(PendingDebugPass {
+                img: self.select_debug_image(pass, &hook.view)?,
+                view: hook.view.clone(),
+            })
         } else {
             None
         }
@@ -792,8 +800,16 @@ impl CompiledRenderGraph {
             pipelines: self.pipelines,
         };
 
+        let resource_creation_sites = self
+            .rg
+            .validator
+            .is_some()
+            .then(|| self.rg.resource_creation_sites);
+
         ExecutingRenderGraph {
             resource_registry,
+            resource_creation_sites,
+            pass_cpu_timings: PassCpuTimings::new(),
             passes: self.rg.passes.into(),
             resources: self.rg.resources,
             exported_resources: self.rg.exported_resources,
@@ -806,64 +822,37 @@ pub struct ExecutingRenderGraph<'exec_params, 'constants> {
     resources: Vec<GraphResourceInfo>,
     exported_resources: Vec<(ExportableGraphResource, vk_sync::AccessType)>,
     resource_registry: ResourceRegistry<'exec_params, 'constants>,
+    // Only kept with validation enabled
+    resource_creation_sites: Option<Vec<&'static Location<'static>>>,
+    pass_cpu_timings: PassCpuTimings,
 }
 
 impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
     pub fn record_main_cb(&mut self, cb: &CommandBuffer) {
-        let mut first_presentation_pass: usize = self.passes.len();
-
-        for (pass_idx, pass) in self.passes.iter().enumerate() {
-            for res in &pass.write {
-                let res = &self.resources[res.handle.id as usize];
-                if matches!(
-                    res,
-                    GraphResourceInfo::Imported(GraphResourceImportInfo::SwapchainImage)
-                ) {
-                    first_presentation_pass = pass_idx;
-                    break;
-                }
-            }
-        }
-
         let mut passes: Vec<_> = std::mem::take(&mut self.passes).into();
+        let first_presentation_pass = find_first_presentation_pass(&passes, &self.resources);
 
         // At the start, transition all resources to the access type they're first used with
         // While we don't have split barriers yet, this will remove some bubbles
         // which would otherwise occur with temporal resources.
         {
-            let mut resource_first_access_states: HashMap<u32, &mut PassResourceAccessType> =
-                HashMap::with_capacity(self.resources.len());
-
-            for pass in &mut passes[0..first_presentation_pass] {
-                for resource_ref in pass.read.iter_mut().chain(pass.write.iter_mut()) {
-                    resource_first_access_states
-                        .entry(resource_ref.handle.id)
-                        .or_insert(&mut resource_ref.access);
-                }
-            }
-
             let params = &self.resource_registry.execution_params;
-            for (resource_idx, access) in resource_first_access_states {
+            for (resource_idx, access) in
+                take_first_access_transitions(&mut passes[0..first_presentation_pass])
+            {
                 let resource = &mut self.resource_registry.resources[resource_idx as usize];
-                Self::transition_resource(
-                    params.device,
-                    cb,
-                    resource,
-                    PassResourceAccessType {
-                        access_type: access.access_type,
-                        sync_type: PassResourceAccessSyncType::SkipSyncIfSameAccessType,
-                    },
-                    false,
-                    "",
-                );
-
-                // Skip the sync when this pass is encountered later.
-                access.sync_type = PassResourceAccessSyncType::SkipSyncIfSameAccessType;
+                Self::transition_resource(params.device, cb, resource, access, false, "");
             }
         }
 
         for pass in passes.drain(..first_presentation_pass) {
-            Self::record_pass_cb(pass, &mut self.resource_registry, cb);
+            Self::record_pass_cb(
+                pass,
+                &mut self.resource_registry,
+                self.resource_creation_sites.as_deref(),
+                cb,
+                &mut self.pass_cpu_timings,
+            );
         }
 
         self.passes = passes.into();
@@ -909,19 +898,29 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
 
         let passes = self.passes;
         for pass in passes {
-            Self::record_pass_cb(pass, &mut self.resource_registry, cb);
+            Self::record_pass_cb(
+                pass,
+                &mut self.resource_registry,
+                self.resource_creation_sites.as_deref(),
+                cb,
+                &mut self.pass_cpu_timings,
+            );
         }
 
         RetiredRenderGraph {
             resources: self.resource_registry.resources,
+            pass_cpu_timings: self.pass_cpu_timings.timings,
         }
     }
 
     fn record_pass_cb(
-        pass: RecordedPass,
+        mut pass: RecordedPass,
         resource_registry: &mut ResourceRegistry,
+        resource_creation_sites: Option<&[&'static Location<'static>]>,
         cb: &CommandBuffer,
+        pass_cpu_timings: &mut PassCpuTimings,
     ) {
+        let cpu_start = Instant::now();
         let params = &resource_registry.execution_params;
 
         // Record a crash marker just before this pass
@@ -981,12 +980,20 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
             }
         }
 
+        let render_fn = pass.render_fn.take();
+
         let mut api = RenderPassApi {
             cb,
             resources: resource_registry,
+            validator: resource_creation_sites.map(|resource_creation_sites| {
+                PassBindingValidator {
+                    pass: &pass,
+                    resource_creation_sites,
+                }
+            }),
         };
 
-        if let Some(render_fn) = pass.render_fn {
+        if let Some(render_fn) = render_fn {
             if let Err(err) = render_fn(&mut api) {
                 panic!("Pass {:?} failed to render: {:#}", pass.name, err);
             }
@@ -1008,6 +1015,8 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
         params
             .device
             .record_crash_marker(cb, format!("end render pass {:?}", pass.name));
+
+        pass_cpu_timings.push(&pass.name, cpu_start);
     }
 
     fn transition_resource(
@@ -1018,13 +1027,7 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
         debug: bool,
         dbg_str: &str,
     ) {
-        if unsafe { RG_ALLOW_PASS_OVERLAP }
-            && resource.access_type == access.access_type
-            && matches!(
-                access.sync_type,
-                PassResourceAccessSyncType::SkipSyncIfSameAccessType
-            )
-        {
+        if can_skip_transition(resource.access_type, access) {
             return;
         }
 
@@ -1106,6 +1109,73 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
     }
 }
 
+pub(crate) fn find_first_presentation_pass(
+    passes: &[RecordedPass],
+    resources: &[GraphResourceInfo],
+) -> usize {
+    let mut first_presentation_pass: usize = passes.len();
+
+    for (pass_idx, pass) in passes.iter().enumerate() {
+        for res in &pass.write {
+            let res = &resources[res.handle.id as usize];
+            if matches!(
+                res,
+                GraphResourceInfo::Imported(GraphResourceImportInfo::SwapchainImage)
+            ) {
+                first_presentation_pass = pass_idx;
+                break;
+            }
+        }
+    }
+
+    first_presentation_pass
+}
+
+/// Finds the access type each resource is first used with in `passes`, and marks those first
+/// accesses as not needing a sync, since they will be transitioned to up-front.
+///
+/// Returns the up-front transitions, ordered by resource index.
+pub(crate) fn take_first_access_transitions(
+    passes: &mut [RecordedPass],
+) -> Vec<(u32, PassResourceAccessType)> {
+    let mut resource_first_access_states: HashMap<u32, &mut PassResourceAccessType> =
+        HashMap::new();
+
+    for pass in passes {
+        for resource_ref in pass.read.iter_mut().chain(pass.write.iter_mut()) {
+            resource_first_access_states
+                .entry(resource_ref.handle.id)
+                .or_insert(&mut resource_ref.access);
+        }
+    }
+
+    let mut transitions: Vec<(u32, PassResourceAccessType)> = resource_first_access_states
+        .into_iter()
+        .map(|(resource_idx, access)| {
+            // Skip the sync when this pass is encountered later.
+            access.sync_type = PassResourceAccessSyncType::SkipSyncIfSameAccessType;
+            (resource_idx, *access)
+        })
+        .collect();
+
+    transitions.sort_by_key(|(resource_idx, _)| *resource_idx);
+    transitions
+}
+
+pub(crate) fn can_skip_transition(
+    current_access_type: vk_sync::AccessType,
+    access: PassResourceAccessType,
+) -> bool {
+    let allow_pass_overlap = unsafe { RG_ALLOW_PASS_OVERLAP };
+
+    allow_pass_overlap
+        && current_access_type == access.access_type
+        && matches!(
+            access.sync_type,
+            PassResourceAccessSyncType::SkipSyncIfSameAccessType
+        )
+}
+
 #[allow(dead_code)]
 fn global_barrier(
     device: &Device,
@@ -1127,6 +1197,7 @@ fn global_barrier(
 
 pub struct RetiredRenderGraph {
     resources: Vec<RegistryResource>,
+    pub(crate) pass_cpu_timings: Vec<PassCpuTiming>,
 }
 
 impl RetiredRenderGraph {
@@ -1141,6 +1212,13 @@ impl RetiredRenderGraph {
         )
     }
 
+    pub(crate) fn exported_access_type(
+        &self,
+        handle: GraphRawResourceHandle,
+    ) -> vk_sync::AccessType {
+        self.resources[handle.id as usize].access_type
+    }
+
     pub fn release_resources(self, transient_resource_cache: &mut TransientResourceCache) {
         for resource in self.resources {
             match resource.resource {
@@ -1170,8 +1248,8 @@ pub enum PassResourceAccessSyncType {
 #[derive(Copy, Clone)]
 pub struct PassResourceAccessType {
     // TODO: multiple
-    access_type: vk_sync::AccessType,
-    sync_type: PassResourceAccessSyncType,
+    pub(crate) access_type: vk_sync::AccessType,
+    pub(crate) sync_type: PassResourceAccessSyncType,
 }
 
 impl PassResourceAccessType {
@@ -1188,10 +1266,25 @@ pub(crate) struct PassResourceRef {
     pub access: PassResourceAccessType,
 }
 
+// What a pass dispatches, when known at graph building time. Not used in execution,
+// but allows inspecting the graph without running the render functions.
+#[derive(Clone, Copy)]
+pub(crate) enum RecordedDispatch {
+    Compute {
+        pipeline: RgComputePipelineHandle,
+        threads: Option<[u32; 3]>,
+    },
+    RayTracing {
+        pipeline: RgRtPipelineHandle,
+        threads: Option<[u32; 3]>,
+    },
+}
+
 pub(crate) struct RecordedPass {
     pub read: Vec<PassResourceRef>,
     pub write: Vec<PassResourceRef>,
     pub render_fn: Option<Box<DynRenderFn>>,
+    pub dispatch: Option<RecordedDispatch>,
     pub name: String,
     pub idx: usize,
 }
@@ -1202,6 +1295,7 @@ impl RecordedPass {
             read: Default::default(),
             write: Default::default(),
             render_fn: Default::default(),
+            dispatch: None,
             name: name.to_owned(),
             idx,
         }
@@ -5,11 +5,14 @@ use kajiya_backend::{
     vulkan::{
         image::*,
         ray_tracing::{RayTracingAcceleration, RayTracingPipelineDesc},
-        shader::{ComputePipelineDesc, PipelineShaderDesc, ShaderPipelineStage, ShaderSource},
+        shader::{
+            ComputePipelineDesc, PipelineShaderDesc, ShaderDefine, ShaderPipelineStage,
+            ShaderSource,
+        },
     },
 };
 
-use crate::Image;
+use crate::{graph::RecordedDispatch, Image};
 
 use super::{
     BindRgRef, Buffer, GpuSrv, GpuUav, Handle, PassBuilder, Ref, RenderPassApi, RenderPassBinding,
@@ -21,6 +24,9 @@ pub trait ConstBlob {
         self: Box<Self>,
         dynamic_constants: &mut dynamic_constants::DynamicConstants,
     ) -> u32;
+
+    /// Size in bytes, validated against the shader's constant buffer
+    fn size(&self) -> usize;
 }
 
 impl<T> ConstBlob for T
@@ -33,6 +39,10 @@ where
     ) -> u32 {
         dynamic_constants.push(self.as_ref())
     }
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<T>()
+    }
 }
 
 struct VecBlob<T>(Vec<T>);
@@ -47,12 +57,18 @@ where
     ) -> u32 {
         dynamic_constants.push_from_iter(self.0.into_iter())
     }
+
+    fn size(&self) -> usize {
+        self.0.len() * std::mem::size_of::<T>()
+    }
 }
 
 pub struct SimpleRenderPassState<RgPipelineHandle> {
     pipeline: RgPipelineHandle,
     bindings: Vec<RenderPassBinding>,
     const_blobs: Vec<(usize, Box<dyn ConstBlob>)>,
+    // (binding index, size) of the patched const blobs
+    const_blob_sizes: Vec<(usize, usize)>,
     raw_descriptor_sets: Vec<(u32, vk::DescriptorSet)>,
 }
 
@@ -65,6 +81,7 @@ where
             pipeline,
             bindings: Vec::new(),
             const_blobs: Vec::new(),
+            const_blob_sizes: Vec::new(),
             raw_descriptor_sets: Vec::new(),
         }
     }
@@ -74,6 +91,7 @@ where
 
         let const_blobs = std::mem::take(&mut self.const_blobs);
         for (binding_idx, blob) in const_blobs {
+            self.const_blob_sizes.push((binding_idx, blob.size()));
             let dynamic_constants_offset = ConstBlob::push_self(blob, dynamic_constants);
             match &mut self.bindings[binding_idx] {
                 RenderPassBinding::DynamicConstants(offset)
@@ -97,6 +115,10 @@ where
             res = res.raw_descriptor_set(set_idx, binding);
         }
 
+        for &(binding_idx, size) in &self.const_blob_sizes {
+            res = res.constants_size(0, binding_idx as u32, size);
+        }
+
         res
     }
 }
@@ -107,8 +129,29 @@ pub struct SimpleRenderPass<'rg, RgPipelineHandle> {
 }
 
 impl<'rg> SimpleRenderPass<'rg, RgComputePipelineHandle> {
-    pub fn new_compute(mut pass: PassBuilder<'rg>, pipeline_path: &str) -> Self {
-        let pipeline = pass.register_compute_pipeline(pipeline_path);
+    pub fn new_compute(pass: PassBuilder<'rg>, pipeline_path: &str) -> Self {
+        Self::new_compute_with_defines(pass, pipeline_path, &[])
+    }
+
+    /// Compiles a permutation of the shader with the given `(name, value)` preprocessor defines.
+    /// Each distinct set of defines creates a separate pipeline.
+    pub fn new_compute_with_defines(
+        mut pass: PassBuilder<'rg>,
+        pipeline_path: &str,
+        defines: &[(&str, &str)],
+    ) -> Self {
+        let pipeline = pass.register_compute_pipeline_with_desc(
+            ComputePipelineDesc::builder()
+                .compute_hlsl(pipeline_path)
+                .defines(
+                    defines
+                        .iter()
+                        .map(|(name, value)| ShaderDefine::new(*name, value))
+                        .collect(),
+                )
+                .build()
+                .unwrap(),
+        );
 
         Self {
             pass,
@@ -130,7 +173,11 @@ impl<'rg> SimpleRenderPass<'rg, RgComputePipelineHandle> {
         }
     }
 
-    pub fn dispatch(self, extent: [u32; 3]) {
+    pub fn dispatch(mut self, extent: [u32; 3]) {
+        self.pass.record_dispatch(RecordedDispatch::Compute {
+            pipeline: self.state.pipeline,
+            threads: Some(extent),
+        });
         let mut state = self.state;
 
         self.pass.render(move |api| {
@@ -146,6 +193,10 @@ impl<'rg> SimpleRenderPass<'rg, RgComputePipelineHandle> {
 
     pub fn dispatch_indirect(mut self, args_buffer: &Handle<Buffer>, args_buffer_offset: u64) {
         let args_buffer_ref = self.pass.read(args_buffer, AccessType::IndirectBuffer);
+        self.pass.record_dispatch(RecordedDispatch::Compute {
+            pipeline: self.state.pipeline,
+            threads: None,
+        });
         let mut state = self.state;
 
         self.pass.render(move |api| {
@@ -209,6 +260,10 @@ impl<'rg> SimpleRenderPass<'rg, RgRtPipelineHandle> {
 
     pub fn trace_rays(mut self, tlas: &Handle<RayTracingAcceleration>, extent: [u32; 3]) {
         let tlas_ref = self.pass.read(tlas, AccessType::AnyShaderReadOther);
+        self.pass.record_dispatch(RecordedDispatch::RayTracing {
+            pipeline: self.state.pipeline,
+            threads: Some(extent),
+        });
         let mut state = self.state;
 
         self.pass.render(move |api| {
@@ -234,6 +289,10 @@ impl<'rg> SimpleRenderPass<'rg, RgRtPipelineHandle> {
     ) {
         let args_buffer_ref = self.pass.read(args_buffer, AccessType::IndirectBuffer);
         let tlas_ref = self.pass.read(tlas, AccessType::AnyShaderReadOther);
+        self.pass.record_dispatch(RecordedDispatch::RayTracing {
+            pipeline: self.state.pipeline,
+            threads: None,
+        });
         let mut state = self.state;
 
         self.pass.render(move |api| {
@@ -291,6 +350,7 @@ impl<'rg, RgPipelineHandle> SimpleRenderPass<'rg, RgPipelineHandle> {
             handle,
             AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
         );
+        self.pass.validate_image_view(handle, &view_desc, false);
 
         self.state.bindings.push(handle_ref.bind_view(view_desc));
 
@@ -307,9 +367,10 @@ impl<'rg, RgPipelineHandle> SimpleRenderPass<'rg, RgPipelineHandle> {
             AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
         );
 
-        self.state
-            .bindings
-            .push(handle_ref.bind_view(ImageViewDescBuilder::default().aspect_mask(aspect_mask)));
+        let view_desc = ImageViewDescBuilder::default().aspect_mask(aspect_mask);
+        self.pass.validate_image_view(handle, &view_desc, false);
+
+        self.state.bindings.push(handle_ref.bind_view(view_desc));
 
         self
     }
@@ -344,6 +405,7 @@ impl<'rg, RgPipelineHandle> SimpleRenderPass<'rg, RgPipelineHandle> {
         view_desc: ImageViewDescBuilder,
     ) -> Self {
         let handle_ref = self.pass.write(handle, AccessType::AnyShaderWrite);
+        self.pass.validate_image_view(handle, &view_desc, true);
 
         self.state.bindings.push(handle_ref.bind_view(view_desc));
 
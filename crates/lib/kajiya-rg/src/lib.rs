@@ -1,18 +1,29 @@
+mod debug_view;
 mod graph;
 mod hl;
+mod mock;
 mod pass_api;
 mod pass_builder;
+mod pass_timings;
 mod resource;
 mod resource_registry;
 mod temporal;
+mod temporal_snapshot;
+mod validation;
 
 pub mod imageops;
 pub mod renderer;
 
+pub use debug_view::{
+    GraphDebugView, RgDebugPass, RgDebugPassImage, RgDebugPickedPixel, RgDebugPixelValue,
+};
 pub use graph::*;
 pub use hl::*;
+pub use mock::*;
 pub use pass_api::*;
 pub use pass_builder::*;
+pub use pass_timings::{RgFrameTimings, RgPassTiming};
 pub use resource::*;
 pub use resource_registry::ResourceRegistry;
 pub use temporal::*;
+pub use validation::{RgValidationError, RgValidationErrorKind};
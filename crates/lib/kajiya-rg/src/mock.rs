@@ -0,0 +1,338 @@
+use kajiya_backend::{
+    vk_sync::AccessType,
+    vulkan::{
+        buffer::BufferDesc,
+        image::ImageDesc,
+        shader::{ShaderPipelineStage, ShaderSource},
+    },
+};
+
+use crate::{
+    graph::{
+        can_skip_transition, find_first_presentation_pass, take_first_access_transitions,
+        GraphResourceCreateInfo, GraphResourceImportInfo, GraphResourceInfo,
+        PassResourceAccessSyncType, PassResourceAccessType, RecordedDispatch, RecordedPass,
+        RenderGraph,
+    },
+    ExportedHandle, GraphResourceDesc, Resource,
+};
+
+/// A GPU command which would have been recorded by executing a render graph.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RgMockEvent {
+    CreateImage {
+        resource: u32,
+        desc: ImageDesc,
+    },
+    CreateBuffer {
+        resource: u32,
+        desc: BufferDesc,
+    },
+    /// Graph-created acceleration structures can't be executed yet, but can be mocked.
+    CreateRayTracingAcceleration {
+        resource: u32,
+    },
+    Import {
+        resource: u32,
+        access_type: AccessType,
+    },
+    BeginPass {
+        name: String,
+    },
+    Barrier {
+        resource: u32,
+        previous_access: AccessType,
+        next_access: AccessType,
+    },
+    Dispatch {
+        shader: ShaderSource,
+        threads: Option<[u32; 3]>,
+    },
+    TraceRays {
+        raygen: ShaderSource,
+        threads: Option<[u32; 3]>,
+    },
+    EndPass,
+}
+
+/// The result of running a render graph through `RenderGraph::execute_mock`.
+pub struct MockRetiredRenderGraph {
+    pub events: Vec<RgMockEvent>,
+    pub(crate) access_types: Vec<AccessType>,
+}
+
+impl MockRetiredRenderGraph {
+    pub fn exported_access_type<Res: Resource>(&self, handle: ExportedHandle<Res>) -> AccessType {
+        self.access_types[handle.raw.id as usize]
+    }
+
+    pub fn barriers(&self) -> impl Iterator<Item = (u32, AccessType, AccessType)> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            RgMockEvent::Barrier {
+                resource,
+                previous_access,
+                next_access,
+            } => Some((*resource, *previous_access, *next_access)),
+            _ => None,
+        })
+    }
+}
+
+impl RenderGraph {
+    /// Walks the graph in the same order as `CompiledRenderGraph` execution would, recording
+    /// resource creation, barriers and dispatches instead of issuing them to a device.
+    /// Pipelines are not compiled, and render functions are not invoked.
+    pub fn execute_mock(mut self) -> MockRetiredRenderGraph {
+        self.assert_valid();
+
+        let resource_info = self.calculate_resource_info();
+        let mut events = Vec::new();
+
+        let mut access_types: Vec<AccessType> = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(resource_idx, resource)| {
+                let resource_id = resource_idx as u32;
+
+                match resource {
+                    GraphResourceInfo::Created(GraphResourceCreateInfo { desc }) => {
+                        match *desc {
+                            GraphResourceDesc::Image(mut desc) => {
+                                desc.usage = resource_info.image_usage_flags[resource_idx];
+                                events.push(RgMockEvent::CreateImage {
+                                    resource: resource_id,
+                                    desc,
+                                });
+                            }
+                            GraphResourceDesc::Buffer(mut desc) => {
+                                desc.usage = resource_info.buffer_usage_flags[resource_idx];
+                                events.push(RgMockEvent::CreateBuffer {
+                                    resource: resource_id,
+                                    desc,
+                                });
+                            }
+                            GraphResourceDesc::RayTracingAcceleration(_) => {
+                                events.push(RgMockEvent::CreateRayTracingAcceleration {
+                                    resource: resource_id,
+                                });
+                            }
+                        }
+
+                        AccessType::Nothing
+                    }
+                    GraphResourceInfo::Imported(import_info) => {
+                        let access_type = match import_info {
+                            GraphResourceImportInfo::Image { access_type, .. }
+                            | GraphResourceImportInfo::Buffer { access_type, .. }
+                            | GraphResourceImportInfo::RayTracingAcceleration {
+                                access_type, ..
+                            } => *access_type,
+                            GraphResourceImportInfo::SwapchainImage => {
+                                AccessType::ComputeShaderWrite
+                            }
+                        };
+
+                        events.push(RgMockEvent::Import {
+                            resource: resource_id,
+                            access_type,
+                        });
+
+                        access_type
+                    }
+                }
+            })
+            .collect();
+
+        let mut passes = std::mem::take(&mut self.passes);
+        let first_presentation_pass = find_first_presentation_pass(&passes, &self.resources);
+
+        for (resource_idx, access) in
+            take_first_access_transitions(&mut passes[0..first_presentation_pass])
+        {
+            mock_transition(&mut events, &mut access_types, resource_idx, access);
+        }
+
+        let (main_passes, presentation_passes) = passes.split_at(first_presentation_pass);
+
+        for pass in main_passes {
+            mock_pass(&self, pass, &mut events, &mut access_types);
+        }
+
+        // Transition exported resources to the requested access types
+        for (resource, access_type) in &self.exported_resources {
+            if *access_type != AccessType::Nothing {
+                mock_transition(
+                    &mut events,
+                    &mut access_types,
+                    resource.raw().id,
+                    PassResourceAccessType::new(
+                        *access_type,
+                        PassResourceAccessSyncType::AlwaysSync,
+                    ),
+                );
+            }
+        }
+
+        for pass in presentation_passes {
+            mock_pass(&self, pass, &mut events, &mut access_types);
+        }
+
+        MockRetiredRenderGraph {
+            events,
+            access_types,
+        }
+    }
+}
+
+fn mock_pass(
+    rg: &RenderGraph,
+    pass: &RecordedPass,
+    events: &mut Vec<RgMockEvent>,
+    access_types: &mut [AccessType],
+) {
+    events.push(RgMockEvent::BeginPass {
+        name: pass.name.clone(),
+    });
+
+    for resource_ref in pass.read.iter().chain(pass.write.iter()) {
+        mock_transition(
+            events,
+            access_types,
+            resource_ref.handle.id,
+            resource_ref.access,
+        );
+    }
+
+    match pass.dispatch {
+        Some(RecordedDispatch::Compute { pipeline, threads }) => {
+            events.push(RgMockEvent::Dispatch {
+                shader: rg.compute_pipelines[pipeline.id].desc.source.clone(),
+                threads,
+            });
+        }
+        Some(RecordedDispatch::RayTracing { pipeline, threads }) => {
+            let raygen = rg.rt_pipelines[pipeline.id]
+                .shaders
+                .iter()
+                .find(|shader| shader.stage == ShaderPipelineStage::RayGen)
+                .expect("ray tracing pipeline without a raygen shader")
+                .source
+                .clone();
+
+            events.push(RgMockEvent::TraceRays { raygen, threads });
+        }
+        None => {}
+    }
+
+    events.push(RgMockEvent::EndPass);
+}
+
+fn mock_transition(
+    events: &mut Vec<RgMockEvent>,
+    access_types: &mut [AccessType],
+    resource_idx: u32,
+    access: PassResourceAccessType,
+) {
+    let current = &mut access_types[resource_idx as usize];
+
+    if can_skip_transition(*current, access) {
+        return;
+    }
+
+    events.push(RgMockEvent::Barrier {
+        resource: resource_idx,
+        previous_access: *current,
+        next_access: access.access_type,
+    });
+
+    *current = access.access_type;
+}
+
+#[test]
+fn test_barriers() {
+    use crate::SimpleRenderPass;
+    use kajiya_backend::ash::vk;
+
+    let mut rg = RenderGraph::new();
+
+    let mut tex = rg.create(ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [8, 8]));
+    let mut output = rg.create(*tex.desc());
+
+    SimpleRenderPass::new_compute(rg.add_pass("fill"), "/shaders/fill.hlsl")
+        .write(&mut tex)
+        .dispatch([8, 8, 1]);
+
+    SimpleRenderPass::new_compute(rg.add_pass("copy"), "/shaders/copy.hlsl")
+        .read(&tex)
+        .write(&mut output)
+        .dispatch([8, 8, 1]);
+
+    let retired = rg.execute_mock();
+
+    assert!(matches!(
+        &retired.events[0],
+        RgMockEvent::CreateImage { resource: 0, desc }
+            if desc.usage == vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED
+    ));
+
+    assert_eq!(
+        retired.barriers().collect::<Vec<_>>(),
+        vec![
+            // Up-front transitions to the first access of every resource
+            (0, AccessType::Nothing, AccessType::AnyShaderWrite),
+            (1, AccessType::Nothing, AccessType::AnyShaderWrite),
+            // "copy" reads what "fill" wrote
+            (
+                0,
+                AccessType::AnyShaderWrite,
+                AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer
+            ),
+        ]
+    );
+
+    assert!(retired.events.contains(&RgMockEvent::Dispatch {
+        shader: ShaderSource::hlsl("/shaders/copy.hlsl"),
+        threads: Some([8, 8, 1]),
+    }));
+}
+
+#[test]
+fn test_temporal_state_transitions() {
+    use crate::{GetOrCreateTemporal, SimpleRenderPass, TemporalRenderGraph};
+    use kajiya_backend::ash::vk;
+
+    let desc = ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [8, 8]);
+    let mut state = Default::default();
+
+    for frame in 0..2 {
+        let mut rg = TemporalRenderGraph::new_mock(state);
+        let mut history = rg.get_or_create_temporal("history", desc).unwrap();
+
+        // The temporal resource can only be taken once per frame
+        assert!(rg.get_or_create_temporal("history", desc).is_err());
+
+        SimpleRenderPass::new_compute(rg.add_pass("accumulate"), "/shaders/accum.hlsl")
+            .write(&mut history)
+            .dispatch([8, 8, 1]);
+
+        let (rg, exported_state) = rg.export_temporal();
+        let retired = rg.execute_mock();
+
+        let expected_import_access = if frame == 0 {
+            AccessType::Nothing
+        } else {
+            AccessType::AnyShaderWrite
+        };
+
+        assert_eq!(
+            retired.events[0],
+            RgMockEvent::Import {
+                resource: 0,
+                access_type: expected_import_access,
+            }
+        );
+
+        state = exported_state.retire_temporal_mock(&retired);
+    }
+}
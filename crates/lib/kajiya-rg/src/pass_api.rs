@@ -3,6 +3,7 @@ use std::{cell::UnsafeCell, sync::Arc};
 use arrayvec::ArrayVec;
 
 use super::{
+    validation::{PassBindingValidator, RgValidationError},
     Buffer, GpuRt, GpuSrv, GpuUav, GraphRawResourceHandle, Image, Ref, ResourceRegistry,
     RgComputePipelineHandle, RgRasterPipelineHandle, RgRtPipelineHandle,
 };
@@ -29,6 +30,8 @@ use kajiya_backend::{
 pub struct RenderPassApi<'a, 'exec_params, 'constants> {
     pub cb: &'a CommandBuffer,
     pub resources: &'a mut ResourceRegistry<'exec_params, 'constants>,
+    // Only with validation enabled
+    pub(crate) validator: Option<PassBindingValidator<'a>>,
 }
 
 pub enum DescriptorSetBinding {
@@ -51,6 +54,8 @@ pub struct RenderPassCommonShaderPipelineBinding<'a> {
     // TODO: fixed size
     bindings: Vec<(u32, &'a [RenderPassBinding])>,
     raw_bindings: Vec<(u32, vk::DescriptorSet)>,
+    // (set, binding, size in bytes) of dynamic constants; checked against the shader
+    constants_sizes: Vec<(u32, u32, usize)>,
 }
 
 pub struct RenderPassPipelineBinding<'a, HandleType> {
@@ -75,6 +80,15 @@ impl<'a, HandleType> RenderPassPipelineBinding<'a, HandleType> {
         self.binding.raw_bindings.push((set_idx, binding));
         self
     }
+
+    /// Declares the size of the `RenderPassBinding::DynamicConstants` at `binding_idx`,
+    /// so that it can be validated against the size of the shader's constant buffer.
+    pub fn constants_size(mut self, set_idx: u32, binding_idx: u32, size: usize) -> Self {
+        self.binding
+            .constants_sizes
+            .push((set_idx, binding_idx, size));
+        self
+    }
 }
 
 pub trait IntoRenderPassPipelineBinding: Sized {
@@ -193,7 +207,11 @@ impl<'a, 'exec_params, 'constants> RenderPassApi<'a, 'exec_params, 'constants> {
                         self.resources
                             .execution_params
                             .frame_constants_layout
-                            .triangle_lights_offset,
+                            .punctual_lights_offset,
+                        self.resources
+                            .execution_params
+                            .frame_constants_layout
+                            .area_lights_offset,
                     ],
                 );
             }
@@ -205,6 +223,19 @@ impl<'a, 'exec_params, 'constants> RenderPassApi<'a, 'exec_params, 'constants> {
                 continue;
             }
 
+            for binding in bindings.iter() {
+                self.validate_binding(binding);
+            }
+
+            if self.validator.is_some() {
+                self.validate_descriptor_set_bindings(
+                    pipeline,
+                    set_idx,
+                    bindings,
+                    &binding.constants_sizes,
+                )?;
+            }
+
             let bindings: Result<Vec<_>, BackendError> = bindings
                 .iter()
                 .map(|binding| {
@@ -305,6 +336,196 @@ impl<'a, 'exec_params, 'constants> RenderPassApi<'a, 'exec_params, 'constants> {
         Ok(())
     }
 
+    /// Checks a bound resource against the ones declared by the pass, if validating.
+    fn validate_binding(&self, binding: &RenderPassBinding) {
+        let validator = if let Some(validator) = &self.validator {
+            validator
+        } else {
+            return;
+        };
+
+        let result = match binding {
+            RenderPassBinding::Image(image) => self.validate_image_binding(validator, image),
+            RenderPassBinding::ImageArray(images) => images
+                .iter()
+                .try_for_each(|image| self.validate_image_binding(validator, image)),
+            RenderPassBinding::Buffer(buffer) => {
+                validator.validate_binding(buffer.handle, buffer.writable, None)
+            }
+            RenderPassBinding::RayTracingAcceleration(acc) => {
+                validator.validate_binding(acc.handle, false, None)
+            }
+            RenderPassBinding::DynamicConstants(_)
+            | RenderPassBinding::DynamicConstantsStorageBuffer(_) => Ok(()),
+        };
+
+        if let Err(err) = result {
+            panic!("Render graph validation: {}", err);
+        }
+    }
+
+    fn validate_image_binding(
+        &self,
+        validator: &PassBindingValidator,
+        image: &RenderPassImageBinding,
+    ) -> Result<(), RgValidationError> {
+        validator.validate_binding(
+            image.handle,
+            image.image_layout == vk::ImageLayout::GENERAL,
+            Some((
+                &self
+                    .resources
+                    .image_from_raw_handle::<GpuSrv>(image.handle)
+                    .desc,
+                &image.view_desc,
+            )),
+        )
+    }
+
+    /// Checks resources bound positionally to a descriptor set against the shader's declarations.
+    /// Only done when validating, as it runs for every dispatch.
+    fn validate_descriptor_set_bindings(
+        &self,
+        pipeline: &ShaderPipelineCommon,
+        set_idx: u32,
+        bindings: &[RenderPassBinding],
+        constants_sizes: &[(u32, u32, usize)],
+    ) -> Result<(), BackendError> {
+        let shader_set_info = &pipeline.set_layout_info[set_idx as usize];
+
+        let binding_name = |binding_idx: u32| {
+            pipeline
+                .binding_reflection
+                .get(&(set_idx, binding_idx))
+                .map(|reflection| reflection.name.as_str())
+                .filter(|name| !name.is_empty())
+                .unwrap_or("<unnamed>")
+        };
+
+        let mismatch = |binding_idx: u32, info: String| BackendError::ShaderBindingMismatch {
+            info: format!(
+                "set {}, binding {} (`{}`): {}",
+                set_idx,
+                binding_idx,
+                binding_name(binding_idx),
+                info
+            ),
+        };
+
+        // Samplers are immutable, and never bound by passes
+        if let Some(binding_idx) = shader_set_info
+            .iter()
+            .filter(|(_, ty)| **ty != vk::DescriptorType::SAMPLER)
+            .map(|(binding_idx, _)| *binding_idx)
+            .filter(|binding_idx| *binding_idx as usize >= bindings.len())
+            .min()
+        {
+            return Err(mismatch(
+                binding_idx,
+                format!(
+                    "declared by the shader, but the pass only binds {} resources",
+                    bindings.len()
+                ),
+            ));
+        }
+
+        for (binding_idx, binding) in bindings.iter().enumerate() {
+            let binding_idx = binding_idx as u32;
+            let shader_ty = if let Some(ty) = shader_set_info.get(&binding_idx) {
+                *ty
+            } else {
+                // Not used by the shader
+                continue;
+            };
+
+            let (pass_ty, images) = match binding {
+                RenderPassBinding::Image(image) => (
+                    image_descriptor_type(image.image_layout),
+                    std::slice::from_ref(image),
+                ),
+                RenderPassBinding::ImageArray(images) => (
+                    images
+                        .first()
+                        .map(|image| image_descriptor_type(image.image_layout))
+                        .unwrap_or(vk::DescriptorType::SAMPLED_IMAGE),
+                    images.as_slice(),
+                ),
+                RenderPassBinding::Buffer(_) => (vk::DescriptorType::STORAGE_BUFFER, &[][..]),
+                RenderPassBinding::RayTracingAcceleration(_) => {
+                    (vk::DescriptorType::ACCELERATION_STRUCTURE_KHR, &[][..])
+                }
+                RenderPassBinding::DynamicConstants(_) => {
+                    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, &[][..])
+                }
+                RenderPassBinding::DynamicConstantsStorageBuffer(_) => {
+                    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, &[][..])
+                }
+            };
+
+            if pass_ty != shader_ty {
+                return Err(mismatch(
+                    binding_idx,
+                    format!(
+                        "the pass binds a {:?} descriptor, but the shader declares a {:?}",
+                        pass_ty, shader_ty
+                    ),
+                ));
+            }
+
+            let reflection = if let Some(reflection) =
+                pipeline.binding_reflection.get(&(set_idx, binding_idx))
+            {
+                reflection
+            } else {
+                continue;
+            };
+
+            if let Some(shader_view_type) = reflection.image_view_type {
+                for image in images {
+                    let view_type = image.view_desc.view_type.unwrap_or_else(|| {
+                        convert_image_type_to_view_type(
+                            self.resources
+                                .image_from_raw_handle::<GpuSrv>(image.handle)
+                                .desc
+                                .image_type,
+                        )
+                    });
+
+                    if view_type != shader_view_type {
+                        return Err(mismatch(
+                            binding_idx,
+                            format!(
+                                "the pass binds a {:?} image view, but the shader declares a {:?}",
+                                view_type, shader_view_type
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            if let Some(shader_size) = reflection.uniform_buffer_size {
+                let pass_size = constants_sizes
+                    .iter()
+                    .find(|(set, binding, _)| *set == set_idx && *binding == binding_idx)
+                    .map(|(_, _, size)| *size);
+
+                if let Some(pass_size) = pass_size {
+                    if pass_size < shader_size as usize {
+                        return Err(mismatch(
+                            binding_idx,
+                            format!(
+                                "the pass provides {} bytes of constants, but the shader reads {}",
+                                pass_size, shader_size
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn begin_render_pass(
         &mut self,
         render_pass: &kajiya_backend::vulkan::shader::RenderPass,
@@ -314,6 +535,23 @@ impl<'a, 'exec_params, 'constants> RenderPassApi<'a, 'exec_params, 'constants> {
     ) -> Result<(), BackendError> {
         let device = self.resources.execution_params.device;
 
+        if let Some(validator) = &self.validator {
+            for (img, view) in color_attachments
+                .iter()
+                .chain(depth_attachment.as_ref().into_iter())
+            {
+                // Attachments may be declared with `raster_read`
+                let desc = &self
+                    .resources
+                    .image_from_raw_handle::<GpuRt>(img.handle)
+                    .desc;
+                if let Err(err) = validator.validate_binding(img.handle, false, Some((desc, view)))
+                {
+                    panic!("Render graph validation: {}", err);
+                }
+            }
+        }
+
         let framebuffer = render_pass
             .framebuffer_cache
             .get_or_create(
@@ -485,6 +723,28 @@ impl<'api, 'a, 'exec_params, 'constants> BoundRasterPipeline<'api, 'a, 'exec_par
                 )
         }
     }
+
+    /// Stages of the pipeline which can access descriptors and push constants
+    pub fn stage_flags(&self) -> vk::ShaderStageFlags {
+        self.pipeline.stage_flags
+    }
+
+    /// Launches `group_count` task shader workgroups, or mesh shader workgroups
+    /// if the pipeline has no task shader.
+    ///
+    /// Panics if mesh shaders are not enabled on the device.
+    pub fn draw_mesh_tasks(&self, command_buffer: vk::CommandBuffer, group_count: [u32; 3]) {
+        let mesh_shader_ext = self
+            .api
+            .device()
+            .mesh_shader_ext
+            .as_ref()
+            .expect("Mesh shaders are not enabled on the device");
+
+        unsafe {
+            mesh_shader_ext.cmd_draw_mesh_tasks(command_buffer, group_count);
+        }
+    }
 }
 
 pub struct RenderPassImageBinding {
@@ -495,6 +755,7 @@ pub struct RenderPassImageBinding {
 
 pub struct RenderPassBufferBinding {
     handle: GraphRawResourceHandle,
+    writable: bool,
 }
 
 pub struct RenderPassRayTracingAccelerationBinding {
@@ -614,6 +875,7 @@ impl BindRgRef for Ref<Buffer, GpuSrv> {
     fn bind(&self) -> RenderPassBinding {
         RenderPassBinding::Buffer(RenderPassBufferBinding {
             handle: self.handle,
+            writable: false,
         })
     }
 }
@@ -622,6 +884,7 @@ impl BindRgRef for Ref<Buffer, GpuUav> {
     fn bind(&self) -> RenderPassBinding {
         RenderPassBinding::Buffer(RenderPassBufferBinding {
             handle: self.handle,
+            writable: true,
         })
     }
 }
@@ -634,6 +897,13 @@ impl BindRgRef for Ref<RayTracingAcceleration, GpuSrv> {
     }
 }
 
+fn image_descriptor_type(image_layout: vk::ImageLayout) -> vk::DescriptorType {
+    match image_layout {
+        vk::ImageLayout::GENERAL => vk::DescriptorType::STORAGE_IMAGE,
+        _ => vk::DescriptorType::SAMPLED_IMAGE,
+    }
+}
+
 fn bind_descriptor_set(
     device: &Device,
     cb: &CommandBuffer,
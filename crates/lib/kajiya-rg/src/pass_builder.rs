@@ -2,9 +2,9 @@ use crate::{PassResourceAccessSyncType, RenderPassApi};
 
 use super::{
     graph::{
-        PassResourceAccessType, PassResourceRef, RecordedPass, RenderGraph, RgComputePipeline,
-        RgComputePipelineHandle, RgRasterPipeline, RgRasterPipelineHandle, RgRtPipeline,
-        RgRtPipelineHandle, TypeEquals,
+        PassResourceAccessType, PassResourceRef, RecordedDispatch, RecordedPass, RenderGraph,
+        RgComputePipeline, RgComputePipelineHandle, RgRasterPipeline, RgRasterPipelineHandle,
+        RgRtPipeline, RgRtPipelineHandle, TypeEquals,
     },
     resource::*,
 };
@@ -30,6 +30,7 @@ impl<'s> Drop for PassBuilder<'s> {
 }
 
 impl<'rg> PassBuilder<'rg> {
+    #[track_caller]
     pub fn create<Desc: ResourceDesc>(
         &mut self,
         desc: Desc,
@@ -242,6 +243,26 @@ impl<'rg> PassBuilder<'rg> {
         }
     }
 
+    pub(crate) fn validate_image_view(
+        &mut self,
+        handle: &Handle<Image>,
+        view_desc: &ImageViewDescBuilder,
+        writable: bool,
+    ) {
+        // Only build the view desc when validating
+        if let Some(mut validator) = self.rg.validator.take() {
+            validator.validate_image_view(
+                self.rg,
+                self.pass.as_ref().unwrap(),
+                handle.raw,
+                &handle.desc,
+                &view_desc.clone().build().unwrap(),
+                writable,
+            );
+            self.rg.validator = Some(validator);
+        }
+    }
+
     pub fn register_compute_pipeline(&mut self, path: impl AsRef<Path>) -> RgComputePipelineHandle {
         let desc = ComputePipelineDesc::builder()
             .compute_hlsl(path.as_ref().to_owned())
@@ -322,6 +343,10 @@ impl<'rg> PassBuilder<'rg> {
         RgRtPipelineHandle { id }
     }
 
+    pub(crate) fn record_dispatch(&mut self, dispatch: RecordedDispatch) {
+        self.pass.as_mut().unwrap().dispatch = Some(dispatch);
+    }
+
     pub fn render(
         mut self,
         render: impl (FnOnce(&mut RenderPassApi) -> Result<(), BackendError>) + 'static,
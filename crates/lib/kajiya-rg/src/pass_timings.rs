@@ -0,0 +1,188 @@
+use std::{fmt::Write, time::Instant};
+
+/// CPU time spent recording commands for a single pass.
+#[derive(Clone, Debug)]
+pub(crate) struct PassCpuTiming {
+    pub(crate) name: String,
+    // Relative to the start of command buffer recording for the frame
+    pub(crate) start_ms: f64,
+    pub(crate) duration_ms: f64,
+}
+
+pub(crate) struct PassCpuTimings {
+    record_start: Instant,
+    pub(crate) timings: Vec<PassCpuTiming>,
+}
+
+impl PassCpuTimings {
+    pub(crate) fn new() -> Self {
+        Self {
+            record_start: Instant::now(),
+            timings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, name: &str, start: Instant) {
+        self.timings.push(PassCpuTiming {
+            name: name.to_owned(),
+            start_ms: (start - self.record_start).as_secs_f64() * 1000.0,
+            duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+        });
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RgPassTiming {
+    pub name: String,
+    pub gpu_ms: f64,
+    /// Start of command recording, relative to the start of recording for the frame.
+    pub cpu_start_ms: Option<f64>,
+    pub cpu_record_ms: Option<f64>,
+}
+
+/// Per-pass timings of the last frame for which GPU timestamps are available.
+#[derive(Clone, Debug, Default)]
+pub struct RgFrameTimings {
+    /// In the order in which the passes were recorded.
+    pub passes: Vec<RgPassTiming>,
+}
+
+impl RgFrameTimings {
+    /// Matches GPU scopes to CPU recording timings by pass name and order. GPU results
+    /// lag a few frames behind, so the most recent frame with the same sequence of passes is used.
+    pub(crate) fn new<'a>(
+        gpu_scopes: impl Iterator<Item = (&'a str, f64)>,
+        cpu_history: impl Iterator<Item = &'a [PassCpuTiming]>,
+    ) -> Self {
+        let gpu_scopes: Vec<(&str, f64)> = gpu_scopes.collect();
+
+        let cpu_timings = cpu_history.find(|cpu| {
+            cpu.len() == gpu_scopes.len()
+                && cpu
+                    .iter()
+                    .zip(&gpu_scopes)
+                    .all(|(cpu, (gpu_name, _))| cpu.name == *gpu_name)
+        });
+
+        let passes = gpu_scopes
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, gpu_ms))| {
+                let cpu = cpu_timings.map(|cpu| &cpu[idx]);
+
+                RgPassTiming {
+                    name: (*name).to_owned(),
+                    gpu_ms: *gpu_ms,
+                    cpu_start_ms: cpu.map(|cpu| cpu.start_ms),
+                    cpu_record_ms: cpu.map(|cpu| cpu.duration_ms),
+                }
+            })
+            .collect();
+
+        Self { passes }
+    }
+
+    pub fn total_gpu_ms(&self) -> f64 {
+        self.passes.iter().map(|pass| pass.gpu_ms).sum()
+    }
+
+    pub fn total_cpu_record_ms(&self) -> f64 {
+        self.passes
+            .iter()
+            .filter_map(|pass| pass.cpu_record_ms)
+            .sum()
+    }
+
+    /// Serializes the timings into the Chrome trace event format, viewable in `chrome://tracing`
+    /// or Perfetto. Only GPU durations are known, so GPU passes are laid out back to back.
+    pub fn to_chrome_trace_json(&self) -> String {
+        const PID: u32 = 1;
+        const CPU_TID: u32 = 1;
+        const GPU_TID: u32 = 2;
+
+        let mut events = vec![
+            format!(
+                r#"{{"name":"thread_name","ph":"M","pid":{},"tid":{},"args":{{"name":"CPU recording"}}}}"#,
+                PID, CPU_TID
+            ),
+            format!(
+                r#"{{"name":"thread_name","ph":"M","pid":{},"tid":{},"args":{{"name":"GPU"}}}}"#,
+                PID, GPU_TID
+            ),
+        ];
+
+        let mut gpu_start_us = 0.0;
+
+        for pass in &self.passes {
+            let name = escape_json_string(&pass.name);
+            let gpu_us = pass.gpu_ms * 1000.0;
+
+            events.push(format!(
+                r#"{{"name":"{}","cat":"gpu","ph":"X","ts":{:.3},"dur":{:.3},"pid":{},"tid":{}}}"#,
+                name, gpu_start_us, gpu_us, PID, GPU_TID
+            ));
+            gpu_start_us += gpu_us;
+
+            if let (Some(start_ms), Some(record_ms)) = (pass.cpu_start_ms, pass.cpu_record_ms) {
+                events.push(format!(
+                    r#"{{"name":"{}","cat":"cpu","ph":"X","ts":{:.3},"dur":{:.3},"pid":{},"tid":{}}}"#,
+                    name,
+                    start_ms * 1000.0,
+                    record_ms * 1000.0,
+                    PID,
+                    CPU_TID
+                ));
+            }
+        }
+
+        format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+    }
+}
+
+fn escape_json_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(res, "\\u{:04x}", c as u32);
+            }
+            c => res.push(c),
+        }
+    }
+
+    res
+}
+
+#[test]
+fn test_cpu_gpu_timing_matching() {
+    let cpu = |names: &[&str]| -> Vec<PassCpuTiming> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| PassCpuTiming {
+                name: (*name).to_owned(),
+                start_ms: idx as f64,
+                duration_ms: 0.5,
+            })
+            .collect()
+    };
+
+    let newer = cpu(&["a", "c"]);
+    let older = cpu(&["a", "b"]);
+
+    let timings = RgFrameTimings::new(
+        [("a", 1.0), ("b", 2.0)].iter().copied(),
+        [newer.as_slice(), older.as_slice()].iter().copied(),
+    );
+
+    assert_eq!(timings.passes.len(), 2);
+    assert_eq!(timings.passes[1].name, "b");
+    assert_eq!(timings.passes[1].cpu_start_ms, Some(1.0));
+    assert_eq!(timings.total_gpu_ms(), 3.0);
+
+    let json = timings.to_chrome_trace_json();
+    assert!(json.contains(r#""name":"b","cat":"gpu","ph":"X","ts":1000.000,"dur":2000.000"#));
+}
@@ -1,21 +1,27 @@
 use crate::{
+    debug_view::{DebugPickTarget, DEBUG_PICK_SLOT_BYTES},
+    pass_timings::PassCpuTiming,
     CompiledRenderGraph, ExecutingRenderGraph, ExportedTemporalRenderGraphState,
-    PredefinedDescriptorSet, RenderGraphExecutionParams, TemporalRenderGraph,
-    TemporalRenderGraphState, TemporalResourceState,
+    PredefinedDescriptorSet, RenderGraphExecutionParams, RgDebugPass, RgDebugPickedPixel,
+    RgFrameTimings, TemporalRenderGraph, TemporalRenderGraphState, TemporalResourceState,
 };
 use kajiya_backend::{
     ash::vk,
     dynamic_constants::*,
     pipeline_cache::*,
     rspirv_reflect,
+    shader_pack::ShaderPack,
     transient_resource_cache::TransientResourceCache,
     vk_sync,
     vulkan::{self, swapchain::Swapchain, RenderBackend},
-    Device,
+    Device, ImageDesc,
 };
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
 use turbosloth::*;
 use vulkan::buffer::{Buffer, BufferDesc};
 
@@ -40,8 +46,21 @@ pub struct Renderer {
 
     compiled_rg: Option<CompiledRenderGraph>,
     temporal_rg_state: TemporalRg,
+
+    // Most recent first. GPU timestamps arrive a few frames late, and need to be matched
+    // with CPU timings from the same frame.
+    pass_cpu_timing_history: VecDeque<Vec<PassCpuTiming>>,
+
+    debug_pass_list: Vec<RgDebugPass>,
+    // One slot per frame in flight, written by the debug view pass
+    debug_pick_buffer: Arc<Buffer>,
+    debug_picked_pixel: Option<RgDebugPickedPixel>,
+    frame_index: u32,
 }
 
+const PASS_CPU_TIMING_HISTORY_LEN: usize = 4;
+const DEBUG_PICK_SLOT_COUNT: u32 = 2;
+
 lazy_static::lazy_static! {
     static ref FRAME_CONSTANTS_LAYOUT: HashMap<u32, rspirv_reflect::DescriptorInfo> = [
     // frame_constants
@@ -62,7 +81,7 @@ lazy_static::lazy_static! {
             name: Default::default(),
         },
     ),
-    // triangle_lights_dyn
+    // punctual_lights_dyn
     (
         2,
         rspirv_reflect::DescriptorInfo {
@@ -71,6 +90,15 @@ lazy_static::lazy_static! {
             name: Default::default(),
         },
     ),
+    // area_lights_dyn
+    (
+        3,
+        rspirv_reflect::DescriptorInfo {
+            ty: rspirv_reflect::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
+            name: Default::default(),
+        },
+    ),
     ]
     .iter()
     .cloned()
@@ -80,7 +108,8 @@ lazy_static::lazy_static! {
 pub struct FrameConstantsLayout {
     pub globals_offset: u32,
     pub instance_dynamic_parameters_offset: u32,
-    pub triangle_lights_offset: u32,
+    pub punctual_lights_offset: u32,
+    pub area_lights_offset: u32,
 }
 
 impl Renderer {
@@ -101,6 +130,15 @@ impl Renderer {
         let frame_descriptor_set =
             Self::create_frame_descriptor_set(backend, &dynamic_constants.buffer);
 
+        let debug_pick_buffer = backend.device.create_buffer(
+            BufferDesc::new_gpu_to_cpu(
+                DEBUG_PICK_SLOT_BYTES * DEBUG_PICK_SLOT_COUNT as usize,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+            ),
+            "debug pick buffer",
+            None,
+        )?;
+
         Ok(Renderer {
             device: backend.device.clone(),
             dynamic_constants,
@@ -110,6 +148,11 @@ impl Renderer {
 
             compiled_rg: None,
             temporal_rg_state: Default::default(),
+            pass_cpu_timing_history: Default::default(),
+            debug_pass_list: Default::default(),
+            debug_pick_buffer: Arc::new(debug_pick_buffer),
+            debug_picked_pixel: None,
+            frame_index: 0,
         })
     }
 
@@ -131,6 +174,17 @@ impl Renderer {
 
         let current_frame = self.device.begin_frame();
 
+        // The GPU is done with the frame which last used this slot
+        self.debug_picked_pixel = {
+            let slot = (self.frame_index % DEBUG_PICK_SLOT_COUNT) as usize * DEBUG_PICK_SLOT_BYTES;
+            let bytes = self.debug_pick_buffer.allocation.mapped_slice().unwrap();
+
+            RgDebugPickedPixel::from_slot(
+                &bytes[slot..slot + DEBUG_PICK_SLOT_BYTES],
+                self.frame_index.wrapping_sub(DEBUG_PICK_SLOT_COUNT),
+            )
+        };
+
         // Both command buffers are accessible now, so begin recording.
         for cb in [
             &current_frame.main_command_buffer,
@@ -222,7 +276,7 @@ impl Renderer {
             .expect("swapchain image");
 
         // Execute the rest of the render graph, and submit the presentation command buffer.
-        let retired_rg = {
+        let mut retired_rg = {
             puffin::profile_scope!("presentation cb");
 
             let presentation_cb = &current_frame.presentation_command_buffer;
@@ -298,10 +352,16 @@ impl Renderer {
             TemporalRg::Exported(rg) => TemporalRg::Inert(rg.retire_temporal(&retired_rg)),
         };
 
+        self.pass_cpu_timing_history
+            .push_front(std::mem::take(&mut retired_rg.pass_cpu_timings));
+        self.pass_cpu_timing_history
+            .truncate(PASS_CPU_TIMING_HISTORY_LEN);
+
         retired_rg.release_resources(&mut self.transient_resource_cache);
 
         self.dynamic_constants.advance_frame();
         self.device.finish_frame(current_frame);
+        self.frame_index = self.frame_index.wrapping_add(1);
     }
 
     // Descriptor set for per-frame data
@@ -315,6 +375,7 @@ impl Renderer {
             vk::DescriptorBindingFlags::PARTIALLY_BOUND,
             vk::DescriptorBindingFlags::PARTIALLY_BOUND,
             vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         ];
 
         let mut binding_flags_create_info =
@@ -341,13 +402,20 @@ impl Renderer {
                                 .stage_flags(vk::ShaderStageFlags::ALL)
                                 .binding(1)
                                 .build(),
-                            // triangle_lights_dyn
+                            // punctual_lights_dyn
                             vk::DescriptorSetLayoutBinding::builder()
                                 .descriptor_count(1)
                                 .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
                                 .stage_flags(vk::ShaderStageFlags::ALL)
                                 .binding(2)
                                 .build(),
+                            // area_lights_dyn
+                            vk::DescriptorSetLayoutBinding::builder()
+                                .descriptor_count(1)
+                                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                                .stage_flags(vk::ShaderStageFlags::ALL)
+                                .binding(3)
+                                .build(),
                         ])
                         .push_next(&mut binding_flags_create_info)
                         .build(),
@@ -363,7 +431,7 @@ impl Renderer {
             },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
-                descriptor_count: 2,
+                descriptor_count: 3,
             },
         ];
 
@@ -413,13 +481,20 @@ impl Renderer {
                     .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
                     .buffer_info(std::slice::from_ref(&storage_buffer_info))
                     .build(),
-                // `triangle_lights_dyn`
+                // `punctual_lights_dyn`
                 vk::WriteDescriptorSet::builder()
                     .dst_binding(2)
                     .dst_set(set)
                     .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
                     .buffer_info(std::slice::from_ref(&storage_buffer_info))
                     .build(),
+                // `area_lights_dyn`
+                vk::WriteDescriptorSet::builder()
+                    .dst_binding(3)
+                    .dst_set(set)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                    .buffer_info(std::slice::from_ref(&storage_buffer_info))
+                    .build(),
             ];
 
             unsafe { device.update_descriptor_sets(&descriptor_set_writes, &[]) };
@@ -445,6 +520,12 @@ impl Renderer {
             self.device.clone(),
         );
 
+        rg.debug_pick_target = Some(DebugPickTarget {
+            buffer: self.debug_pick_buffer.clone(),
+            slot: self.frame_index % DEBUG_PICK_SLOT_COUNT,
+            frame: self.frame_index,
+        });
+
         rg.predefined_descriptor_set_layouts.insert(
             2,
             PredefinedDescriptorSet {
@@ -453,6 +534,7 @@ impl Renderer {
         );
 
         prepare_render_graph(&mut rg);
+        self.debug_pass_list = rg.debug_pass_list();
         let (rg, temporal_rg_state) = rg.export_temporal();
 
         self.compiled_rg = Some(rg.compile(&mut self.pipeline_cache));
@@ -475,9 +557,15 @@ impl Renderer {
                 };
 
                 for (res_key, res) in temporal_rg_state.0.resources {
-                    // `insert` is infrequent here, and we can avoid cloning the key.
-                    #[allow(clippy::map_entry)]
-                    if !self_temporal_rg_state.resources.contains_key(&res_key) {
+                    // Also replace resources which were re-created with a different descriptor.
+                    let is_new = self_temporal_rg_state
+                        .resources
+                        .get(&res_key)
+                        .map_or(true, |existing| {
+                            existing.resource().desc() != res.resource().desc()
+                        });
+
+                    if is_new {
                         let res = match res {
                             res @ TemporalResourceState::Inert { .. } => res,
                             TemporalResourceState::Imported { resource, .. }
@@ -501,4 +589,79 @@ impl Renderer {
     pub fn device(&self) -> &Arc<Device> {
         &self.device
     }
+
+    /// Passes and images of the last prepared frame, for use with `GraphDebugHook`.
+    pub fn debug_pass_list(&self) -> &[RgDebugPass] {
+        &self.debug_pass_list
+    }
+
+    /// Value of the pixel selected with `GraphDebugView::pick_uv`, from a recently completed frame.
+    pub fn debug_picked_pixel(&self) -> Option<RgDebugPickedPixel> {
+        self.debug_picked_pixel
+    }
+
+    /// Pipelines whose last compilation failed. Those which compiled before
+    /// keep using their last good version.
+    pub fn pipeline_compile_errors(&self) -> Vec<PipelineCompileError> {
+        self.pipeline_cache.compile_errors()
+    }
+
+    /// Collects the SPIR-V of HLSL shaders compiled by subsequent `prepare_frame` calls.
+    pub fn record_shader_pack(&mut self) {
+        self.pipeline_cache.record_shader_pack();
+    }
+
+    pub fn take_recorded_shader_pack(&mut self) -> Option<ShaderPack> {
+        self.pipeline_cache.take_recorded_shader_pack()
+    }
+
+    /// GPU durations and CPU recording times of render graph passes in the last frame
+    /// whose GPU timestamps have been read back. `None` until the first such frame.
+    pub fn last_frame_pass_timings(&self) -> Option<RgFrameTimings> {
+        let report = kajiya_backend::gpu_profiler::profiler().last_report()?;
+
+        Some(RgFrameTimings::new(
+            report
+                .scopes
+                .iter()
+                .map(|scope| (scope.name.as_str(), scope.duration.ms())),
+            self.pass_cpu_timing_history.iter().map(Vec::as_slice),
+        ))
+    }
+
+    /// Temporal resources persisting between frames. Only accessible between `draw_frame`
+    /// and the next `prepare_frame`.
+    pub fn temporal_state(&self) -> &TemporalRenderGraphState {
+        match &self.temporal_rg_state {
+            TemporalRg::Inert(state) => state,
+            TemporalRg::Exported(_) => {
+                panic!("Trying to access temporal state but render graph is still active")
+            }
+        }
+    }
+
+    pub fn temporal_state_mut(&mut self) -> &mut TemporalRenderGraphState {
+        match &mut self.temporal_rg_state {
+            TemporalRg::Inert(state) => state,
+            TemporalRg::Exported(_) => {
+                panic!("Trying to access temporal state but render graph is still active")
+            }
+        }
+    }
+
+    pub fn save_temporal_snapshot(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.temporal_state().save_snapshot(&self.device, path)
+    }
+
+    pub fn read_back_temporal_image(&self, key: &str) -> anyhow::Result<(ImageDesc, Vec<u8>)> {
+        self.temporal_state().read_back_image(&self.device, key)
+    }
+
+    pub fn load_temporal_snapshot(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<()> {
+        let device = self.device.clone();
+        self.temporal_state_mut().load_snapshot(&device, path)
+    }
 }
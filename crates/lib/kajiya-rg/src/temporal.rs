@@ -5,11 +5,11 @@ use std::{
 
 use anyhow::Context;
 
-use kajiya_backend::{vk_sync::AccessType, Device, Image, ImageDesc};
+use kajiya_backend::{ash::vk, vk_sync::AccessType, Device, Image, ImageDesc};
 
 use super::{
-    Buffer, BufferDesc, ExportableGraphResource, ExportedHandle, Handle, RenderGraph, Resource,
-    ResourceDesc, RetiredRenderGraph, TypeEquals,
+    Buffer, BufferDesc, ExportableGraphResource, ExportedHandle, GraphRawResourceHandle, Handle,
+    MockRetiredRenderGraph, RenderGraph, Resource, ResourceDesc, RetiredRenderGraph, TypeEquals,
 };
 
 pub struct ReadOnlyHandle<ResType: Resource>(Handle<ResType>);
@@ -43,12 +43,33 @@ impl From<String> for TemporalResourceKey {
     }
 }
 
+impl TemporalResourceKey {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemporalResourceDesc {
+    Image(ImageDesc),
+    Buffer(BufferDesc),
+}
+
 #[derive(Clone)]
 pub(crate) enum TemporalResource {
     Image(Arc<Image>),
     Buffer(Arc<Buffer>),
 }
 
+impl TemporalResource {
+    pub(crate) fn desc(&self) -> TemporalResourceDesc {
+        match self {
+            TemporalResource::Image(image) => TemporalResourceDesc::Image(image.desc),
+            TemporalResource::Buffer(buffer) => TemporalResourceDesc::Buffer(buffer.desc),
+        }
+    }
+}
+
 pub(crate) enum ExportedResourceHandle {
     Image(ExportedHandle<Image>),
     Buffer(ExportedHandle<Buffer>),
@@ -69,6 +90,16 @@ pub(crate) enum TemporalResourceState {
     },
 }
 
+impl TemporalResourceState {
+    pub(crate) fn resource(&self) -> &TemporalResource {
+        match self {
+            TemporalResourceState::Inert { resource, .. }
+            | TemporalResourceState::Imported { resource, .. }
+            | TemporalResourceState::Exported { resource, .. } => resource,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct TemporalRenderGraphState {
     pub(crate) resources: HashMap<TemporalResourceKey, TemporalResourceState>,
@@ -99,13 +130,50 @@ impl TemporalRenderGraphState {
                 .collect(),
         }
     }
+
+    /// Lists all temporal resources along with the descriptors they were created with.
+    pub fn resources(&self) -> impl Iterator<Item = (&TemporalResourceKey, TemporalResourceDesc)> {
+        self.resources
+            .iter()
+            .map(|(key, state)| (key, state.resource().desc()))
+    }
+
+    /// Drops the resource; the next `get_or_create_temporal` with this key will create
+    /// a fresh, zero-initialized one. Returns `false` if there was no such resource.
+    pub fn invalidate(&mut self, device: &Device, key: &TemporalResourceKey) -> bool {
+        if let Some(state) = self.resources.remove(key) {
+            release_temporal_resource(Some(device), state.resource());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drops all resources for which `f` returns `false`.
+    pub fn retain(
+        &mut self,
+        device: &Device,
+        mut f: impl FnMut(&TemporalResourceKey, TemporalResourceDesc) -> bool,
+    ) {
+        let dropped: Vec<TemporalResourceKey> = self
+            .resources
+            .iter()
+            .filter(|(key, state)| !f(key, state.resource().desc()))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in dropped {
+            self.invalidate(device, &key);
+        }
+    }
 }
 
 pub struct ExportedTemporalRenderGraphState(pub(crate) TemporalRenderGraphState);
 
 pub struct TemporalRenderGraph {
     rg: RenderGraph,
-    device: Arc<Device>,
+    // `None` for mock graphs, which never touch the GPU.
+    device: Option<Arc<Device>>,
     temporal_state: TemporalRenderGraphState,
 }
 
@@ -127,13 +195,26 @@ impl TemporalRenderGraph {
     pub fn new(state: TemporalRenderGraphState, device: Arc<Device>) -> Self {
         Self {
             rg: RenderGraph::new(),
-            device,
+            device: Some(device),
+            temporal_state: state,
+        }
+    }
+
+    /// Creates a graph without a GPU device, for use with `RenderGraph::execute_mock`.
+    /// Temporal images are created as placeholders without any memory behind them;
+    /// temporal buffers are not supported.
+    pub fn new_mock(state: TemporalRenderGraphState) -> Self {
+        Self {
+            rg: RenderGraph::new(),
+            device: None,
             temporal_state: state,
         }
     }
 
     pub fn device(&self) -> &Device {
-        self.device.as_ref()
+        self.device
+            .as_deref()
+            .expect("TemporalRenderGraph::device called on a mock graph")
     }
 }
 
@@ -148,7 +229,54 @@ pub trait GetOrCreateTemporal<Desc: ResourceDesc> {
         Desc: TypeEquals<Other = <<Desc as ResourceDesc>::Resource as Resource>::Desc>;
 }
 
+// Temporal resources can be snapshotted to and restored from disk,
+// which requires them to be usable as copy sources and destinations.
+fn temporal_image_desc(desc: ImageDesc) -> ImageDesc {
+    desc.usage(desc.usage | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST)
+}
+
+fn temporal_buffer_desc(mut desc: BufferDesc) -> BufferDesc {
+    desc.usage |= vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+    desc
+}
+
+fn create_temporal_image(device: Option<&Device>, desc: ImageDesc) -> anyhow::Result<Arc<Image>> {
+    Ok(Arc::new(if let Some(device) = device {
+        device
+            // TODO: Zero-init
+            .create_image(desc, vec![])
+            .with_context(|| format!("Creating image {:?}", desc))?
+    } else {
+        Image::new_placeholder(desc)
+    }))
+}
+
+// Images are destroyed once no frame in flight uses them. Mock graphs' placeholder
+// images have nothing to destroy.
+pub(crate) fn release_temporal_resource(device: Option<&Device>, resource: &TemporalResource) {
+    if let (Some(device), TemporalResource::Image(image)) = (device, resource) {
+        device.defer_release_image(image.clone());
+    }
+}
+
+fn create_temporal_buffer(
+    device: Option<&Device>,
+    key: &TemporalResourceKey,
+    desc: BufferDesc,
+) -> anyhow::Result<Arc<Buffer>> {
+    let device = device
+        .with_context(|| format!("Temporal buffer {:?} can't be created in a mock graph", key))?;
+
+    Ok(Arc::new(device.create_buffer(
+        desc,
+        &key.0,
+        // Zero-init
+        Some(vec![0; desc.size].as_slice()),
+    )?))
+}
+
 impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
+    #[track_caller]
     fn get_or_create_temporal(
         &mut self,
         key: impl Into<TemporalResourceKey>,
@@ -156,6 +284,7 @@ impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
         //) -> anyhow::Result<Handle<Image>> {
     ) -> anyhow::Result<Handle<Image>> {
         let key = key.into();
+        let desc = temporal_image_desc(desc);
 
         match self.temporal_state.resources.entry(key.clone()) {
             hash_map::Entry::Occupied(mut entry) => {
@@ -166,20 +295,26 @@ impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
                         resource,
                         access_type,
                     } => {
-                        let resource = resource.clone();
-
-                        match &resource {
+                        let (image, access_type) = match resource {
+                            TemporalResource::Image(image) if image.desc == desc => {
+                                (image.clone(), *access_type)
+                            }
                             TemporalResource::Image(image) => {
-                                let handle = self.rg.import(image.clone(), *access_type);
+                                // Most likely a change of the render extent. Only this resource
+                                // gets re-created; the rest of the temporal state is kept.
+                                log::info!(
+                                    "Temporal image {:?} changed from {:?} to {:?}; re-creating it",
+                                    key,
+                                    image.desc,
+                                    desc
+                                );
 
-                                *state = TemporalResourceState::Imported {
-                                    resource,
-                                    handle: ExportableGraphResource::Image(
-                                        handle.clone_unchecked(),
-                                    ),
-                                };
+                                release_temporal_resource(self.device.as_deref(), resource);
 
-                                Ok(handle)
+                                (
+                                    create_temporal_image(self.device.as_deref(), desc)?,
+                                    AccessType::Nothing,
+                                )
                             }
                             TemporalResource::Buffer(_) => {
                                 anyhow::bail!(
@@ -187,7 +322,16 @@ impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
                                     key
                                 );
                             }
-                        }
+                        };
+
+                        let handle = self.rg.import(image.clone(), access_type);
+
+                        *state = TemporalResourceState::Imported {
+                            resource: TemporalResource::Image(image),
+                            handle: ExportableGraphResource::Image(handle.clone_unchecked()),
+                        };
+
+                        Ok(handle)
                     }
                     TemporalResourceState::Imported { .. } => Err(anyhow::anyhow!(
                         "Temporal resource already taken: {:?}",
@@ -199,12 +343,7 @@ impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
                 }
             }
             hash_map::Entry::Vacant(entry) => {
-                let resource = Arc::new(
-                    self.device
-                        // TODO: Zero-init
-                        .create_image(desc, vec![])
-                        .with_context(|| format!("Creating image {:?}", desc))?,
-                );
+                let resource = create_temporal_image(self.device.as_deref(), desc)?;
                 let handle = self.rg.import(resource.clone(), AccessType::Nothing);
                 entry.insert(TemporalResourceState::Imported {
                     resource: TemporalResource::Image(resource),
@@ -217,6 +356,7 @@ impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
 }
 
 impl GetOrCreateTemporal<BufferDesc> for TemporalRenderGraph {
+    #[track_caller]
     fn get_or_create_temporal(
         &mut self,
         key: impl Into<TemporalResourceKey>,
@@ -224,6 +364,7 @@ impl GetOrCreateTemporal<BufferDesc> for TemporalRenderGraph {
         //) -> anyhow::Result<Handle<Image>> {
     ) -> anyhow::Result<Handle<Buffer>> {
         let key = key.into();
+        let desc = temporal_buffer_desc(desc);
 
         match self.temporal_state.resources.entry(key.clone()) {
             hash_map::Entry::Occupied(mut entry) => {
@@ -234,20 +375,22 @@ impl GetOrCreateTemporal<BufferDesc> for TemporalRenderGraph {
                         resource,
                         access_type,
                     } => {
-                        let resource = resource.clone();
-
-                        match &resource {
+                        let (buffer, access_type) = match resource {
+                            TemporalResource::Buffer(buffer) if buffer.desc == desc => {
+                                (buffer.clone(), *access_type)
+                            }
                             TemporalResource::Buffer(buffer) => {
-                                let handle = self.rg.import(buffer.clone(), *access_type);
-
-                                *state = TemporalResourceState::Imported {
-                                    resource,
-                                    handle: ExportableGraphResource::Buffer(
-                                        handle.clone_unchecked(),
-                                    ),
-                                };
+                                log::info!(
+                                    "Temporal buffer {:?} changed from {:?} to {:?}; re-creating it",
+                                    key,
+                                    buffer.desc,
+                                    desc
+                                );
 
-                                Ok(handle)
+                                (
+                                    create_temporal_buffer(self.device.as_deref(), &key, desc)?,
+                                    AccessType::Nothing,
+                                )
                             }
                             TemporalResource::Image(_) => {
                                 anyhow::bail!(
@@ -255,7 +398,16 @@ impl GetOrCreateTemporal<BufferDesc> for TemporalRenderGraph {
                                     key
                                 );
                             }
-                        }
+                        };
+
+                        let handle = self.rg.import(buffer.clone(), access_type);
+
+                        *state = TemporalResourceState::Imported {
+                            resource: TemporalResource::Buffer(buffer),
+                            handle: ExportableGraphResource::Buffer(handle.clone_unchecked()),
+                        };
+
+                        Ok(handle)
                     }
                     TemporalResourceState::Imported { .. } => Err(anyhow::anyhow!(
                         "Temporal resource already taken: {:?}",
@@ -267,12 +419,7 @@ impl GetOrCreateTemporal<BufferDesc> for TemporalRenderGraph {
                 }
             }
             hash_map::Entry::Vacant(entry) => {
-                let resource = Arc::new(self.device.create_buffer(
-                    desc,
-                    &key.0,
-                    // Zero-init
-                    Some(vec![0; desc.size].as_slice()),
-                )?);
+                let resource = create_temporal_buffer(self.device.as_deref(), &key, desc)?;
                 let handle = self.rg.import(resource.clone(), AccessType::Nothing);
                 entry.insert(TemporalResourceState::Imported {
                     resource: TemporalResource::Buffer(resource),
@@ -322,6 +469,17 @@ impl TemporalRenderGraph {
 
 impl ExportedTemporalRenderGraphState {
     pub fn retire_temporal(self, rg: &RetiredRenderGraph) -> TemporalRenderGraphState {
+        self.retire_temporal_impl(|handle| rg.exported_access_type(handle))
+    }
+
+    pub fn retire_temporal_mock(self, rg: &MockRetiredRenderGraph) -> TemporalRenderGraphState {
+        self.retire_temporal_impl(|handle| rg.access_types[handle.id as usize])
+    }
+
+    fn retire_temporal_impl(
+        self,
+        exported_access_type: impl Fn(GraphRawResourceHandle) -> AccessType,
+    ) -> TemporalRenderGraphState {
         let mut state = self.0;
 
         for state in state.resources.values_mut() {
@@ -332,23 +490,55 @@ impl ExportedTemporalRenderGraphState {
                 TemporalResourceState::Imported { .. } => {
                     unreachable!()
                 }
-                TemporalResourceState::Exported { resource, handle } => match handle {
-                    ExportedResourceHandle::Image(handle) => {
-                        *state = TemporalResourceState::Inert {
-                            resource: resource.clone(),
-                            access_type: rg.exported_resource(*handle).1,
-                        }
+                TemporalResourceState::Exported { resource, handle } => {
+                    let raw = match handle {
+                        ExportedResourceHandle::Image(handle) => handle.raw,
+                        ExportedResourceHandle::Buffer(handle) => handle.raw,
+                    };
+
+                    *state = TemporalResourceState::Inert {
+                        resource: resource.clone(),
+                        access_type: exported_access_type(raw),
                     }
-                    ExportedResourceHandle::Buffer(handle) => {
-                        *state = TemporalResourceState::Inert {
-                            resource: resource.clone(),
-                            access_type: rg.exported_resource(*handle).1,
-                        }
-                    }
-                },
+                }
             }
         }
 
         state
     }
 }
+
+#[test]
+fn test_resize_keeps_unrelated_resources() {
+    let small = ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [8, 8]);
+    let large = ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [16, 16]);
+    let cache = ImageDesc::new_3d(vk::Format::R32_UINT, [4, 4, 4]);
+
+    let run_frame = |state: TemporalRenderGraphState, history_desc: ImageDesc| {
+        let mut rg = TemporalRenderGraph::new_mock(state);
+        rg.get_or_create_temporal("history", history_desc).unwrap();
+        rg.get_or_create_temporal("cache", cache).unwrap();
+
+        let (rg, state) = rg.export_temporal();
+        state.retire_temporal_mock(&rg.execute_mock())
+    };
+
+    let state = run_frame(TemporalRenderGraphState::default(), small);
+    let cache_before = match state.resources[&TemporalResourceKey::from("cache")].resource() {
+        TemporalResource::Image(image) => image.clone(),
+        TemporalResource::Buffer(_) => unreachable!(),
+    };
+
+    let state = run_frame(state, large);
+
+    let descs: HashMap<_, _> = state.resources().collect();
+    assert!(matches!(
+        descs[&TemporalResourceKey::from("history")],
+        TemporalResourceDesc::Image(desc) if desc.extent == [16, 16, 1]
+    ));
+
+    match state.resources[&TemporalResourceKey::from("cache")].resource() {
+        TemporalResource::Image(image) => assert!(Arc::ptr_eq(image, &cache_before)),
+        TemporalResource::Buffer(_) => unreachable!(),
+    }
+}
@@ -0,0 +1,655 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use kajiya_backend::{
+    ash::vk,
+    gpu_allocator::MemoryLocation,
+    vk_sync::{self, AccessType},
+    vulkan::{
+        barrier::{image_aspect_mask_from_format, record_image_barrier, ImageBarrier},
+        buffer::{Buffer, BufferDesc},
+    },
+    Device, Image, ImageDesc, ImageType,
+};
+use nanoserde::{DeBin, SerBin};
+
+use crate::{
+    temporal::release_temporal_resource, TemporalRenderGraphState, TemporalResource,
+    TemporalResourceKey, TemporalResourceState,
+};
+
+const SNAPSHOT_MAGIC: u32 = 0x6b61_7472; // "katr"
+const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(SerBin, DeBin)]
+struct SnapshotFile {
+    magic: u32,
+    version: u32,
+    images: Vec<SnapshotImage>,
+    buffers: Vec<SnapshotBuffer>,
+}
+
+#[derive(SerBin, DeBin)]
+struct SnapshotImage {
+    key: String,
+    image_type: u32,
+    usage: u32,
+    flags: u32,
+    format: i32,
+    extent: Vec<u32>,
+    tiling: i32,
+    mip_levels: u32,
+    array_elements: u32,
+    // Tightly packed texels of all array layers of each mip, starting with mip 0.
+    // Empty if the image was never written to.
+    data: Vec<u8>,
+}
+
+#[derive(SerBin, DeBin)]
+struct SnapshotBuffer {
+    key: String,
+    size: u64,
+    usage: u32,
+    memory_location: u32,
+    // Zero if the buffer has no alignment requirement
+    alignment: u64,
+    data: Vec<u8>,
+}
+
+// Moves resource contents between the GPU and the CPU. Mocked in tests, so that the
+// snapshot bookkeeping can be checked without a device.
+trait SnapshotDevice {
+    fn read_back_image(&self, image: &Image, access_type: AccessType) -> anyhow::Result<Vec<u8>>;
+    fn read_back_buffer(&self, buffer: &Buffer, access_type: AccessType)
+        -> anyhow::Result<Vec<u8>>;
+    /// Creates an image with the given texels, or undefined contents if `data` is `None`.
+    fn create_image(&self, desc: ImageDesc, data: Option<&[u8]>) -> anyhow::Result<Image>;
+    fn create_buffer(&self, desc: BufferDesc, name: &str, data: &[u8]) -> anyhow::Result<Buffer>;
+    /// Releases a resource replaced by one from the snapshot.
+    fn release(&self, resource: &TemporalResource);
+}
+
+impl SnapshotDevice for Device {
+    fn read_back_image(&self, image: &Image, access_type: AccessType) -> anyhow::Result<Vec<u8>> {
+        read_back_image(self, image, access_type)
+    }
+
+    fn read_back_buffer(
+        &self,
+        buffer: &Buffer,
+        access_type: AccessType,
+    ) -> anyhow::Result<Vec<u8>> {
+        read_back_buffer(self, buffer, access_type)
+    }
+
+    fn create_image(&self, desc: ImageDesc, data: Option<&[u8]>) -> anyhow::Result<Image> {
+        match data {
+            Some(data) => upload_image(self, desc, data),
+            None => Device::create_image(self, desc, vec![])
+                .with_context(|| format!("Creating image {:?}", desc)),
+        }
+    }
+
+    fn create_buffer(&self, desc: BufferDesc, name: &str, data: &[u8]) -> anyhow::Result<Buffer> {
+        Ok(Device::create_buffer(self, desc, name, Some(data))?)
+    }
+
+    fn release(&self, resource: &TemporalResource) {
+        release_temporal_resource(Some(self), resource);
+    }
+}
+
+impl TemporalRenderGraphState {
+    /// Copies the contents of all temporal resources to a file.
+    ///
+    /// Must be called between frames. Stalls the GPU.
+    pub fn save_snapshot(&self, device: &Device, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.save_snapshot_impl(device, path.as_ref())
+    }
+
+    /// Copies the texels of one temporal image to the CPU, packed as in `save_snapshot`.
+    ///
+    /// Must be called between frames. Stalls the GPU.
+    pub fn read_back_image(
+        &self,
+        device: &Device,
+        key: &str,
+    ) -> anyhow::Result<(ImageDesc, Vec<u8>)> {
+        self.read_back_image_impl(device, key)
+    }
+
+    /// Re-creates temporal resources from a file written by `save_snapshot`.
+    /// Resources not present in the snapshot are left intact.
+    ///
+    /// Restored resources keep the descriptors they were saved with; if the renderer then
+    /// requests them with different descriptors, they will be re-created from scratch.
+    /// CPU-side renderer state (frame indices, jitter sequences, etc.) is not part of the snapshot.
+    pub fn load_snapshot(&mut self, device: &Device, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.load_snapshot_impl(device, path.as_ref())
+    }
+
+    fn save_snapshot_impl(&self, device: &impl SnapshotDevice, path: &Path) -> anyhow::Result<()> {
+        let mut images = Vec::new();
+        let mut buffers = Vec::new();
+
+        for (key, state) in &self.resources {
+            let (resource, access_type) = match state {
+                TemporalResourceState::Inert {
+                    resource,
+                    access_type,
+                } => (resource, *access_type),
+                TemporalResourceState::Imported { .. } | TemporalResourceState::Exported { .. } => {
+                    anyhow::bail!("Temporal resource {:?} is in use by a render graph", key)
+                }
+            };
+
+            match resource {
+                TemporalResource::Image(image) => {
+                    let data = if access_type == AccessType::Nothing {
+                        Vec::new()
+                    } else {
+                        device
+                            .read_back_image(image, access_type)
+                            .with_context(|| format!("Reading back temporal image {:?}", key))?
+                    };
+
+                    let desc = &image.desc;
+                    images.push(SnapshotImage {
+                        key: key.as_str().to_owned(),
+                        image_type: desc.image_type as u32,
+                        usage: desc.usage.as_raw(),
+                        flags: desc.flags.as_raw(),
+                        format: desc.format.as_raw(),
+                        extent: desc.extent.to_vec(),
+                        tiling: desc.tiling.as_raw(),
+                        mip_levels: desc.mip_levels as u32,
+                        array_elements: desc.array_elements,
+                        data,
+                    });
+                }
+                TemporalResource::Buffer(buffer) => {
+                    let data = device
+                        .read_back_buffer(buffer, access_type)
+                        .with_context(|| format!("Reading back temporal buffer {:?}", key))?;
+
+                    let desc = &buffer.desc;
+                    buffers.push(SnapshotBuffer {
+                        key: key.as_str().to_owned(),
+                        size: desc.size as u64,
+                        usage: desc.usage.as_raw(),
+                        memory_location: memory_location_to_u32(desc.memory_location),
+                        alignment: desc.alignment.unwrap_or(0),
+                        data,
+                    });
+                }
+            }
+        }
+
+        let file = SnapshotFile {
+            magic: SNAPSHOT_MAGIC,
+            version: SNAPSHOT_VERSION,
+            images,
+            buffers,
+        };
+
+        std::fs::write(path, file.serialize_bin())
+            .with_context(|| format!("Writing temporal snapshot {:?}", path))
+    }
+
+    fn read_back_image_impl(
+        &self,
+        device: &impl SnapshotDevice,
+        key: &str,
+    ) -> anyhow::Result<(ImageDesc, Vec<u8>)> {
+        let key = TemporalResourceKey::from(key);
+
+        let (resource, access_type) = match self.resources.get(&key) {
+            Some(TemporalResourceState::Inert {
+                resource,
+                access_type,
+            }) => (resource, *access_type),
+            Some(_) => anyhow::bail!("Temporal resource {:?} is in use by a render graph", key),
+            None => anyhow::bail!("No temporal resource {:?}", key),
+        };
+
+        let image = match resource {
+            TemporalResource::Image(image) => image,
+            TemporalResource::Buffer(_) => {
+                anyhow::bail!("Temporal resource {:?} is not an image", key)
+            }
+        };
+
+        if access_type == AccessType::Nothing {
+            anyhow::bail!("Temporal image {:?} was never written to", key);
+        }
+
+        let data = device
+            .read_back_image(image, access_type)
+            .with_context(|| format!("Reading back temporal image {:?}", key))?;
+
+        Ok((image.desc, data))
+    }
+
+    fn load_snapshot_impl(
+        &mut self,
+        device: &impl SnapshotDevice,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Reading temporal snapshot {:?}", path))?;
+        let file = SnapshotFile::deserialize_bin(&bytes)
+            .map_err(|err| anyhow::anyhow!("Parsing temporal snapshot {:?}: {:?}", path, err))?;
+
+        if file.magic != SNAPSHOT_MAGIC || file.version != SNAPSHOT_VERSION {
+            anyhow::bail!(
+                "{:?} is not a temporal snapshot, or has an unsupported version",
+                path
+            );
+        }
+
+        for image in file.images {
+            let key = TemporalResourceKey::from(image.key);
+            let desc = ImageDesc {
+                image_type: image_type_from_u32(image.image_type)?,
+                usage: vk::ImageUsageFlags::from_raw(image.usage),
+                flags: vk::ImageCreateFlags::from_raw(image.flags),
+                format: vk::Format::from_raw(image.format),
+                extent: match image.extent.as_slice() {
+                    &[x, y, z] => [x, y, z],
+                    _ => anyhow::bail!("Invalid extent of temporal image {:?}", key),
+                },
+                tiling: vk::ImageTiling::from_raw(image.tiling),
+                mip_levels: image.mip_levels as u16,
+                array_elements: image.array_elements,
+            };
+
+            let (image, access_type) = if image.data.is_empty() {
+                (device.create_image(desc, None)?, AccessType::Nothing)
+            } else {
+                (
+                    device
+                        .create_image(desc, Some(&image.data))
+                        .with_context(|| format!("Restoring temporal image {:?}", key))?,
+                    AccessType::TransferWrite,
+                )
+            };
+
+            let replaced = self.resources.insert(
+                key,
+                TemporalResourceState::Inert {
+                    resource: TemporalResource::Image(Arc::new(image)),
+                    access_type,
+                },
+            );
+
+            if let Some(replaced) = replaced {
+                device.release(replaced.resource());
+            }
+        }
+
+        for buffer in file.buffers {
+            let key = TemporalResourceKey::from(buffer.key);
+            let desc = BufferDesc {
+                size: buffer.size as usize,
+                usage: vk::BufferUsageFlags::from_raw(buffer.usage),
+                memory_location: memory_location_from_u32(buffer.memory_location)?,
+                alignment: Some(buffer.alignment).filter(|&alignment| alignment != 0),
+            };
+
+            if buffer.data.len() != desc.size {
+                anyhow::bail!("Size mismatch in temporal buffer {:?}", key);
+            }
+
+            let resource = device.create_buffer(desc, key.as_str(), &buffer.data)?;
+
+            let replaced = self.resources.insert(
+                key,
+                TemporalResourceState::Inert {
+                    resource: TemporalResource::Buffer(Arc::new(resource)),
+                    access_type: AccessType::Nothing,
+                },
+            );
+
+            if let Some(replaced) = replaced {
+                device.release(replaced.resource());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn image_copy_regions(desc: &ImageDesc) -> anyhow::Result<(Vec<vk::BufferImageCopy>, usize)> {
+    let texel_size = kajiya_backend::format_texel_size_bytes(desc.format)
+        .with_context(|| format!("Unsupported format {:?}", desc.format))?;
+    let aspect_mask = image_aspect_mask_from_format(desc.format);
+    let layer_count = desc.array_layer_count();
+
+    let mut regions = Vec::with_capacity(desc.mip_levels as usize);
+    let mut offset = 0;
+
+    for mip in 0..desc.mip_levels as u32 {
+        let extent = vk::Extent3D {
+            width: (desc.extent[0] >> mip).max(1),
+            height: (desc.extent[1] >> mip).max(1),
+            depth: (desc.extent[2] >> mip).max(1),
+        };
+
+        regions.push(
+            vk::BufferImageCopy::builder()
+                .buffer_offset(offset as u64)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(aspect_mask)
+                        .mip_level(mip)
+                        .layer_count(layer_count)
+                        .build(),
+                )
+                .image_extent(extent)
+                .build(),
+        );
+
+        offset += extent.width as usize
+            * extent.height as usize
+            * extent.depth as usize
+            * layer_count as usize
+            * texel_size;
+    }
+
+    Ok((regions, offset))
+}
+
+fn read_back_image(
+    device: &Device,
+    image: &Image,
+    access_type: AccessType,
+) -> anyhow::Result<Vec<u8>> {
+    let (regions, size) = image_copy_regions(&image.desc)?;
+    let aspect_mask = image_aspect_mask_from_format(image.desc.format);
+
+    let readback_buffer = device.create_buffer(
+        BufferDesc::new_gpu_to_cpu(size, vk::BufferUsageFlags::TRANSFER_DST),
+        "temporal snapshot readback",
+        None,
+    )?;
+
+    let copy_result = device.with_setup_cb(|cb| unsafe {
+        record_image_barrier(
+            device,
+            cb,
+            ImageBarrier::new(
+                image.raw,
+                access_type,
+                AccessType::TransferRead,
+                aspect_mask,
+            ),
+        );
+
+        device.raw.cmd_copy_image_to_buffer(
+            cb,
+            image.raw,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            readback_buffer.raw,
+            &regions,
+        );
+
+        record_image_barrier(
+            device,
+            cb,
+            ImageBarrier::new(
+                image.raw,
+                AccessType::TransferRead,
+                access_type,
+                aspect_mask,
+            ),
+        );
+    });
+
+    let data = copy_result.map_err(anyhow::Error::from).and_then(|_| {
+        Ok(readback_buffer
+            .allocation
+            .mapped_slice()
+            .context("readback buffer is not mapped")?[0..size]
+            .to_vec())
+    });
+
+    device.immediate_destroy_buffer(readback_buffer);
+    data
+}
+
+fn upload_image(device: &Device, desc: ImageDesc, data: &[u8]) -> anyhow::Result<Image> {
+    let (regions, size) = image_copy_regions(&desc)?;
+
+    if data.len() != size {
+        anyhow::bail!("Expected {} bytes of image data, got {}", size, data.len());
+    }
+
+    let image = device.create_image(desc, vec![])?;
+    let aspect_mask = image_aspect_mask_from_format(desc.format);
+
+    let mut upload_buffer = device.create_buffer(
+        BufferDesc::new_cpu_to_gpu(size, vk::BufferUsageFlags::TRANSFER_SRC),
+        "temporal snapshot upload",
+        None,
+    )?;
+
+    upload_buffer.allocation.mapped_slice_mut().unwrap()[0..size].copy_from_slice(data);
+
+    let copy_result = device.with_setup_cb(|cb| unsafe {
+        record_image_barrier(
+            device,
+            cb,
+            ImageBarrier::new(
+                image.raw,
+                AccessType::Nothing,
+                AccessType::TransferWrite,
+                aspect_mask,
+            )
+            .with_discard(true),
+        );
+
+        device.raw.cmd_copy_buffer_to_image(
+            cb,
+            upload_buffer.raw,
+            image.raw,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &regions,
+        );
+    });
+
+    device.immediate_destroy_buffer(upload_buffer);
+    copy_result?;
+
+    Ok(image)
+}
+
+fn read_back_buffer(
+    device: &Device,
+    buffer: &Buffer,
+    access_type: AccessType,
+) -> anyhow::Result<Vec<u8>> {
+    let size = buffer.desc.size;
+
+    let readback_buffer = device.create_buffer(
+        BufferDesc::new_gpu_to_cpu(size, vk::BufferUsageFlags::TRANSFER_DST),
+        "temporal snapshot readback",
+        None,
+    )?;
+
+    let copy_result = device.with_setup_cb(|cb| unsafe {
+        vk_sync::cmd::pipeline_barrier(
+            device.raw.fp_v1_0(),
+            cb,
+            Some(vk_sync::GlobalBarrier {
+                previous_accesses: &[access_type],
+                next_accesses: &[AccessType::TransferRead],
+            }),
+            &[],
+            &[],
+        );
+
+        device.raw.cmd_copy_buffer(
+            cb,
+            buffer.raw,
+            readback_buffer.raw,
+            &[vk::BufferCopy::builder().size(size as u64).build()],
+        );
+    });
+
+    let data = copy_result.map_err(anyhow::Error::from).and_then(|_| {
+        Ok(readback_buffer
+            .allocation
+            .mapped_slice()
+            .context("readback buffer is not mapped")?[0..size]
+            .to_vec())
+    });
+
+    device.immediate_destroy_buffer(readback_buffer);
+    data
+}
+
+fn image_type_from_u32(image_type: u32) -> anyhow::Result<ImageType> {
+    Ok(match image_type {
+        0 => ImageType::Tex1d,
+        1 => ImageType::Tex1dArray,
+        2 => ImageType::Tex2d,
+        3 => ImageType::Tex2dArray,
+        4 => ImageType::Tex3d,
+        5 => ImageType::Cube,
+        6 => ImageType::CubeArray,
+        _ => anyhow::bail!("Invalid image type {}", image_type),
+    })
+}
+
+fn memory_location_to_u32(location: MemoryLocation) -> u32 {
+    match location {
+        MemoryLocation::Unknown => 0,
+        MemoryLocation::GpuOnly => 1,
+        MemoryLocation::CpuToGpu => 2,
+        MemoryLocation::GpuToCpu => 3,
+    }
+}
+
+fn memory_location_from_u32(location: u32) -> anyhow::Result<MemoryLocation> {
+    Ok(match location {
+        0 => MemoryLocation::Unknown,
+        1 => MemoryLocation::GpuOnly,
+        2 => MemoryLocation::CpuToGpu,
+        3 => MemoryLocation::GpuToCpu,
+        _ => anyhow::bail!("Invalid memory location {}", location),
+    })
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct MockSnapshotDevice {
+    // Descs and texels of images created with data
+    uploads: std::cell::RefCell<Vec<(ImageDesc, Vec<u8>)>>,
+    released: std::cell::Cell<usize>,
+}
+
+#[cfg(test)]
+impl SnapshotDevice for MockSnapshotDevice {
+    fn read_back_image(&self, image: &Image, _access_type: AccessType) -> anyhow::Result<Vec<u8>> {
+        // Tells images of different extents apart
+        let (_, size) = image_copy_regions(&image.desc)?;
+        Ok((0..size)
+            .map(|i| (i as u32 ^ image.desc.extent[0]) as u8)
+            .collect())
+    }
+
+    fn read_back_buffer(
+        &self,
+        _buffer: &Buffer,
+        _access_type: AccessType,
+    ) -> anyhow::Result<Vec<u8>> {
+        anyhow::bail!("Temporal buffers can't be mocked")
+    }
+
+    fn create_image(&self, desc: ImageDesc, data: Option<&[u8]>) -> anyhow::Result<Image> {
+        if let Some(data) = data {
+            self.uploads.borrow_mut().push((desc, data.to_vec()));
+        }
+
+        Ok(Image::new_placeholder(desc))
+    }
+
+    fn create_buffer(
+        &self,
+        _desc: BufferDesc,
+        _name: &str,
+        _data: &[u8],
+    ) -> anyhow::Result<Buffer> {
+        anyhow::bail!("Temporal buffers can't be mocked")
+    }
+
+    fn release(&self, _resource: &TemporalResource) {
+        self.released.set(self.released.get() + 1);
+    }
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    use crate::{GetOrCreateTemporal, SimpleRenderPass, TemporalRenderGraph};
+    use std::collections::HashMap;
+
+    let written_desc = ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [8, 4]);
+    let unwritten_desc = ImageDesc::new_3d(vk::Format::R32_UINT, [4, 4, 4]);
+
+    let mut rg = TemporalRenderGraph::new_mock(Default::default());
+    let mut written = rg.get_or_create_temporal("written", written_desc).unwrap();
+    rg.get_or_create_temporal("unwritten", unwritten_desc)
+        .unwrap();
+
+    SimpleRenderPass::new_compute(rg.add_pass("fill"), "/shaders/fill.hlsl")
+        .write(&mut written)
+        .dispatch([8, 4, 1]);
+
+    let (rg, state) = rg.export_temporal();
+    let state = state.retire_temporal_mock(&rg.execute_mock());
+
+    let device = MockSnapshotDevice::default();
+    let path = std::env::temp_dir().join(format!(
+        "kajiya-temporal-snapshot-test-{}.bin",
+        std::process::id()
+    ));
+    state.save_snapshot_impl(&device, &path).unwrap();
+
+    // Restore over a state which has a different resource under one of the keys
+    let mut rg = TemporalRenderGraph::new_mock(Default::default());
+    rg.get_or_create_temporal("written", ImageDesc::new_2d(vk::Format::R8_UNORM, [2, 2]))
+        .unwrap();
+    let (rg, restored) = rg.export_temporal();
+    let mut restored = restored.retire_temporal_mock(&rg.execute_mock());
+
+    let load_result = restored.load_snapshot_impl(&device, &path);
+    std::fs::remove_file(&path).unwrap();
+    load_result.unwrap();
+
+    let descs = |state: &TemporalRenderGraphState| -> HashMap<_, _> {
+        state
+            .resources()
+            .map(|(key, desc)| (key.clone(), desc))
+            .collect()
+    };
+    assert_eq!(descs(&restored), descs(&state));
+    assert_eq!(device.released.get(), 1);
+
+    // Only images which were written to carry texels
+    let written_image = match state.resources[&TemporalResourceKey::from("written")].resource() {
+        TemporalResource::Image(image) => image.clone(),
+        TemporalResource::Buffer(_) => unreachable!(),
+    };
+    let expected_texels = device
+        .read_back_image(&written_image, AccessType::AnyShaderWrite)
+        .unwrap();
+    assert_eq!(
+        *device.uploads.borrow(),
+        vec![(written_image.desc, expected_texels)]
+    );
+
+    let (desc, _) = restored.read_back_image_impl(&device, "written").unwrap();
+    assert_eq!(desc, written_image.desc);
+    assert!(restored.read_back_image_impl(&device, "unwritten").is_err());
+    assert!(restored.read_back_image_impl(&device, "missing").is_err());
+}
@@ -0,0 +1,603 @@
+use std::{fmt, ops::Range, panic::Location};
+
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{
+        barrier::image_aspect_mask_from_format,
+        image::{convert_image_type_to_view_type, ImageDesc, ImageType, ImageViewDesc},
+    },
+};
+
+use crate::{
+    graph::{GraphResourceImportInfo, GraphResourceInfo, RecordedPass, RenderGraph},
+    GraphRawResourceHandle,
+};
+
+#[derive(Debug, Clone)]
+pub enum RgValidationErrorKind {
+    ReadBeforeWrite,
+    OverlappingWrites,
+    IncompatibleViewType {
+        image_type: ImageType,
+        view_type: vk::ImageViewType,
+    },
+    MipRangeOutOfBounds {
+        base_mip_level: u32,
+        level_count: u32,
+        mip_levels: u32,
+    },
+    LayerRangeOutOfBounds {
+        layer_count: u32,
+        array_layers: u32,
+    },
+    MissingDepthAspect,
+    UndeclaredRead,
+    UndeclaredWrite,
+}
+
+#[derive(Debug, Clone)]
+pub struct RgValidationError {
+    pub pass_name: String,
+    pub pass_idx: usize,
+    pub resource_created_at: Option<&'static Location<'static>>,
+    pub kind: RgValidationErrorKind,
+}
+
+impl fmt::Display for RgValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pass {:?} (#{}): ", self.pass_name, self.pass_idx)?;
+
+        match &self.kind {
+            RgValidationErrorKind::ReadBeforeWrite => {
+                write!(f, "reads a resource which no previous pass has written to")?
+            }
+            RgValidationErrorKind::OverlappingWrites => write!(
+                f,
+                "writes to the same resource more than once without non-overlapping views"
+            )?,
+            RgValidationErrorKind::IncompatibleViewType {
+                image_type,
+                view_type,
+            } => write!(
+                f,
+                "binds a {:?} view of a {:?} image",
+                view_type, image_type
+            )?,
+            RgValidationErrorKind::MipRangeOutOfBounds {
+                base_mip_level,
+                level_count,
+                mip_levels,
+            } => write!(
+                f,
+                "binds mips {}..{} of an image with {} mip levels",
+                base_mip_level,
+                base_mip_level + level_count,
+                mip_levels
+            )?,
+            RgValidationErrorKind::LayerRangeOutOfBounds {
+                layer_count,
+                array_layers,
+            } => write!(
+                f,
+                "binds {} layers of an image with {} array layers",
+                layer_count, array_layers
+            )?,
+            RgValidationErrorKind::MissingDepthAspect => write!(
+                f,
+                "binds a depth image without vk::ImageAspectFlags::DEPTH in the view"
+            )?,
+            RgValidationErrorKind::UndeclaredRead => write!(
+                f,
+                "binds a resource which it does not declare; missing `.read()`?"
+            )?,
+            RgValidationErrorKind::UndeclaredWrite => write!(
+                f,
+                "binds a resource for writing which it does not declare; missing `.write()`?"
+            )?,
+        }
+
+        if let Some(location) = self.resource_created_at {
+            write!(f, "; resource created at {}", location)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct RenderGraphValidator {
+    // Per resource: whether any recorded pass has written to it
+    written: Vec<bool>,
+    // Mip ranges of views written by the pass currently being built
+    pass_write_views: Vec<(u32, Range<u32>)>,
+    pub(crate) errors: Vec<RgValidationError>,
+}
+
+impl RenderGraphValidator {
+    pub(crate) fn mark_pass_writes(&mut self, pass: &RecordedPass) {
+        for res in &pass.write {
+            let idx = res.handle.id as usize;
+            if self.written.len() <= idx {
+                self.written.resize(idx + 1, false);
+            }
+            self.written[idx] = true;
+        }
+    }
+
+    fn push_error(
+        &mut self,
+        rg: &RenderGraph,
+        pass: &RecordedPass,
+        resource: Option<GraphRawResourceHandle>,
+        kind: RgValidationErrorKind,
+    ) {
+        self.errors.push(validation_error(
+            &rg.resource_creation_sites,
+            pass,
+            resource,
+            kind,
+        ));
+    }
+
+    pub(crate) fn validate_image_view(
+        &mut self,
+        rg: &RenderGraph,
+        pass: &RecordedPass,
+        handle: GraphRawResourceHandle,
+        image_desc: &ImageDesc,
+        view_desc: &ImageViewDesc,
+        writable: bool,
+    ) {
+        for kind in image_view_errors(image_desc, view_desc) {
+            self.push_error(rg, pass, Some(handle), kind);
+        }
+
+        if writable {
+            self.pass_write_views
+                .push((handle.id, view_mip_range(image_desc, view_desc)));
+        }
+    }
+
+    pub(crate) fn validate_pass(&mut self, rg: &RenderGraph, pass: &RecordedPass) {
+        let write_views = std::mem::take(&mut self.pass_write_views);
+
+        for res in &pass.read {
+            let idx = res.handle.id as usize;
+            let initialized = match &rg.resources[idx] {
+                GraphResourceInfo::Created(_) => {
+                    self.written.get(idx).copied().unwrap_or_default()
+                        || pass.write.iter().any(|w| w.handle.id == res.handle.id)
+                }
+                GraphResourceInfo::Imported(GraphResourceImportInfo::SwapchainImage) => false,
+                GraphResourceInfo::Imported(_) => true,
+            };
+
+            if !initialized {
+                self.push_error(
+                    rg,
+                    pass,
+                    Some(res.handle),
+                    RgValidationErrorKind::ReadBeforeWrite,
+                );
+            }
+        }
+
+        for (i, res) in pass.write.iter().enumerate() {
+            // Report each resource once, at its first write
+            if pass.write[..i].iter().any(|w| w.handle.id == res.handle.id) {
+                continue;
+            }
+
+            let write_count = pass
+                .write
+                .iter()
+                .filter(|w| w.handle.id == res.handle.id)
+                .count();
+
+            if write_count < 2 {
+                continue;
+            }
+
+            let views: Vec<&Range<u32>> = write_views
+                .iter()
+                .filter(|(id, _)| *id == res.handle.id)
+                .map(|(_, mips)| mips)
+                .collect();
+
+            let overlapping = views.len() < write_count
+                || views.iter().enumerate().any(|(i, a)| {
+                    views[i + 1..]
+                        .iter()
+                        .any(|b| a.start < b.end && b.start < a.end)
+                });
+
+            if overlapping {
+                self.push_error(
+                    rg,
+                    pass,
+                    Some(res.handle),
+                    RgValidationErrorKind::OverlappingWrites,
+                );
+            }
+        }
+
+        self.mark_pass_writes(pass);
+    }
+}
+
+/// Checks the resources bound by a pass's render function against the ones the pass
+/// declared, as the graph executes. Unlike the checks made while passes are added,
+/// this also covers passes which use `PassBuilder` directly, and render pass attachments.
+pub(crate) struct PassBindingValidator<'a> {
+    pub(crate) pass: &'a RecordedPass,
+    pub(crate) resource_creation_sites: &'a [&'static Location<'static>],
+}
+
+impl<'a> PassBindingValidator<'a> {
+    /// `image` is the desc of the bound image, and the view it's bound with.
+    pub(crate) fn validate_binding(
+        &self,
+        handle: GraphRawResourceHandle,
+        writable: bool,
+        image: Option<(&ImageDesc, &ImageViewDesc)>,
+    ) -> Result<(), RgValidationError> {
+        let declared_write = self.pass.write.iter().any(|w| w.handle.id == handle.id);
+        let declared_read = self.pass.read.iter().any(|r| r.handle.id == handle.id);
+
+        let undeclared = if writable && !declared_write {
+            Some(RgValidationErrorKind::UndeclaredWrite)
+        } else if !declared_read && !declared_write {
+            Some(RgValidationErrorKind::UndeclaredRead)
+        } else {
+            None
+        };
+
+        let view_errors = image
+            .map(|(image_desc, view_desc)| image_view_errors(image_desc, view_desc))
+            .unwrap_or_default();
+
+        match undeclared.into_iter().chain(view_errors).next() {
+            Some(kind) => Err(validation_error(
+                self.resource_creation_sites,
+                self.pass,
+                Some(handle),
+                kind,
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn validation_error(
+    resource_creation_sites: &[&'static Location<'static>],
+    pass: &RecordedPass,
+    resource: Option<GraphRawResourceHandle>,
+    kind: RgValidationErrorKind,
+) -> RgValidationError {
+    RgValidationError {
+        pass_name: pass.name.clone(),
+        pass_idx: pass.idx,
+        resource_created_at: resource
+            .and_then(|res| resource_creation_sites.get(res.id as usize).copied()),
+        kind,
+    }
+}
+
+// Must match `view_desc_impl`
+fn view_mip_range(image_desc: &ImageDesc, view_desc: &ImageViewDesc) -> Range<u32> {
+    let level_count = view_desc
+        .level_count
+        .unwrap_or(image_desc.mip_levels as u32);
+    view_desc.base_mip_level..view_desc.base_mip_level + level_count
+}
+
+fn image_view_errors(
+    image_desc: &ImageDesc,
+    view_desc: &ImageViewDesc,
+) -> Vec<RgValidationErrorKind> {
+    let mut errors = Vec::new();
+
+    if let Some(view_type) = view_desc.view_type {
+        if !is_view_type_compatible(image_desc, view_type) {
+            errors.push(RgValidationErrorKind::IncompatibleViewType {
+                image_type: image_desc.image_type,
+                view_type,
+            });
+        }
+    }
+
+    let mip_levels = image_desc.mip_levels as u32;
+    let mips = view_mip_range(image_desc, view_desc);
+
+    if mips.is_empty() || mips.end > mip_levels {
+        errors.push(RgValidationErrorKind::MipRangeOutOfBounds {
+            base_mip_level: mips.start,
+            level_count: mips.end - mips.start,
+            mip_levels,
+        });
+    }
+
+    let array_layers = image_desc.array_layer_count();
+    if let Some(layer_count) = view_desc.layer_count {
+        if layer_count == 0 || layer_count > array_layers {
+            errors.push(RgValidationErrorKind::LayerRangeOutOfBounds {
+                layer_count,
+                array_layers,
+            });
+        }
+    }
+
+    if image_aspect_mask_from_format(image_desc.format).contains(vk::ImageAspectFlags::DEPTH)
+        && !view_desc.aspect_mask.contains(vk::ImageAspectFlags::DEPTH)
+    {
+        errors.push(RgValidationErrorKind::MissingDepthAspect);
+    }
+
+    errors
+}
+
+fn is_view_type_compatible(image_desc: &ImageDesc, view_type: vk::ImageViewType) -> bool {
+    if view_type == convert_image_type_to_view_type(image_desc.image_type) {
+        return true;
+    }
+
+    match image_desc.image_type {
+        ImageType::Tex1d | ImageType::Tex1dArray => {
+            view_type == vk::ImageViewType::TYPE_1D || view_type == vk::ImageViewType::TYPE_1D_ARRAY
+        }
+        ImageType::Tex2d | ImageType::Tex2dArray => {
+            view_type == vk::ImageViewType::TYPE_2D || view_type == vk::ImageViewType::TYPE_2D_ARRAY
+        }
+        ImageType::Tex3d => {
+            (view_type == vk::ImageViewType::TYPE_2D
+                || view_type == vk::ImageViewType::TYPE_2D_ARRAY)
+                && image_desc
+                    .flags
+                    .contains(vk::ImageCreateFlags::TYPE_2D_ARRAY_COMPATIBLE)
+        }
+        ImageType::Cube | ImageType::CubeArray => {
+            view_type == vk::ImageViewType::TYPE_2D
+                || view_type == vk::ImageViewType::TYPE_2D_ARRAY
+                || view_type == vk::ImageViewType::CUBE
+                || (view_type == vk::ImageViewType::CUBE_ARRAY
+                    && image_desc.image_type == ImageType::CubeArray)
+        }
+    }
+}
+
+#[test]
+fn test_read_before_write() {
+    use kajiya_backend::vk_sync::AccessType;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let mut written = rg.create(ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, [8, 8]));
+    let never_written = rg.create(ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, [8, 8]));
+
+    {
+        let mut pass = rg.add_pass("write");
+        pass.write(&mut written, AccessType::ComputeShaderWrite);
+    }
+
+    {
+        let mut pass = rg.add_pass("read");
+        pass.read(&written, AccessType::ComputeShaderReadOther);
+        pass.read(&never_written, AccessType::ComputeShaderReadOther);
+    }
+
+    let errors = rg.validation_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pass_name, "read");
+    assert!(matches!(
+        errors[0].kind,
+        RgValidationErrorKind::ReadBeforeWrite
+    ));
+    assert_eq!(errors[0].resource_created_at.unwrap().file(), file!());
+}
+
+#[test]
+fn test_overlapping_writes() {
+    use crate::SimpleRenderPass;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let mut img =
+        rg.create(ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [8, 8]).mip_levels(2));
+
+    SimpleRenderPass::new_compute(rg.add_pass("disjoint mips"), "/shaders/blur.hlsl")
+        .write_view(
+            &mut img,
+            ImageViewDesc::builder()
+                .base_mip_level(0)
+                .level_count(Some(1)),
+        )
+        .write_view(
+            &mut img,
+            ImageViewDesc::builder()
+                .base_mip_level(1)
+                .level_count(Some(1)),
+        )
+        .dispatch([8, 8, 1]);
+    assert!(rg.validation_errors().is_empty());
+
+    SimpleRenderPass::new_compute(rg.add_pass("same mips"), "/shaders/blur.hlsl")
+        .write(&mut img)
+        .write_view(
+            &mut img,
+            ImageViewDesc::builder()
+                .base_mip_level(1)
+                .level_count(Some(1)),
+        )
+        .dispatch([8, 8, 1]);
+
+    let errors = rg.validation_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        errors[0].kind,
+        RgValidationErrorKind::OverlappingWrites
+    ));
+}
+
+#[test]
+fn test_incompatible_view_type() {
+    use crate::SimpleRenderPass;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let mut img = rg.create(ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [8, 8]));
+
+    SimpleRenderPass::new_compute(rg.add_pass("cube view"), "/shaders/blur.hlsl")
+        .write_view(
+            &mut img,
+            ImageViewDesc::builder().view_type(vk::ImageViewType::CUBE),
+        )
+        .dispatch([8, 8, 1]);
+
+    let errors = rg.validation_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pass_name, "cube view");
+    assert!(matches!(
+        errors[0].kind,
+        RgValidationErrorKind::IncompatibleViewType { .. }
+    ));
+}
+
+#[test]
+fn test_mip_range_matches_created_view() {
+    use crate::SimpleRenderPass;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let mut img =
+        rg.create(ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [8, 8]).mip_levels(2));
+
+    // Without a level count, the view covers `mip_levels` mips from the base
+    SimpleRenderPass::new_compute(rg.add_pass("implicit level count"), "/shaders/blur.hlsl")
+        .write_view(&mut img, ImageViewDesc::builder().base_mip_level(1))
+        .dispatch([8, 8, 1]);
+
+    let errors = rg.validation_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        errors[0].kind,
+        RgValidationErrorKind::MipRangeOutOfBounds {
+            base_mip_level: 1,
+            level_count: 2,
+            mip_levels: 2,
+        }
+    ));
+}
+
+#[test]
+#[should_panic(expected = "reads a resource which no previous pass has written to")]
+fn test_invalid_graph_panics() {
+    use kajiya_backend::vk_sync::AccessType;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let never_written = rg.create(ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, [8, 8]));
+    rg.add_pass("read")
+        .read(&never_written, AccessType::ComputeShaderReadOther);
+
+    rg.execute_mock();
+}
+
+#[test]
+fn test_undeclared_bindings() {
+    use kajiya_backend::vk_sync::AccessType;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let desc = ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, [8, 8]);
+    let mut img = rg.create(desc);
+    let mut other = rg.create(desc);
+
+    let other_ref = rg
+        .add_pass("write other")
+        .write(&mut other, AccessType::ComputeShaderWrite);
+
+    let img_ref = rg
+        .add_pass("write img")
+        .write(&mut img, AccessType::ComputeShaderWrite);
+
+    // Reads what "write img" wrote, but binds it with the write's `Ref`
+    rg.add_pass("read img")
+        .read(&img, AccessType::ComputeShaderReadOther);
+
+    assert!(rg.validation_errors().is_empty());
+
+    let validator = |pass_idx: usize| PassBindingValidator {
+        pass: &rg.passes[pass_idx],
+        resource_creation_sites: &rg.resource_creation_sites,
+    };
+
+    assert!(validator(1)
+        .validate_binding(img_ref.handle, true, None)
+        .is_ok());
+    assert!(validator(1)
+        .validate_binding(img_ref.handle, false, None)
+        .is_ok());
+    assert!(validator(2)
+        .validate_binding(img_ref.handle, false, None)
+        .is_ok());
+
+    let err = validator(2)
+        .validate_binding(img_ref.handle, true, None)
+        .unwrap_err();
+    assert_eq!(err.pass_name, "read img");
+    assert!(matches!(err.kind, RgValidationErrorKind::UndeclaredWrite));
+
+    let err = validator(1)
+        .validate_binding(other_ref.handle, false, None)
+        .unwrap_err();
+    assert!(matches!(err.kind, RgValidationErrorKind::UndeclaredRead));
+    assert_eq!(err.resource_created_at.unwrap().file(), file!());
+}
+
+#[test]
+fn test_bound_view_validation() {
+    use kajiya_backend::vk_sync::AccessType;
+
+    let mut rg = RenderGraph::new();
+    rg.enable_validation();
+
+    let desc = ImageDesc::new_2d(vk::Format::D32_SFLOAT, [8, 8]);
+    let mut depth = rg.create(desc);
+
+    // Views bound by render functions aren't known until the graph executes
+    let depth_ref = rg
+        .add_pass("raw depth")
+        .write(&mut depth, AccessType::ComputeShaderWrite);
+    assert!(rg.validation_errors().is_empty());
+
+    let validator = PassBindingValidator {
+        pass: &rg.passes[0],
+        resource_creation_sites: &rg.resource_creation_sites,
+    };
+
+    let depth_view = ImageViewDesc::builder()
+        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+        .build()
+        .unwrap();
+    assert!(validator
+        .validate_binding(depth_ref.handle, true, Some((&desc, &depth_view)))
+        .is_ok());
+
+    let err = validator
+        .validate_binding(
+            depth_ref.handle,
+            true,
+            Some((&desc, &ImageViewDesc::default())),
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.kind,
+        RgValidationErrorKind::MissingDepthAspect
+    ));
+}
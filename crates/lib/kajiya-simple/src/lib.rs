@@ -1,5 +1,6 @@
 mod input;
 mod main_loop;
+mod poster;
 
 pub use glam::*;
 pub use input::*;
@@ -11,10 +12,12 @@ pub use kajiya::{
     camera::*,
     frame_desc::WorldFrameDesc,
     math::*,
+    renderers::post::{DisplayTarget, DisplayTransform, HdrDisplaySettings, PostProcessSettings},
     world_renderer::{RenderDebugMode, RenderMode},
 };
 pub use log;
 pub use main_loop::*;
+pub use poster::PosterDesc;
 pub use winit::{
     self,
     event::{ElementState, KeyboardInput, MouseButton, WindowEvent},
@@ -1,8 +1,12 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, path::PathBuf};
 
 use kajiya::{
-    backend::{vulkan::RenderBackendConfig, *},
+    backend::{
+        vulkan::{swapchain::HdrMetadata, RenderBackendConfig},
+        *,
+    },
     frame_desc::WorldFrameDesc,
+    renderers::post::{DisplayTarget, HdrDisplaySettings},
     rg,
     ui_renderer::UiRenderer,
     world_renderer::WorldRenderer,
@@ -13,6 +17,8 @@ use kajiya_imgui::ImGuiBackend;
 
 use turbosloth::*;
 
+use crate::poster::{PosterDesc, PosterRender};
+
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -27,6 +33,24 @@ pub struct FrameContext<'a> {
     pub world_renderer: &'a mut WorldRenderer,
     pub window: &'a winit::window::Window,
 
+    /// Per-pass GPU and CPU timings of a recently completed frame
+    pub pass_timings: Option<&'a rg::RgFrameTimings>,
+
+    /// Passes and images of the last frame, for selecting what to inspect via `GraphDebugHook`
+    pub rg_debug_passes: &'a [rg::RgDebugPass],
+    pub rg_debug_picked_pixel: Option<rg::RgDebugPickedPixel>,
+
+    /// Shader compilation errors, remapped to the original source files
+    pub shader_compile_errors: &'a [pipeline_cache::PipelineCompileError],
+
+    /// In [0, 1] while a poster is being rendered. See `render_poster`.
+    pub poster_progress: Option<f32>,
+    poster_request: &'a mut Option<PosterDesc>,
+
+    /// If the swapchain is HDR, `WorldRenderer::display_target` is overridden to match it.
+    /// Otherwise HDR and Display P3 targets fall back to Rec.709, as SDR swapchains are sRGB.
+    pub hdr_swapchain: bool,
+
     #[cfg(feature = "dear-imgui")]
     pub imgui: Option<ImguiContext<'a>>,
 }
@@ -35,6 +59,15 @@ impl<'a> FrameContext<'a> {
     pub fn aspect_ratio(&self) -> f32 {
         self.render_extent[0] as f32 / self.render_extent[1] as f32
     }
+
+    /// Starts rendering a high resolution still with the reference path tracer,
+    /// in tiles of `render_extent`, over the following frames. The camera returned
+    /// by the frame function is used for every tile, and should stay still meanwhile.
+    ///
+    /// Replaces a poster currently in progress.
+    pub fn render_poster(&mut self, desc: PosterDesc) {
+        *self.poster_request = Some(desc);
+    }
 }
 
 #[cfg(feature = "dear-imgui")]
@@ -92,6 +125,8 @@ pub struct SimpleMainLoopBuilder {
     default_log_level: log::LevelFilter,
     window_scale: WindowScale,
     temporal_upsampling: f32,
+    shader_pack: Option<PathBuf>,
+    hdr_format: Option<HdrSwapchainFormat>,
 }
 
 impl Default for SimpleMainLoopBuilder {
@@ -111,6 +146,8 @@ impl SimpleMainLoopBuilder {
             default_log_level: log::LevelFilter::Warn,
             window_scale: WindowScale::SystemNative,
             temporal_upsampling: 1.0,
+            shader_pack: None,
+            hdr_format: None,
         }
     }
 
@@ -158,6 +195,19 @@ impl SimpleMainLoopBuilder {
         self
     }
 
+    /// Load HLSL shaders from a pack created by the `shader-pack` tool
+    /// instead of compiling them. dxc is not needed at runtime then.
+    pub fn shader_pack(mut self, shader_pack: Option<PathBuf>) -> Self {
+        self.shader_pack = shader_pack;
+        self
+    }
+
+    /// Output to an HDR swapchain, if the display supports it.
+    pub fn hdr(mut self, hdr_format: Option<HdrSwapchainFormat>) -> Self {
+        self.hdr_format = hdr_format;
+        self
+    }
+
     pub fn build(self, window_builder: WindowBuilder) -> anyhow::Result<SimpleMainLoop> {
         SimpleMainLoop::build(self, window_builder)
     }
@@ -188,6 +238,10 @@ impl SimpleMainLoop {
         kajiya::logging::set_up_logging(builder.default_log_level)?;
         std::env::set_var("SMOL_THREADS", "64"); // HACK; TODO: get a real executor
 
+        if let Some(shader_pack) = &builder.shader_pack {
+            shader_pack::use_shader_pack(shader_pack::ShaderPack::load(shader_pack)?);
+        }
+
         // Note: asking for the logical size means that if the OS is using DPI scaling,
         // we'll get a physically larger window (with more pixels).
         // The internal rendering resolution will still be what was asked of the `builder`,
@@ -247,6 +301,7 @@ impl SimpleMainLoop {
                 vsync: builder.vsync,
                 graphics_debugging: builder.graphics_debugging,
                 device_index: builder.physical_device_index,
+                hdr_format: builder.hdr_format,
             },
         )?;
 
@@ -336,6 +391,12 @@ impl SimpleMainLoop {
         // and pipelines are be compiled, so it will most likely have a spike.
         let mut fake_dt_countdown: i32 = 1;
 
+        let mut poster_request: Option<PosterDesc> = None;
+        let mut poster_render: Option<PosterRender> = None;
+
+        let hdr_swapchain = render_backend.swapchain.is_hdr();
+        let mut hdr_metadata: Option<HdrDisplaySettings> = None;
+
         let mut running = true;
         while running {
             gpu_profiler::profiler().begin_frame();
@@ -419,12 +480,22 @@ impl SimpleMainLoop {
                 }
             };
 
-            let frame_desc = frame_fn(FrameContext {
+            let pass_timings = rg_renderer.last_frame_pass_timings();
+            let shader_compile_errors = rg_renderer.pipeline_compile_errors();
+
+            let mut frame_desc = frame_fn(FrameContext {
                 dt_filtered,
                 render_extent,
                 events: &events,
                 world_renderer: &mut world_renderer,
                 window: &window,
+                pass_timings: pass_timings.as_ref(),
+                rg_debug_passes: rg_renderer.debug_pass_list(),
+                rg_debug_picked_pixel: rg_renderer.debug_picked_pixel(),
+                shader_compile_errors: &shader_compile_errors,
+                poster_progress: poster_render.as_ref().map(PosterRender::progress),
+                poster_request: &mut poster_request,
+                hdr_swapchain,
 
                 #[cfg(feature = "dear-imgui")]
                 imgui: Some(ImguiContext {
@@ -438,6 +509,32 @@ impl SimpleMainLoop {
 
             events.clear();
 
+            if let Some(desc) = poster_request.take() {
+                // Don't leave the render mode of a replaced poster behind
+                if let Some(poster) = &poster_render {
+                    poster.restore_render_mode(&mut world_renderer);
+                }
+
+                poster_render = Some(PosterRender::new(desc, render_extent, &world_renderer));
+            }
+
+            if let Some(poster) = &mut poster_render {
+                poster.prepare_frame(&mut world_renderer, &mut frame_desc);
+            }
+
+            world_renderer.display_target =
+                swapchain_display_target(&render_backend.swapchain, world_renderer.display_target);
+
+            if hdr_swapchain && hdr_metadata != Some(world_renderer.hdr_display) {
+                let hdr_display = world_renderer.hdr_display;
+                render_backend.swapchain.set_hdr_metadata(HdrMetadata {
+                    max_luminance: hdr_display.peak_brightness_nits,
+                    min_luminance: 0.0,
+                    max_frame_average_light_level: 0.0,
+                });
+                hdr_metadata = Some(hdr_display);
+            }
+
             // Physical window extent in pixels
             let swapchain_extent = [window.inner_size().width, window.inner_size().height];
 
@@ -448,24 +545,14 @@ impl SimpleMainLoop {
                     let main_img = world_renderer.prepare_render_graph(rg, &frame_desc);
                     let ui_img = ui_renderer.prepare_render_graph(rg);
 
-                    let mut swap_chain = rg.get_swap_chain();
-                    rg::SimpleRenderPass::new_compute(
-                        rg.add_pass("final blit"),
-                        "/shaders/final_blit.hlsl",
-                    )
-                    .read(&main_img)
-                    .read(&ui_img)
-                    .write(&mut swap_chain)
-                    .constants((
-                        main_img.desc().extent_inv_extent_2d(),
-                        [
-                            swapchain_extent[0] as f32,
-                            swapchain_extent[1] as f32,
-                            1.0 / swapchain_extent[0] as f32,
-                            1.0 / swapchain_extent[1] as f32,
-                        ],
-                    ))
-                    .dispatch([swapchain_extent[0], swapchain_extent[1], 1]);
+                    add_final_blit_pass(
+                        rg,
+                        &main_img,
+                        &ui_img,
+                        swapchain_extent,
+                        world_renderer.display_target,
+                        world_renderer.hdr_display.paper_white_nits,
+                    );
                 })
             };
 
@@ -484,6 +571,17 @@ impl SimpleMainLoop {
                     );
                     world_renderer.retire_frame();
                     last_error_text = None;
+
+                    if let Some(poster) = &mut poster_render {
+                        match poster.finish_frame(&mut world_renderer, &rg_renderer) {
+                            Ok(false) => {}
+                            Ok(true) => poster_render = None,
+                            Err(err) => {
+                                log::error!("Failed to render the poster: {:?}", err);
+                                poster_render = None;
+                            }
+                        }
+                    }
                 }
                 Err(e) => {
                     let error_text = Some(format!("{:?}", e));
@@ -503,3 +601,48 @@ impl SimpleMainLoop {
         Ok(())
     }
 }
+
+/// The display target which matches the color space of the swapchain.
+/// On SDR swapchains, `preferred` is kept unless it's an HDR target.
+fn swapchain_display_target(
+    swapchain: &vulkan::swapchain::Swapchain,
+    preferred: DisplayTarget,
+) -> DisplayTarget {
+    match swapchain.desc.format.color_space {
+        ash::vk::ColorSpaceKHR::HDR10_ST2084_EXT => DisplayTarget::Rec2020Pq,
+        ash::vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => DisplayTarget::ScRgb,
+        ash::vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT => DisplayTarget::DisplayP3,
+        // The surface is sRGB, so anything wider would be displayed with the wrong primaries
+        _ if preferred != DisplayTarget::Rec709 => DisplayTarget::Rec709,
+        _ => preferred,
+    }
+}
+
+/// Composites the main and UI images into the swap chain, encoding them for `display_target`.
+/// On HDR targets, the UI is shown at `paper_white_nits`.
+pub fn add_final_blit_pass(
+    rg: &mut rg::RenderGraph,
+    main_img: &rg::Handle<Image>,
+    ui_img: &rg::Handle<Image>,
+    swapchain_extent: [u32; 2],
+    display_target: DisplayTarget,
+    paper_white_nits: f32,
+) {
+    let mut swap_chain = rg.get_swap_chain();
+    rg::SimpleRenderPass::new_compute(rg.add_pass("final blit"), "/shaders/final_blit.hlsl")
+        .read(main_img)
+        .read(ui_img)
+        .write(&mut swap_chain)
+        .constants((
+            main_img.desc().extent_inv_extent_2d(),
+            [
+                swapchain_extent[0] as f32,
+                swapchain_extent[1] as f32,
+                1.0 / swapchain_extent[0] as f32,
+                1.0 / swapchain_extent[1] as f32,
+            ],
+            display_target as u32,
+            paper_white_nits,
+        ))
+        .dispatch([swapchain_extent[0], swapchain_extent[1], 1]);
+}
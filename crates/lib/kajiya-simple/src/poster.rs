@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use glam::{UVec2, Vec2};
+use kajiya::{
+    backend::ash::vk,
+    camera::{transform_camera_ndc, FrameRect},
+    frame_desc::WorldFrameDesc,
+    rg::renderer::Renderer,
+    world_render_passes::REFERENCE_ACCUMULATION_KEY,
+    world_renderer::{RenderMode, WorldRenderer},
+};
+
+// Must match the accumulation limit in `reference_path_trace.rgen.hlsl`
+const MAX_REFERENCE_SAMPLES: u32 = 1000;
+
+/// A still image rendered by the reference path tracer at a resolution which doesn't fit
+/// in a single frame. See `FrameContext::render_poster`.
+#[derive(Clone, Debug)]
+pub struct PosterDesc {
+    pub extent: [u32; 2],
+    /// Frames accumulated in every tile. At most 1000.
+    pub samples_per_pixel: u32,
+    /// Written as OpenEXR, with linear radiance before exposure and tone mapping.
+    pub output_path: PathBuf,
+}
+
+/// Renders a poster over many frames, one tile of the render extent at a time.
+///
+/// Every tile is an off-axis sub-frustum of the application's camera, with the frame
+/// widened or narrowed to the aspect ratio of the poster. The tiles exactly partition
+/// the image plane, so pixels line up across their boundaries without seams.
+pub(crate) struct PosterRender {
+    desc: PosterDesc,
+    tile_extent: [u32; 2],
+    tile_count: [u32; 2],
+    current_tile: u32,
+    frames_in_tile: u32,
+    prev_render_mode: RenderMode,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl PosterRender {
+    pub fn new(desc: PosterDesc, tile_extent: [u32; 2], world_renderer: &WorldRenderer) -> Self {
+        let tile_count = [
+            (desc.extent[0] + tile_extent[0] - 1) / tile_extent[0],
+            (desc.extent[1] + tile_extent[1] - 1) / tile_extent[1],
+        ];
+
+        log::info!(
+            "Rendering a {}x{} poster in {}x{} tiles",
+            desc.extent[0],
+            desc.extent[1],
+            tile_count[0],
+            tile_count[1]
+        );
+
+        Self {
+            pixels: vec![[0.0; 3]; desc.extent[0] as usize * desc.extent[1] as usize],
+            desc,
+            tile_extent,
+            tile_count,
+            current_tile: 0,
+            frames_in_tile: 0,
+            prev_render_mode: world_renderer.render_mode,
+        }
+    }
+
+    fn total_tile_count(&self) -> u32 {
+        self.tile_count[0] * self.tile_count[1]
+    }
+
+    fn samples_per_tile(&self) -> u32 {
+        self.desc.samples_per_pixel.clamp(1, MAX_REFERENCE_SAMPLES)
+    }
+
+    /// In [0, 1]
+    pub fn progress(&self) -> f32 {
+        (self.current_tile * self.samples_per_tile() + self.frames_in_tile) as f32
+            / (self.total_tile_count() * self.samples_per_tile()) as f32
+    }
+
+    /// Top-left pixel of the current tile within the poster
+    fn tile_origin(&self) -> [u32; 2] {
+        [
+            (self.current_tile % self.tile_count[0]) * self.tile_extent[0],
+            (self.current_tile / self.tile_count[0]) * self.tile_extent[1],
+        ]
+    }
+
+    /// Points the frame at the current tile.
+    pub fn prepare_frame(
+        &mut self,
+        world_renderer: &mut WorldRenderer,
+        frame_desc: &mut WorldFrameDesc,
+    ) {
+        world_renderer.render_mode = RenderMode::Reference;
+        if 0 == self.frames_in_tile {
+            world_renderer.reset_reference_accumulation = true;
+        }
+
+        // Keep the vertical field of view, and match the poster's aspect ratio
+        let poster_aspect_ratio = self.desc.extent[0] as f32 / self.desc.extent[1] as f32;
+        let camera_matrices = transform_camera_ndc(
+            frame_desc.camera_matrices,
+            Vec2::new(
+                frame_desc.camera_matrices.aspect_ratio() / poster_aspect_ratio,
+                1.0,
+            ),
+            Vec2::ZERO,
+        );
+
+        // Edge tiles can extend past the poster; the excess is discarded.
+        let poster_extent = UVec2::from(self.desc.extent).as_vec2();
+        let tile_min = UVec2::from(self.tile_origin()).as_vec2();
+        let tile_max = tile_min + UVec2::from(self.tile_extent).as_vec2();
+        let rect = FrameRect {
+            min: tile_min / poster_extent,
+            max: tile_max / poster_extent,
+        };
+
+        frame_desc.camera_matrices = rect.crop_camera_matrices(camera_matrices);
+        frame_desc.render_extent = self.tile_extent;
+    }
+
+    /// Called after every drawn frame. Returns `true` once the poster has been written,
+    /// or on failure, after which the previous render mode is restored.
+    pub fn finish_frame(
+        &mut self,
+        world_renderer: &mut WorldRenderer,
+        rg_renderer: &Renderer,
+    ) -> anyhow::Result<bool> {
+        let result = self.advance(rg_renderer);
+        if !matches!(result, Ok(false)) {
+            self.restore_render_mode(world_renderer);
+        }
+        result
+    }
+
+    pub fn restore_render_mode(&self, world_renderer: &mut WorldRenderer) {
+        world_renderer.render_mode = self.prev_render_mode;
+    }
+
+    fn advance(&mut self, rg_renderer: &Renderer) -> anyhow::Result<bool> {
+        self.frames_in_tile += 1;
+        if self.frames_in_tile < self.samples_per_tile() {
+            return Ok(false);
+        }
+
+        self.copy_tile(rg_renderer)?;
+
+        self.frames_in_tile = 0;
+        self.current_tile += 1;
+
+        if self.current_tile < self.total_tile_count() {
+            return Ok(false);
+        }
+
+        self.write_exr()?;
+        Ok(true)
+    }
+
+    fn copy_tile(&mut self, rg_renderer: &Renderer) -> anyhow::Result<()> {
+        let (desc, data) = rg_renderer.read_back_temporal_image(REFERENCE_ACCUMULATION_KEY)?;
+
+        if desc.format != vk::Format::R32G32B32A32_SFLOAT
+            || desc.extent[0] != self.tile_extent[0]
+            || desc.extent[1] != self.tile_extent[1]
+        {
+            anyhow::bail!("Unexpected reference accumulation image: {:?}", desc);
+        }
+
+        let texels: Vec<[f32; 4]> = data
+            .chunks_exact(16)
+            .map(|texel| {
+                let channel =
+                    |i: usize| f32::from_ne_bytes(texel[i * 4..(i + 1) * 4].try_into().unwrap());
+                [channel(0), channel(1), channel(2), channel(3)]
+            })
+            .collect();
+
+        let origin = self.tile_origin();
+        let width = self.tile_extent[0].min(self.desc.extent[0] - origin[0]);
+        let height = self.tile_extent[1].min(self.desc.extent[1] - origin[1]);
+
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b, _] = texels[(y * self.tile_extent[0] + x) as usize];
+                self.pixels[((origin[1] + y) * self.desc.extent[0] + origin[0] + x) as usize] =
+                    [r, g, b];
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_exr(&self) -> anyhow::Result<()> {
+        let width = self.desc.extent[0] as usize;
+
+        exr::prelude::write_rgb_file(
+            &self.desc.output_path,
+            width,
+            self.desc.extent[1] as usize,
+            |x, y| {
+                let [r, g, b] = self.pixels[y * width + x];
+                (r, g, b)
+            },
+        )
+        .with_context(|| format!("Writing poster to {:?}", self.desc.output_path))?;
+
+        log::info!("Wrote poster to {:?}", self.desc.output_path);
+        Ok(())
+    }
+}
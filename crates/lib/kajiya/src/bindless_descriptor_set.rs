@@ -22,6 +22,18 @@ lazy_static::lazy_static! {
             dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
             name: Default::default(),
         }),
+        // `triangle_lights`
+        (3, rspirv_reflect::DescriptorInfo {
+            ty: rspirv_reflect::DescriptorType::STORAGE_BUFFER,
+            dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
+            name: Default::default(),
+        }),
+        // `triangle_light_cdf`
+        (4, rspirv_reflect::DescriptorInfo {
+            ty: rspirv_reflect::DescriptorType::STORAGE_BUFFER,
+            dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
+            name: Default::default(),
+        }),
         // `bindless_textures`
         (BINDLESS_TEXURES_BINDING_INDEX as u32, rspirv_reflect::DescriptorInfo {
             ty: rspirv_reflect::DescriptorType::SAMPLED_IMAGE,
@@ -34,12 +46,14 @@ lazy_static::lazy_static! {
     .collect();
 }
 
-pub const BINDLESS_TEXURES_BINDING_INDEX: usize = 3;
+pub const BINDLESS_TEXURES_BINDING_INDEX: usize = 5;
 
 pub fn create_bindless_descriptor_set(device: &device::Device) -> vk::DescriptorSet {
     let raw_device = &device.raw;
 
     let set_binding_flags = [
+        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         vk::DescriptorBindingFlags::PARTIALLY_BOUND,
@@ -79,6 +93,20 @@ pub fn create_bindless_descriptor_set(device: &device::Device) -> vk::Descriptor
                             .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
                             .stage_flags(vk::ShaderStageFlags::ALL)
                             .build(),
+                        // `triangle_lights`
+                        vk::DescriptorSetLayoutBinding::builder()
+                            .binding(3)
+                            .descriptor_count(1)
+                            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                            .stage_flags(vk::ShaderStageFlags::ALL)
+                            .build(),
+                        // `triangle_light_cdf`
+                        vk::DescriptorSetLayoutBinding::builder()
+                            .binding(4)
+                            .descriptor_count(1)
+                            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                            .stage_flags(vk::ShaderStageFlags::ALL)
+                            .build(),
                         // `bindless_textures`
                         vk::DescriptorSetLayoutBinding::builder()
                             .binding(BINDLESS_TEXURES_BINDING_INDEX as _)
@@ -98,7 +126,7 @@ pub fn create_bindless_descriptor_set(device: &device::Device) -> vk::Descriptor
     let descriptor_sizes = [
         vk::DescriptorPoolSize {
             ty: vk::DescriptorType::STORAGE_BUFFER,
-            descriptor_count: 3,
+            descriptor_count: 5,
         },
         vk::DescriptorPoolSize {
             ty: vk::DescriptorType::SAMPLED_IMAGE,
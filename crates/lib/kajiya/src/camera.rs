@@ -1,4 +1,5 @@
 use crate::math::*;
+use glam::UVec2;
 use rust_shaders_shared::camera::CameraMatrices;
 
 pub trait IntoCameraBodyMatrices {
@@ -37,11 +38,110 @@ where
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraProjection {
+    /// Reverse-Z with an infinite far plane; see `CameraLens::vertical_fov`.
+    Perspective,
+    /// Reverse-Z between the near and far planes.
+    Orthographic {
+        /// Height of the view volume, in world units
+        vertical_size: f32,
+        far_plane_distance: f32,
+    },
+}
+
+/// A rectangle within the camera's frame, in UV coordinates: `[0, 0]` is the top-left
+/// corner of the frame, and `[1, 1]` the bottom-right one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl FrameRect {
+    pub const FULL: Self = Self {
+        min: Vec2::ZERO,
+        max: Vec2::ONE,
+    };
+
+    /// One of `tile_count` equally sized tiles, indexed from the top-left.
+    /// Adjacent tiles share the exact same edges, so they meet without gaps.
+    pub fn tile(tile: [u32; 2], tile_count: [u32; 2]) -> Self {
+        let tile = UVec2::from(tile);
+        let tile_count = UVec2::from(tile_count).as_vec2();
+        Self {
+            min: tile.as_vec2() / tile_count,
+            max: (tile + 1).as_vec2() / tile_count,
+        }
+    }
+
+    /// Moves the rectangle by a fraction of a pixel, for an image of `extent` pixels
+    /// covering the rectangle. Used to jitter sub-frustums.
+    pub fn offset_by_pixels(self, offset: Vec2, extent: [u32; 2]) -> Self {
+        let offset = offset * (self.max - self.min) / UVec2::from(extent).as_vec2();
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Narrows the projection of `matrices` down to this part of their frame.
+    pub fn crop_camera_matrices(&self, matrices: CameraMatrices) -> CameraMatrices {
+        // In normalized device coordinates, with +y pointing up
+        let ndc_min = Vec2::new(2.0 * self.min.x - 1.0, 1.0 - 2.0 * self.max.y);
+        let ndc_max = Vec2::new(2.0 * self.max.x - 1.0, 1.0 - 2.0 * self.min.y);
+
+        let scale = 2.0 / (ndc_max - ndc_min);
+        let offset = (ndc_max + ndc_min) / (ndc_max - ndc_min);
+        transform_camera_ndc(matrices, scale, offset)
+    }
+}
+
+/// Remaps the normalized device coordinates of a projection as `ndc * scale - offset`.
+/// The transform is linear in clip space, so it works for any kind of projection.
+pub fn transform_camera_ndc(matrices: CameraMatrices, scale: Vec2, offset: Vec2) -> CameraMatrices {
+    let clip_transform = Mat4::from_cols(
+        Vec4::new(scale.x, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, scale.y, 0.0, 0.0),
+        Vec4::new(0.0, 0.0, 1.0, 0.0),
+        Vec4::new(-offset.x, -offset.y, 0.0, 1.0),
+    );
+
+    let clip_transform_inv = Mat4::from_cols(
+        Vec4::new(1.0 / scale.x, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 1.0 / scale.y, 0.0, 0.0),
+        Vec4::new(0.0, 0.0, 1.0, 0.0),
+        Vec4::new(offset.x / scale.x, offset.y / scale.y, 0.0, 1.0),
+    );
+
+    CameraMatrices {
+        view_to_clip: clip_transform * matrices.view_to_clip,
+        clip_to_view: matrices.clip_to_view * clip_transform_inv,
+        ..matrices
+    }
+}
+
+impl Default for FrameRect {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct CameraLens {
     pub near_plane_distance: f32,
+    /// Width over height of the full frame, regardless of `crop`
     pub aspect_ratio: f32,
+    /// In degrees; only used by `CameraProjection::Perspective`
     pub vertical_fov: f32,
+    pub projection: CameraProjection,
+    /// Off-axis shift of the frame on the image plane, as a fraction of the frame's height.
+    /// Unlike rotating the camera, this keeps parallel lines parallel, as with
+    /// the shift lenses used in architectural photography.
+    pub shift: Vec2,
+    /// The part of the frame to render. Anything but `FrameRect::FULL` results in
+    /// an asymmetric sub-frustum, e.g. for rendering a large image in tiles.
+    pub crop: FrameRect,
 }
 
 impl Default for CameraLens {
@@ -50,6 +150,9 @@ impl Default for CameraLens {
             near_plane_distance: 0.01, // 1mm
             aspect_ratio: 1.0,
             vertical_fov: 52.0,
+            projection: CameraProjection::Perspective,
+            shift: Vec2::ZERO,
+            crop: FrameRect::FULL,
         }
     }
 }
@@ -85,42 +188,264 @@ impl CameraBodyMatrices {
 }
 
 impl CameraLens {
+    /// The same lens, rendering only `rect` of the current crop.
+    pub fn sub_frustum(&self, rect: FrameRect) -> Self {
+        let size = self.crop.max - self.crop.min;
+        Self {
+            crop: FrameRect {
+                min: self.crop.min + rect.min * size,
+                max: self.crop.min + rect.max * size,
+            },
+            ..*self
+        }
+    }
+
     fn calc_matrices(&self) -> CameraLensMatrices {
-        let fov = self.vertical_fov.to_radians();
-        let znear = self.near_plane_distance;
+        // Half-extents of the full frame on the image plane;
+        // at unit distance from the eye for perspective projections.
+        let half_height = match self.projection {
+            CameraProjection::Perspective => (0.5 * self.vertical_fov.to_radians()).tan(),
+            CameraProjection::Orthographic { vertical_size, .. } => 0.5 * vertical_size,
+        };
+        let half_extent = Vec2::new(half_height * self.aspect_ratio, half_height);
+        let center = self.shift * 2.0 * half_height;
 
-        let h = (0.5 * fov).cos() / (0.5 * fov).sin();
-        let w = h / self.aspect_ratio;
-
-        /*let mut m = Mat4::ZERO;
-        m.m11 = w;
-        m.m22 = h;
-        m.m34 = znear;
-        m.m43 = -1.0;
-        m*/
-        let view_to_clip = Mat4::from_cols(
-            Vec4::new(w, 0.0, 0.0, 0.0),
-            Vec4::new(0.0, h, 0.0, 0.0),
-            Vec4::new(0.0, 0.0, 0.0, -1.0),
-            Vec4::new(0.0, 0.0, znear, 0.0),
-        );
+        // Bounds of the cropped frame. UV has +y pointing down, and view space, up.
+        let frame_min = center - half_extent;
+        let frame_size = half_extent * 2.0;
+        let left = frame_min.x + frame_size.x * self.crop.min.x;
+        let right = frame_min.x + frame_size.x * self.crop.max.x;
+        let bottom = frame_min.y + frame_size.y * (1.0 - self.crop.max.y);
+        let top = frame_min.y + frame_size.y * (1.0 - self.crop.min.y);
 
-        /*let mut m = Mat4::ZERO;
-        m.m11 = 1.0 / w;
-        m.m22 = 1.0 / h;
-        m.m34 = -1.0;
-        m.m43 = 1.0 / znear;
-        m*/
-        let clip_to_view = Mat4::from_cols(
-            Vec4::new(1.0 / w, 0.0, 0.0, 0.0),
-            Vec4::new(0.0, 1.0 / h, 0.0, 0.0),
-            Vec4::new(0.0, 0.0, 0.0, 1.0 / znear),
-            Vec4::new(0.0, 0.0, -1.0, 0.0),
+        // Scale and offset mapping the bounds to [-1, 1]
+        let scale = Vec2::new(2.0 / (right - left), 2.0 / (top - bottom));
+        let offset = Vec2::new(
+            (right + left) / (right - left),
+            (top + bottom) / (top - bottom),
         );
 
+        let znear = self.near_plane_distance;
+
+        let (view_to_clip, clip_to_view) = match self.projection {
+            CameraProjection::Perspective => {
+                let view_to_clip = Mat4::from_cols(
+                    Vec4::new(scale.x, 0.0, 0.0, 0.0),
+                    Vec4::new(0.0, scale.y, 0.0, 0.0),
+                    Vec4::new(offset.x, offset.y, 0.0, -1.0),
+                    Vec4::new(0.0, 0.0, znear, 0.0),
+                );
+
+                let clip_to_view = Mat4::from_cols(
+                    Vec4::new(1.0 / scale.x, 0.0, 0.0, 0.0),
+                    Vec4::new(0.0, 1.0 / scale.y, 0.0, 0.0),
+                    Vec4::new(0.0, 0.0, 0.0, 1.0 / znear),
+                    Vec4::new(offset.x / scale.x, offset.y / scale.y, -1.0, 0.0),
+                );
+
+                (view_to_clip, clip_to_view)
+            }
+            CameraProjection::Orthographic {
+                far_plane_distance, ..
+            } => {
+                let zfar = far_plane_distance;
+                let depth_range = zfar - znear;
+
+                // Depth is 1 at the near plane, and 0 at the far one
+                let view_to_clip = Mat4::from_cols(
+                    Vec4::new(scale.x, 0.0, 0.0, 0.0),
+                    Vec4::new(0.0, scale.y, 0.0, 0.0),
+                    Vec4::new(0.0, 0.0, 1.0 / depth_range, 0.0),
+                    Vec4::new(-offset.x, -offset.y, zfar / depth_range, 1.0),
+                );
+
+                let clip_to_view = Mat4::from_cols(
+                    Vec4::new(1.0 / scale.x, 0.0, 0.0, 0.0),
+                    Vec4::new(0.0, 1.0 / scale.y, 0.0, 0.0),
+                    Vec4::new(0.0, 0.0, depth_range, 0.0),
+                    Vec4::new(offset.x / scale.x, offset.y / scale.y, -zfar, 1.0),
+                );
+
+                (view_to_clip, clip_to_view)
+            }
+        };
+
         CameraLensMatrices {
             view_to_clip,
             clip_to_view,
         }
     }
 }
+
+/// A camera with physical exposure and lens parameters.
+///
+/// Exposure follows the photographic EV100 convention, so the scene's lights should be
+/// specified in physical units (nits, lux) when this drives the exposure.
+/// Distances are in world units, which are assumed to be meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicalCamera {
+    /// Relative aperture; e.g. 2.8 for f/2.8
+    pub f_stop: f32,
+    /// Exposure time in seconds; also the interval integrated by motion blur.
+    pub shutter_time: f32,
+    /// Sensor sensitivity, in ISO arithmetic units
+    pub iso: f32,
+    pub focal_length_mm: f32,
+    pub sensor_height_mm: f32,
+    /// Distance from the lens to the plane in perfect focus
+    pub focus_distance: f32,
+    /// Blur out-of-focus geometry according to the aperture and focus distance.
+    pub depth_of_field: bool,
+}
+
+impl Default for PhysicalCamera {
+    /// A 50mm lens on a full-frame sensor, at f/16, 1/125s, ISO 100 ("sunny 16").
+    fn default() -> Self {
+        Self {
+            f_stop: 16.0,
+            shutter_time: 1.0 / 125.0,
+            iso: 100.0,
+            focal_length_mm: 50.0,
+            sensor_height_mm: 24.0,
+            focus_distance: 10.0,
+            depth_of_field: true,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    /// Exposure value at ISO 100 equivalent to the aperture, shutter and sensitivity.
+    pub fn ev100(&self) -> f32 {
+        (self.f_stop * self.f_stop / self.shutter_time * 100.0 / self.iso).log2()
+    }
+
+    /// Multiplier from scene luminance to normalized sensor exposure,
+    /// based on the saturation-based sensitivity. See "Moving Frostbite to PBR", section 5.1
+    pub fn exposure(&self) -> f32 {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+
+    /// Vertical field of view in degrees, as used by `CameraLens`
+    pub fn vertical_fov(&self) -> f32 {
+        (2.0 * (0.5 * self.sensor_height_mm / self.focal_length_mm).atan()).to_degrees()
+    }
+
+    /// Radius of the entrance pupil, in world units
+    pub fn aperture_radius(&self) -> f32 {
+        0.5 * self.focal_length_mm * 1e-3 / self.f_stop
+    }
+
+    /// A pinhole lens with the field of view of this camera
+    pub fn lens(&self, aspect_ratio: f32) -> CameraLens {
+        CameraLens {
+            aspect_ratio,
+            vertical_fov: self.vertical_fov(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_lenses() -> [CameraLens; 2] {
+    let perspective = CameraLens {
+        aspect_ratio: 16.0 / 9.0,
+        shift: Vec2::new(0.1, -0.05),
+        ..Default::default()
+    };
+    let orthographic = CameraLens {
+        projection: CameraProjection::Orthographic {
+            vertical_size: 10.0,
+            far_plane_distance: 100.0,
+        },
+        ..perspective
+    };
+    [perspective, orthographic]
+}
+
+#[test]
+fn test_cropped_matrices_stay_inverse() {
+    let body = (Vec3::new(1.0, 2.0, 3.0), Quat::from_rotation_y(0.5));
+    let rects = [
+        FrameRect::FULL,
+        FrameRect::tile([2, 1], [3, 2]),
+        FrameRect::tile([0, 3], [4, 4]).offset_by_pixels(Vec2::new(0.3, -0.4), [64, 64]),
+    ];
+
+    for lens in test_lenses() {
+        let matrices = body.through(&lens);
+
+        for rect in rects {
+            let cropped = rect.crop_camera_matrices(matrices);
+            assert!(
+                (cropped.clip_to_view * cropped.view_to_clip).abs_diff_eq(Mat4::IDENTITY, 1e-4),
+                "{:?}, {:?}",
+                lens.projection,
+                rect
+            );
+
+            // Cropping the projection is the same as cropping the lens
+            let lens_cropped = body.through(&lens.sub_frustum(rect));
+            assert!(
+                cropped
+                    .view_to_clip
+                    .abs_diff_eq(lens_cropped.view_to_clip, 1e-4),
+                "{:?}, {:?}",
+                lens.projection,
+                rect
+            );
+        }
+
+        let transformed = transform_camera_ndc(matrices, Vec2::new(3.0, 0.5), Vec2::new(-1.0, 2.0));
+        assert!(
+            (transformed.clip_to_view * transformed.view_to_clip).abs_diff_eq(Mat4::IDENTITY, 1e-4),
+            "{:?}",
+            lens.projection
+        );
+    }
+}
+
+#[test]
+fn test_adjacent_tiles_share_edges() {
+    let tile_count = [3, 2];
+
+    for lens in test_lenses() {
+        let matrices = (Vec3::ZERO, Quat::IDENTITY).through(&lens);
+
+        // Pairs of tiles sharing an edge, and the axis across it
+        let mut pairs = Vec::new();
+        for y in 0..tile_count[1] {
+            for x in 0..tile_count[0] {
+                if x + 1 < tile_count[0] {
+                    pairs.push(([x, y], [x + 1, y], 0));
+                }
+                if y + 1 < tile_count[1] {
+                    pairs.push(([x, y], [x, y + 1], 1));
+                }
+            }
+        }
+
+        for (tile, next, axis) in pairs {
+            let a = FrameRect::tile(tile, tile_count);
+            let b = FrameRect::tile(next, tile_count);
+            assert_eq!(a.max[axis], b.min[axis]);
+
+            // A point on the shared edge, halfway along it; UV has +y pointing down
+            let mut edge_uv = (a.min + a.max) * 0.5;
+            edge_uv[axis] = a.max[axis];
+            let edge_ndc = Vec2::new(2.0 * edge_uv.x - 1.0, 1.0 - 2.0 * edge_uv.y);
+            let edge_vs = matrices.clip_to_view * edge_ndc.extend(0.5).extend(1.0);
+
+            let project = |rect: FrameRect| {
+                let clip = rect.crop_camera_matrices(matrices).view_to_clip * edge_vs;
+                clip.truncate().truncate() / clip.w
+            };
+            let (a_ndc, b_ndc) = (project(a), project(b));
+
+            // On opposite sides of the two tiles, and at the exact same place along the edge
+            let across = if axis == 0 { 1.0 } else { -1.0 };
+            assert!((a_ndc[axis] - across).abs() < 1e-5, "{:?}", a_ndc);
+            assert!((b_ndc[axis] + across).abs() < 1e-5, "{:?}", b_ndc);
+            assert_eq!(a_ndc[1 - axis], b_ndc[1 - axis]);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::Context;
+use std::path::Path;
+
+/// A 3D color lookup table in the Adobe/Resolve `.cube` format.
+pub struct CubeLut {
+    pub title: Option<String>,
+    /// Entries along each edge of the cube
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    /// `size`³ entries, with red changing fastest, then green, then blue.
+    pub data: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading cube LUT {:?}", path))?;
+        Self::parse(&text).with_context(|| format!("Parsing cube LUT {:?}", path))
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parse_floats = |s: &str| -> anyhow::Result<Vec<f32>> {
+                s.split_whitespace()
+                    .map(|v| {
+                        v.parse::<f32>().with_context(|| {
+                            format!("line {}: invalid number {:?}", line_idx + 1, v)
+                        })
+                    })
+                    .collect()
+            };
+
+            let parse_rgb = |s: &str| -> anyhow::Result<[f32; 3]> {
+                match parse_floats(s)?.as_slice() {
+                    &[r, g, b] => Ok([r, g, b]),
+                    _ => anyhow::bail!("line {}: expected three numbers", line_idx + 1),
+                }
+            };
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match keyword {
+                "TITLE" => title = Some(rest.trim_matches('"').to_owned()),
+                "LUT_3D_SIZE" => {
+                    let n: u32 = rest
+                        .parse()
+                        .with_context(|| format!("line {}: invalid LUT size", line_idx + 1))?;
+                    if !(2..=256).contains(&n) {
+                        anyhow::bail!("line {}: unsupported LUT size {}", line_idx + 1, n);
+                    }
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => anyhow::bail!("1D LUTs are not supported"),
+                "DOMAIN_MIN" => domain_min = parse_rgb(rest)?,
+                "DOMAIN_MAX" => domain_max = parse_rgb(rest)?,
+                // DaVinci Resolve's variant of the domain
+                "LUT_3D_INPUT_RANGE" => match parse_floats(rest)?.as_slice() {
+                    &[min, max] => {
+                        domain_min = [min; 3];
+                        domain_max = [max; 3];
+                    }
+                    _ => anyhow::bail!("line {}: expected two numbers", line_idx + 1),
+                },
+                _ => data.push(parse_rgb(line)?),
+            }
+        }
+
+        let size = size.context("Missing LUT_3D_SIZE")?;
+        let expected_len = (size * size * size) as usize;
+        if data.len() != expected_len {
+            anyhow::bail!(
+                "Expected {} entries for a LUT of size {}, got {}",
+                expected_len,
+                size,
+                data.len()
+            );
+        }
+
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            anyhow::bail!("Empty LUT domain: {:?}..{:?}", domain_min, domain_max);
+        }
+
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+
+    /// Maps every color to itself.
+    pub fn identity(size: u32) -> Self {
+        let max = (size - 1) as f32;
+        let data = (0..size * size * size)
+            .map(|i| {
+                [
+                    (i % size) as f32 / max,
+                    (i / size % size) as f32 / max,
+                    (i / (size * size)) as f32 / max,
+                ]
+            })
+            .collect();
+
+        Self {
+            title: None,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+fn cube_text(header: &str, entry_count: usize) -> String {
+    let mut text = header.to_owned();
+    for i in 0..entry_count {
+        text += &format!("{} 0.5 1\n", i as f32 / 8.0);
+    }
+    text
+}
+
+#[test]
+fn test_parse_cube_lut() {
+    let text = cube_text(
+        "# comment\nTITLE \"Test LUT\"\n\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 -1 0\nDOMAIN_MAX 1 2 4\n",
+        8,
+    );
+    let lut = CubeLut::parse(&text).unwrap();
+
+    assert_eq!(lut.title.as_deref(), Some("Test LUT"));
+    assert_eq!(lut.size, 2);
+    assert_eq!(lut.domain_min, [0.0, -1.0, 0.0]);
+    assert_eq!(lut.domain_max, [1.0, 2.0, 4.0]);
+    assert_eq!(lut.data.len(), 8);
+    assert_eq!(lut.data[0], [0.0, 0.5, 1.0]);
+    assert_eq!(lut.data[7], [0.875, 0.5, 1.0]);
+}
+
+#[test]
+fn test_parse_cube_lut_defaults() {
+    let lut = CubeLut::parse(&cube_text("LUT_3D_SIZE 2\n", 8)).unwrap();
+
+    assert_eq!(lut.title, None);
+    assert_eq!(lut.domain_min, [0.0; 3]);
+    assert_eq!(lut.domain_max, [1.0; 3]);
+}
+
+#[test]
+fn test_parse_cube_lut_input_range() {
+    let lut = CubeLut::parse(&cube_text(
+        "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE -0.5 1.5\n",
+        8,
+    ))
+    .unwrap();
+
+    assert_eq!(lut.domain_min, [-0.5; 3]);
+    assert_eq!(lut.domain_max, [1.5; 3]);
+}
+
+#[test]
+fn test_parse_cube_lut_errors() {
+    let is_err = |text: &str| CubeLut::parse(text).is_err();
+
+    // Size
+    assert!(is_err(&cube_text("", 8)));
+    assert!(is_err(&cube_text("LUT_3D_SIZE 1\n", 1)));
+    assert!(is_err(&cube_text("LUT_3D_SIZE 257\n", 8)));
+    assert!(is_err(&cube_text("LUT_3D_SIZE two\n", 8)));
+    assert!(is_err(&cube_text("LUT_1D_SIZE 2\n", 2)));
+
+    // Entry count
+    assert!(is_err(&cube_text("LUT_3D_SIZE 2\n", 7)));
+    assert!(is_err(&cube_text("LUT_3D_SIZE 2\n", 9)));
+    assert!(is_err("LUT_3D_SIZE 2\n0 0\n"));
+    assert!(is_err("LUT_3D_SIZE 2\n0 0 x\n"));
+
+    // Domain
+    assert!(is_err(&cube_text("LUT_3D_SIZE 2\nDOMAIN_MIN 0 0\n", 8)));
+    assert!(is_err(&cube_text("LUT_3D_SIZE 2\nDOMAIN_MAX 1 0 1\n", 8)));
+    assert!(is_err(&cube_text(
+        "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0\n",
+        8
+    )));
+    assert!(is_err(&cube_text(
+        "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 1 1\n",
+        8
+    )));
+}
+
+#[test]
+fn test_identity_cube_lut() {
+    let lut = CubeLut::identity(3);
+
+    assert_eq!(lut.data.len(), 27);
+    assert_eq!(lut.data[0], [0.0, 0.0, 0.0]);
+    assert_eq!(lut.data[1], [0.5, 0.0, 0.0]);
+    assert_eq!(lut.data[3], [0.0, 0.5, 0.0]);
+    assert_eq!(lut.data[9], [0.0, 0.0, 0.5]);
+    assert_eq!(lut.data[26], [1.0, 1.0, 1.0]);
+}
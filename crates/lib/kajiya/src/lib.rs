@@ -1,8 +1,10 @@
 pub mod camera;
+pub mod cube_lut;
 pub mod default_world_renderer;
 pub mod frame_desc;
 pub mod image_cache;
 pub mod image_lut;
+pub mod lights;
 pub mod logging;
 pub mod lut_renderers;
 pub mod math;
@@ -15,6 +17,7 @@ pub mod world_renderer_mmap_adapter;
 
 mod bindless_descriptor_set;
 mod buffer_builder;
+mod meshlets;
 
 pub use kajiya_asset as asset;
 pub use kajiya_backend as backend;
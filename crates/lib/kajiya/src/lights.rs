@@ -0,0 +1,303 @@
+use glam::{Quat, Vec3};
+use kajiya_backend::dynamic_constants::MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES;
+use std::mem::size_of;
+
+/// Lights are uploaded to fixed-size storage buffers every frame,
+/// so `WorldRenderer::add_light` refuses to add more than this.
+pub const MAX_PUNCTUAL_LIGHTS: usize =
+    MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES / size_of::<GpuPunctualLight>();
+
+/// See `MAX_PUNCTUAL_LIGHTS`; enforced by `WorldRenderer::add_area_light`.
+pub const MAX_AREA_LIGHTS: usize =
+    MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES / size_of::<GpuAreaLight>();
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct LightHandle(pub usize);
+
+/// Analytic lights without any extent. Their ray-traced shadows are hard.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PunctualLightKind {
+    Point {
+        position: Vec3,
+    },
+    /// Intensity falls off smoothly between the inner and outer cone half-angles, in radians.
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+    /// Like the sun, but without a disk in the sky or soft shadows.
+    /// `direction` is the one the light travels in.
+    Directional {
+        direction: Vec3,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LightDesc {
+    pub kind: PunctualLightKind,
+    pub color: Vec3,
+    /// Radiant intensity of point and spot lights, and irradiance of directional lights,
+    /// in the same units as emissive radiance.
+    pub intensity: f32,
+    /// Distance at which point and spot lights have faded out completely.
+    /// Inverse-square falloff only if `None`.
+    pub range: Option<f32>,
+    pub cast_shadows: bool,
+}
+
+impl LightDesc {
+    pub fn point(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self::new(PunctualLightKind::Point { position }, color, intensity)
+    }
+
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+        color: Vec3,
+        intensity: f32,
+    ) -> Self {
+        Self::new(
+            PunctualLightKind::Spot {
+                position,
+                direction,
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            color,
+            intensity,
+        )
+    }
+
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self::new(
+            PunctualLightKind::Directional { direction },
+            color,
+            intensity,
+        )
+    }
+
+    fn new(kind: PunctualLightKind, color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind,
+            color,
+            intensity,
+            range: None,
+            cast_shadows: true,
+        }
+    }
+
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn with_cast_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+}
+
+// Must match `PUNCTUAL_LIGHT_*` in `inc/lights/punctual.hlsl`
+const PUNCTUAL_LIGHT_POINT: u32 = 0;
+const PUNCTUAL_LIGHT_SPOT: u32 = 1;
+const PUNCTUAL_LIGHT_DIRECTIONAL: u32 = 2;
+
+const PUNCTUAL_LIGHT_FLAG_CAST_SHADOWS: u32 = 1;
+
+/// Must match `PunctualLightPacked` in `inc/lights/packed.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct GpuPunctualLight {
+    position: [f32; 3],
+    kind: u32,
+    // The direction light travels in
+    direction: [f32; 3],
+    // Zero for infinite range
+    inv_range: f32,
+    intensity: [f32; 3],
+    spot_angle_scale: f32,
+    spot_angle_offset: f32,
+    flags: u32,
+    pad: [u32; 2],
+}
+
+impl From<&LightDesc> for GpuPunctualLight {
+    fn from(desc: &LightDesc) -> Self {
+        let (kind, position, direction) = match desc.kind {
+            PunctualLightKind::Point { position } => (PUNCTUAL_LIGHT_POINT, position, Vec3::Z),
+            PunctualLightKind::Spot {
+                position,
+                direction,
+                ..
+            } => (PUNCTUAL_LIGHT_SPOT, position, direction.normalize()),
+            PunctualLightKind::Directional { direction } => (
+                PUNCTUAL_LIGHT_DIRECTIONAL,
+                Vec3::ZERO,
+                direction.normalize(),
+            ),
+        };
+
+        // The cone falloff is `saturate(cos_angle * scale + offset)^2`; constant 1 for non-spots.
+        let (spot_angle_scale, spot_angle_offset) = match desc.kind {
+            PunctualLightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+                ..
+            } => {
+                let cos_outer = outer_cone_angle.cos();
+                let cos_inner = inner_cone_angle.min(outer_cone_angle).cos();
+                let scale = 1.0 / (cos_inner - cos_outer).max(1e-4);
+                (scale, -cos_outer * scale)
+            }
+            _ => (0.0, 1.0),
+        };
+
+        Self {
+            position: position.into(),
+            kind,
+            direction: direction.into(),
+            inv_range: desc.range.map_or(0.0, |range| 1.0 / range.max(1e-4)),
+            intensity: (desc.color * desc.intensity).into(),
+            spot_angle_scale,
+            spot_angle_offset,
+            flags: if desc.cast_shadows {
+                PUNCTUAL_LIGHT_FLAG_CAST_SHADOWS
+            } else {
+                0
+            },
+            pad: [0; 2],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct AreaLightHandle(pub usize);
+
+/// Emitting shapes, in the local space of the light.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AreaLightShape {
+    /// In the XY plane, emitting towards +Z.
+    Rect {
+        width: f32,
+        height: f32,
+    },
+    /// In the XY plane, emitting towards +Z.
+    Disk {
+        radius: f32,
+    },
+    Sphere {
+        radius: f32,
+    },
+    /// A capsule along the X axis. `length` does not include the end caps.
+    Tube {
+        radius: f32,
+        length: f32,
+    },
+}
+
+/// Lights with an extent, casting soft shadows. They are not part of the scene geometry,
+/// so camera and reflection rays don't see them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AreaLightDesc {
+    pub shape: AreaLightShape,
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub color: Vec3,
+    /// Radiance of the emitting surface
+    pub intensity: f32,
+    /// Rects and disks emit from their back face as well
+    pub two_sided: bool,
+    pub cast_shadows: bool,
+}
+
+impl AreaLightDesc {
+    pub fn new(shape: AreaLightShape, position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            shape,
+            position,
+            rotation: Quat::IDENTITY,
+            color,
+            intensity,
+            two_sided: false,
+            cast_shadows: true,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+
+    pub fn with_cast_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+}
+
+// Must match `AREA_LIGHT_*` in `inc/lights/area.hlsl`
+const AREA_LIGHT_RECT: u32 = 0;
+const AREA_LIGHT_DISK: u32 = 1;
+const AREA_LIGHT_SPHERE: u32 = 2;
+const AREA_LIGHT_TUBE: u32 = 3;
+
+const AREA_LIGHT_FLAG_CAST_SHADOWS: u32 = 1;
+const AREA_LIGHT_FLAG_TWO_SIDED: u32 = 2;
+
+/// Must match `AreaLightPacked` in `inc/lights/packed.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct GpuAreaLight {
+    position: [f32; 3],
+    shape: u32,
+    // Local X axis of the light
+    tangent: [f32; 3],
+    // Half the width of rects; radius of the other shapes
+    half_size_x: f32,
+    // Local Y axis of the light
+    bitangent: [f32; 3],
+    // Half the height of rects; half the length of tubes
+    half_size_y: f32,
+    radiance: [f32; 3],
+    flags: u32,
+}
+
+impl From<&AreaLightDesc> for GpuAreaLight {
+    fn from(desc: &AreaLightDesc) -> Self {
+        let (shape, half_size_x, half_size_y) = match desc.shape {
+            AreaLightShape::Rect { width, height } => (AREA_LIGHT_RECT, width * 0.5, height * 0.5),
+            AreaLightShape::Disk { radius } => (AREA_LIGHT_DISK, radius, 0.0),
+            AreaLightShape::Sphere { radius } => (AREA_LIGHT_SPHERE, radius, 0.0),
+            AreaLightShape::Tube { radius, length } => (AREA_LIGHT_TUBE, radius, length * 0.5),
+        };
+
+        let mut flags = 0;
+        if desc.cast_shadows {
+            flags |= AREA_LIGHT_FLAG_CAST_SHADOWS;
+        }
+        if desc.two_sided {
+            flags |= AREA_LIGHT_FLAG_TWO_SIDED;
+        }
+
+        let rotation = desc.rotation.normalize();
+
+        Self {
+            position: desc.position.into(),
+            shape,
+            tangent: (rotation * Vec3::X).into(),
+            half_size_x: half_size_x.max(1e-4),
+            bitangent: (rotation * Vec3::Y).into(),
+            half_size_y: half_size_y.max(0.0),
+            radiance: (desc.color * desc.intensity).into(),
+            flags,
+        }
+    }
+}
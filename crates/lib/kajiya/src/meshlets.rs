@@ -0,0 +1,242 @@
+use glam::Vec3;
+
+pub const MAX_MESHLET_VERTICES: usize = 64;
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+
+/// Bounds used for culling a meshlet in the task shader.
+/// Mirrors `Meshlet` in `assets/shaders/inc/meshlet.hlsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct GpuMeshlet {
+    pub center: [f32; 3],
+    pub radius: f32,
+    pub cone_axis: [f32; 3],
+    /// Sine of the normal cone half-angle. 1.0 disables cone culling.
+    pub cone_cutoff: f32,
+    /// Byte offset of the `u32` vertex indices of the meshlet in the vertex buffer
+    pub vertex_offset: u32,
+    /// Byte offset of the triangles of the meshlet in the vertex buffer,
+    /// three 8-bit local vertex indices packed into each `u32`
+    pub triangle_offset: u32,
+    pub vertex_count: u32,
+    pub triangle_count: u32,
+}
+
+pub struct MeshletBuildResult {
+    /// `vertex_offset` and `triangle_offset` are relative to the start
+    /// of `vertices` and `triangles` respectively.
+    pub meshlets: Vec<GpuMeshlet>,
+    /// Indices into the vertex arrays of the mesh
+    pub vertices: Vec<u32>,
+    pub triangles: Vec<u32>,
+}
+
+/// Splits a triangle list into meshlets, greedily, in index buffer order.
+pub fn build_meshlets(indices: &[u32], positions: impl Fn(u32) -> Vec3) -> MeshletBuildResult {
+    let mut res = MeshletBuildResult {
+        meshlets: Vec::new(),
+        vertices: Vec::new(),
+        triangles: Vec::new(),
+    };
+
+    let mut meshlet_vertices: Vec<u32> = Vec::with_capacity(MAX_MESHLET_VERTICES);
+    let mut meshlet_triangles: Vec<[u8; 3]> = Vec::with_capacity(MAX_MESHLET_TRIANGLES);
+
+    for tri in indices.chunks_exact(3) {
+        let new_vertex_count = tri
+            .iter()
+            .enumerate()
+            .filter(|&(i, v)| !meshlet_vertices.contains(v) && !tri[..i].contains(v))
+            .count();
+
+        if meshlet_vertices.len() + new_vertex_count > MAX_MESHLET_VERTICES
+            || meshlet_triangles.len() == MAX_MESHLET_TRIANGLES
+        {
+            flush_meshlet(
+                &mut res,
+                &mut meshlet_vertices,
+                &mut meshlet_triangles,
+                &positions,
+            );
+        }
+
+        let mut local_tri = [0u8; 3];
+        for (local, &v) in local_tri.iter_mut().zip(tri) {
+            *local = match meshlet_vertices.iter().position(|&mv| mv == v) {
+                Some(idx) => idx as u8,
+                None => {
+                    meshlet_vertices.push(v);
+                    (meshlet_vertices.len() - 1) as u8
+                }
+            };
+        }
+        meshlet_triangles.push(local_tri);
+    }
+
+    flush_meshlet(
+        &mut res,
+        &mut meshlet_vertices,
+        &mut meshlet_triangles,
+        &positions,
+    );
+
+    res
+}
+
+fn flush_meshlet(
+    res: &mut MeshletBuildResult,
+    vertices: &mut Vec<u32>,
+    triangles: &mut Vec<[u8; 3]>,
+    positions: &impl Fn(u32) -> Vec3,
+) {
+    if triangles.is_empty() {
+        return;
+    }
+
+    let points: Vec<Vec3> = vertices.iter().map(|&v| positions(v)).collect();
+
+    // AABB center; not the tightest sphere, but cheap and stable
+    let (min, max) = points.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    );
+    let center = (min + max) * 0.5;
+    let radius = points
+        .iter()
+        .map(|&p| p.distance(center))
+        .fold(0.0f32, f32::max);
+
+    let normals: Vec<Vec3> = triangles
+        .iter()
+        .filter_map(|tri| {
+            let [a, b, c] = tri.map(|v| points[v as usize]);
+            let n = (b - a).cross(c - a);
+            (n.length_squared() > 0.0).then(|| n.normalize())
+        })
+        .collect();
+
+    let (cone_axis, cone_cutoff) = normal_cone(&normals);
+
+    res.meshlets.push(GpuMeshlet {
+        center: center.into(),
+        radius,
+        cone_axis: cone_axis.into(),
+        cone_cutoff,
+        vertex_offset: (res.vertices.len() * std::mem::size_of::<u32>()) as u32,
+        triangle_offset: (res.triangles.len() * std::mem::size_of::<u32>()) as u32,
+        vertex_count: vertices.len() as u32,
+        triangle_count: triangles.len() as u32,
+    });
+
+    res.vertices.append(vertices);
+    res.triangles.extend(
+        triangles
+            .drain(..)
+            .map(|[a, b, c]| a as u32 | (b as u32) << 8 | (c as u32) << 16),
+    );
+}
+
+// Returns the axis and the sine of the half-angle of a cone containing all the normals.
+// Cones which are too wide to ever cull anything get a cutoff of 1.
+fn normal_cone(normals: &[Vec3]) -> (Vec3, f32) {
+    let axis = normals.iter().copied().sum::<Vec3>();
+    if axis.length_squared() == 0.0 {
+        return (Vec3::Z, 1.0);
+    }
+    let axis = axis.normalize();
+
+    let min_dot = normals.iter().map(|n| n.dot(axis)).fold(1.0f32, f32::min);
+
+    if min_dot <= 0.1 {
+        (axis, 1.0)
+    } else {
+        (axis, (1.0 - min_dot * min_dot).sqrt())
+    }
+}
+
+#[cfg(test)]
+fn grid_mesh(size: u32) -> (Vec<u32>, Vec<Vec3>) {
+    let positions = (0..=size)
+        .flat_map(|y| (0..=size).map(move |x| Vec3::new(x as f32, y as f32, 0.0)))
+        .collect();
+
+    let vertex = |x: u32, y: u32| y * (size + 1) + x;
+    let indices = (0..size)
+        .flat_map(|y| (0..size).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            [
+                vertex(x, y),
+                vertex(x + 1, y),
+                vertex(x + 1, y + 1),
+                vertex(x, y),
+                vertex(x + 1, y + 1),
+                vertex(x, y + 1),
+            ]
+        })
+        .collect();
+
+    (indices, positions)
+}
+
+#[test]
+fn test_meshlets_reproduce_triangles() {
+    let (indices, positions) = grid_mesh(32);
+    let res = build_meshlets(&indices, |v| positions[v as usize]);
+
+    assert!(res.meshlets.len() > 1);
+
+    let mut rebuilt_indices = Vec::new();
+    for meshlet in &res.meshlets {
+        assert!(meshlet.vertex_count as usize <= MAX_MESHLET_VERTICES);
+        assert!(meshlet.triangle_count as usize <= MAX_MESHLET_TRIANGLES);
+
+        let vertex_start = meshlet.vertex_offset as usize / std::mem::size_of::<u32>();
+        let vertices = &res.vertices[vertex_start..vertex_start + meshlet.vertex_count as usize];
+
+        let triangle_start = meshlet.triangle_offset as usize / std::mem::size_of::<u32>();
+        for &packed in
+            &res.triangles[triangle_start..triangle_start + meshlet.triangle_count as usize]
+        {
+            for shift in [0, 8, 16] {
+                let local = (packed >> shift) & 0xff;
+                assert!(local < meshlet.vertex_count);
+                rebuilt_indices.push(vertices[local as usize]);
+            }
+        }
+
+        // The bounding sphere contains every vertex
+        let center = Vec3::from(meshlet.center);
+        for &v in vertices {
+            assert!(positions[v as usize].distance(center) <= meshlet.radius + 1e-4);
+        }
+    }
+
+    assert_eq!(rebuilt_indices, indices);
+}
+
+#[test]
+fn test_meshlet_normal_cones() {
+    // Counter-clockwise in the XY plane; facing +Z
+    let (indices, positions) = grid_mesh(4);
+    let res = build_meshlets(&indices, |v| positions[v as usize]);
+
+    assert_eq!(res.meshlets.len(), 1);
+    let meshlet = res.meshlets[0];
+    assert!(Vec3::from(meshlet.cone_axis).distance(Vec3::Z) < 1e-4);
+    assert!(meshlet.cone_cutoff < 1e-3);
+
+    // Two triangles facing opposite ways can't be culled
+    let res = build_meshlets(&[0, 1, 6, 0, 6, 1], |v| positions[v as usize]);
+    assert_eq!(res.meshlets[0].cone_cutoff, 1.0);
+}
+
+#[test]
+fn test_meshlets_of_degenerate_input() {
+    let res = build_meshlets(&[], |_| Vec3::ZERO);
+    assert!(res.meshlets.is_empty());
+
+    // Degenerate triangles are kept, but don't contribute to the normal cone
+    let res = build_meshlets(&[0, 0, 0], |_| Vec3::ZERO);
+    assert_eq!(res.meshlets.len(), 1);
+    assert_eq!(res.meshlets[0].cone_cutoff, 1.0);
+}
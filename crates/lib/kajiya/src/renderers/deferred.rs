@@ -17,6 +17,7 @@ pub fn light_gbuffer(
     output: &mut rg::Handle<Image>,
     sky_cube: &rg::Handle<Image>,
     convolved_sky_cube: &rg::Handle<Image>,
+    analytic_lighting: &rg::Handle<Image>,
     bindless_descriptor_set: vk::DescriptorSet,
     debug_shading_mode: usize,
     debug_show_wrc: bool,
@@ -33,6 +34,7 @@ pub fn light_gbuffer(
         .write(output)
         .read(sky_cube)
         .read(convolved_sky_cube)
+        .read(analytic_lighting)
         .constants((
             gbuffer_depth.gbuffer.desc().extent_inv_extent_2d(),
             debug_shading_mode as u32,
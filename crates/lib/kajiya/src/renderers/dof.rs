@@ -2,6 +2,7 @@ use kajiya_backend::{ash::vk, vulkan::image::*};
 use kajiya_rg::{self as rg};
 use rg::{RenderGraph, SimpleRenderPass};
 
+/// Thin lens depth of field, with the aperture and focus distance from frame constants.
 pub fn dof(
     rg: &mut RenderGraph,
     input: &rg::Handle<Image>,
@@ -21,6 +22,7 @@ pub fn dof(
         .read_aspect(depth, vk::ImageAspectFlags::DEPTH)
         .write(&mut coc)
         .write(&mut coc_tiles)
+        .constants(coc.desc().extent_inv_extent_2d())
         .dispatch(coc.desc().extent);
 
     let mut dof = rg.create(ImageDesc::new_2d(
@@ -0,0 +1,161 @@
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{buffer::*, image::*},
+};
+use kajiya_rg::{self as rg, GetOrCreateTemporal, SimpleRenderPass};
+
+use super::raster_meshes::{
+    gpu_instance_transforms, IndirectDraws, RasterMeshesData, DRAW_INDEXED_INDIRECT_COMMAND_SIZE,
+};
+
+/// Culls mesh instances on the GPU against the view frustum, and against a depth pyramid
+/// (HiZ) built from the previous frame's depth. Produces indirect draws for `raster_meshes`.
+pub struct GpuCullingRenderer {
+    pub use_occlusion_culling: bool,
+    // Extent of the depth pyramid written last frame, if any. Reset in frames which
+    // don't build it, so that a stale pyramid is never reprojected.
+    hiz_extent: Option<[u32; 2]>,
+}
+
+impl Default for GpuCullingRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GpuCullingRenderer {
+    pub fn new() -> Self {
+        Self {
+            use_occlusion_culling: true,
+            hiz_extent: None,
+        }
+    }
+
+    fn hiz_desc(extent: [u32; 2]) -> ImageDesc {
+        ImageDesc::new_2d(vk::Format::R32_SFLOAT, extent)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE)
+            .all_mip_levels()
+    }
+
+    /// Returns the draws, and the depth pyramid to be passed to `build_hiz` once
+    /// this frame's depth is available.
+    pub fn cull_instances(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        mesh_data: &RasterMeshesData<'_>,
+        render_extent: [u32; 2],
+    ) -> (IndirectDraws, rg::Handle<Image>) {
+        let instance_count = mesh_data.instances.len() as u32;
+
+        let hiz = rg
+            .get_or_create_temporal("gpu_culling.hiz", Self::hiz_desc(render_extent))
+            .unwrap();
+
+        // The pyramid is garbage until it's been built at the current resolution
+        let use_occlusion_culling =
+            self.use_occlusion_culling && self.hiz_extent == Some(render_extent);
+
+        let mut commands = rg.create(BufferDesc::new_gpu_only(
+            instance_count.max(1) as usize * DRAW_INDEXED_INDIRECT_COMMAND_SIZE,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+        ));
+
+        let mut count = rg.create(BufferDesc::new_gpu_only(
+            std::mem::size_of::<u32>(),
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+        ));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("_clear draw count"),
+            "/shaders/gpu_culling/clear_draw_count.hlsl",
+        )
+        .write(&mut count)
+        .dispatch([1, 1, 1]);
+
+        let instance_transforms = mesh_data
+            .instances
+            .iter()
+            .map(gpu_instance_transforms)
+            .collect::<Vec<_>>();
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("cull instances"),
+            "/shaders/gpu_culling/cull_instances.hlsl",
+        )
+        .dynamic_storage_buffer_vec(instance_transforms)
+        .dynamic_storage_buffer_vec(mesh_data.gpu_raster_instances())
+        .read(&hiz)
+        .write(&mut commands)
+        .write(&mut count)
+        .constants((
+            instance_count,
+            use_occlusion_culling as u32,
+            render_extent,
+            hiz.desc().mip_levels as u32,
+        ))
+        .dispatch([instance_count, 1, 1]);
+
+        (
+            IndirectDraws {
+                commands,
+                count,
+                max_count: instance_count,
+            },
+            hiz,
+        )
+    }
+
+    /// Must be called in frames which don't call `build_hiz`.
+    pub fn invalidate_hiz(&mut self) {
+        self.hiz_extent = None;
+    }
+
+    /// Builds the depth pyramid used for occlusion culling in the next frame.
+    pub fn build_hiz(
+        &mut self,
+        rg: &mut rg::RenderGraph,
+        depth: &rg::Handle<Image>,
+        mut hiz: rg::Handle<Image>,
+    ) {
+        let extent = depth.desc().extent_2d();
+        assert_eq!(extent, hiz.desc().extent_2d());
+
+        SimpleRenderPass::new_compute(rg.add_pass("_hiz0"), "/shaders/copy_depth_to_r.hlsl")
+            .read_aspect(depth, vk::ImageAspectFlags::DEPTH)
+            .write_view(
+                &mut hiz,
+                ImageViewDesc::builder()
+                    .base_mip_level(0)
+                    .level_count(Some(1)),
+            )
+            .dispatch(depth.desc().extent);
+
+        for target_mip in 1..(hiz.desc().mip_levels as u32) {
+            let input_extent =
+                hiz.desc()
+                    .div_extent([1 << (target_mip - 1), 1 << (target_mip - 1), 1]);
+            let output_extent = hiz.desc().div_extent([1 << target_mip, 1 << target_mip, 1]);
+
+            SimpleRenderPass::new_compute(
+                rg.add_pass(&format!("_hiz{}", target_mip)),
+                "/shaders/gpu_culling/hiz_downsample.hlsl",
+            )
+            .read_view(
+                &hiz,
+                ImageViewDesc::builder()
+                    .base_mip_level(target_mip - 1)
+                    .level_count(Some(1)),
+            )
+            .write_view(
+                &mut hiz,
+                ImageViewDesc::builder()
+                    .base_mip_level(target_mip)
+                    .level_count(Some(1)),
+            )
+            .constants((input_extent.extent_2d(), output_extent.extent_2d()))
+            .dispatch(output_extent.extent);
+        }
+
+        self.hiz_extent = Some(extent);
+    }
+}
@@ -23,15 +23,17 @@ impl IblRenderer {
     }
 
     pub fn load_image(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
-        let img = load_image(path.as_ref())?;
+        self.set_image(load_image(path.as_ref())?);
+        Ok(())
+    }
 
-        self.image = Some(img);
+    /// Uses an equirectangular `image` in place of the sky.
+    pub fn set_image(&mut self, image: ImageRgba16f) {
+        self.image = Some(image);
 
         // Force re-creation of the texture
         // TODO: deallocate the old one 😅
         self.texture = None;
-
-        Ok(())
     }
 
     pub fn render(
@@ -4,13 +4,19 @@ use kajiya_backend::{
 };
 use kajiya_rg::{self as rg, SimpleRenderPass};
 
-use super::{rtr::SPATIAL_RESOLVE_OFFSETS, GbufferDepth};
+use super::{rtr::SPATIAL_RESOLVE_OFFSETS, GbufferDepth, PingPongTemporalResource};
 
-pub struct LightingRenderer {}
+pub struct LightingRenderer {
+    triangle_light_reservoir_tex: PingPongTemporalResource,
+}
 
 impl LightingRenderer {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            triangle_light_reservoir_tex: PingPongTemporalResource::new(
+                "lighting.triangle_light_reservoir",
+            ),
+        }
     }
 }
 
@@ -21,12 +27,15 @@ impl Default for LightingRenderer {
 }
 
 impl LightingRenderer {
+    /// Specular lighting from triangle lights. Candidates picked by power are resampled
+    /// by their contribution, and reused over time via reservoirs.
     #[allow(clippy::too_many_arguments)]
     pub fn render_specular(
         &mut self,
         output_tex: &mut rg::Handle<Image>,
         rg: &mut rg::TemporalRenderGraph,
         gbuffer_depth: &GbufferDepth,
+        reprojection_map: &rg::Handle<Image>,
         bindless_descriptor_set: vk::DescriptorSet,
         tlas: &rg::Handle<RayTracingAcceleration>,
     ) {
@@ -46,6 +55,16 @@ impl LightingRenderer {
 
         let mut refl2_tex = rg.create(refl0_tex.desc().format(vk::Format::R8G8B8A8_SNORM));
 
+        let (mut reservoir_output_tex, reservoir_history_tex) =
+            self.triangle_light_reservoir_tex.get_output_and_history(
+                rg,
+                ImageDesc::new_2d(
+                    vk::Format::R32G32B32A32_UINT,
+                    gbuffer_desc.half_res().extent_2d(),
+                )
+                .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
+            );
+
         SimpleRenderPass::new_rt(
             rg.add_pass("sample lights"),
             ShaderSource::hlsl("/shaders/lighting/sample_lights.rgen.hlsl"),
@@ -55,10 +74,14 @@ impl LightingRenderer {
             ],
             [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
         )
+        .read(&gbuffer_depth.gbuffer)
         .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read(reprojection_map)
+        .read(&reservoir_history_tex)
         .write(&mut refl0_tex)
         .write(&mut refl1_tex)
         .write(&mut refl2_tex)
+        .write(&mut reservoir_output_tex)
         .constants((gbuffer_desc.extent_inv_extent_2d(),))
         .raw_descriptor_set(1, bindless_descriptor_set)
         .trace_rays(tlas, refl0_tex.desc().extent);
@@ -85,4 +108,109 @@ impl LightingRenderer {
         ))
         .dispatch(output_tex.desc().extent);
     }
+
+    /// Direct lighting from the punctual lights in `frame_constants`, with ray-traced shadows.
+    pub fn render_punctual_lights(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        gbuffer_depth: &GbufferDepth,
+        bindless_descriptor_set: vk::DescriptorSet,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+    ) -> rg::Handle<Image> {
+        let mut output_tex = rg.create(
+            gbuffer_depth
+                .gbuffer
+                .desc()
+                .usage(vk::ImageUsageFlags::empty())
+                .format(vk::Format::R16G16B16A16_SFLOAT),
+        );
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("punctual lights"),
+            ShaderSource::hlsl("/shaders/lighting/punctual_lights.rgen.hlsl"),
+            [
+                // Duplicated because `rt.hlsl` hardcodes miss index to 1
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            std::iter::empty(),
+        )
+        .read(&gbuffer_depth.gbuffer)
+        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read(&gbuffer_depth.geometric_normal)
+        .write(&mut output_tex)
+        .constants((output_tex.desc().extent_inv_extent_2d(),))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, output_tex.desc().extent);
+
+        output_tex
+    }
+
+    /// Traces area lights from `frame_constants`. Shadows are returned separately,
+    /// so that they can be denoised before `composite_area_lights`.
+    pub fn trace_area_lights(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        gbuffer_depth: &GbufferDepth,
+        bindless_descriptor_set: vk::DescriptorSet,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+    ) -> TracedAreaLights {
+        let gbuffer_desc = gbuffer_depth.gbuffer.desc();
+
+        let mut unshadowed = rg.create(
+            gbuffer_desc
+                .usage(vk::ImageUsageFlags::empty())
+                .format(vk::Format::R16G16B16A16_SFLOAT),
+        );
+        let mut shadow_mask = rg.create(gbuffer_depth.depth.desc().format(vk::Format::R8_UNORM));
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("area lights"),
+            ShaderSource::hlsl("/shaders/lighting/area_lights.rgen.hlsl"),
+            [
+                // Duplicated because `rt.hlsl` hardcodes miss index to 1
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            std::iter::empty(),
+        )
+        .read(&gbuffer_depth.gbuffer)
+        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read(&gbuffer_depth.geometric_normal)
+        .write(&mut unshadowed)
+        .write(&mut shadow_mask)
+        .constants((gbuffer_desc.extent_inv_extent_2d(),))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, unshadowed.desc().extent);
+
+        TracedAreaLights {
+            unshadowed,
+            shadow_mask,
+        }
+    }
+
+    /// Adds shadowed area lighting to `output_tex`.
+    pub fn composite_area_lights(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        unshadowed: &rg::Handle<Image>,
+        denoised_shadow_mask: &rg::Handle<Image>,
+        output_tex: &mut rg::Handle<Image>,
+    ) {
+        SimpleRenderPass::new_compute(
+            rg.add_pass("composite area lights"),
+            "/shaders/lighting/composite_area_lights.hlsl",
+        )
+        .read(unshadowed)
+        .read(denoised_shadow_mask)
+        .write(output_tex)
+        .dispatch(output_tex.desc().extent);
+    }
+}
+
+pub struct TracedAreaLights {
+    /// Area lighting without shadows
+    pub unshadowed: rg::Handle<Image>,
+    /// Fraction of `unshadowed` which reaches the surface; one shadow ray per light
+    pub shadow_mask: rg::Handle<Image>,
 }
@@ -5,6 +5,7 @@ use kajiya_rg::{self as rg, GetOrCreateTemporal};
 
 pub mod deferred;
 pub mod dof;
+pub mod gpu_culling;
 pub mod half_res;
 pub mod ibl;
 pub mod ircache;
@@ -22,6 +23,7 @@ pub mod shadows;
 pub mod sky;
 pub mod ssgi;
 pub mod taa;
+pub mod triangle_lights;
 pub mod ussgi;
 pub mod wrc;
 
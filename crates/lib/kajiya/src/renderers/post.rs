@@ -1,10 +1,11 @@
 use std::sync::Arc;
 
+use image::RgbaImage;
 use kajiya_backend::{ash::vk, vk_sync::AccessType, vulkan::image::*, BackendError, Device};
 use kajiya_rg::{self as rg};
 use rg::{Buffer, BufferDesc, RenderGraph, SimpleRenderPass};
 
-use crate::world_renderer::HistogramClipping;
+use crate::{cube_lut::CubeLut, world_renderer::HistogramClipping};
 
 pub fn blur_pyramid(rg: &mut RenderGraph, input: &rg::Handle<Image>) -> rg::Handle<Image> {
     let skip_n_bottom_mips = 1;
@@ -61,7 +62,12 @@ pub fn blur_pyramid(rg: &mut RenderGraph, input: &rg::Handle<Image>) -> rg::Hand
     output
 }
 
-pub fn rev_blur_pyramid(rg: &mut RenderGraph, in_pyramid: &rg::Handle<Image>) -> rg::Handle<Image> {
+/// `radius` in [0, 1] is how much the coarser levels of the pyramid contribute.
+pub fn rev_blur_pyramid(
+    rg: &mut RenderGraph,
+    in_pyramid: &rg::Handle<Image>,
+    radius: f32,
+) -> rg::Handle<Image> {
     let mut output = rg.create(*in_pyramid.desc());
 
     for target_mip in (0..(output.desc().mip_levels as u32 - 1)).rev() {
@@ -74,7 +80,7 @@ pub fn rev_blur_pyramid(rg: &mut RenderGraph, in_pyramid: &rg::Handle<Image>) ->
         let self_weight = if src_mip == output.desc().mip_levels as u32 {
             0.0f32
         } else {
-            0.5f32
+            1.0 - radius.clamp(0.0, 1.0)
         };
 
         SimpleRenderPass::new_compute_rust(
@@ -106,6 +112,178 @@ pub fn rev_blur_pyramid(rg: &mut RenderGraph, in_pyramid: &rg::Handle<Image>) ->
     output
 }
 
+/// How scene-linear radiance is compressed into the range of the display.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisplayTransform {
+    /// Perceptually neutral, with hue-preserving chroma attenuation. See `display_transform.hlsl`.
+    Neutral = 0,
+    /// The ACES RRT and sRGB ODT, as fitted by Stephen Hill.
+    /// On HDR targets, the shoulder is stretched to reach the peak brightness.
+    Aces = 1,
+    /// AgX with its default look. Stretched on HDR targets like `Aces`.
+    Agx = 2,
+}
+
+/// Primaries and transfer function of the display. Must match the color space of the swapchain.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisplayTarget {
+    /// sRGB primaries and transfer function
+    Rec709 = 0,
+    /// DCI-P3 primaries with a D65 white point, and the sRGB transfer function.
+    /// Needs a `DISPLAY_P3_NONLINEAR_EXT` swapchain; `kajiya-simple` falls back to `Rec709` otherwise.
+    DisplayP3 = 1,
+    /// Rec.2020 primaries and the SMPTE ST 2084 perceptual quantizer, as in HDR10
+    Rec2020Pq = 2,
+    /// Linear, with sRGB primaries and 1.0 at 80 nits. Values outside of [0, 1] reach
+    /// wider gamuts and brighter highlights.
+    ScRgb = 3,
+}
+
+impl DisplayTarget {
+    /// HDR targets output absolute luminance; see `HdrDisplaySettings`.
+    pub fn is_hdr(self) -> bool {
+        matches!(self, Self::Rec2020Pq | Self::ScRgb)
+    }
+}
+
+/// Brightness of HDR displays. Ignored by SDR targets.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HdrDisplaySettings {
+    /// Luminance of SDR white, including the UI
+    pub paper_white_nits: f32,
+    /// Luminance the display transform compresses highlights towards
+    pub peak_brightness_nits: f32,
+}
+
+impl Default for HdrDisplaySettings {
+    fn default() -> Self {
+        Self {
+            // BT.2408 reference white
+            paper_white_nits: 203.0,
+            peak_brightness_nits: 1000.0,
+        }
+    }
+}
+
+/// Ghosts and a halo of the bright parts of the image, at a low resolution.
+/// See `post/lens_flare.hlsl`.
+pub fn lens_flare(rg: &mut RenderGraph, blur_pyramid: &rg::Handle<Image>) -> rg::Handle<Image> {
+    // An eighth of the input resolution; the flare is blurry anyway.
+    let src_mip = 2.min(blur_pyramid.desc().mip_levels as u32 - 1);
+
+    let mut output = rg.create(
+        blur_pyramid
+            .desc()
+            .div_up_extent([1 << src_mip, 1 << src_mip, 1])
+            .mip_levels(1),
+    );
+
+    SimpleRenderPass::new_compute(rg.add_pass("lens flare"), "/shaders/post/lens_flare.hlsl")
+        .read_view(
+            blur_pyramid,
+            ImageViewDesc::builder()
+                .base_mip_level(src_mip)
+                .level_count(Some(1)),
+        )
+        .write(&mut output)
+        .constants(output.desc().extent_inv_extent_2d())
+        .dispatch(output.desc().extent);
+
+    output
+}
+
+/// Lens and film effects of the post-process pass.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PostProcessSettings {
+    /// Fraction of the image replaced by its blurred version
+    pub bloom_intensity: f32,
+    /// Exposed luminance below which there's no bloom. Zero blooms everything.
+    pub bloom_threshold: f32,
+    /// In [0, 1]; how far the bloom spreads
+    pub bloom_radius: f32,
+    /// Darkening towards the corners of the frame. Zero disables it.
+    pub vignette: f32,
+    /// Shift of the red and blue channels at the edges of the frame, in pixels
+    pub chromatic_aberration: f32,
+    /// Strength of the per-frame noise applied to the display signal
+    pub film_grain: f32,
+    /// Extra bloom and flare where the lens dirt texture is bright. See `load_lens_dirt`.
+    pub lens_dirt_intensity: f32,
+    /// Strength of the ghosts and halo mirrored through the center of the frame.
+    /// Zero disables them.
+    pub lens_flare_intensity: f32,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            bloom_intensity: 0.05,
+            bloom_threshold: 0.0,
+            bloom_radius: 0.5,
+            vignette: 1.0,
+            chromatic_aberration: 0.0,
+            film_grain: 0.0,
+            lens_dirt_intensity: 1.0,
+            lens_flare_intensity: 0.0,
+        }
+    }
+}
+
+/// A cube LUT uploaded to the GPU
+struct OutputLut {
+    image: Arc<Image>,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+impl OutputLut {
+    fn new(device: &Device, lut: &CubeLut) -> Result<Self, BackendError> {
+        let data: Vec<half::f16> = lut
+            .data
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .map(half::f16::from_f32)
+            .collect();
+
+        const TEXEL_BYTES: u32 = 8;
+
+        let image = device.create_image(
+            ImageDesc::new_3d(
+                vk::Format::R16G16B16A16_SFLOAT,
+                [lut.size, lut.size, lut.size],
+            )
+            .usage(vk::ImageUsageFlags::SAMPLED),
+            vec![ImageSubResourceData {
+                data: bytemuck::cast_slice(data.as_slice()),
+                row_pitch: (lut.size * TEXEL_BYTES) as usize,
+                slice_pitch: (lut.size * lut.size * TEXEL_BYTES) as usize,
+            }],
+        )?;
+
+        Ok(Self {
+            image: Arc::new(image),
+            domain_min: lut.domain_min,
+            domain_max: lut.domain_max,
+        })
+    }
+}
+
+fn create_lens_dirt_image(device: &Device, image: &RgbaImage) -> Result<Arc<Image>, BackendError> {
+    const TEXEL_BYTES: u32 = 4;
+
+    let image = device.create_image(
+        ImageDesc::new_2d(vk::Format::R8G8B8A8_SRGB, [image.width(), image.height()])
+            .usage(vk::ImageUsageFlags::SAMPLED),
+        vec![ImageSubResourceData {
+            data: image.as_raw(),
+            row_pitch: (image.width() * TEXEL_BYTES) as usize,
+            slice_pitch: (image.width() * image.height() * TEXEL_BYTES) as usize,
+        }],
+    )?;
+
+    Ok(Arc::new(image))
+}
+
 const LUMINANCE_HISTOGRAM_BIN_COUNT: usize = 256;
 const LUMINANCE_HISTOGRAM_MIN_LOG2: f64 = -16.0;
 const LUMINANCE_HISTOGRAM_MAX_LOG2: f64 = 16.0;
@@ -113,6 +291,14 @@ const LUMINANCE_HISTOGRAM_MAX_LOG2: f64 = 16.0;
 pub struct PostProcessRenderer {
     histogram_buffer: Arc<Buffer>,
     pub image_log2_lum: f32,
+
+    /// Bound when there's no output LUT, so the pass doesn't need a variant without one.
+    identity_lut: OutputLut,
+    output_lut: Option<OutputLut>,
+
+    /// Bound when there's no lens dirt; black, so it adds nothing.
+    black_lens_dirt: Arc<Image>,
+    lens_dirt: Option<Arc<Image>>,
 }
 
 impl PostProcessRenderer {
@@ -127,9 +313,60 @@ impl PostProcessRenderer {
                 None,
             )?),
             image_log2_lum: 0.0,
+            identity_lut: OutputLut::new(device, &CubeLut::identity(2))?,
+            output_lut: None,
+            black_lens_dirt: create_lens_dirt_image(device, &RgbaImage::new(1, 1))?,
+            lens_dirt: None,
         })
     }
 
+    /// Applies `lut` to the display signal; that is after the display transform,
+    /// and in the primaries and transfer function of the `DisplayTarget`.
+    /// This is where grading LUTs made for the target display go.
+    ///
+    /// `ScRgb` has no upper bound, so its LUTs take a Rec.2020 PQ signal, same as `Rec2020Pq`.
+    pub fn load_output_lut(&mut self, device: &Device, lut: &CubeLut) -> Result<(), BackendError> {
+        if let Some(old) = self.output_lut.replace(OutputLut::new(device, lut)?) {
+            device.defer_release_image(old.image);
+        }
+        Ok(())
+    }
+
+    pub fn unload_output_lut(&mut self, device: &Device) {
+        if let Some(old) = self.output_lut.take() {
+            device.defer_release_image(old.image);
+        }
+    }
+
+    pub fn has_output_lut(&self) -> bool {
+        self.output_lut.is_some()
+    }
+
+    /// Modulates the bloom and lens flare with `image`, stretched over the frame.
+    pub fn load_lens_dirt(
+        &mut self,
+        device: &Device,
+        image: &RgbaImage,
+    ) -> Result<(), BackendError> {
+        if let Some(old) = self
+            .lens_dirt
+            .replace(create_lens_dirt_image(device, image)?)
+        {
+            device.defer_release_image(old);
+        }
+        Ok(())
+    }
+
+    pub fn unload_lens_dirt(&mut self, device: &Device) {
+        if let Some(old) = self.lens_dirt.take() {
+            device.defer_release_image(old);
+        }
+    }
+
+    pub fn has_lens_dirt(&self) -> bool {
+        self.lens_dirt.is_some()
+    }
+
     fn calculate_luminance_histogram(
         &mut self,
         rg: &mut RenderGraph,
@@ -239,14 +476,40 @@ impl PostProcessRenderer {
         post_exposure_mult: f32,
         contrast: f32,
         exposure_histogram_clipping: HistogramClipping,
+        display_transform: DisplayTransform,
+        display_target: DisplayTarget,
+        hdr_display: HdrDisplaySettings,
+        settings: &PostProcessSettings,
     ) -> rg::Handle<Image> {
         self.read_back_histogram(exposure_histogram_clipping);
 
         let blur_pyramid = blur_pyramid(rg, input);
         let histogram = self.calculate_luminance_histogram(rg, &blur_pyramid);
 
-        let rev_blur_pyramid = rev_blur_pyramid(rg, &blur_pyramid);
+        let rev_blur_pyramid = rev_blur_pyramid(rg, &blur_pyramid, settings.bloom_radius);
+
+        // Cheap at its resolution, so it's not worth a shader variant to skip it.
+        let lens_flare = lens_flare(rg, &blur_pyramid);
+
+        let lut = self.output_lut.as_ref().unwrap_or(&self.identity_lut);
+        let lut_enabled = self.output_lut.is_some();
+        let lut_size = lut.image.desc.extent[0];
+        let lut_domain_min = lut.domain_min;
+        let lut_domain_max = lut.domain_max;
+        let lut = rg.import(
+            lut.image.clone(),
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+
+        let lens_dirt = rg.import(
+            self.lens_dirt
+                .as_ref()
+                .unwrap_or(&self.black_lens_dirt)
+                .clone(),
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
 
+        // Display-linear, in the primaries of `display_target`.
         let mut output = rg.create(input.desc().format(vk::Format::B10G11R11_UFLOAT_PACK32));
 
         //let blurred_luminance = edge_preserving_filter_luminance(rg, input);
@@ -258,12 +521,42 @@ impl PostProcessRenderer {
             .read(&rev_blur_pyramid)
             .read(&histogram)
             //.read(&blurred_luminance)
+            .read(&lut)
+            .read(&lens_dirt)
+            .read(&lens_flare)
             .write(&mut output)
             .raw_descriptor_set(1, bindless_descriptor_set)
             .constants((
                 output.desc().extent_inv_extent_2d(),
                 post_exposure_mult,
                 contrast,
+                display_transform as u32,
+                display_target as u32,
+                [
+                    lut_domain_min[0],
+                    lut_domain_min[1],
+                    lut_domain_min[2],
+                    lut_enabled as u32 as f32,
+                ],
+                [
+                    lut_domain_max[0],
+                    lut_domain_max[1],
+                    lut_domain_max[2],
+                    lut_size as f32,
+                ],
+                hdr_display.paper_white_nits,
+                hdr_display.peak_brightness_nits,
+                settings.bloom_intensity,
+                settings.bloom_threshold,
+                settings.vignette,
+                settings.chromatic_aberration,
+                settings.film_grain,
+                if self.lens_dirt.is_some() {
+                    settings.lens_dirt_intensity
+                } else {
+                    0.0
+                },
+                settings.lens_flare_intensity,
             ))
             .dispatch(output.desc().extent);
 
@@ -37,3 +37,53 @@ pub fn inclusive_prefix_scan_u32_1m(rg: &mut rg::RenderGraph, input_buf: &mut rg
     .read(&segment_sum_buf)
     .dispatch([(SEGMENT_SIZE * SEGMENT_SIZE / 2) as u32, 1, 1]); // TODO: indirect
 }
+
+/// Inclusive prefix sum of the first `element_count` floats of `input_buf`, up to 1M of them.
+/// Only the segments covering `element_count` are processed, but the buffer must still
+/// have room for 1M elements.
+pub fn inclusive_prefix_scan_f32(
+    rg: &mut rg::RenderGraph,
+    input_buf: &mut rg::Handle<Buffer>,
+    element_count: u32,
+) {
+    const SEGMENT_SIZE: usize = 1024;
+    const DEFINES: &[(&str, &str)] = &[("SCAN_FLOAT", "1")];
+
+    assert!(element_count as usize <= SEGMENT_SIZE * SEGMENT_SIZE);
+    let segment_count = (element_count as usize + SEGMENT_SIZE - 1) / SEGMENT_SIZE;
+
+    SimpleRenderPass::new_compute_with_defines(
+        rg.add_pass("_prefix scan 1"),
+        "/shaders/prefix_scan/inclusive_prefix_scan.hlsl",
+        DEFINES,
+    )
+    .write(input_buf)
+    .dispatch([(segment_count * SEGMENT_SIZE / 2) as u32, 1, 1]);
+
+    // A single segment is already fully scanned
+    if segment_count < 2 {
+        return;
+    }
+
+    let mut segment_sum_buf = rg.create(BufferDesc::new_gpu_only(
+        size_of::<f32>() * SEGMENT_SIZE,
+        vk::BufferUsageFlags::empty(),
+    ));
+    SimpleRenderPass::new_compute_with_defines(
+        rg.add_pass("_prefix scan 2"),
+        "/shaders/prefix_scan/inclusive_prefix_scan_segments.hlsl",
+        DEFINES,
+    )
+    .read(input_buf)
+    .write(&mut segment_sum_buf)
+    .dispatch([(SEGMENT_SIZE / 2) as u32, 1, 1]);
+
+    SimpleRenderPass::new_compute_with_defines(
+        rg.add_pass("_prefix scan merge"),
+        "/shaders/prefix_scan/inclusive_prefix_scan_merge.hlsl",
+        DEFINES,
+    )
+    .write(input_buf)
+    .read(&segment_sum_buf)
+    .dispatch([(segment_count * SEGMENT_SIZE / 2) as u32, 1, 1]);
+}
@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
+use glam::Vec3;
 use kajiya_backend::{
     ash::vk,
     vk_sync::AccessType,
     vulkan::{buffer::*, image::*, shader::*},
 };
 use kajiya_rg::{self as rg};
-use rg::{IntoRenderPassPipelineBinding, RenderGraph, RenderPassBinding};
+use rg::{IntoRenderPassPipelineBinding, RenderGraph, RenderPassApi, RenderPassBinding};
 
 use crate::world_renderer::MeshInstance;
 
@@ -16,6 +17,11 @@ use super::GbufferDepth;
 pub struct UploadedTriMesh {
     pub index_buffer_offset: u64,
     pub index_count: u32,
+    /// Zero unless mesh shaders are enabled
+    pub meshlet_count: u32,
+    /// Object-space bounds, used for culling
+    pub aabb_min: Vec3,
+    pub aabb_max: Vec3,
 }
 
 pub struct RasterMeshesData<'a> {
@@ -25,12 +31,64 @@ pub struct RasterMeshesData<'a> {
     pub bindless_descriptor_set: vk::DescriptorSet,
 }
 
+/// Per-instance data for rasterization and GPU culling.
+/// Must match `RasterInstance` in `inc/raster_instance.hlsl`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct GpuRasterInstance {
+    aabb_min: [f32; 3],
+    mesh_index: u32,
+    aabb_max: [f32; 3],
+    index_count: u32,
+    first_index: u32,
+    /// Zero for instances which are hidden from the camera
+    camera_visible: u32,
+    pad: [u32; 2],
+}
+
+impl RasterMeshesData<'_> {
+    pub(crate) fn gpu_raster_instances(&self) -> Vec<GpuRasterInstance> {
+        self.instances
+            .iter()
+            .map(|inst| {
+                let mesh = &self.meshes[inst.mesh.0];
+                GpuRasterInstance {
+                    aabb_min: mesh.aabb_min.into(),
+                    mesh_index: inst.mesh.0 as u32,
+                    aabb_max: mesh.aabb_max.into(),
+                    index_count: mesh.index_count,
+                    // Indices are read from the start of the vertex buffer
+                    first_index: (mesh.index_buffer_offset / std::mem::size_of::<u32>() as u64)
+                        as u32,
+                    camera_visible: inst.flags.visible_to_camera as u32,
+                    pad: [0; 2],
+                }
+            })
+            .collect()
+    }
+}
+
+/// Draws produced by `GpuCullingRenderer::cull_instances`
+pub struct IndirectDraws {
+    /// `VkDrawIndexedIndirectCommand`s
+    pub commands: rg::Handle<Buffer>,
+    /// A single `u32`
+    pub count: rg::Handle<Buffer>,
+    pub max_count: u32,
+}
+
+pub const DRAW_INDEXED_INDIRECT_COMMAND_SIZE: usize = 5 * std::mem::size_of::<u32>();
+
+/// Rasterizes the G-buffer. If `indirect_draws` is given, only the instances
+/// which survived GPU culling are drawn; otherwise all instances are drawn.
+/// That is also the case without `Device::draw_indirect_count_enabled`.
 pub fn raster_meshes(
     rg: &mut RenderGraph,
     render_pass: Arc<RenderPass>,
     gbuffer_depth: &mut GbufferDepth,
     velocity_img: &mut rg::Handle<Image>,
     mesh_data: RasterMeshesData<'_>,
+    indirect_draws: Option<&IndirectDraws>,
 ) {
     let mut pass = rg.add_pass("raster simple");
 
@@ -49,13 +107,20 @@ pub fn raster_meshes(
         ],
         RasterPipelineDesc::builder()
             .render_pass(render_pass.clone())
-            .face_cull(false)
-            .push_constants_bytes(2 * std::mem::size_of::<u32>()),
+            .face_cull(false),
     );
 
-    let meshes: Vec<UploadedTriMesh> = mesh_data.meshes.to_vec();
+    let raster_instances = mesh_data.gpu_raster_instances();
     let instances: Vec<MeshInstance> = mesh_data.instances.to_vec();
 
+    let indirect_draws = indirect_draws.map(|draws| {
+        (
+            pass.read(&draws.commands, AccessType::IndirectBuffer),
+            pass.read(&draws.count, AccessType::IndirectBuffer),
+            draws.max_count,
+        )
+    });
+
     let depth_ref = pass.raster(
         &mut gbuffer_depth.depth,
         AccessType::DepthAttachmentWriteStencilReadOnly,
@@ -74,41 +139,10 @@ pub fn raster_meshes(
     pass.render(move |api| {
         let [width, height, _] = gbuffer_ref.desc().extent;
 
-        let instance_transforms_offset =
-            api.dynamic_constants()
-                .push_from_iter(instances.iter().map(|inst| {
-                    let transform = [
-                        inst.transform.x_axis.x,
-                        inst.transform.y_axis.x,
-                        inst.transform.z_axis.x,
-                        inst.transform.translation.x,
-                        inst.transform.x_axis.y,
-                        inst.transform.y_axis.y,
-                        inst.transform.z_axis.y,
-                        inst.transform.translation.y,
-                        inst.transform.x_axis.z,
-                        inst.transform.y_axis.z,
-                        inst.transform.z_axis.z,
-                        inst.transform.translation.z,
-                    ];
-
-                    let prev_transform = [
-                        inst.prev_transform.x_axis.x,
-                        inst.prev_transform.y_axis.x,
-                        inst.prev_transform.z_axis.x,
-                        inst.prev_transform.translation.x,
-                        inst.prev_transform.x_axis.y,
-                        inst.prev_transform.y_axis.y,
-                        inst.prev_transform.z_axis.y,
-                        inst.prev_transform.translation.y,
-                        inst.prev_transform.x_axis.z,
-                        inst.prev_transform.y_axis.z,
-                        inst.prev_transform.z_axis.z,
-                        inst.prev_transform.translation.z,
-                    ];
-
-                    (transform, prev_transform)
-                }));
+        let instance_transforms_offset = push_instance_transforms(api, &instances);
+        let raster_instances_offset = api
+            .dynamic_constants()
+            .push_from_iter(raster_instances.iter().copied());
 
         api.begin_render_pass(
             &render_pass,
@@ -129,14 +163,17 @@ pub fn raster_meshes(
 
         api.set_default_view_and_scissor([width, height]);
 
-        let pipeline = api.bind_raster_pipeline(
+        let _pipeline = api.bind_raster_pipeline(
             pipeline
                 .into_binding()
                 .descriptor_set(
                     0,
-                    &[RenderPassBinding::DynamicConstantsStorageBuffer(
-                        instance_transforms_offset,
-                    )],
+                    &[
+                        RenderPassBinding::DynamicConstantsStorageBuffer(
+                            instance_transforms_offset,
+                        ),
+                        RenderPassBinding::DynamicConstantsStorageBuffer(raster_instances_offset),
+                    ],
                 )
                 .raw_descriptor_set(1, bindless_descriptor_set),
         )?;
@@ -145,30 +182,179 @@ pub fn raster_meshes(
             let raw_device = &api.device().raw;
             let cb = api.cb;
 
-            for (draw_idx, instance) in instances.into_iter().enumerate() {
-                let mesh = &meshes[instance.mesh.0];
+            raw_device.cmd_bind_index_buffer(cb.raw, vertex_buffer.raw, 0, vk::IndexType::UINT32);
 
-                raw_device.cmd_bind_index_buffer(
+            let draw_indirect_count_ext = api.device().draw_indirect_count_ext.as_ref();
+
+            if let Some(((commands_ref, count_ref, max_count), draw_indirect_count_ext)) =
+                indirect_draws.zip(draw_indirect_count_ext)
+            {
+                draw_indirect_count_ext.cmd_draw_indexed_indirect_count(
                     cb.raw,
-                    vertex_buffer.raw,
-                    mesh.index_buffer_offset,
-                    vk::IndexType::UINT32,
+                    api.resources.buffer(commands_ref).raw,
+                    0,
+                    api.resources.buffer(count_ref).raw,
+                    0,
+                    max_count,
+                    DRAW_INDEXED_INDIRECT_COMMAND_SIZE as u32,
                 );
+            } else {
+                // `firstInstance` identifies the instance in the shaders
+                for (draw_idx, inst) in raster_instances.iter().enumerate() {
+                    if inst.camera_visible == 0 {
+                        continue;
+                    }
+
+                    raw_device.cmd_draw_indexed(
+                        cb.raw,
+                        inst.index_count,
+                        1,
+                        inst.first_index,
+                        0,
+                        draw_idx as u32,
+                    );
+                }
+            }
+        }
 
-                let push_constants = (draw_idx as u32, instance.mesh.0 as u32);
+        api.end_render_pass();
 
-                pipeline.push_constants(
-                    cb.raw,
-                    vk::ShaderStageFlags::ALL_GRAPHICS,
+        Ok(())
+    });
+}
+
+// Must match `MESHLET_TASK_GROUP_SIZE` in `inc/meshlet.hlsl`
+const MESHLET_TASK_GROUP_SIZE: u32 = 32;
+
+const MESHLET_CULL_FLAG_CONE: u32 = 1;
+
+pub struct RasterMeshletsOptions {
+    /// Cull meshlets facing away from the camera. Skipped for double-sided instances.
+    pub cone_culling: bool,
+}
+
+/// Like `raster_meshes`, but using task and mesh shaders. Meshlets are culled
+/// against the view frustum, and optionally by their normal cones.
+///
+/// Requires `Device::mesh_shaders_enabled`.
+pub fn raster_meshes_meshlets(
+    rg: &mut RenderGraph,
+    render_pass: Arc<RenderPass>,
+    gbuffer_depth: &mut GbufferDepth,
+    velocity_img: &mut rg::Handle<Image>,
+    mesh_data: RasterMeshesData<'_>,
+    options: RasterMeshletsOptions,
+) {
+    let mut pass = rg.add_pass("raster meshlets");
+
+    let pipeline = pass.register_raster_pipeline(
+        &[
+            PipelineShaderDesc::builder(ShaderPipelineStage::Task)
+                .hlsl_source("/shaders/raster_meshlets_as.hlsl")
+                .build()
+                .unwrap(),
+            PipelineShaderDesc::builder(ShaderPipelineStage::Mesh)
+                .hlsl_source("/shaders/raster_meshlets_ms.hlsl")
+                .build()
+                .unwrap(),
+            PipelineShaderDesc::builder(ShaderPipelineStage::Pixel)
+                .hlsl_source("/shaders/raster_simple_ps.hlsl")
+                .build()
+                .unwrap(),
+        ],
+        RasterPipelineDesc::builder()
+            .render_pass(render_pass.clone())
+            .face_cull(false)
+            .push_constants_bytes(3 * std::mem::size_of::<u32>()),
+    );
+
+    let meshes: Vec<UploadedTriMesh> = mesh_data.meshes.to_vec();
+    let instances: Vec<MeshInstance> = mesh_data.instances.to_vec();
+
+    let depth_ref = pass.raster(
+        &mut gbuffer_depth.depth,
+        AccessType::DepthAttachmentWriteStencilReadOnly,
+    );
+
+    let geometric_normal_ref = pass.raster(
+        &mut gbuffer_depth.geometric_normal,
+        AccessType::ColorAttachmentWrite,
+    );
+    let gbuffer_ref = pass.raster(&mut gbuffer_depth.gbuffer, AccessType::ColorAttachmentWrite);
+    let velocity_ref = pass.raster(velocity_img, AccessType::ColorAttachmentWrite);
+
+    let bindless_descriptor_set = mesh_data.bindless_descriptor_set;
+
+    let cone_culling = options.cone_culling;
+
+    pass.render(move |api| {
+        let [width, height, _] = gbuffer_ref.desc().extent;
+
+        let instance_transforms_offset = push_instance_transforms(api, &instances);
+
+        api.begin_render_pass(
+            &render_pass,
+            [width, height],
+            &[
+                (geometric_normal_ref, &ImageViewDesc::default()),
+                (gbuffer_ref, &ImageViewDesc::default()),
+                (velocity_ref, &ImageViewDesc::default()),
+            ],
+            Some((
+                depth_ref,
+                &ImageViewDesc::builder()
+                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                    .build()
+                    .unwrap(),
+            )),
+        )?;
+
+        api.set_default_view_and_scissor([width, height]);
+
+        let pipeline = api.bind_raster_pipeline(
+            pipeline
+                .into_binding()
+                .descriptor_set(
                     0,
-                    std::slice::from_raw_parts(
-                        &push_constants as *const _ as *const u8,
-                        std::mem::size_of_val(&push_constants),
-                    ),
-                );
+                    &[RenderPassBinding::DynamicConstantsStorageBuffer(
+                        instance_transforms_offset,
+                    )],
+                )
+                .raw_descriptor_set(1, bindless_descriptor_set),
+        )?;
+
+        let cb = api.cb;
 
-                raw_device.cmd_draw_indexed(cb.raw, mesh.index_count, 1, 0, 0, 0);
+        for (draw_idx, instance) in instances.into_iter().enumerate() {
+            let mesh = &meshes[instance.mesh.0];
+            if mesh.meshlet_count == 0 || !instance.flags.visible_to_camera {
+                continue;
             }
+
+            // Back faces of double-sided geometry are visible
+            let cull_flags = if cone_culling && !instance.flags.double_sided {
+                MESHLET_CULL_FLAG_CONE
+            } else {
+                0
+            };
+
+            let push_constants = [draw_idx as u32, instance.mesh.0 as u32, cull_flags];
+
+            pipeline.push_constants(
+                cb.raw,
+                pipeline.stage_flags(),
+                0,
+                bytemuck::cast_slice(&push_constants),
+            );
+
+            pipeline.draw_mesh_tasks(
+                cb.raw,
+                [
+                    (mesh.meshlet_count + MESHLET_TASK_GROUP_SIZE - 1) / MESHLET_TASK_GROUP_SIZE,
+                    1,
+                    1,
+                ],
+            );
         }
 
         api.end_render_pass();
@@ -176,3 +362,43 @@ pub fn raster_meshes(
         Ok(())
     });
 }
+
+pub(crate) fn push_instance_transforms(api: &mut RenderPassApi, instances: &[MeshInstance]) -> u32 {
+    api.dynamic_constants()
+        .push_from_iter(instances.iter().map(gpu_instance_transforms))
+}
+
+/// Current and previous transforms as `row_major float3x4`, matching `InstanceTransform` in the shaders
+pub(crate) fn gpu_instance_transforms(inst: &MeshInstance) -> ([f32; 12], [f32; 12]) {
+    let transform = [
+        inst.transform.x_axis.x,
+        inst.transform.y_axis.x,
+        inst.transform.z_axis.x,
+        inst.transform.translation.x,
+        inst.transform.x_axis.y,
+        inst.transform.y_axis.y,
+        inst.transform.z_axis.y,
+        inst.transform.translation.y,
+        inst.transform.x_axis.z,
+        inst.transform.y_axis.z,
+        inst.transform.z_axis.z,
+        inst.transform.translation.z,
+    ];
+
+    let prev_transform = [
+        inst.prev_transform.x_axis.x,
+        inst.prev_transform.y_axis.x,
+        inst.prev_transform.z_axis.x,
+        inst.prev_transform.translation.x,
+        inst.prev_transform.x_axis.y,
+        inst.prev_transform.y_axis.y,
+        inst.prev_transform.z_axis.y,
+        inst.prev_transform.translation.y,
+        inst.prev_transform.x_axis.z,
+        inst.prev_transform.y_axis.z,
+        inst.prev_transform.z_axis.z,
+        inst.prev_transform.translation.z,
+    ];
+
+    (transform, prev_transform)
+}
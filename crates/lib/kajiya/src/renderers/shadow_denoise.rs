@@ -9,14 +9,19 @@ pub struct ShadowDenoiseRenderer {
 
 impl Default for ShadowDenoiseRenderer {
     fn default() -> Self {
-        Self {
-            accum: PingPongTemporalResource::new("shadow_denoise_accum"),
-            moments: PingPongTemporalResource::new("shadow_denoise_moments"),
-        }
+        Self::new("shadow_denoise")
     }
 }
 
 impl ShadowDenoiseRenderer {
+    /// `name` keeps the temporal resources of multiple denoisers apart.
+    pub fn new(name: &str) -> Self {
+        Self {
+            accum: PingPongTemporalResource::new(&format!("{}_accum", name)),
+            moments: PingPongTemporalResource::new(&format!("{}_moments", name)),
+        }
+    }
+
     pub fn render(
         &mut self,
         rg: &mut rg::TemporalRenderGraph,
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{buffer::*, device::Device},
+    BackendError,
+};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use super::prefix_scan::inclusive_prefix_scan_f32;
+
+/// Limited by the size of the prefix scan used to build the light selection CDF
+pub const MAX_TRIANGLE_LIGHTS: usize = 1024 * 1024;
+
+// Must match `TriangleLightPacked` in `inc/lights/packed.hlsl`
+const TRIANGLE_LIGHT_SIZE_BYTES: usize = 12 * std::mem::size_of::<f32>();
+
+/// The emissive triangles of one material of a mesh instance.
+/// Must match `EmissiveInstance` in `lighting/expand_triangle_lights.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct GpuEmissiveInstance {
+    // row_major float3x4
+    pub transform: [f32; 12],
+    // Byte offset of the mesh-space triangles in the vertex buffer
+    pub light_data_offset: u32,
+    // Index of the first triangle in the expanded world-space list
+    pub first_light: u32,
+    // Into `instance_dynamic_parameters_dyn`
+    pub instance_index: u32,
+    pub mesh_index: u32,
+    pub material_id: u32,
+    // Keep the size a multiple of 16 bytes for HLSL
+    pub pad: [u32; 3],
+}
+
+/// Expands the emissive triangles of all instances into a world-space list every frame,
+/// along with a CDF of their power. Shaders pick lights with `select_triangle_light`,
+/// at a cost logarithmic in the light count. The selection ignores distance and orientation,
+/// so noise still grows with the number of lights; the specular pass in `LightingRenderer`
+/// resamples several of these candidates with temporal reservoirs to counter that.
+///
+/// Radiance is resolved from the current materials and instance parameters,
+/// so emissive changes don't require re-adding meshes.
+///
+/// Both buffers live in the bindless descriptor set.
+pub struct TriangleLightsRenderer {
+    light_buffer: Arc<Buffer>,
+    cdf_buffer: Arc<Buffer>,
+}
+
+impl TriangleLightsRenderer {
+    pub fn new(device: &Device) -> Result<Self, BackendError> {
+        Ok(Self {
+            light_buffer: Arc::new(device.create_buffer(
+                BufferDesc::new_gpu_only(
+                    TRIANGLE_LIGHT_SIZE_BYTES * MAX_TRIANGLE_LIGHTS,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                ),
+                "triangle lights",
+                None,
+            )?),
+            cdf_buffer: Arc::new(device.create_buffer(
+                BufferDesc::new_gpu_only(
+                    std::mem::size_of::<f32>() * MAX_TRIANGLE_LIGHTS,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                ),
+                "triangle light cdf",
+                None,
+            )?),
+        })
+    }
+
+    pub(crate) fn light_buffer(&self) -> &Buffer {
+        &self.light_buffer
+    }
+
+    pub(crate) fn cdf_buffer(&self) -> &Buffer {
+        &self.cdf_buffer
+    }
+
+    /// Does nothing without lights, in which case shaders must not sample them.
+    pub(crate) fn prepare(
+        &self,
+        rg: &mut rg::RenderGraph,
+        bindless_descriptor_set: vk::DescriptorSet,
+        emissive_instances: Vec<GpuEmissiveInstance>,
+        light_count: u32,
+    ) {
+        assert!(light_count as usize <= MAX_TRIANGLE_LIGHTS);
+
+        if light_count == 0 {
+            return;
+        }
+
+        let mut light_buffer = rg.import(self.light_buffer.clone(), AccessType::AnyShaderReadOther);
+        let mut cdf_buffer = rg.import(self.cdf_buffer.clone(), AccessType::AnyShaderReadOther);
+
+        let emissive_instance_count = emissive_instances.len() as u32;
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("expand triangle lights"),
+            "/shaders/lighting/expand_triangle_lights.hlsl",
+        )
+        .dynamic_storage_buffer_vec(emissive_instances)
+        .write(&mut light_buffer)
+        .write(&mut cdf_buffer)
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .constants((emissive_instance_count, light_count))
+        .dispatch([light_count, 1, 1]);
+
+        inclusive_prefix_scan_f32(rg, &mut cdf_buffer, light_count);
+
+        // Shaders read the lights via the bindless descriptor set, so the render graph
+        // doesn't know about their accesses. Make the results visible to all of them.
+        let mut pass = rg.add_pass("_triangle lights barrier");
+        pass.read(&light_buffer, AccessType::AnyShaderReadOther);
+        pass.read(&cdf_buffer, AccessType::AnyShaderReadOther);
+    }
+}
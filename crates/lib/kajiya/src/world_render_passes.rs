@@ -1,7 +1,7 @@
 use crate::{
     frame_desc::WorldFrameDesc,
     renderers::{
-        deferred::light_gbuffer, motion_blur::motion_blur, raster_meshes::*,
+        deferred::light_gbuffer, dof::dof, motion_blur::motion_blur, raster_meshes::*,
         reference::reference_path_trace, shadows::trace_sun_shadow_mask, GbufferDepth,
     },
     world_renderer::{RenderDebugMode, WorldRenderer},
@@ -9,6 +9,10 @@ use crate::{
 use kajiya_backend::{ash::vk, vulkan::image::*};
 use kajiya_rg::{self as rg, GetOrCreateTemporal};
 
+/// Temporal image accumulating `RenderMode::Reference` frames: linear radiance in RGB,
+/// and the sample count in alpha. `R32G32B32A32_SFLOAT` of the frame's render extent.
+pub const REFERENCE_ACCUMULATION_KEY: &str = "refpt.accum";
+
 impl WorldRenderer {
     pub(super) fn prepare_render_graph_standard(
         &mut self,
@@ -16,6 +20,7 @@ impl WorldRenderer {
         frame_desc: &WorldFrameDesc,
     ) -> rg::Handle<Image> {
         let tlas = if rg.device().ray_tracing_enabled() {
+            self.prepare_triangle_lights(rg);
             Some(self.prepare_top_level_acceleration(rg))
         } else {
             None
@@ -65,18 +70,56 @@ impl WorldRenderer {
                 frame_desc.render_extent,
             ));
 
-            raster_meshes(
-                rg,
-                self.raster_simple_render_pass.clone(),
-                &mut gbuffer_depth,
-                &mut velocity_img,
-                RasterMeshesData {
-                    meshes: self.meshes.as_slice(),
-                    instances: self.instances.as_slice(),
-                    vertex_buffer: self.vertex_buffer.lock().clone(),
-                    bindless_descriptor_set: self.bindless_descriptor_set,
-                },
-            );
+            let mesh_data = RasterMeshesData {
+                meshes: self.meshes.as_slice(),
+                instances: self.instances.as_slice(),
+                vertex_buffer: self.vertex_buffer.lock().clone(),
+                bindless_descriptor_set: self.bindless_descriptor_set,
+            };
+
+            let mut hiz = None;
+
+            if self.use_meshlets && rg.device().mesh_shaders_enabled() {
+                raster_meshes_meshlets(
+                    rg,
+                    self.raster_simple_render_pass.clone(),
+                    &mut gbuffer_depth,
+                    &mut velocity_img,
+                    mesh_data,
+                    RasterMeshletsOptions {
+                        cone_culling: self.meshlet_cone_culling,
+                    },
+                );
+            } else if self.use_gpu_culling && rg.device().draw_indirect_count_enabled() {
+                let (draws, prev_hiz) =
+                    self.gpu_culling
+                        .cull_instances(rg, &mesh_data, frame_desc.render_extent);
+                hiz = Some(prev_hiz);
+
+                raster_meshes(
+                    rg,
+                    self.raster_simple_render_pass.clone(),
+                    &mut gbuffer_depth,
+                    &mut velocity_img,
+                    mesh_data,
+                    Some(&draws),
+                );
+            } else {
+                raster_meshes(
+                    rg,
+                    self.raster_simple_render_pass.clone(),
+                    &mut gbuffer_depth,
+                    &mut velocity_img,
+                    mesh_data,
+                    None,
+                );
+            }
+
+            if let Some(hiz) = hiz {
+                self.gpu_culling.build_hiz(rg, &gbuffer_depth.depth, hiz);
+            } else {
+                self.gpu_culling.invalidate_hiz();
+            }
 
             (gbuffer_depth, velocity_img)
         };
@@ -162,11 +205,7 @@ impl WorldRenderer {
             rtdgi_candidates = None;
         }
 
-        // TODO: don't iter over all the things
-        let any_triangle_lights = self
-            .instances
-            .iter()
-            .any(|inst| !self.mesh_lights[inst.mesh.0].lights.is_empty());
+        let any_triangle_lights = self.triangle_light_count() > 0;
 
         let mut rtr = if let Some(((tlas, rtdgi_irradiance), rtdgi_candidates)) = tlas
             .as_ref()
@@ -196,6 +235,7 @@ impl WorldRenderer {
                     &mut rtr.resolved_tex,
                     rg,
                     &gbuffer_depth,
+                    &reprojection_map,
                     self.bindless_descriptor_set,
                     tlas,
                 );
@@ -204,6 +244,63 @@ impl WorldRenderer {
 
         let rtr = rtr.filter_temporal(rg, &gbuffer_depth, &reprojection_map);
 
+        let punctual_light_count = self.punctual_light_count();
+        let area_light_count = self.area_light_count();
+
+        // Direct lighting from analytic lights: punctual and area
+        let analytic_lighting = match tlas.as_ref() {
+            Some(tlas) if punctual_light_count > 0 || area_light_count > 0 => {
+                let mut output = if punctual_light_count > 0 {
+                    self.lighting.render_punctual_lights(
+                        rg,
+                        &gbuffer_depth,
+                        self.bindless_descriptor_set,
+                        tlas,
+                    )
+                } else {
+                    let mut img = rg.create(ImageDesc::new_2d(
+                        vk::Format::R16G16B16A16_SFLOAT,
+                        gbuffer_depth.gbuffer.desc().extent_2d(),
+                    ));
+                    rg::imageops::clear_color(rg, &mut img, [0.0f32; 4]);
+                    img
+                };
+
+                if area_light_count > 0 {
+                    let area_lights = self.lighting.trace_area_lights(
+                        rg,
+                        &gbuffer_depth,
+                        self.bindless_descriptor_set,
+                        tlas,
+                    );
+
+                    let denoised_shadow_mask = self.area_light_shadow_denoise.render(
+                        rg,
+                        &gbuffer_depth,
+                        &area_lights.shadow_mask,
+                        &reprojection_map,
+                    );
+
+                    self.lighting.composite_area_lights(
+                        rg,
+                        &area_lights.unshadowed,
+                        &denoised_shadow_mask,
+                        &mut output,
+                    );
+                }
+
+                output
+            }
+            _ => {
+                let mut img = rg.create(ImageDesc::new_2d(
+                    vk::Format::R16G16B16A16_SFLOAT,
+                    gbuffer_depth.gbuffer.desc().extent_2d(),
+                ));
+                rg::imageops::clear_color(rg, &mut img, [0.0f32; 4]);
+                img
+            }
+        };
+
         let mut debug_out_tex = rg.create(ImageDesc::new_2d(
             vk::Format::R16G16B16A16_SFLOAT,
             gbuffer_depth.gbuffer.desc().extent_2d(),
@@ -228,11 +325,16 @@ impl WorldRenderer {
             &mut debug_out_tex,
             &sky_cube,
             &convolved_sky_cube,
+            &analytic_lighting,
             self.bindless_descriptor_set,
             self.debug_shading_mode,
             self.debug_show_wrc,
         );
 
+        if self.depth_of_field_camera().is_some() {
+            debug_out_tex = dof(rg, &debug_out_tex, &gbuffer_depth.depth);
+        }
+
         #[allow(unused_mut)]
         let mut anti_aliased = None;
 
@@ -247,13 +349,10 @@ impl WorldRenderer {
             ));
         }
 
-        //let dof = crate::renderers::dof::dof(rg, &debug_out_tex, &gbuffer_depth.depth);
-
         let anti_aliased = anti_aliased.unwrap_or_else(|| {
             self.taa
                 .render(
                     rg,
-                    //&dof,
                     &debug_out_tex,
                     &reprojection_map,
                     &gbuffer_depth.depth,
@@ -262,8 +361,13 @@ impl WorldRenderer {
                 .this_frame_out
         });
 
-        let mut final_post_input =
-            motion_blur(rg, &anti_aliased, &gbuffer_depth.depth, &reprojection_map);
+        let mut final_post_input = motion_blur(
+            rg,
+            &anti_aliased,
+            &gbuffer_depth.depth,
+            &reprojection_map,
+            self.motion_blur_scale(),
+        );
 
         if let Some(tlas) = tlas.as_ref() {
             if matches!(self.debug_mode, RenderDebugMode::WorldRadianceCache) {
@@ -286,6 +390,10 @@ impl WorldRenderer {
             self.exposure_state().post_mult,
             self.contrast,
             self.dynamic_exposure.histogram_clipping,
+            self.display_transform,
+            self.display_target,
+            self.hdr_display,
+            &self.post_settings,
         );
 
         rg.debugged_resource.take().unwrap_or(post_processed)
@@ -296,9 +404,11 @@ impl WorldRenderer {
         rg: &mut rg::TemporalRenderGraph,
         frame_desc: &WorldFrameDesc,
     ) -> rg::Handle<Image> {
+        self.gpu_culling.invalidate_hiz();
+
         let mut accum_img = rg
             .get_or_create_temporal(
-                "refpt.accum",
+                REFERENCE_ACCUMULATION_KEY,
                 ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, frame_desc.render_extent).usage(
                     vk::ImageUsageFlags::SAMPLED
                         | vk::ImageUsageFlags::STORAGE
@@ -313,6 +423,7 @@ impl WorldRenderer {
         }
 
         if rg.device().ray_tracing_enabled() {
+            self.prepare_triangle_lights(rg);
             let tlas = self.prepare_top_level_acceleration(rg);
 
             reference_path_trace(rg, &mut accum_img, self.bindless_descriptor_set, &tlas);
@@ -326,6 +437,10 @@ impl WorldRenderer {
             self.exposure_state().post_mult,
             self.contrast,
             self.dynamic_exposure.histogram_clipping,
+            self.display_transform,
+            self.display_target,
+            self.hdr_display,
+            &self.post_settings,
         )
     }
 }
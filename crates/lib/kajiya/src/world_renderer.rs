@@ -4,16 +4,38 @@ use crate::{
         BINDLESS_TEXURES_BINDING_INDEX,
     },
     buffer_builder::BufferBuilder,
+    camera::PhysicalCamera,
+    cube_lut::CubeLut,
     frame_desc::WorldFrameDesc,
     image_lut::{ComputeImageLut, ImageLut},
+    lights::{
+        AreaLightDesc, AreaLightHandle, GpuAreaLight, GpuPunctualLight, LightDesc, LightHandle,
+        MAX_AREA_LIGHTS, MAX_PUNCTUAL_LIGHTS,
+    },
+    meshlets::build_meshlets,
     renderers::{
-        ibl::IblRenderer, ircache::IrcacheRenderer, lighting::LightingRenderer,
-        post::PostProcessRenderer, raster_meshes::*, rtdgi::RtdgiRenderer, rtr::*,
-        shadow_denoise::ShadowDenoiseRenderer, ssgi::*, taa::TaaRenderer,
+        gpu_culling::GpuCullingRenderer,
+        ibl::IblRenderer,
+        ircache::IrcacheRenderer,
+        lighting::LightingRenderer,
+        post::{
+            DisplayTarget, DisplayTransform, HdrDisplaySettings, PostProcessRenderer,
+            PostProcessSettings,
+        },
+        raster_meshes::*,
+        rtdgi::RtdgiRenderer,
+        rtr::*,
+        shadow_denoise::ShadowDenoiseRenderer,
+        ssgi::*,
+        taa::TaaRenderer,
+        triangle_lights::{GpuEmissiveInstance, TriangleLightsRenderer, MAX_TRIANGLE_LIGHTS},
     },
 };
+use anyhow::Context;
 use glam::{Affine3A, Vec2, Vec3};
-use kajiya_asset::mesh::{AssetRef, GpuImage, MeshMaterialFlags, PackedTriMesh, PackedVertex};
+use kajiya_asset::mesh::{
+    AssetRef, GpuImage, MeshMaterial, MeshMaterialFlags, PackedTriMesh, PackedVertex,
+};
 use kajiya_backend::{
     ash::vk::{self, ImageView},
     dynamic_constants::DynamicConstants,
@@ -51,11 +73,22 @@ struct GpuMesh {
 
     mat_data_offset: u32,
     index_offset: u32,
+
+    // Only populated when mesh shaders are enabled
+    meshlet_offset: u32,
+    meshlet_count: u32,
 }
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
 pub struct MeshHandle(pub usize);
 
+/// Memory used by the bottom-level acceleration structures of all meshes
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BlasMemoryStats {
+    pub total_bytes: usize,
+    pub compaction_savings_bytes: usize,
+}
+
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
 pub struct InstanceHandle(pub usize);
 
@@ -80,22 +113,117 @@ const TLAS_PREALLOCATE_BYTES: usize = 1024 * 1024 * 32;
 #[derive(Clone, Copy)]
 pub struct InstanceDynamicParameters {
     pub emissive_multiplier: f32,
+    /// Replaces the emissive color of the instance's emissive materials.
+    /// Emissive maps still modulate it.
+    pub emissive_color_override: Option<Vec3>,
 }
 
 impl Default for InstanceDynamicParameters {
     fn default() -> Self {
         Self {
             emissive_multiplier: 1.0,
+            emissive_color_override: None,
+        }
+    }
+}
+
+// Must match `INSTANCE_DYNAMIC_FLAG_*` in `inc/frame_constants.hlsl`
+const INSTANCE_DYNAMIC_FLAG_EMISSIVE_COLOR_OVERRIDE: u32 = 1;
+
+/// Must match `InstanceDynamicConstants` in `inc/frame_constants.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuInstanceDynamicParameters {
+    emissive_multiplier: f32,
+    emissive_color_override: [f32; 3],
+    flags: u32,
+}
+
+impl From<&InstanceDynamicParameters> for GpuInstanceDynamicParameters {
+    fn from(params: &InstanceDynamicParameters) -> Self {
+        Self {
+            emissive_multiplier: params.emissive_multiplier,
+            emissive_color_override: params.emissive_color_override.unwrap_or_default().into(),
+            flags: if params.emissive_color_override.is_some() {
+                INSTANCE_DYNAMIC_FLAG_EMISSIVE_COLOR_OVERRIDE
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// Which kinds of rays see an instance, and how.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InstanceFlags {
+    /// Rasterized into the G-buffer, and hit by primary rays of the path tracer
+    pub visible_to_camera: bool,
+    /// Hit by diffuse GI, reflection and irradiance cache rays
+    pub visible_in_indirect: bool,
+    pub cast_shadows: bool,
+    /// Rays hit the back faces of the triangles too
+    pub double_sided: bool,
+}
+
+impl Default for InstanceFlags {
+    fn default() -> Self {
+        Self {
+            visible_to_camera: true,
+            visible_in_indirect: true,
+            cast_shadows: true,
+            double_sided: false,
+        }
+    }
+}
+
+impl InstanceFlags {
+    /// Seen directly, but not in reflections, GI or shadows; e.g. decals
+    pub fn camera_only() -> Self {
+        Self {
+            visible_to_camera: true,
+            visible_in_indirect: false,
+            cast_shadows: false,
+            double_sided: false,
+        }
+    }
+
+    /// Invisible, but casts shadows
+    pub fn shadow_caster_only() -> Self {
+        Self {
+            visible_to_camera: false,
+            visible_in_indirect: false,
+            cast_shadows: true,
+            double_sided: false,
         }
     }
+
+    fn ray_tracing_mask(&self) -> u8 {
+        let mut mask = 0;
+        if self.visible_to_camera {
+            mask |= RT_INSTANCE_MASK_CAMERA;
+        }
+        if self.visible_in_indirect {
+            mask |= RT_INSTANCE_MASK_INDIRECT;
+        }
+        if self.cast_shadows {
+            mask |= RT_INSTANCE_MASK_SHADOW;
+        }
+        mask
+    }
 }
 
+// Must match `RT_INSTANCE_MASK_*` in `inc/rt.hlsl`
+const RT_INSTANCE_MASK_CAMERA: u8 = 1 << 0;
+const RT_INSTANCE_MASK_INDIRECT: u8 = 1 << 1;
+const RT_INSTANCE_MASK_SHADOW: u8 = 1 << 2;
+
 #[derive(Clone, Copy)]
 pub struct MeshInstance {
     pub transform: Affine3A,
     pub prev_transform: Affine3A,
     pub mesh: MeshHandle,
     pub dynamic_parameters: InstanceDynamicParameters,
+    pub flags: InstanceFlags,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -104,39 +232,37 @@ pub enum RenderDebugMode {
     WorldRadianceCache,
 }
 
+/// An emissive triangle in mesh space. Its radiance comes from the material,
+/// and is resolved on the GPU along with the instance's emissive parameters.
+/// Must match `expand_triangle_lights.hlsl`
 #[derive(Clone, Copy)]
 #[repr(C)]
-pub struct TriangleLight {
-    pub verts: [[f32; 3]; 3],
-    pub radiance: [f32; 3],
+struct MeshTriangleLight {
+    verts: [[f32; 3]; 3],
+    uvs: [[f32; 2]; 3],
+    pad: u32,
 }
 
-impl TriangleLight {
-    pub fn transform(
-        self,
-        translation: Vec3,
-        rotation: impl core::ops::Mul<Vec3, Output = Vec3> + Copy,
-    ) -> Self {
-        Self {
-            verts: [
-                (rotation * Vec3::from(self.verts[0]) + translation).into(),
-                (rotation * Vec3::from(self.verts[1]) + translation).into(),
-                (rotation * Vec3::from(self.verts[2]) + translation).into(),
-            ],
-            radiance: self.radiance,
-        }
-    }
-
-    pub fn scale_radiance(self, scale: Vec3) -> Self {
-        Self {
-            verts: self.verts,
-            radiance: (Vec3::from(self.radiance) * scale).into(),
-        }
-    }
+/// Emissive triangles of one material of a mesh, stored in mesh space in the vertex buffer.
+/// Instances of the mesh expand them to world space on the GPU every frame.
+pub struct MeshEmissiveMaterial {
+    pub material_id: u32,
+    pub light_data_offset: u32,
+    pub light_count: u32,
+    pub emissive: Vec3,
+    /// If not, the triangles aren't sampled as lights, but are still visible to rays.
+    pub used_as_light: bool,
 }
 
+#[derive(Default)]
 pub struct MeshLightSet {
-    pub lights: Vec<TriangleLight>,
+    pub materials: Vec<MeshEmissiveMaterial>,
+}
+
+/// CPU copy of the materials of an uploaded mesh, so that they can be modified later.
+struct UploadedMeshMaterials {
+    data_offset: u32,
+    materials: Vec<MeshMaterial>,
 }
 
 pub struct WorldRenderer {
@@ -147,6 +273,10 @@ pub struct WorldRenderer {
     pub(super) meshes: Vec<UploadedTriMesh>,
 
     pub(super) mesh_lights: Vec<MeshLightSet>,
+    mesh_materials: Vec<UploadedMeshMaterials>,
+    triangle_lights: TriangleLightsRenderer,
+    // Of all instances, as last expanded by `prepare_triangle_lights`
+    triangle_light_count: u32,
 
     // ----
     // SoA
@@ -157,18 +287,43 @@ pub struct WorldRenderer {
     // The `usize` indexes into `instances` and `instance_handles`
     pub(super) instance_handle_to_index: HashMap<InstanceHandle, usize>,
 
+    // ----
+    // SoA
+    lights: Vec<LightDesc>,
+    light_handles: Vec<LightHandle>,
+    // ----
+
+    // The `usize` indexes into `lights` and `light_handles`
+    light_handle_to_index: HashMap<LightHandle, usize>,
+
+    // ----
+    // SoA
+    area_lights: Vec<AreaLightDesc>,
+    area_light_handles: Vec<AreaLightHandle>,
+    // ----
+
+    // The `usize` indexes into `area_lights` and `area_light_handles`
+    area_light_handle_to_index: HashMap<AreaLightHandle, usize>,
+
     pub(super) vertex_buffer: Mutex<Arc<Buffer>>,
     vertex_buffer_written: u64,
 
     mesh_buffer: Mutex<Arc<Buffer>>,
 
     mesh_blas: Vec<Arc<RayTracingAcceleration>>,
+    blas_memory: BlasMemoryStats,
     tlas: Option<Arc<RayTracingAcceleration>>,
+    // Meshes of the instances in the last full TLAS build. The TLAS can only be
+    // updated in place while these stay the same.
+    tlas_instance_meshes: Vec<MeshHandle>,
+    frames_since_tlas_rebuild: u32,
     accel_scratch: RayTracingAccelerationScratchBuffer,
 
     bindless_images: Vec<Arc<Image>>,
     next_bindless_image_id: usize,
     next_instance_handle: usize,
+    next_light_handle: usize,
+    next_area_light_handle: usize,
     bindless_texture_sizes: Buffer,
 
     image_luts: Vec<ImageLut>,
@@ -190,7 +345,9 @@ pub struct WorldRenderer {
     pub rtdgi: RtdgiRenderer,
     pub taa: TaaRenderer,
     pub shadow_denoise: ShadowDenoiseRenderer,
+    pub area_light_shadow_denoise: ShadowDenoiseRenderer,
     pub ibl: IblRenderer,
+    pub gpu_culling: GpuCullingRenderer,
 
     #[cfg(feature = "dlss")]
     pub dlss: DlssRenderer,
@@ -200,9 +357,34 @@ pub struct WorldRenderer {
     pub debug_mode: RenderDebugMode,
     pub debug_shading_mode: usize,
     pub debug_show_wrc: bool,
+
+    /// Cull instances and issue G-buffer draws on the GPU; see `GpuCullingRenderer`.
+    /// Off by default: occlusion is only tested against the previous frame's depth,
+    /// so disoccluded instances pop in a frame late.
+    /// Ignored unless `Device::draw_indirect_count_enabled`.
+    pub use_gpu_culling: bool,
+    /// Rasterize the G-buffer with task and mesh shaders, culling individual meshlets.
+    /// Ignored unless `Device::mesh_shaders_enabled`.
+    pub use_meshlets: bool,
+    /// Cull back-facing meshlets of single-sided instances.
+    pub meshlet_cone_culling: bool,
+    /// Frames between full rebuilds of the TLAS. In between, the TLAS is only
+    /// updated in place to the new instance transforms, unless instances are added or removed.
+    pub tlas_rebuild_interval: u32,
+
+    /// Exposure compensation, in stops
     pub ev_shift: f32,
     pub dynamic_exposure: DynamicExposureState,
     pub contrast: f32,
+    pub display_transform: DisplayTransform,
+    /// Must match the color space of the swapchain.
+    pub display_target: DisplayTarget,
+    pub hdr_display: HdrDisplaySettings,
+    pub post_settings: PostProcessSettings,
+
+    /// If set, exposure comes from the camera's EV100 instead of `dynamic_exposure`,
+    /// and it drives depth of field and the motion blur shutter interval.
+    pub physical_camera: Option<PhysicalCamera>,
 
     pub sun_size_multiplier: f32,
     pub sun_color_multiplier: Vec3,
@@ -212,6 +394,9 @@ pub struct WorldRenderer {
 
     // One for each render mode
     pub(crate) exposure_state: [ExposureState; 2],
+
+    // As last passed to `prepare_frame_constants`
+    delta_time_seconds: f32,
 }
 
 #[derive(Default, Clone, Copy)]
@@ -396,6 +581,8 @@ impl WorldRenderer {
             )
             .unwrap();
 
+        let triangle_lights = TriangleLightsRenderer::new(backend.device.as_ref())?;
+
         let bindless_descriptor_set = create_bindless_descriptor_set(backend.device.as_ref());
 
         // `meshes`
@@ -422,6 +609,22 @@ impl WorldRenderer {
             &bindless_texture_sizes,
         );
 
+        // `triangle_lights`
+        Self::write_descriptor_set_buffer(
+            &backend.device.raw,
+            bindless_descriptor_set,
+            3,
+            triangle_lights.light_buffer(),
+        );
+
+        // `triangle_light_cdf`
+        Self::write_descriptor_set_buffer(
+            &backend.device.raw,
+            bindless_descriptor_set,
+            4,
+            triangle_lights.cdf_buffer(),
+        );
+
         let supersample_count = 128;
         let supersample_offsets = (1..=supersample_count)
             .map(|i| Vec2::new(radical_inverse(i, 2) - 0.5, radical_inverse(i, 3) - 0.5))
@@ -452,10 +655,24 @@ impl WorldRenderer {
             instance_handles: Default::default(),
             instance_handle_to_index: Default::default(),
 
+            lights: Default::default(),
+            light_handles: Default::default(),
+            light_handle_to_index: Default::default(),
+
+            area_lights: Default::default(),
+            area_light_handles: Default::default(),
+            area_light_handle_to_index: Default::default(),
+
             mesh_lights: Default::default(),
+            mesh_materials: Default::default(),
+            triangle_lights,
+            triangle_light_count: 0,
 
             mesh_blas: Default::default(),
+            blas_memory: Default::default(),
             tlas: Default::default(),
+            tlas_instance_meshes: Default::default(),
+            frames_since_tlas_rebuild: 0,
             accel_scratch,
 
             mesh_buffer: Mutex::new(Arc::new(mesh_buffer)),
@@ -467,6 +684,8 @@ impl WorldRenderer {
 
             next_bindless_image_id: 0,
             next_instance_handle: 0,
+            next_light_handle: 0,
+            next_area_light_handle: 0,
             bindless_texture_sizes,
 
             rg_debug_hook: None,
@@ -484,7 +703,9 @@ impl WorldRenderer {
             rtdgi: RtdgiRenderer::default(),
             taa: TaaRenderer::new(),
             shadow_denoise: ShadowDenoiseRenderer::default(),
+            area_light_shadow_denoise: ShadowDenoiseRenderer::new("area_light_shadow_denoise"),
             ibl: IblRenderer::default(),
+            gpu_culling: GpuCullingRenderer::default(),
 
             #[cfg(feature = "dlss")]
             dlss,
@@ -501,9 +722,18 @@ impl WorldRenderer {
                 4
             },
             debug_show_wrc: false,
+            use_gpu_culling: false,
+            use_meshlets: false,
+            meshlet_cone_culling: false,
+            tlas_rebuild_interval: 60,
             ev_shift: 0.0,
             dynamic_exposure: Default::default(),
             contrast: 1.0,
+            display_transform: DisplayTransform::Neutral,
+            display_target: DisplayTarget::Rec709,
+            hdr_display: Default::default(),
+            post_settings: Default::default(),
+            physical_camera: None,
 
             sun_size_multiplier: 1.0, // Sun as seen from Earth
             sun_color_multiplier: Vec3::ONE,
@@ -512,6 +742,7 @@ impl WorldRenderer {
             render_overrides: Default::default(),
 
             exposure_state: Default::default(),
+            delta_time_seconds: 1.0 / 60.0,
         })
     }
 
@@ -564,6 +795,31 @@ impl WorldRenderer {
         handle
     }
 
+    /// Loads a `.cube` LUT to apply to the display signal. See `PostProcessRenderer::load_output_lut`.
+    pub fn load_output_lut(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let lut = CubeLut::load(path)?;
+        self.post.load_output_lut(self.device.as_ref(), &lut)?;
+        Ok(())
+    }
+
+    pub fn unload_output_lut(&mut self) {
+        self.post.unload_output_lut(self.device.as_ref());
+    }
+
+    /// Loads an image to modulate the bloom and lens flare with. See `PostProcessRenderer::load_lens_dirt`.
+    pub fn load_lens_dirt(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("Loading lens dirt {:?}", path))?
+            .to_rgba8();
+        self.post.load_lens_dirt(self.device.as_ref(), &image)?;
+        Ok(())
+    }
+
+    pub fn unload_lens_dirt(&mut self) {
+        self.post.unload_lens_dirt(self.device.as_ref());
+    }
+
     pub fn add_image_lut(&mut self, computer: impl ComputeImageLut + 'static, id: usize) {
         self.image_luts
             .push(ImageLut::new(self.device.as_ref(), Box::new(computer)));
@@ -647,13 +903,19 @@ impl WorldRenderer {
             }
         }
 
+        let is_emissive = |mat: &MeshMaterial| {
+            mat.emissive[0] > 0.0 || mat.emissive[1] > 0.0 || mat.emissive[2] > 0.0
+        };
+
         // If using emissives as lights, flag it in the material parameters
         if opts.use_lights {
-            for mat in materials.iter_mut() {
+            for mat in materials.iter_mut().filter(|mat| is_emissive(mat)) {
                 mat.flags |= MeshMaterialFlags::MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT;
             }
         }
 
+        let uploaded_materials = materials.clone();
+
         let vertex_data_offset = self.vertex_buffer_written as u32;
 
         let mut buffer_builder = BufferBuilder::new();
@@ -671,6 +933,62 @@ impl WorldRenderer {
             buffer_builder.append(mesh.tangents.as_slice()) as u32 + vertex_data_offset;
         let mat_data_offset = buffer_builder.append(materials) as u32 + vertex_data_offset;
 
+        let (meshlet_offset, meshlet_count) = if self.device.mesh_shaders_enabled() {
+            let mut meshlets = build_meshlets(mesh.indices.as_slice(), |v| {
+                Vec3::from(mesh.verts[v as usize].pos)
+            });
+
+            let meshlet_vertices_offset =
+                buffer_builder.append(meshlets.vertices) as u32 + vertex_data_offset;
+            let meshlet_triangles_offset =
+                buffer_builder.append(meshlets.triangles) as u32 + vertex_data_offset;
+
+            for meshlet in &mut meshlets.meshlets {
+                meshlet.vertex_offset += meshlet_vertices_offset;
+                meshlet.triangle_offset += meshlet_triangles_offset;
+            }
+
+            let meshlet_count = meshlets.meshlets.len() as u32;
+            let meshlet_offset =
+                buffer_builder.append(meshlets.meshlets) as u32 + vertex_data_offset;
+
+            (meshlet_offset, meshlet_count)
+        } else {
+            (0, 0)
+        };
+
+        // Emissive triangles are always uploaded, grouped by material,
+        // so that materials can be toggled as lights after loading.
+        let mut lights_per_material: Vec<Vec<MeshTriangleLight>> =
+            vec![Vec::new(); uploaded_materials.len()];
+        for indices in mesh.indices.as_slice().chunks_exact(3) {
+            let material_id = mesh.material_ids[indices[0] as usize] as usize;
+            if !is_emissive(&uploaded_materials[material_id]) {
+                continue;
+            }
+
+            lights_per_material[material_id].push(MeshTriangleLight {
+                verts: [0, 1, 2].map(|i| mesh.verts[indices[i] as usize].pos),
+                uvs: [0, 1, 2].map(|i| mesh.uvs[indices[i] as usize]),
+                pad: 0,
+            });
+        }
+
+        let mut mesh_lights = MeshLightSet::default();
+        for (material_id, lights) in lights_per_material.into_iter().enumerate() {
+            if lights.is_empty() {
+                continue;
+            }
+
+            mesh_lights.materials.push(MeshEmissiveMaterial {
+                material_id: material_id as u32,
+                light_count: lights.len() as u32,
+                emissive: uploaded_materials[material_id].emissive.into(),
+                used_as_light: opts.use_lights,
+                light_data_offset: buffer_builder.append(lights) as u32 + vertex_data_offset,
+            });
+        }
+
         let total_buffer_size = buffer_builder.current_offset();
         let mut vertex_buffer = self.vertex_buffer.lock();
         buffer_builder
@@ -720,6 +1038,14 @@ impl WorldRenderer {
                 })
                 .expect("blas");
 
+            self.blas_memory.total_bytes += blas.size();
+            self.blas_memory.compaction_savings_bytes += blas.compaction_savings();
+            log::info!(
+                "BLAS memory: {} KiB, {} KiB saved by compaction",
+                self.blas_memory.total_bytes / 1024,
+                self.blas_memory.compaction_savings_bytes / 1024,
+            );
+
             self.mesh_blas.push(Arc::new(blas));
         }
 
@@ -731,45 +1057,27 @@ impl WorldRenderer {
             vertex_tangent_offset,
             mat_data_offset,
             index_offset: vertex_index_offset,
+            meshlet_offset,
+            meshlet_count,
         };
 
+        let (aabb_min, aabb_max) = mesh.verts.as_slice().iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| (min.min(v.pos.into()), max.max(v.pos.into())),
+        );
+
         self.meshes.push(UploadedTriMesh {
             index_buffer_offset: vertex_index_offset as u64,
             index_count: mesh.indices.len() as _,
+            meshlet_count,
+            aabb_min,
+            aabb_max,
         });
 
-        let mesh_lights = if opts.use_lights {
-            let emissive_materials = mesh
-                .materials
-                .iter()
-                .map(|mat| mat.emissive[0] > 0.0 || mat.emissive[1] > 0.0 || mat.emissive[2] > 0.0)
-                .collect::<Vec<bool>>();
-
-            let mut mesh_lights: Vec<TriangleLight> = Vec::new();
-            for indices in mesh.indices.as_slice().chunks_exact(3) {
-                let mat_idx = mesh.material_ids[indices[0] as usize] as usize;
-                if !emissive_materials[mat_idx] {
-                    continue;
-                }
-
-                let v0 = mesh.verts[indices[0] as usize].pos;
-                let v1 = mesh.verts[indices[1] as usize].pos;
-                let v2 = mesh.verts[indices[2] as usize].pos;
-                let radiance = mesh.materials[mat_idx].emissive;
-
-                mesh_lights.push(TriangleLight {
-                    verts: [v0, v1, v2],
-                    radiance,
-                });
-            }
-
-            mesh_lights
-        } else {
-            Vec::new()
-        };
-
-        self.mesh_lights.push(MeshLightSet {
-            lights: mesh_lights,
+        self.mesh_lights.push(mesh_lights);
+        self.mesh_materials.push(UploadedMeshMaterials {
+            data_offset: mat_data_offset,
+            materials: uploaded_materials,
         });
 
         MeshHandle(mesh_idx)
@@ -787,6 +1095,7 @@ impl WorldRenderer {
             prev_transform: transform,
             mesh,
             dynamic_parameters: InstanceDynamicParameters::default(),
+            flags: InstanceFlags::default(),
         });
         self.instance_handles.push(handle);
 
@@ -817,6 +1126,168 @@ impl WorldRenderer {
         self.instances[index].transform = transform;
     }
 
+    pub fn get_instance_flags(&self, inst: InstanceHandle) -> InstanceFlags {
+        let index = self.instance_handle_to_index[&inst];
+        self.instances[index].flags
+    }
+
+    pub fn set_instance_flags(&mut self, inst: InstanceHandle, flags: InstanceFlags) {
+        let index = self.instance_handle_to_index[&inst];
+        self.instances[index].flags = flags;
+    }
+
+    /// Toggles whether the emissive triangles of a material are sampled as lights,
+    /// overriding `AddMeshOptions::use_lights`. Does nothing for materials without emission.
+    pub fn set_mesh_material_used_as_light(
+        &mut self,
+        mesh: MeshHandle,
+        material_id: u32,
+        used_as_light: bool,
+    ) {
+        let emissive_material = self.mesh_lights[mesh.0]
+            .materials
+            .iter_mut()
+            .find(|mat| mat.material_id == material_id);
+
+        let emissive_material = if let Some(emissive_material) = emissive_material {
+            emissive_material
+        } else {
+            return;
+        };
+
+        if emissive_material.used_as_light == used_as_light {
+            return;
+        }
+
+        emissive_material.used_as_light = used_as_light;
+
+        // Hits on emissive surfaces which are lights only count when coming from the eye,
+        // so the flag needs to match what's being sampled.
+        let uploaded = &mut self.mesh_materials[mesh.0];
+        let material = &mut uploaded.materials[material_id as usize];
+        if used_as_light {
+            material.flags |= MeshMaterialFlags::MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT;
+        } else {
+            material.flags &= !MeshMaterialFlags::MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT;
+        }
+
+        let mut buffer_builder = BufferBuilder::new();
+        buffer_builder.append(vec![*material]);
+
+        let mut vertex_buffer = self.vertex_buffer.lock();
+        buffer_builder
+            .upload(
+                self.device.as_ref(),
+                Arc::get_mut(&mut *vertex_buffer).expect("refs may not be retained"),
+                (uploaded.data_offset as usize + material_id as usize * size_of::<MeshMaterial>())
+                    as u64,
+            )
+            .map_err(|err| self.device.report_error(err))
+            .unwrap();
+    }
+
+    /// Fails if there are `MAX_PUNCTUAL_LIGHTS` already.
+    pub fn add_light(&mut self, desc: LightDesc) -> anyhow::Result<LightHandle> {
+        anyhow::ensure!(
+            self.lights.len() < MAX_PUNCTUAL_LIGHTS,
+            "Too many punctual lights; at most {} are supported",
+            MAX_PUNCTUAL_LIGHTS
+        );
+
+        let handle = LightHandle(self.next_light_handle);
+        self.next_light_handle += 1;
+
+        let index = self.lights.len();
+
+        self.lights.push(desc);
+        self.light_handles.push(handle);
+
+        assert_eq!(self.lights.len(), self.light_handles.len());
+
+        self.light_handle_to_index.insert(handle, index);
+
+        Ok(handle)
+    }
+
+    pub fn remove_light(&mut self, light: LightHandle) {
+        let index = self
+            .light_handle_to_index
+            .remove(&light)
+            .expect("no such light");
+        self.lights.swap_remove(index);
+        self.light_handles.swap_remove(index);
+
+        // Same as in `remove_instance`
+        if let Some(new_handle) = self.light_handles.get(index).copied() {
+            self.light_handle_to_index.insert(new_handle, index);
+        }
+    }
+
+    pub fn get_light_params(&self, light: LightHandle) -> &LightDesc {
+        let index = self.light_handle_to_index[&light];
+        &self.lights[index]
+    }
+
+    pub fn set_light_params(&mut self, light: LightHandle, desc: LightDesc) {
+        let index = self.light_handle_to_index[&light];
+        self.lights[index] = desc;
+    }
+
+    pub(crate) fn punctual_light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Fails if there are `MAX_AREA_LIGHTS` already.
+    pub fn add_area_light(&mut self, desc: AreaLightDesc) -> anyhow::Result<AreaLightHandle> {
+        anyhow::ensure!(
+            self.area_lights.len() < MAX_AREA_LIGHTS,
+            "Too many area lights; at most {} are supported",
+            MAX_AREA_LIGHTS
+        );
+
+        let handle = AreaLightHandle(self.next_area_light_handle);
+        self.next_area_light_handle += 1;
+
+        let index = self.area_lights.len();
+
+        self.area_lights.push(desc);
+        self.area_light_handles.push(handle);
+
+        assert_eq!(self.area_lights.len(), self.area_light_handles.len());
+
+        self.area_light_handle_to_index.insert(handle, index);
+
+        Ok(handle)
+    }
+
+    pub fn remove_area_light(&mut self, light: AreaLightHandle) {
+        let index = self
+            .area_light_handle_to_index
+            .remove(&light)
+            .expect("no such area light");
+        self.area_lights.swap_remove(index);
+        self.area_light_handles.swap_remove(index);
+
+        // Same as in `remove_instance`
+        if let Some(new_handle) = self.area_light_handles.get(index).copied() {
+            self.area_light_handle_to_index.insert(new_handle, index);
+        }
+    }
+
+    pub fn get_area_light_params(&self, light: AreaLightHandle) -> &AreaLightDesc {
+        let index = self.area_light_handle_to_index[&light];
+        &self.area_lights[index]
+    }
+
+    pub fn set_area_light_params(&mut self, light: AreaLightHandle, desc: AreaLightDesc) {
+        let index = self.area_light_handle_to_index[&light];
+        self.area_lights[index] = desc;
+    }
+
+    pub(crate) fn area_light_count(&self) -> usize {
+        self.area_lights.len()
+    }
+
     pub fn get_instance_dynamic_parameters(
         &self,
         inst: InstanceHandle,
@@ -842,11 +1313,7 @@ impl WorldRenderer {
                     instances: self
                         .instances
                         .iter()
-                        .map(|inst| RayTracingInstanceDesc {
-                            blas: self.mesh_blas[inst.mesh.0].clone(),
-                            transformation: inst.transform,
-                            mesh_index: inst.mesh.0 as u32,
-                        })
+                        .map(|inst| self.ray_tracing_instance_desc(inst))
                         .collect::<Vec<_>>(),
                     preallocate_bytes: TLAS_PREALLOCATE_BYTES,
                 },
@@ -855,6 +1322,22 @@ impl WorldRenderer {
             .expect("tlas");
 
         self.tlas = Some(Arc::new(tlas));
+        self.tlas_instance_meshes = self.instances.iter().map(|inst| inst.mesh).collect();
+        self.frames_since_tlas_rebuild = 0;
+    }
+
+    fn ray_tracing_instance_desc(&self, inst: &MeshInstance) -> RayTracingInstanceDesc {
+        RayTracingInstanceDesc {
+            blas: self.mesh_blas[inst.mesh.0].clone(),
+            transformation: inst.transform,
+            mesh_index: inst.mesh.0 as u32,
+            mask: inst.flags.ray_tracing_mask(),
+            double_sided: inst.flags.double_sided,
+        }
+    }
+
+    pub fn blas_memory_stats(&self) -> BlasMemoryStats {
+        self.blas_memory
     }
 
     #[allow(dead_code)]
@@ -862,6 +1345,49 @@ impl WorldRenderer {
         self.frame_idx = 0;
     }
 
+    /// Expands the emissive triangles of all instances into the world-space list sampled
+    /// by shaders, and builds its light selection CDF. Instances which would take the light
+    /// count past `MAX_TRIANGLE_LIGHTS` don't contribute any lights.
+    pub(super) fn prepare_triangle_lights(&mut self, rg: &mut rg::RenderGraph) {
+        let mut emissive_instances: Vec<GpuEmissiveInstance> = Vec::new();
+        let mut light_count: u32 = 0;
+
+        for (instance_index, inst) in self.instances.iter().enumerate() {
+            for material in &self.mesh_lights[inst.mesh.0].materials {
+                if !material.used_as_light
+                    || (light_count + material.light_count) as usize > MAX_TRIANGLE_LIGHTS
+                {
+                    continue;
+                }
+
+                emissive_instances.push(GpuEmissiveInstance {
+                    transform: gpu_instance_transforms(inst).0,
+                    light_data_offset: material.light_data_offset,
+                    first_light: light_count,
+                    instance_index: instance_index as u32,
+                    mesh_index: inst.mesh.0 as u32,
+                    material_id: material.material_id,
+                    pad: [0; 3],
+                });
+
+                light_count += material.light_count;
+            }
+        }
+
+        self.triangle_light_count = light_count;
+
+        self.triangle_lights.prepare(
+            rg,
+            self.bindless_descriptor_set,
+            emissive_instances,
+            light_count,
+        );
+    }
+
+    pub(crate) fn triangle_light_count(&self) -> u32 {
+        self.triangle_light_count
+    }
+
     pub(super) fn prepare_top_level_acceleration(
         &mut self,
         rg: &mut rg::TemporalRenderGraph,
@@ -874,14 +1400,35 @@ impl WorldRenderer {
         let instances = self
             .instances
             .iter()
-            .map(|inst| RayTracingInstanceDesc {
-                blas: self.mesh_blas[inst.mesh.0].clone(),
-                transformation: inst.transform,
-                mesh_index: inst.mesh.0 as u32,
-            })
+            .map(|inst| self.ray_tracing_instance_desc(inst))
             .collect::<Vec<_>>();
 
-        let mut pass = rg.add_pass("rebuild tlas");
+        // Refit the TLAS if only transforms have changed since the last build,
+        // but rebuild it periodically, as refitting degrades its quality.
+        let instances_changed = self.tlas_instance_meshes.len() != self.instances.len()
+            || self
+                .tlas_instance_meshes
+                .iter()
+                .zip(&self.instances)
+                .any(|(mesh, inst)| *mesh != inst.mesh);
+
+        let full_rebuild =
+            instances_changed || self.frames_since_tlas_rebuild + 1 >= self.tlas_rebuild_interval;
+
+        if full_rebuild {
+            if instances_changed {
+                self.tlas_instance_meshes = self.instances.iter().map(|inst| inst.mesh).collect();
+            }
+            self.frames_since_tlas_rebuild = 0;
+        } else {
+            self.frames_since_tlas_rebuild += 1;
+        }
+
+        let mut pass = rg.add_pass(if full_rebuild {
+            "rebuild tlas"
+        } else {
+            "update tlas"
+        });
         let tlas_ref = pass.write(&mut tlas, AccessType::TransferWrite);
 
         let accel_scratch = self.accel_scratch.clone();
@@ -896,13 +1443,23 @@ impl WorldRenderer {
             let tlas = api.resources.rt_acceleration(tlas_ref);
 
             let cb = api.cb;
-            api.device().rebuild_ray_tracing_top_acceleration(
-                cb.raw,
-                instance_buffer_address,
-                instances.len(),
-                tlas,
-                &accel_scratch,
-            );
+            if full_rebuild {
+                api.device().rebuild_ray_tracing_top_acceleration(
+                    cb.raw,
+                    instance_buffer_address,
+                    instances.len(),
+                    tlas,
+                    &accel_scratch,
+                );
+            } else {
+                api.device().update_ray_tracing_top_acceleration(
+                    cb.raw,
+                    instance_buffer_address,
+                    instances.len(),
+                    tlas,
+                    &accel_scratch,
+                );
+            }
 
             Ok(())
         });
@@ -917,10 +1474,14 @@ impl WorldRenderer {
     }
 
     fn update_pre_exposure(&mut self) {
-        let dt = 1.0 / 60.0; // TODO
+        self.dynamic_exposure
+            .update(-self.post.image_log2_lum, self.delta_time_seconds);
 
-        self.dynamic_exposure.update(-self.post.image_log2_lum, dt);
-        let ev_mult = (self.ev_shift + self.dynamic_exposure.ev_smoothed()).exp2();
+        let ev_mult = if let Some(camera) = &self.physical_camera {
+            camera.exposure() * self.ev_shift.exp2()
+        } else {
+            (self.ev_shift + self.dynamic_exposure.ev_smoothed()).exp2()
+        };
 
         let exposure_state = &mut self.exposure_state[self.render_mode as usize];
 
@@ -947,6 +1508,20 @@ impl WorldRenderer {
         exposure_state.pre_mult_delta = exposure_state.pre_mult / exposure_state.pre_mult_prev;
     }
 
+    pub(crate) fn depth_of_field_camera(&self) -> Option<&PhysicalCamera> {
+        self.physical_camera
+            .as_ref()
+            .filter(|camera| camera.depth_of_field)
+    }
+
+    /// Length of the motion blur relative to the motion over one frame
+    pub(crate) fn motion_blur_scale(&self) -> f32 {
+        // Motion vectors only span one frame, so longer exposures can't be reconstructed
+        self.physical_camera.as_ref().map_or(1.0, |camera| {
+            (camera.shutter_time / self.delta_time_seconds.max(1e-6)).min(1.0)
+        })
+    }
+
     pub fn exposure_state(&self) -> ExposureState {
         self.exposure_state[self.render_mode as usize]
     }
@@ -1033,28 +1608,6 @@ impl WorldRenderer {
             frame_desc.render_extent.into(),
         );
 
-        let triangle_lights: Vec<TriangleLight> = self
-            .instances
-            .iter()
-            .flat_map(|inst| {
-                let (_scale, rotation, translation) =
-                    inst.transform.to_scale_rotation_translation();
-                let inst_position = translation;
-                let inst_rotation = rotation;
-
-                let emissive_multiplier = Vec3::splat(inst.dynamic_parameters.emissive_multiplier);
-
-                self.mesh_lights[inst.mesh.0]
-                    .lights
-                    .iter()
-                    .map(move |light: &TriangleLight| {
-                        light
-                            .transform(inst_position, inst_rotation)
-                            .scale_radiance(emissive_multiplier)
-                    })
-            })
-            .collect();
-
         // Initialize constants for the maximum allowed cascade count, even if we're not using them,
         // so that we don't need to change the layout of frame constants up to this limit.
         let mut ircache_cascades: [IrcacheCascadeConstants; IRCACHE_CASCADE_COUNT] =
@@ -1079,12 +1632,21 @@ impl WorldRenderer {
 
             sun_color_multiplier: self.sun_color_multiplier.extend(0.0),
             sky_ambient: self.sky_ambient.extend(0.0),
-            triangle_light_count: triangle_lights.len() as _,
+            triangle_light_count: self.triangle_light_count,
 
             pre_exposure: self.exposure_state().pre_mult,
             pre_exposure_prev: self.exposure_state().pre_mult_prev,
             pre_exposure_delta: self.exposure_state().pre_mult_delta,
-            pad0: 0.0,
+            punctual_light_count: self.lights.len() as _,
+
+            area_light_count: self.area_lights.len() as _,
+            lens_aperture_radius: self
+                .depth_of_field_camera()
+                .map_or(0.0, |camera| camera.aperture_radius()),
+            lens_focus_distance: self
+                .depth_of_field_camera()
+                .map_or(0.0, |camera| camera.focus_distance),
+            pad0: 0,
 
             render_overrides: self.render_overrides,
 
@@ -1092,18 +1654,26 @@ impl WorldRenderer {
             ircache_cascades,
         });
 
-        let instance_dynamic_parameters_offset = dynamic_constants
-            .push_from_iter(self.instances.iter().map(|inst| inst.dynamic_parameters));
+        let instance_dynamic_parameters_offset = dynamic_constants.push_from_iter(
+            self.instances
+                .iter()
+                .map(|inst| GpuInstanceDynamicParameters::from(&inst.dynamic_parameters)),
+        );
+
+        let punctual_lights_offset: u32 =
+            dynamic_constants.push_from_iter(self.lights.iter().map(GpuPunctualLight::from));
 
-        let triangle_lights_offset: u32 =
-            dynamic_constants.push_from_iter(triangle_lights.into_iter());
+        let area_lights_offset: u32 =
+            dynamic_constants.push_from_iter(self.area_lights.iter().map(GpuAreaLight::from));
 
         self.prev_camera_matrices = Some(frame_desc.camera_matrices);
+        self.delta_time_seconds = delta_time_seconds;
 
         rg::renderer::FrameConstantsLayout {
             globals_offset,
             instance_dynamic_parameters_offset,
-            triangle_lights_offset,
+            punctual_lights_offset,
+            area_lights_offset,
         }
     }
 
@@ -10,6 +10,9 @@ pub struct IrcacheCascadeConstants {
     pub voxels_scrolled_this_frame: IVec4,
 }
 
+/// Working color space: all radiance, including colors in these constants, is scene-linear
+/// with Rec.709 (sRGB) primaries and a D65 white point. Lighting buffers also carry `pre_exposure`.
+/// Post processing converts it to the primaries of the display; see `DisplayTarget`.
 #[repr(C, align(16))]
 #[derive(Copy, Clone)]
 pub struct FrameConstants {
@@ -28,7 +31,13 @@ pub struct FrameConstants {
     pub pre_exposure: f32,
     pub pre_exposure_prev: f32,
     pub pre_exposure_delta: f32,
-    pub pad0: f32,
+    pub punctual_light_count: u32,
+
+    pub area_light_count: u32,
+    // Thin lens parameters in world units; zero radius for a pinhole camera.
+    pub lens_aperture_radius: f32,
+    pub lens_focus_distance: f32,
+    pub pad0: u32,
 
     pub render_overrides: RenderOverrides,
 
@@ -11,6 +11,8 @@ pub struct MeshDescriptor {
     pub vertex_tangent_offset: u32,
     pub mat_data_offset: u32,
     pub index_offset: u32,
+    pub meshlet_offset: u32,
+    pub meshlet_count: u32,
 }
 
 #[repr(C, align(16))]
@@ -66,22 +66,21 @@ pub fn signum_f32(x: f32) -> f32 {
     }
 }
 
+// Works with both perspective and orthographic projections.
 pub fn depth_to_view_z(depth: f32, frame_constants: &FrameConstants) -> f32 {
-    (depth
-        * -frame_constants
-            .view_constants
-            .clip_to_view
-            .to_cols_array_2d()[2][3])
-        .recip()
+    let m = frame_constants
+        .view_constants
+        .clip_to_view
+        .to_cols_array_2d();
+    (m[2][2] * depth + m[3][2]) / (m[2][3] * depth + m[3][3])
 }
 
 pub fn depth_to_view_z_vec4(depth: Vec4, frame_constants: &FrameConstants) -> Vec4 {
-    (depth
-        * -frame_constants
-            .view_constants
-            .clip_to_view
-            .to_cols_array_2d()[2][3])
-        .recip()
+    let m = frame_constants
+        .view_constants
+        .clip_to_view
+        .to_cols_array_2d();
+    (depth * m[2][2] + Vec4::splat(m[3][2])) / (depth * m[2][3] + Vec4::splat(m[3][3]))
 }
 
 // Note: `const_mat3` is initialized with columns, while `float3x3` in HLSL is row-order,
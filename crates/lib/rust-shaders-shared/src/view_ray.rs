@@ -1,6 +1,13 @@
 use crate::{frame_constants::FrameConstants, util::*};
 use glam::*;
 
+/// Depth 0 is at infinity for perspective projections, but on the far plane for orthographic ones.
+/// Returns the direction from the origin towards the far point, in both cases with z = -1.
+fn ray_dir_vs_from_far_point(far_vs_h: Vec4, origin_vs_h: Vec4) -> Vec4 {
+    let dir = far_vs_h.xyz() * origin_vs_h.w - origin_vs_h.xyz() * far_vs_h.w;
+    (dir / -dir.z).extend(0.0)
+}
+
 #[repr(C)]
 pub struct ViewRayContext {
     pub ray_dir_cs: Vec4,
@@ -44,14 +51,15 @@ impl ViewRayContext {
     pub fn from_uv(uv: Vec2, frame_constants: &FrameConstants) -> Self {
         let view_constants = frame_constants.view_constants;
 
-        let ray_dir_cs = uv_to_cs(uv).extend(0.0).extend(1.0);
-        let ray_dir_vs_h = view_constants.sample_to_view * ray_dir_cs;
-        let ray_dir_ws_h = view_constants.view_to_world * ray_dir_vs_h;
-
         let ray_origin_cs = uv_to_cs(uv).extend(1.0).extend(1.0);
         let ray_origin_vs_h = view_constants.sample_to_view * ray_origin_cs;
         let ray_origin_ws_h = view_constants.view_to_world * ray_origin_vs_h;
 
+        let ray_dir_cs = uv_to_cs(uv).extend(0.0).extend(1.0);
+        let ray_dir_vs_h =
+            ray_dir_vs_from_far_point(view_constants.sample_to_view * ray_dir_cs, ray_origin_vs_h);
+        let ray_dir_ws_h = view_constants.view_to_world * ray_dir_vs_h;
+
         ViewRayContext {
             ray_dir_cs,
             ray_dir_vs_h,
@@ -68,14 +76,15 @@ impl ViewRayContext {
     pub fn from_uv_and_depth(uv: Vec2, depth: f32, frame_constants: &FrameConstants) -> Self {
         let view_constants = frame_constants.view_constants;
 
-        let ray_dir_cs = uv_to_cs(uv).extend(0.0).extend(1.0);
-        let ray_dir_vs_h = view_constants.sample_to_view * ray_dir_cs;
-        let ray_dir_ws_h = view_constants.view_to_world * ray_dir_vs_h;
-
         let ray_origin_cs = uv_to_cs(uv).extend(1.0).extend(1.0);
         let ray_origin_vs_h = view_constants.sample_to_view * ray_origin_cs;
         let ray_origin_ws_h = view_constants.view_to_world * ray_origin_vs_h;
 
+        let ray_dir_cs = uv_to_cs(uv).extend(0.0).extend(1.0);
+        let ray_dir_vs_h =
+            ray_dir_vs_from_far_point(view_constants.sample_to_view * ray_dir_cs, ray_origin_vs_h);
+        let ray_dir_ws_h = view_constants.view_to_world * ray_dir_vs_h;
+
         let ray_hit_cs = uv_to_cs(uv).extend(depth).extend(1.0);
         let ray_hit_vs_h = view_constants.sample_to_view * ray_hit_cs;
         let ray_hit_ws_h = view_constants.view_to_world * ray_hit_vs_h;